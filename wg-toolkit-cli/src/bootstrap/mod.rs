@@ -6,14 +6,13 @@ use std::borrow::Cow;
 use std::path::Path;
 
 use wgtk::res::ResFilesystem;
-use wgtk::pxml;
 
 use crate::{BootstrapArgs, CliResult};
 
 mod parse;
 mod model;
 
-use model::{Entity, Interface, Method, Model, PropertyFlags, Ty, TyKind, VariableHeaderSize};
+use model::{Entity, Interface, Method, Model, Property, PropertyFlags, Ty, TyKind, VariableHeaderSize};
 
 // NOTE: For the future, if python bytecode interpretation is needed to automatically
 // generate enumeration or try to gather function arguments' names, see:
@@ -42,36 +41,32 @@ fn load(fs: ResFilesystem) -> io::Result<Model> {
     let mut model = Model::default();
 
     println!("== Reading aliases...");
-    let alias_reader = fs.read("scripts/entity_defs/alias.xml")?;
-    let alias_elt = pxml::from_reader(alias_reader).unwrap();
+    let alias_elt = fs.read_pxml("scripts/entity_defs/alias.xml")?;
     parse::parse_aliases(&alias_elt, &mut model.tys);
 
     println!("== Reading interfaces...");
-    for interface_file in fs.read_dir("scripts/entity_defs/interfaces")? {
+    for interface_file in fs.list_by_extension("scripts/entity_defs/interfaces", "def")? {
         
         let interface_file = interface_file?;
-        let Some((interface_name, "")) = interface_file.name().split_once(".def") else {
+        let Some(interface_name) = interface_file.name().strip_suffix(".def") else {
             continue;
         };
 
         println!(" = {interface_name}");
 
-        let interface_reader = fs.read(interface_file.path())?;
-        let interface_elt = pxml::from_reader(interface_reader).unwrap();
+        let interface_elt = fs.read_pxml(interface_file.path())?;
         let interface = parse::parse_interface(&interface_elt, &mut model.tys, interface_name.to_string());
         model.interfaces.push(interface);
 
     }
 
     println!("== Reading entities...");
-    let entities_reader = fs.read("scripts/entities.xml")?;
-    let entities_elt = pxml::from_reader(entities_reader).unwrap();
+    let entities_elt = fs.read_pxml("scripts/entities.xml")?;
     let entities_elt = entities_elt.get_child("ClientServerEntities").unwrap().as_element().unwrap();
     for (index, (entity_name, _)) in entities_elt.iter_children_all().enumerate() {
         
         println!(" = {entity_name}");
-        let entity_reader = fs.read(format!("scripts/entity_defs/{entity_name}.def"))?;
-        let entity_elt = pxml::from_reader(entity_reader).unwrap();
+        let entity_elt = fs.read_pxml(format!("scripts/entity_defs/{entity_name}.def"))?;
         let entity = parse::parse_entity(&entity_elt, &mut model.tys, index + 1, entity_name.to_string());
         model.entities.push(entity);
 
@@ -274,8 +269,9 @@ fn generate_entity(
         generate_entity_methods(&mut writer, model, entity, app_state)?;
     }
     
+    // The entity type id, as sent when creating entities on the client.
     writeln!(writer, "impl {} {{", entity.interface.name)?;
-    writeln!(writer, "    const TYPE_ID: u16 = 0x{:02X};", entity.id)?;
+    writeln!(writer, "    pub const TYPE_ID: u16 = 0x{:02X};", entity.id)?;
     writeln!(writer, "}}")?;
     writeln!(writer)?;
 
@@ -317,16 +313,11 @@ fn generate_entity_methods(
         model: &'m Model, 
         interface: &'m Interface,
         app_state: &mut AppState,
-    ) {
+    ) -> io::Result<()> {
 
         for interface_name in &interface.implements {
-
-            let interface = model.interfaces.iter()
-                .find(|i| &i.name == interface_name)
-                .expect("unknown implemented interface");
-
-            add_internal_methods(exposed_methods, model, interface, &mut *app_state);
-
+            let implemented = find_implemented_interface(model, interface, interface_name)?;
+            add_internal_methods(exposed_methods, model, implemented, &mut *app_state)?;
         }
         
         for method in (app_state.interface_methods)(interface) {
@@ -339,10 +330,12 @@ fn generate_entity_methods(
             }
         }
 
+        Ok(())
+
     }
 
     let mut methods = Vec::new();
-    add_internal_methods(&mut methods, model, &entity.interface, &mut *app_state);
+    add_internal_methods(&mut methods, model, &entity.interface, &mut *app_state)?;
 
     // We want to sort fixed methods first and variable last, and then sort between
     // their configured fixed or variable size.
@@ -399,45 +392,51 @@ fn generate_interface(
     writeln!(writer, "// ============================================== //")?;
     writeln!(writer)?;
     
+    // Properties of implemented interfaces are flattened before the interface's own 
+    // properties, this is the order used by the engine when encoding entities.
+    let mut properties = Vec::new();
+    add_interface_properties(&mut properties, model, interface)?;
+
     writeln!(writer, "wgtk::__struct_simple_codec! {{")?;
     writeln!(writer, "    #[derive(Debug)]")?;
     writeln!(writer, "    pub struct {} {{", interface.name)?;
-    
-    for interface_name in &interface.implements {
-        if !state.empty_interfaces.contains(interface_name) {
-            writeln!(writer, "        pub i_{interface_name}: {interface_name},")?;
-        }
-    }
 
-    let mut count = 0;
-    for property in &interface.properties {
-        if matches!(property.flags, PropertyFlags::AllClients | PropertyFlags::OwnClient | PropertyFlags::BaseAndClient) {
+    for (property_interface, property) in properties {
 
-            let mut name = Cow::Borrowed("");
-            let mut ty = Cow::Borrowed("");
+        // Only client-visible properties are encoded, unknown flags are kept as a
+        // comment so that they can be reviewed.
+        if let PropertyFlags::Unknown(raw) = &property.flags {
+            writeln!(writer, "        // {}: unknown flags {raw:?}", property.name)?;
+            continue;
+        } else if !property.flags.is_client_visible() {
+            continue;
+        }
 
-            for patch in PATCHES {
-                if let Patch::InterfaceProperty(func) = patch {
-                    (func)(&interface.name, &property.name, &mut name, &mut ty);
-                }
-            }
+        let mut name = Cow::Borrowed("");
+        let mut ty = Cow::Borrowed("");
 
-            if name.is_empty() {
-                name = Cow::Borrowed(&property.name);
+        for patch in PATCHES {
+            if let Patch::InterfaceProperty(func) = patch {
+                (func)(&property_interface.name, &property.name, &mut name, &mut ty);
             }
+        }
 
-            if ty.is_empty() {
-                ty = generate_type_ref(&property.ty);
-            }
+        if name.is_empty() {
+            name = Cow::Borrowed(&property.name);
+        }
 
-            writeln!(writer, "        pub {name}: {ty},")?;
-            count += 1;
+        if ty.is_empty() {
+            ty = generate_type_ref(&property.ty);
+        }
 
+        if property_interface.name != interface.name {
+            writeln!(writer, "        /// {} (from {})", property.flags.as_str(), property_interface.name)?;
+        } else {
+            writeln!(writer, "        /// {}", property.flags.as_str())?;
         }
-    }
 
-    if count == 0 {
-        state.empty_interfaces.insert(interface.name.clone());
+        writeln!(writer, "        pub {name}: {ty},")?;
+
     }
 
     writeln!(writer, "    }}")?;
@@ -452,6 +451,32 @@ fn generate_interface(
 
 }
 
+/// Recursively register all properties of the given interface, properties of implemented
+/// interfaces come first, in order of implementation.
+fn add_interface_properties<'m>(
+    properties: &mut Vec<(&'m Interface, &'m Property)>,
+    model: &'m Model,
+    interface: &'m Interface,
+) -> io::Result<()> {
+
+    for interface_name in &interface.implements {
+        let implemented = find_implemented_interface(model, interface, interface_name)?;
+        add_interface_properties(properties, model, implemented)?;
+    }
+
+    properties.extend(interface.properties.iter().map(|property| (interface, property)));
+    Ok(())
+
+}
+
+/// Find an interface implemented by the given interface, returning an error if it is
+/// not defined in the model.
+fn find_implemented_interface<'m>(model: &'m Model, interface: &Interface, name: &str) -> io::Result<&'m Interface> {
+    model.interfaces.iter()
+        .find(|i| i.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("interface {} implements unknown interface {name}", interface.name)))
+}
+
 fn generate_interface_methods(
     mut writer: impl Write,
     _model: &Model, 
@@ -526,7 +551,7 @@ fn compute_type_stream_size(ty: &Ty) -> Option<usize> {
         TyKind::Vector4 => Some(4 * 4),
         TyKind::String => None,
         TyKind::Python => None,
-        TyKind::Mailbox => None,  // TODO:
+        TyKind::Mailbox => Some(4 + 8),
         TyKind::Alias(ty) => 
             compute_type_stream_size(ty),
         TyKind::Dict(ty_dict) => 
@@ -564,9 +589,6 @@ fn is_method_exposed(method: &Method) -> bool {
 /// Internal state when bootstrapping.
 #[derive(Debug)]
 struct State {
-    /// A set of interfaces without any fields (sizeof=0) for which it's useless to 
-    /// generate variants.
-    empty_interfaces: HashSet<String>,
     apps: [AppState; 3],
 }
 
@@ -580,7 +602,6 @@ struct AppState {
 impl State {
     fn new() -> Self {
         Self { 
-            empty_interfaces: HashSet::new(), 
             apps: [
                 AppState::new("client", "Client", |i| &i.client_methods),
                 AppState::new("base", "Base", |i| &i.base_methods),
@@ -842,3 +863,214 @@ const PATCHES: &[Patch] = &[
         }
     })
 ];
+
+
+#[cfg(test)]
+mod tests {
+
+    use wgtk::pxml::{self, Element, Value};
+
+    use super::*;
+
+    /// Shortcut for a string value.
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    /// Shortcut for an element value with the given proper value and children.
+    fn element<const N: usize>(value: &str, children: [(&str, Value); N]) -> Value {
+        let mut elt = Element::new();
+        elt.value = string(value);
+        for (key, child) in children {
+            elt.add_children(key, child);
+        }
+        Value::Element(Box::new(elt))
+    }
+
+    /// Shortcut for a property definition with the given type and flags.
+    fn property(ty: Value, flags: &str) -> Value {
+        element("", [("Type", ty), ("Flags", string(flags))])
+    }
+
+    fn write_pxml(path: &Path, value: Value) {
+        let Value::Element(elt) = value else { unreachable!() };
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        pxml::to_writer(File::create(path).unwrap(), &elt).unwrap();
+    }
+
+    /// Write a minimal set of entity definitions to the given resources directory.
+    fn write_defs(res_dir: &Path) {
+
+        fs::create_dir_all(res_dir.join("packages")).unwrap();
+
+        let defs_dir = res_dir.join("scripts").join("entity_defs");
+        let id_dict = || element("FIXED_DICT", [
+            ("Properties", element("", [("id", element("", [("Type", string("OBJECT_ID"))]))])),
+        ]);
+
+        write_pxml(&defs_dir.join("alias.xml"), element("", [
+            ("OBJECT_ID", string("INT32")),
+            ("ITEM", element("FIXED_DICT", [
+                ("Properties", element("", [
+                    ("id", element("", [("Type", string("OBJECT_ID"))])),
+                    ("counts", element("", [("Type", element("ARRAY", [("of", string("UINT8"))]))])),
+                ])),
+            ])),
+        ]));
+
+        write_pxml(&defs_dir.join("interfaces").join("Named.def"), element("", [
+            ("Properties", element("", [
+                ("name", property(string("STRING"), "ALL_CLIENTS")),
+                ("secret", property(string("INT32"), "BASE")),
+                ("weird", property(string("UINT8"), "WEIRD")),
+            ])),
+            ("ClientMethods", element("", [
+                ("onRenamed", element("", [("Arg", string("STRING"))])),
+            ])),
+        ]));
+
+        write_pxml(&res_dir.join("scripts").join("entities.xml"), element("", [
+            ("ClientServerEntities", element("", [("Thing", string(""))])),
+        ]));
+
+        // Both lists have structurally identical anonymous types.
+        write_pxml(&defs_dir.join("Thing.def"), element("", [
+            ("Implements", element("", [("Interface", string("Named"))])),
+            ("Properties", element("", [
+                ("position", property(string("VECTOR3"), "OWN_CLIENT")),
+                ("first", property(element("ARRAY", [("of", id_dict())]), "BASE_AND_CLIENT")),
+                ("second", property(element("ARRAY", [("of", id_dict())]), "BASE_AND_CLIENT")),
+            ])),
+            ("BaseMethods", element("", [
+                ("use", element("", [("Exposed", string("")), ("Arg", string("ITEM"))])),
+            ])),
+        ]));
+
+    }
+
+    /// Load the model from the given resources and generate it, returning the content
+    /// of each generated file.
+    fn generate_files(res_dir: &Path, dest_dir: &Path) -> Vec<(&'static str, String)> {
+        let model = load(ResFilesystem::new(res_dir).unwrap()).unwrap();
+        generate(dest_dir, &model, &mut State::new()).unwrap();
+        ["mod.rs", "alias.rs", "interface.rs", "entity.rs"].map(|file_name| {
+            (file_name, fs::read_to_string(dest_dir.join(file_name)).unwrap())
+        }).to_vec()
+    }
+
+    #[test]
+    fn snapshot() {
+
+        let dir = std::env::temp_dir().join(format!("wgtk-bootstrap-snapshot-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        write_defs(&dir.join("res"));
+
+        let files = generate_files(&dir.join("res"), &dir.join("gen"));
+        let (_, entity) = files.iter().find(|(file_name, _)| *file_name == "entity.rs").unwrap();
+        assert_eq!(entity, ENTITY_SNAPSHOT);
+
+        fs::remove_dir_all(&dir).unwrap();
+
+    }
+
+    #[test]
+    fn stable() {
+
+        let dir = std::env::temp_dir().join(format!("wgtk-bootstrap-stable-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        write_defs(&dir.join("res"));
+
+        let first_files = generate_files(&dir.join("res"), &dir.join("gen0"));
+        let second_files = generate_files(&dir.join("res"), &dir.join("gen1"));
+        assert_eq!(first_files, second_files);
+
+        // Generating again in the same directory replaces the previous module.
+        let again_files = generate_files(&dir.join("res"), &dir.join("gen0"));
+        assert_eq!(first_files, again_files);
+
+        // Both structurally identical anonymous dicts share a single structure.
+        let (_, alias) = first_files.iter().find(|(file_name, _)| *file_name == "alias.rs").unwrap();
+        assert_eq!(alias.matches("pub struct ANON_").count(), 1);
+        assert!(alias.contains("pub struct ANON_CC6797B3 {"));
+
+        fs::remove_dir_all(&dir).unwrap();
+
+    }
+
+    /// Expected generated entity module for the definitions written by [`write_defs`],
+    /// interface properties come first, unknown flags are kept as a comment and
+    /// properties not sent to the client are skipped.
+    const ENTITY_SNAPSHOT: &str = r#"use wgtk::net::app::common::entity::{Entity, SimpleEntity};
+
+use super::alias::*;
+use super::interface::*;
+
+// ============================================== //
+// ======              Thing               ====== //
+// ============================================== //
+
+wgtk::__struct_simple_codec! {
+    #[derive(Debug)]
+    pub struct Thing {
+        /// ALL_CLIENTS (from Named)
+        pub name: AutoString,
+        // weird: unknown flags "WEIRD"
+        /// OWN_CLIENT
+        pub position: Vec3,
+        /// BASE_AND_CLIENT
+        pub first: Vec<ANON_CC6797B3>,
+        /// BASE_AND_CLIENT
+        pub second: Vec<ANON_CC6797B3>,
+    }
+}
+
+wgtk::__struct_simple_codec! {  // Methods on client
+
+}
+
+wgtk::__struct_simple_codec! {  // Methods on base
+
+    #[derive(Debug)]
+    pub struct Thing_use {
+        pub a0: ITEM,
+    }
+
+}
+
+wgtk::__struct_simple_codec! {  // Methods on cell
+
+}
+
+wgtk::__enum_entity_methods! {  // Entity methods on client
+    #[derive(Debug)]
+    pub enum Thing_Client {
+        Named_onRenamed(0x00, var8),
+    }
+}
+
+wgtk::__enum_entity_methods! {  // Entity methods on base
+    #[derive(Debug)]
+    pub enum Thing_Base {
+        Thing_use(0x00, var8),
+    }
+}
+
+wgtk::__enum_entity_methods! {  // Entity methods on cell
+    #[derive(Debug)]
+    pub enum Thing_Cell {
+    }
+}
+
+impl Thing {
+    pub const TYPE_ID: u16 = 0x01;
+}
+
+impl SimpleEntity for Thing {
+    type ClientMethod = Thing_Client;
+    type BaseMethod = Thing_Base;
+    type CellMethod = Thing_Cell;
+}
+
+"#;
+
+}
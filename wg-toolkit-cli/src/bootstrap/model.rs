@@ -1,18 +1,26 @@
-use std::fmt::Debug;
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
 use std::sync::Arc;
 
 use indexmap::IndexMap;
 
+use wgtk::util::fnv::fnv1a_32;
+
 
 /// Type system, containing all named types.
 #[derive(Debug, Default)]
 pub struct TySystem {
     types: IndexMap<Box<str>, Ty>,
-    anonymous_count: usize,
+    /// Anonymous types mapped from their structural signature, used to deduplicate
+    /// structurally identical anonymous types.
+    anonymous: HashMap<String, Ty>,
 }
 
 impl TySystem {
 
+    /// Register a new type, if no name is given then the type is anonymous and its name
+    /// is derived from its structure, so that it's stable across runs, structurally
+    /// identical anonymous types are registered only once.
     pub fn register(&mut self, name: Option<String>, kind: TyKind) -> Ty {
         
         if let Some(name) = name.as_deref() {
@@ -22,9 +30,27 @@ impl TySystem {
         let name = match name {
             Some(name) => name,
             None => {
-                let name = format!("ANON{}", self.anonymous_count);
-                self.anonymous_count += 1;
-                name
+                
+                let signature = kind.signature();
+                if let Some(ty) = self.anonymous.get(&signature) {
+                    return ty.clone();
+                }
+
+                // Two different structures may have the same hash, in such case a suffix
+                // is added, it depends on the registration order which is stable.
+                let hash_name = format!("ANON_{:08X}", fnv1a_32(signature.as_bytes()));
+                let mut name = hash_name.clone();
+                let mut suffix = 1;
+                while self.types.contains_key(name.as_str()) {
+                    suffix += 1;
+                    name = format!("{hash_name}_{suffix}");
+                }
+                
+                let ty = Ty::new(name.clone().into_boxed_str(), kind);
+                self.types.insert(name.into_boxed_str(), ty.clone());
+                self.anonymous.insert(signature, ty.clone());
+                return ty;
+
             }
         }.into_boxed_str();
 
@@ -139,6 +165,30 @@ pub enum TyKind {
     Tuple(TySeq),
 }
 
+impl TyKind {
+
+    /// Return a string describing the structure of this type kind, referenced types are
+    /// described by their name.
+    fn signature(&self) -> String {
+        let mut ret = String::new();
+        match self {
+            Self::Alias(ty) => write!(ret, "ALIAS({})", ty.name()).unwrap(),
+            Self::Dict(dict) => {
+                ret.push_str("FIXED_DICT(");
+                for prop in &dict.properties {
+                    write!(ret, "{}:{},", prop.name, prop.ty.name()).unwrap();
+                }
+                ret.push(')');
+            }
+            Self::Array(seq) => write!(ret, "ARRAY({};{:?})", seq.ty.name(), seq.size).unwrap(),
+            Self::Tuple(seq) => write!(ret, "TUPLE({};{:?})", seq.ty.name(), seq.size).unwrap(),
+            _ => write!(ret, "{self:?}").unwrap(),
+        }
+        ret
+    }
+
+}
+
 #[derive(Debug, Default)]
 pub struct TyDict {
     pub properties: Vec<TyDictProp>,
@@ -247,7 +297,7 @@ pub struct Property {
     pub flags: PropertyFlags,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PropertyFlags {
     None,
     Base,
@@ -256,6 +306,31 @@ pub enum PropertyFlags {
     CellPrivate,
     CellPublic,
     AllClients,
+    /// Flags that are not known, the raw value is kept as-is.
+    Unknown(String),
+}
+
+impl PropertyFlags {
+
+    /// Return the raw name of these flags, as found in definition files.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::None => "",
+            Self::Base => "BASE",
+            Self::BaseAndClient => "BASE_AND_CLIENT",
+            Self::OwnClient => "OWN_CLIENT",
+            Self::CellPrivate => "CELL_PRIVATE",
+            Self::CellPublic => "CELL_PUBLIC",
+            Self::AllClients => "ALL_CLIENTS",
+            Self::Unknown(raw) => raw,
+        }
+    }
+
+    /// Return true if properties with these flags are sent to the client.
+    pub fn is_client_visible(&self) -> bool {
+        matches!(self, Self::AllClients | Self::OwnClient | Self::BaseAndClient)
+    }
+
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -118,7 +118,7 @@ pub fn parse_property(elt: &Element, tys: &mut TySystem, name: String) -> Proper
             "CELL_PRIVATE" => PropertyFlags::CellPrivate,
             "CELL_PUBLIC" => PropertyFlags::CellPublic,
             "ALL_CLIENTS" => PropertyFlags::AllClients,
-            raw => panic!("unknown property flags: {raw}"),
+            raw => PropertyFlags::Unknown(raw.to_string()),
         },
     }
 
@@ -74,6 +74,16 @@ pub struct PackedXmlArgs {
     /// that can be read by the game engine.
     #[arg(short, long, conflicts_with = "raw")]
     pub xml: bool,
+    /// When outputting XML, start with an XML declaration, some tools expect this
+    /// declaration to be present. The output is always UTF-8, so it's the only encoding
+    /// accepted.
+    #[arg(long, requires = "xml", value_name = "ENCODING", num_args = 0..=1, default_missing_value = "UTF-8", value_parser = ["UTF-8"], ignore_case = true)]
+    pub xml_declaration: Option<String>,
+    /// When outputting XML, add a comment before the root element with the given text,
+    /// after the XML declaration if any. This can be used to annotate generated files,
+    /// double hyphens are separated by a space because they are not allowed in comments.
+    #[arg(long, requires = "xml", value_name = "TEXT")]
+    pub xml_comment: Option<String>,
     /// Enable raw output style, outputting the binary encoded element.
     #[arg(short, long, conflicts_with = "xml")]
     pub raw: bool,
@@ -1,4 +1,5 @@
 use std::collections::{hash_map, HashMap};
+use std::borrow::Cow;
 use std::io::{self, Cursor, Read, Write};
 use std::fs::File;
 
@@ -58,6 +59,12 @@ pub fn cmd_pxml(args: PackedXmlArgs) -> CliResult<()> {
     let mut indent = String::new();
 
     if args.xml {
+        if args.xml_declaration.is_some() {
+            println!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        }
+        if let Some(comment) = &args.xml_comment {
+            println!("<!-- {} -->", escape_xml_comment(comment));
+        }
         println!("<{root_xml_tag}>");
         indent.push_str("  ");
     }
@@ -119,15 +126,14 @@ fn apply_filter(element: &mut Element, filter: &str) -> CliResult<()> {
             } else {
                 return Err(format!("Invalid method call: {src} (no closing paren)"))
             }
-        } else if let Some(src_val) = context.find(src, false) {
-            val = src_val.clone();
         } else {
-            return Err(format!("Failed to find source: {src}"));
+            val = context.find(src, false)
+                .map_err(|e| format!("Failed to find source: {src}: {e}"))?
+                .clone();
         }
 
-        let Some(dst) = context.find(dst, true) else {
-            return Err(format!("Failed to create destination: {dst}"));
-        };
+        let dst = context.find(dst, true)
+            .map_err(|e| format!("Failed to create destination: {dst}: {e}"))?;
 
         dst.clone_from(&val);
         
@@ -199,7 +205,7 @@ fn print_value(value: &Value, indent: &mut String, xml: bool) {
         }
         Value::String(s) => {
             if xml {
-                print!("{s}");
+                print!("{}", escape_xml(s));
             } else {
                 print!("{s:?}");
             }
@@ -234,10 +240,58 @@ fn print_value(value: &Value, indent: &mut String, xml: bool) {
 
 }
 
-/// This function resolves a path and get a mutable reference to the value.
-fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Option<&'xml mut Value> {
+/// Escape the special characters of the given string so that it can be used as XML
+/// text or attribute value.
+fn escape_xml(s: &str) -> Cow<'_, str> {
+
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut ret = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            c => ret.push(c),
+        }
+    }
+
+    Cow::Owned(ret)
+
+}
+
+/// Escape the given string so that it can be used as XML comment text, double hyphens
+/// are not allowed in comments so a space is inserted between consecutive hyphens. The
+/// comment should be surrounded by spaces, so that it doesn't end with an hyphen.
+fn escape_xml_comment(s: &str) -> Cow<'_, str> {
+
+    if !s.contains("--") {
+        return Cow::Borrowed(s);
+    }
+
+    let mut ret = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        if c == '-' && ret.ends_with('-') {
+            ret.push(' ');
+        }
+        ret.push(c);
+    }
+
+    Cow::Owned(ret)
+
+}
+
+/// This function resolves a path and get a mutable reference to the value. The full
+/// path is only used in error messages, the given path being a suffix of it.
+fn resolve_path<'xml>(elt: &'xml mut Element, full_path: &str, path: &str, create: bool) -> Result<&'xml mut Value, String> {
 
     let (mut child_key, rest) = path.split_once('/').unwrap_or((path, ""));
+    // The path up to and including the current child key, for error messages.
+    let current_path = &full_path[..full_path.len() - path.len() + child_key.len()];
 
     let mut index_specified = false;
     let mut index_create = false;
@@ -250,7 +304,7 @@ fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Optio
             
             // We don't want anything after the closing bracket.
             if !after.is_empty() {
-                return None;
+                return Err(format!("unexpected characters after index at {current_path}"));
             }
 
             // If the index starts with a ^ then it means that we want to create a new
@@ -259,7 +313,7 @@ fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Optio
 
                 // We create mode is disabled...
                 if !create {
-                    return None;
+                    return Err(format!("cannot create a child outside of destination at {current_path}"));
                 }
 
                 before = &before[1..];
@@ -273,14 +327,14 @@ fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Optio
 
             // An empty index is equal to 0.
             if !before.is_empty() {
-                index = before.parse().ok()?;
+                index = before.parse()
+                    .map_err(|e| format!("invalid index at {current_path}: {e}"))?;
             }
 
             index_specified = true;
 
         } else {
-            // No closing bracket.
-            return None;
+            return Err(format!("missing closing bracket at {current_path}"));
         }
     }
 
@@ -291,7 +345,7 @@ fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Optio
 
         // We can't specify an index when targeting element's value, because there is one.
         if index_specified {
-            return None;
+            return Err(format!("cannot index the element's value at {current_path}"));
         }
 
         value = Some(&mut elt.value);
@@ -304,7 +358,7 @@ fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Optio
             
             let offset = -index as usize;
             if offset > elt.len() {
-                return None;
+                return Err(format!("index out of bounds at {current_path}"));
             }
 
             elt.len() - offset
@@ -325,18 +379,18 @@ fn resolve_path<'xml>(elt: &'xml mut Element, path: &str, create: bool) -> Optio
     }
 
     let Some(value) = value else {
-        return None;
+        return Err(format!("no child at {current_path}"));
     };
 
     if !rest.is_empty() {
         if let Value::Element(elt) = value {
-            return resolve_path(&mut *elt, rest, create);
+            return resolve_path(&mut *elt, full_path, rest, create);
         } else {
-            return None;
+            return Err(format!("expected element, found {} at {current_path}", value.type_name()));
         }
     }
 
-    Some(value)
+    Ok(value)
 
 }
 
@@ -357,9 +411,10 @@ impl<'xml> FilterContext<'xml> {
         }
     }
 
-    fn find(&mut self, mut path: &str, create: bool) -> Option<&mut Value> {
+    fn find(&mut self, full_path: &str, create: bool) -> Result<&mut Value, String> {
 
         let mut element = &mut *self.element;
+        let mut path = full_path;
 
         // Depending on this being temp variable or not.
         if path.starts_with('$') {
@@ -372,7 +427,7 @@ impl<'xml> FilterContext<'xml> {
                 hash_map::Entry::Vacant(v) if create => {
                     v.insert(Value::default())
                 }
-                hash_map::Entry::Vacant(_) => return None,
+                hash_map::Entry::Vacant(_) => return Err(format!("no variable ${var}")),
             };
 
             if !rest.is_empty() {
@@ -380,16 +435,70 @@ impl<'xml> FilterContext<'xml> {
                     element = &mut **elt;
                     path = rest;
                 } else {
-                    return None;  // Could not go into an non-element value
+                    return Err(format!("expected element, found {} at ${var}", val.type_name()));
                 }
             } else {
                 // No further path, we return the value itself.
-                return Some(val);
+                return Ok(val);
             }
 
         }
 
-        resolve_path(element, path, create)
+        resolve_path(element, full_path, path, create)
+
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::{Cli, Command};
+
+    #[test]
+    fn xml_escape() {
+        assert!(matches!(escape_xml("nothing to escape"), Cow::Borrowed(_)));
+        assert_eq!(escape_xml("a & b < c > d \" e ' f"), "a &amp; b &lt; c &gt; d &quot; e &apos; f");
+        assert_eq!(escape_xml("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn xml_declaration() {
+
+        let parse = |args: &[&str]| Cli::try_parse_from(["wgtk", "pxml", "--xml"].iter().chain(args))
+            .map(|cli| match cli.cmd {
+                Command::PackedXml(args) => args.xml_declaration,
+                _ => panic!("expected pxml command"),
+            });
+
+        assert_eq!(parse(&[]).unwrap(), None);
+        assert_eq!(parse(&["--xml-declaration"]).unwrap().as_deref(), Some("UTF-8"));
+        assert_eq!(parse(&["--xml-declaration=utf-8"]).unwrap().as_deref(), Some("utf-8"));
+        assert!(parse(&["--xml-declaration=latin-1"]).is_err());
+
+    }
+
+    #[test]
+    fn xml_comment() {
+
+        assert!(matches!(escape_xml_comment("no double hyphen - here"), Cow::Borrowed(_)));
+        assert_eq!(escape_xml_comment("a--b"), "a- -b");
+        assert_eq!(escape_xml_comment("a---b"), "a- - -b");
+        assert_eq!(escape_xml_comment("trailing-"), "trailing-");
+        assert_eq!(escape_xml_comment("trailing--"), "trailing- -");
+
+        // The comment as printed must be well-formed: no double hyphen in its body and
+        // not ending with an hyphen before the closing delimiter.
+        for s in ["a--b", "-", "--", "trailing-", "x---", "--> <!--"] {
+            let comment = format!("<!-- {} -->", escape_xml_comment(s));
+            let body = &comment[4..comment.len() - 3];
+            assert!(!body.contains("--"), "{comment}");
+            assert!(!body.ends_with('-'), "{comment}");
+        }
 
     }
 
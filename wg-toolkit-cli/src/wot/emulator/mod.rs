@@ -1,18 +1,20 @@
 //! The emulator implementation of the login + base applications, we are trying to
 //! reproduce the official server implementation.
 
-use std::collections::{hash_map, HashMap};
+use std::collections::HashMap;
 use std::net::{SocketAddr, SocketAddrV4};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::thread;
 
 use tracing::{info, instrument, warn};
 
 use rsa::rand_core::{OsRng, RngCore};
 use rsa::RsaPrivateKey;
+use blowfish::cipher::KeyInit;
 use blowfish::Blowfish;
 
 use wgtk::net::app::{login, base};
+use wgtk::util::DurationFmt;
 
 use crate::CliResult;
 
@@ -33,20 +35,15 @@ pub fn run(
     let base_app = base::App::new(base_app_addr.into())
         .map_err(|e| format!("Failed to bind base app: {e}"))?;
 
-    let shared = Arc::new(Shared {
-        login_clients: Mutex::new(HashMap::new()),
-    });
-
     let login_thread = LoginThread {
         app: login_app,
-        shared: Arc::clone(&shared),
+        login_keys: base_app.login_keys().clone(),
         base_app_addr,
         login_challenges: HashMap::new(),
     };
 
     let base_thread = BaseThread {
         app: base_app,
-        shared,
     };
 
     thread::scope(move |scope| {
@@ -61,7 +58,7 @@ pub fn run(
 #[derive(Debug)]
 struct LoginThread {
     app: login::App,
-    shared: Arc<Shared>,
+    login_keys: base::LoginKeys,
     base_app_addr: SocketAddrV4,
     login_challenges: HashMap<SocketAddr, bool>,
 }
@@ -69,18 +66,6 @@ struct LoginThread {
 #[derive(Debug)]
 struct BaseThread {
     app: base::App,
-    shared: Arc<Shared>,
-}
-
-#[derive(Debug)]
-struct Shared {
-    login_clients: Mutex<HashMap<u32, LoginClient>>,
-}
-
-#[derive(Debug)]
-struct LoginClient {
-    addr: SocketAddr,
-    blowfish: Arc<Blowfish>,
 }
 
 impl LoginThread {
@@ -98,6 +83,7 @@ impl LoginThread {
 
         loop {
             match self.app.poll() {
+                Event::Idle => {}
                 Event::IoError(error) => {
                     if let Some(addr) = error.addr {
                         warn!(%addr, "Error: {}", error.error);
@@ -106,32 +92,34 @@ impl LoginThread {
                     }
                 }
                 Event::Ping(ping) => {
-                    info!(addr = %ping.addr, "Ping-Pong: {:?}", ping.latency);
+                    info!(addr = %ping.addr, "Ping-Pong: {}", DurationFmt(ping.latency));
                 }
                 Event::Login(login) => {
 
                     if !*self.login_challenges.entry(login.addr).or_default() {
-                        info!(addr = %login.addr, "Login pending, sending challenge");
+                        info!(addr = %login.addr, encrypted = login.encrypted, "Login pending, sending challenge");
                         self.app.answer_login_challenge(login.addr);
                     } else {
 
                         info!(addr = %login.addr, "Login success");
 
-                        let mut clients = self.shared.login_clients.lock().unwrap();
-                        let (login_key, slot) = loop {
+                        let Ok(blowfish) = Blowfish::new_from_slice(&login.request.blowfish_key) else {
+                            warn!(addr = %login.addr, "Invalid blowfish key");
+                            continue;
+                        };
+
+                        // Register the login key to the base app before answering.
+                        let blowfish = Arc::new(blowfish);
+                        let login_key = loop {
                             let login_key = OsRng.next_u32();
-                            match clients.entry(login_key) {
-                                hash_map::Entry::Occupied(_) => continue,
-                                hash_map::Entry::Vacant(v) => break (login_key, v),
+                            if self.login_keys.add(login_key, login.addr, Arc::clone(&blowfish)) {
+                                break login_key;
                             }
                         };
 
-                        let blowfish = self.app.answer_login_success(login.addr, self.base_app_addr, login_key, String::new()).unwrap();
-
-                        slot.insert(LoginClient {
-                            addr: login.addr,
-                            blowfish,
-                        });
+                        if let Err(e) = self.app.answer_login_success(login.addr, self.base_app_addr.into(), login_key, String::new()) {
+                            warn!(addr = %login.addr, "Failed to answer login success: {e}");
+                        }
 
                         // app.answer_login_error(login.addr, LoginError::Banned, "{\"bans\":\"{\\\"expiryTime\\\":1726435530,\\\"reason\\\":\\\"It's the reason\\\"}\"}".to_string());
                         
@@ -169,24 +157,17 @@ impl BaseThread {
                     }
                 }
                 base::Event::Login(login) => {
-                    
-                    let mut clients = self.shared.login_clients.lock().unwrap();
-                    let client = match clients.remove(&login.login_key) {
-                        Some(client) => client,
-                        None => {
-                            info!(addr = %login.addr, "Login #{}... Invalid key", login.attempt_num);
-                            continue;
-                        }
-                    };
-
-                    if client.addr != login.addr {
-                        info!(addr = %login.addr, "Login #{}... Invalid address", login.attempt_num);
-                        continue;
-                    }
-                    
-                    info!(addr = %login.addr, "Login #{}... Success", login.attempt_num);
-                    self.app.answer_login_success(login.addr, client.blowfish);
-
+                    info!(addr = %login.addr, unk = login.unk, "Login #{}... Success", login.attempt_num);
+                    self.app.answer_login_success(login.addr, login.blowfish);
+                }
+                base::Event::LoginRejected(rejected) => {
+                    info!(addr = %rejected.addr, "Login #{}... Rejected key 0x{:08X}: {:?}", rejected.attempt_num, rejected.login_key, rejected.reason);
+                }
+                base::Event::BaseMethod(method) => {
+                    info!(addr = %method.addr, "Base method #{} on entity {}, {} bytes", method.method_index, method.entity_id, method.body.len());
+                }
+                base::Event::ClientTimeout(timeout) => {
+                    info!(addr = %timeout.addr, "Client timed out, removed {} entities", timeout.entity_ids.len());
                 }
                 
             }
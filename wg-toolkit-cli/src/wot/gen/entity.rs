@@ -360,7 +360,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Account {
-    const TYPE_ID: u16 = 0x01;
+    pub const TYPE_ID: u16 = 0x01;
 }
 
 impl SimpleEntity for Account {
@@ -1056,7 +1056,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Avatar {
-    const TYPE_ID: u16 = 0x02;
+    pub const TYPE_ID: u16 = 0x02;
 }
 
 impl SimpleEntity for Avatar {
@@ -1116,7 +1116,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ArenaInfo {
-    const TYPE_ID: u16 = 0x03;
+    pub const TYPE_ID: u16 = 0x03;
 }
 
 impl SimpleEntity for ArenaInfo {
@@ -1173,7 +1173,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableObject {
-    const TYPE_ID: u16 = 0x04;
+    pub const TYPE_ID: u16 = 0x04;
 }
 
 impl SimpleEntity for ClientSelectableObject {
@@ -1223,7 +1223,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl HangarVehicle {
-    const TYPE_ID: u16 = 0x05;
+    pub const TYPE_ID: u16 = 0x05;
 }
 
 impl SimpleEntity for HangarVehicle {
@@ -1464,7 +1464,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Vehicle {
-    const TYPE_ID: u16 = 0x06;
+    pub const TYPE_ID: u16 = 0x06;
 }
 
 impl SimpleEntity for Vehicle {
@@ -1518,7 +1518,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl AreaDestructibles {
-    const TYPE_ID: u16 = 0x07;
+    pub const TYPE_ID: u16 = 0x07;
 }
 
 impl SimpleEntity for AreaDestructibles {
@@ -1568,7 +1568,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl OfflineEntity {
-    const TYPE_ID: u16 = 0x08;
+    pub const TYPE_ID: u16 = 0x08;
 }
 
 impl SimpleEntity for OfflineEntity {
@@ -1633,7 +1633,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Flock {
-    const TYPE_ID: u16 = 0x09;
+    pub const TYPE_ID: u16 = 0x09;
 }
 
 impl SimpleEntity for Flock {
@@ -1704,7 +1704,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl FlockExotic {
-    const TYPE_ID: u16 = 0x0A;
+    pub const TYPE_ID: u16 = 0x0A;
 }
 
 impl SimpleEntity for FlockExotic {
@@ -1774,7 +1774,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Login {
-    const TYPE_ID: u16 = 0x0B;
+    pub const TYPE_ID: u16 = 0x0B;
 }
 
 impl SimpleEntity for Login {
@@ -1844,7 +1844,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl DetachedTurret {
-    const TYPE_ID: u16 = 0x0C;
+    pub const TYPE_ID: u16 = 0x0C;
 }
 
 impl SimpleEntity for DetachedTurret {
@@ -1895,7 +1895,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl DebugDrawEntity {
-    const TYPE_ID: u16 = 0x0D;
+    pub const TYPE_ID: u16 = 0x0D;
 }
 
 impl SimpleEntity for DebugDrawEntity {
@@ -1945,7 +1945,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableCameraObject {
-    const TYPE_ID: u16 = 0x0E;
+    pub const TYPE_ID: u16 = 0x0E;
 }
 
 impl SimpleEntity for ClientSelectableCameraObject {
@@ -1996,7 +1996,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableCameraVehicle {
-    const TYPE_ID: u16 = 0x0F;
+    pub const TYPE_ID: u16 = 0x0F;
 }
 
 impl SimpleEntity for ClientSelectableCameraVehicle {
@@ -2047,7 +2047,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableWebLinksOpener {
-    const TYPE_ID: u16 = 0x10;
+    pub const TYPE_ID: u16 = 0x10;
 }
 
 impl SimpleEntity for ClientSelectableWebLinksOpener {
@@ -2101,7 +2101,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableEasterEgg {
-    const TYPE_ID: u16 = 0x11;
+    pub const TYPE_ID: u16 = 0x11;
 }
 
 impl SimpleEntity for ClientSelectableEasterEgg {
@@ -2151,7 +2151,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl EmptyEntity {
-    const TYPE_ID: u16 = 0x12;
+    pub const TYPE_ID: u16 = 0x12;
 }
 
 impl SimpleEntity for EmptyEntity {
@@ -2201,7 +2201,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl LimitedVisibilityEntity {
-    const TYPE_ID: u16 = 0x13;
+    pub const TYPE_ID: u16 = 0x13;
 }
 
 impl SimpleEntity for LimitedVisibilityEntity {
@@ -2254,7 +2254,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl HeroTank {
-    const TYPE_ID: u16 = 0x14;
+    pub const TYPE_ID: u16 = 0x14;
 }
 
 impl SimpleEntity for HeroTank {
@@ -2308,7 +2308,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl PlatoonTank {
-    const TYPE_ID: u16 = 0x15;
+    pub const TYPE_ID: u16 = 0x15;
 }
 
 impl SimpleEntity for PlatoonTank {
@@ -2359,7 +2359,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl PlatoonLighting {
-    const TYPE_ID: u16 = 0x16;
+    pub const TYPE_ID: u16 = 0x16;
 }
 
 impl SimpleEntity for PlatoonLighting {
@@ -2421,7 +2421,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl SectorBase {
-    const TYPE_ID: u16 = 0x17;
+    pub const TYPE_ID: u16 = 0x17;
 }
 
 impl SimpleEntity for SectorBase {
@@ -2487,7 +2487,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Sector {
-    const TYPE_ID: u16 = 0x18;
+    pub const TYPE_ID: u16 = 0x18;
 }
 
 impl SimpleEntity for Sector {
@@ -2570,7 +2570,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl DestructibleEntity {
-    const TYPE_ID: u16 = 0x19;
+    pub const TYPE_ID: u16 = 0x19;
 }
 
 impl SimpleEntity for DestructibleEntity {
@@ -2622,7 +2622,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl StepRepairPoint {
-    const TYPE_ID: u16 = 0x1A;
+    pub const TYPE_ID: u16 = 0x1A;
 }
 
 impl SimpleEntity for StepRepairPoint {
@@ -2677,7 +2677,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ProtectionZone {
-    const TYPE_ID: u16 = 0x1B;
+    pub const TYPE_ID: u16 = 0x1B;
 }
 
 impl SimpleEntity for ProtectionZone {
@@ -2729,7 +2729,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl HangarPoster {
-    const TYPE_ID: u16 = 0x1C;
+    pub const TYPE_ID: u16 = 0x1C;
 }
 
 impl SimpleEntity for HangarPoster {
@@ -2796,7 +2796,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl TeamInfo {
-    const TYPE_ID: u16 = 0x1D;
+    pub const TYPE_ID: u16 = 0x1D;
 }
 
 impl SimpleEntity for TeamInfo {
@@ -2847,7 +2847,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl AvatarInfo {
-    const TYPE_ID: u16 = 0x1E;
+    pub const TYPE_ID: u16 = 0x1E;
 }
 
 impl SimpleEntity for AvatarInfo {
@@ -2897,7 +2897,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ArenaObserverInfo {
-    const TYPE_ID: u16 = 0x1F;
+    pub const TYPE_ID: u16 = 0x1F;
 }
 
 impl SimpleEntity for ArenaObserverInfo {
@@ -2959,7 +2959,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl AreaOfEffect {
-    const TYPE_ID: u16 = 0x20;
+    pub const TYPE_ID: u16 = 0x20;
 }
 
 impl SimpleEntity for AreaOfEffect {
@@ -3009,7 +3009,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl AttackBomber {
-    const TYPE_ID: u16 = 0x21;
+    pub const TYPE_ID: u16 = 0x21;
 }
 
 impl SimpleEntity for AttackBomber {
@@ -3060,7 +3060,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl AttackArtilleryFort {
-    const TYPE_ID: u16 = 0x22;
+    pub const TYPE_ID: u16 = 0x22;
 }
 
 impl SimpleEntity for AttackArtilleryFort {
@@ -3110,7 +3110,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl PersonalDeathZone {
-    const TYPE_ID: u16 = 0x23;
+    pub const TYPE_ID: u16 = 0x23;
 }
 
 impl SimpleEntity for PersonalDeathZone {
@@ -3160,7 +3160,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableRankedObject {
-    const TYPE_ID: u16 = 0x24;
+    pub const TYPE_ID: u16 = 0x24;
 }
 
 impl SimpleEntity for ClientSelectableRankedObject {
@@ -3228,7 +3228,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl SimulatedVehicle {
-    const TYPE_ID: u16 = 0x25;
+    pub const TYPE_ID: u16 = 0x25;
 }
 
 impl SimpleEntity for SimulatedVehicle {
@@ -3279,7 +3279,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ClientSelectableHangarsSwitcher {
-    const TYPE_ID: u16 = 0x26;
+    pub const TYPE_ID: u16 = 0x26;
 }
 
 impl SimpleEntity for ClientSelectableHangarsSwitcher {
@@ -3362,7 +3362,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl StaticDeathZone {
-    const TYPE_ID: u16 = 0x27;
+    pub const TYPE_ID: u16 = 0x27;
 }
 
 impl SimpleEntity for StaticDeathZone {
@@ -3419,7 +3419,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl BasicMine {
-    const TYPE_ID: u16 = 0x28;
+    pub const TYPE_ID: u16 = 0x28;
 }
 
 impl SimpleEntity for BasicMine {
@@ -3473,7 +3473,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl ApplicationPoint {
-    const TYPE_ID: u16 = 0x29;
+    pub const TYPE_ID: u16 = 0x29;
 }
 
 impl SimpleEntity for ApplicationPoint {
@@ -3563,7 +3563,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl NetworkEntity {
-    const TYPE_ID: u16 = 0x2A;
+    pub const TYPE_ID: u16 = 0x2A;
 }
 
 impl SimpleEntity for NetworkEntity {
@@ -3614,7 +3614,7 @@ wgtk::__enum_entity_methods! {  // Entity methods on cell
 }
 
 impl Comp7Lighting {
-    const TYPE_ID: u16 = 0x2B;
+    pub const TYPE_ID: u16 = 0x2B;
 }
 
 impl SimpleEntity for Comp7Lighting {
@@ -21,7 +21,7 @@ use wgtk::net::app::{login, base, client, proxy};
 use wgtk::net::app::common::entity::Entity;
 use wgtk::net::app::proxy::PacketDirection;
 
-use wgtk::util::io::serde_pickle_de_options;
+use wgtk::util::{pickle, DurationFmt};
 
 use crate::CliResult;
 use super::gen;
@@ -42,7 +42,8 @@ pub fn run(
         login_app.set_encryption(encryption_key);
     }
 
-    login_app.set_forced_base_app_addr(base_app_addr);
+    login_app.set_forced_base_app_addr(base_app_addr.into())
+        .map_err(|e| format!("Failed to force base app address: {e}"))?;
 
     let base_app = proxy::App::new(base_app_addr.into())
         .map_err(|e| format!("Failed to bind base app: {e}"))?;
@@ -68,7 +69,7 @@ pub fn run(
         entities: HashMap::new(),
         selected_entity_id: None,
         player_entity_id: None,
-        partial_resources: HashMap::new(),
+        resources: client::ResourceAssembler::new(),
     };
     
     thread::scope(move |scope| {
@@ -95,7 +96,7 @@ struct BaseThread {
     entities: HashMap<u32, &'static EntityType>,
     selected_entity_id: Option<u32>,
     player_entity_id: Option<u32>,
-    partial_resources: HashMap<u16, PartialResource>,
+    resources: client::ResourceAssembler,
 }
 
 #[derive(Debug)]
@@ -106,22 +107,10 @@ struct Shared {
 
 #[derive(Debug)]
 struct PendingClient {
-    base_app_addr: SocketAddrV4,
+    base_app_addr: SocketAddr,
     blowfish: Arc<Blowfish>,
 }
 
-/// Describe a partial resource being download, a header must have been sent.
-#[derive(Debug)]
-struct PartialResource {
-    /// The byte description sent in the resource header.
-    description: Vec<u8>,
-    /// The next sequence number expected, any other sequence number abort the download
-    /// with an error.
-    sequence_num: u8,
-    /// The full assembled data.
-    data: Vec<u8>,
-}
-
 impl LoginThread {
 
     #[instrument(name = "login", skip_all)]
@@ -137,6 +126,7 @@ impl LoginThread {
 
         loop {
             match self.app.poll() {
+                Event::Idle => {}
                 Event::IoError(error) => {
                     if let Some(addr) = error.addr {
                         warn!(%addr, "Error: {}", error.error);
@@ -145,7 +135,7 @@ impl LoginThread {
                     }
                 }
                 Event::Ping(ping) => {
-                    info!(addr = %ping.addr, "Ping-Pong: {:?}", ping.latency);
+                    info!(addr = %ping.addr, "Ping-Pong: {}", DurationFmt(ping.latency));
                 }
                 Event::LoginSuccess(success) => {
                     info!(addr = %success.addr, "Login success");
@@ -189,7 +179,7 @@ impl BaseThread {
                         
                         self.app.bind_peer(
                             rejection.addr, 
-                            SocketAddr::V4(pending_client.base_app_addr), 
+                            pending_client.base_app_addr, 
                             Some(pending_client.blowfish),
                             None).unwrap();
 
@@ -254,7 +244,7 @@ impl BaseThread {
             }
             DisconnectClient::ID => {
                 let dc = elt.read_simple::<DisconnectClient>()?;
-                info!(%addr, "-> Disconnect: 0x{:02X}", dc.element.reason);
+                info!(%addr, "-> Disconnect: {:?}", dc.element.reason);
             }
             id if id::BASE_ENTITY_METHOD.contains(id) => {
 
@@ -270,6 +260,19 @@ impl BaseThread {
                 warn!(%addr, "-> Base entity method (unknown selected entity): msg#{} {:?} (request: {:?})", id - id::BASE_ENTITY_METHOD.first, elt.element, elt.request_id);
                 return Ok(false);
 
+            }
+            id if id::CELL_ENTITY_METHOD.contains(id) => {
+
+                if let Some(entity_id) = self.player_entity_id {
+                    // Unwrap because selected entity should exist!
+                    let entity_type = *self.entities.get(&entity_id).unwrap();
+                    return (entity_type.cell_entity_method)(&mut *self, addr, entity_id, elt);
+                }
+
+                let elt = elt.read_simple::<DebugElementUndefined<0>>()?;
+                warn!(%addr, "-> Cell entity method (unknown selected entity): msg#{} {:?} (request: {:?})", id - id::CELL_ENTITY_METHOD.first, elt.element, elt.request_id);
+                return Ok(false);
+
             }
             id => {
                 let elt = elt.read_simple::<DebugElementUndefined<0>>()?;
@@ -348,9 +351,13 @@ impl BaseThread {
                 }
 
             }
+            SwitchBaseApp::ID => {
+                let sba = elt.read_simple::<SwitchBaseApp>()?;
+                info!(%addr, "<- Switch base app: {}, reset entities: {}", sba.element.base_addr, sba.element.reset_entities);
+            }
             LoggedOff::ID => {
                 let lo = elt.read_simple::<LoggedOff>()?;
-                info!(%addr, "<- Logged off: 0x{:02X}", lo.element.reason);
+                info!(%addr, "<- Logged off: {:?}", lo.element.reason);
             }
             CreateBasePlayerHeader::ID => {
 
@@ -371,7 +378,13 @@ impl BaseThread {
             }
             CreateCellPlayer::ID => {
                 let ccp = elt.read_simple::<CreateCellPlayer>()?;
-                warn!(%addr, "<- Create cell player: {:?}", ccp.element);
+                if let Some(player_entity_id) = self.player_entity_id {
+                    info!(%addr, "<- Create cell player: entity {player_entity_id}, space {}, vehicle {}, position {}, direction {}, data: {} bytes",
+                        ccp.element.space_id, ccp.element.vehicle_entity_id, ccp.element.position, 
+                        ccp.element.direction, ccp.element.entity_data.len());
+                } else {
+                    warn!(%addr, "<- Create cell player: no player entity, {:?}", ccp.element);
+                }
             }
             SelectPlayerEntity::ID => {
                 let _spe = elt.read_simple::<SelectPlayerEntity>()?;
@@ -382,86 +395,47 @@ impl BaseThread {
                 }
                 self.selected_entity_id = self.player_entity_id;
             }
+            ForcedPosition::ID => {
+                let fp = elt.read_simple::<ForcedPosition>()?;
+                info!(%addr, "<- Forced position: entity {}, space {}, vehicle {}, position {}, direction {}",
+                    fp.element.entity_id, fp.element.space_id, fp.element.vehicle_entity_id,
+                    fp.element.position, fp.element.direction);
+            }
             ResourceHeader::ID => {
 
                 let rh = elt.read_simple::<ResourceHeader>()?;
                 info!(%addr, "<- Resource header: {}", rh.element.id);
 
                 // Intentionally overwrite any previous downloading resource!
-                self.partial_resources.insert(rh.element.id, PartialResource {
-                    description: rh.element.description,
-                    sequence_num: 0,
-                    data: Vec::new(),
-                });
+                self.resources.push_header(rh.element);
 
             }
             ResourceFragment::ID => {
 
                 let rf = elt.read_simple::<ResourceFragment>()?;
                 let res_id = rf.element.id;
+                let len = rf.element.data.len();
+                let sequence_num = rf.element.sequence_num;
 
-                let Some(partial_resource) = self.partial_resources.get_mut(&res_id) else {
-                    warn!(%addr, "<- Resource fragment: {res_id}, len: {}, missing header", rf.element.data.len());
-                    return Ok(true);
-                };
-
-                if rf.element.sequence_num != partial_resource.sequence_num {
+                if let Err(e) = self.resources.push_fragment(rf.element) {
                     // Just forgetting about the resource!
-                    warn!(%addr, "<- Resource fragment: {res_id}, len: {}, invalid sequence number, expected {}, got {}", 
-                    rf.element.data.len(), partial_resource.sequence_num, rf.element.sequence_num);
-                    let _ = self.partial_resources.remove(&res_id);
+                    warn!(%addr, "<- Resource fragment: {res_id}, len: {len}, {e}");
                     return Ok(true);
                 }
 
-                partial_resource.sequence_num += 1;
-                partial_resource.data.extend_from_slice(&rf.element.data);
-                info!(%addr, "<- Resource fragment: {res_id}, len: {}, sequence number: {}", 
-                    rf.element.data.len(), partial_resource.sequence_num);
+                info!(%addr, "<- Resource fragment: {res_id}, len: {len}, sequence number: {sequence_num}");
                 
                 // Process the finished fragment!
-                if rf.element.last {
-
-                    let resource = self.partial_resources.remove(&rf.element.id).unwrap();
-                    
-                    // See: scripts/client/game.py#L223
-                    let (total_len, crc32) = match serde_pickle::value_from_reader(&resource.description[..], serde_pickle_de_options()) {
-                        Ok(serde_pickle::Value::Tuple(values)) if values.len() == 2 => {
-                            if let &[serde_pickle::Value::I64(total_len), serde_pickle::Value::I64(crc32)] = &values[..] {
-                                (total_len as u32, crc32 as u32)
-                            } else {
-                                warn!(%addr, "<- Invalid resource description: unexpected values: {values:?}");
-                                return Ok(true);
-                            }
-                        }
-                        Ok(v) => {
-                            warn!(%addr, "<- Invalid resource description: python: {v}");
-                            return Ok(true);
-                        }
-                        Err(e) => {
-                            warn!(%addr, "<- Invalid resource description: {e}");
-                            return Ok(true);
-                        }
-                    };
+                if let Some((_description, data)) = self.resources.take_completed() {
 
-                    let actual_total_len = resource.data.len();
-                    if actual_total_len != total_len as usize {
-                        warn!(%addr, "<- Invalid resource length, expected: {total_len}, got: {actual_total_len}");
-                        return Ok(true);
-                    }
-
-                    let actual_crc32 = crc32fast::hash(&resource.data);
-                    if actual_crc32 != crc32 {
-                        warn!(%addr, "<- Invalid resource crc32, expected: 0x{crc32:08X}, got: 0x{actual_crc32:08X}");
-                        return Ok(true);
-                    }
-
-                    info!(%addr, "<- Resource completed: {res_id}, len: {actual_total_len}, crc32: 0x{crc32:08X}");
+                    let crc32 = crc32fast::hash(&data);
+                    info!(%addr, "<- Resource completed: {res_id}, len: {}, crc32: 0x{crc32:08X}", data.len());
 
                     // TODO: The full data looks like to be a zlib-compressed pickle.
                     // TODO: onCmdResponse for requested SYNC use RES_SUCCESS=0, RES_STREAM=1, RES_CACHE=2 for result_id
                     //       When RES_STREAM is used, then a resource (header+fragment) is expected with the associated request_id.
 
-                    match serde_pickle::value_from_reader(ZlibDecoder::new(&resource.data[..]), serde_pickle_de_options()) {
+                    match pickle::value_from_reader(ZlibDecoder::new(&data[..])) {
                         Ok(val) => {
                             
                             let dump_file = self.shared.dump_dir.join(format!("res_{crc32:08x}.txt"));
@@ -475,18 +449,11 @@ impl BaseThread {
 
                             warn!(%addr, "<- Resource: python error: {e}");
 
-                            // FIXME: It appears that the current serde-pickle impl doesn't
-                            // support recursive structures, however the structure that is 
-                            // initially requested with 'CMD_SYNC_DATA' contains some.
-                            // FIXME: The resource that is received by the from the chat
-                            // command contains a "deque" object, which cannot be parsed
-                            // so we get a "unresolved global reference" error.
-
                             let raw_file = self.shared.dump_dir.join(format!("res_{crc32:08x}.raw"));
                             info!(%addr, "<- Saving resource to: {}", raw_file.display());
 
                             let mut raw_writer = File::create(raw_file).unwrap();
-                            std::io::copy(&mut ZlibDecoder::new(&resource.data[..]), &mut raw_writer).unwrap();
+                            std::io::copy(&mut ZlibDecoder::new(&data[..]), &mut raw_writer).unwrap();
 
                         }
                     }
@@ -566,6 +533,17 @@ impl BaseThread {
         Ok(true)
     }
 
+    fn read_cell_entity_method<E>(&mut self, addr: SocketAddr, entity_id: u32, elt: ElementReader) -> io::Result<bool>
+    where 
+        E: Entity,
+        E::CellMethod: fmt::Debug,
+    {
+        use base::element::CellEntityMethod;
+        let em = elt.read_simple::<CellEntityMethod<E::CellMethod>>()?;
+        info!(%addr, "-> Cell entity method: ({entity_id}) {:?}", em.element.inner);
+        Ok(true)
+    }
+
 }
 
 /// Represent an entity type and its associated static functions.
@@ -574,6 +552,7 @@ struct EntityType {
     create_base_player: fn(&mut BaseThread, SocketAddr, ElementReader) -> io::Result<bool>,
     entity_method: fn(&mut BaseThread, SocketAddr, u32, ElementReader) -> io::Result<bool>,
     base_entity_method: fn(&mut BaseThread, SocketAddr, u32, ElementReader) -> io::Result<bool>,
+    cell_entity_method: fn(&mut BaseThread, SocketAddr, u32, ElementReader) -> io::Result<bool>,
 }
 
 impl EntityType {
@@ -583,11 +562,13 @@ impl EntityType {
         E: Entity + fmt::Debug,
         E::ClientMethod: fmt::Debug,
         E::BaseMethod: fmt::Debug,
+        E::CellMethod: fmt::Debug,
     {
         Self {
             create_base_player: BaseThread::read_create_base_player::<E>,
             entity_method: BaseThread::read_entity_method::<E>,
             base_entity_method: BaseThread::read_base_entity_method::<E>,
+            cell_entity_method: BaseThread::read_cell_entity_method::<E>,
         }
     }
 
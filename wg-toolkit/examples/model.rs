@@ -32,7 +32,8 @@ fn main() {
         writeln!(obj_file, "v {} {} {}", v.position.x, v.position.y, v.position.z).unwrap();
     }
     for v in vertices {
-        writeln!(obj_file, "vt {} {}", v.uv.x, v.uv.y).unwrap();
+        let uv = v.uv().unwrap_or_default();
+        writeln!(obj_file, "vt {} {}", uv.x, uv.y).unwrap();
     }
     for v in vertices {
         writeln!(obj_file, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z).unwrap();
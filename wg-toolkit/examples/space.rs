@@ -16,11 +16,15 @@ fn main() {
         println!("- {:?}", section);
     }
 
-    let bwst: BWST = space.decode_section().unwrap();
-    let bwal: BWAL = space.decode_section().unwrap();
-    let bwcs: BWCS = space.decode_section().unwrap();
-    let bwsg: BWSG = space.decode_section().unwrap();
-    let bwt2: BWT2 = space.decode_section().unwrap();
+    let bwst: BWST = space.decode_section().unwrap().unwrap();
+    let bwal: BWAL = space.decode_section().unwrap().unwrap();
+    let bwcs: BWCS = space.decode_section().unwrap().unwrap();
+    let bwsg: BWSG = space.decode_section().unwrap().unwrap();
+    let bwt2: BWT2 = space.decode_section().unwrap().unwrap();
+
+    for (asset, path) in bwal.iter_resolved(&bwst) {
+        println!("{:?} {:?}", asset.asset_type, path);
+    }
 
     for chunk in &bwt2.chunks {
         println!("[{}/{}] {:?}", chunk.loc_x, chunk.loc_y, bwst.get_string(chunk.resource_fnv));
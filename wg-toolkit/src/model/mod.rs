@@ -1,14 +1,18 @@
 //! Compiled model memory representation, encoding and decoding.
 
-use std::io::{Read, Seek};
+use std::io::{self, Read, Seek};
 
+use glam::{Affine3A, Vec2, Vec3};
 use thiserror::Error;
 
+use crate::res::ResFilesystem;
+use crate::pxml::{self, Value};
+
 pub mod primitive;
 pub mod visual;
 
 use self::visual::{Visual, RenderSet};
-use self::primitive::{PrimitiveReader, Vertices, Indices, Vertex, Primitive, Group};
+use self::primitive::{PrimitiveReader, Vertices, Indices, IndexWidth, Vertex, Primitive, Group};
 
 
 /// Decode and resolve a compiled model.
@@ -41,7 +45,9 @@ where
 
         render_sets_data.push(RenderSetData {
             vertices: vertices.vertices,
+            has_uv: vertices.has_uv,
             primitives: indices.primitives,
+            index_width: indices.width,
             groups: indices.groups,
         });
 
@@ -55,6 +61,67 @@ where
 }
 
 
+/// Decode and resolve a compiled model from its `.model` file in the given resource
+/// filesystem. The model file references its visual by name, without extension, the
+/// visual is first looked up relative to the model's directory and then relative to
+/// the resource root. The processed visual and primitives files are preferred, and
+/// the primitives file must have the same name as the visual, a candidate visual 
+/// without its primitives file is skipped.
+pub fn from_res(fs: &ResFilesystem, model_path: &str) -> Result<Model, DeError> {
+
+    let model_elt = pxml::from_reader(fs.read(model_path)?)?;
+    let visual_name = model_elt.get_child("nodefullVisual")
+        .or_else(|| model_elt.get_child("nodelessVisual"))
+        .and_then(Value::as_string)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .ok_or(DeError::MissingVisual)?;
+
+    let is_file = |path: &str| matches!(fs.stat(path), Ok(stat) if stat.is_file());
+
+    for base_path in visual_base_paths(model_path, visual_name) {
+        for (visual_ext, primitives_ext) in [("visual_processed", "primitives_processed"), ("visual", "primitives")] {
+
+            let visual_path = format!("{base_path}.{visual_ext}");
+            let primitives_path = format!("{base_path}.{primitives_ext}");
+            if !is_file(&visual_path) || !is_file(&primitives_path) {
+                continue;
+            }
+
+            let visual_reader = fs.read(&visual_path)?;
+            let primitives_reader = fs.read(&primitives_path)?;
+            return from_readers(visual_reader, primitives_reader);
+
+        }
+    }
+
+    Err(DeError::VisualNotFound(visual_name.to_string()))
+
+}
+
+/// Return the candidate paths, without extension, of a visual referenced by a model,
+/// in order of preference.
+fn visual_base_paths(model_path: &str, visual_name: &str) -> Vec<String> {
+
+    let visual_name = visual_name.trim_start_matches('/');
+    let visual_name = visual_name.strip_suffix(".visual_processed")
+        .or_else(|| visual_name.strip_suffix(".visual"))
+        .unwrap_or(visual_name);
+
+    let mut paths = Vec::with_capacity(2);
+    if let Some((model_dir, _)) = model_path.trim_start_matches('/').rsplit_once('/') {
+        paths.push(format!("{model_dir}/{visual_name}"));
+    }
+
+    if paths.first().map(String::as_str) != Some(visual_name) {
+        paths.push(visual_name.to_string());
+    }
+
+    paths
+
+}
+
+
 #[derive(Debug)]
 pub struct Model {
     /// Description of the visual components of the model.
@@ -68,8 +135,12 @@ pub struct RenderSetData {
     /// All vertices for the model. To access correct vertices,
     /// use correct method of the model to get access to them.
     pub vertices: Vec<Vertex>,
+    /// True if the vertices have texture coordinates.
+    pub has_uv: bool,
     /// Indices of the model, linking all vertices.
     pub primitives: Vec<Primitive>,
+    /// Width of the indices in the source file, primitives' indices are always 32-bit.
+    pub index_width: IndexWidth,
     /// Groups of indices.
     pub groups: Vec<Group>,
 }
@@ -84,26 +155,261 @@ impl Model {
         ))
     }
 
+    /// Return the index of the render set with the least triangles, this can be used
+    /// to select the lowest level of detail when render sets represent LODs, because
+    /// visuals don't provide explicit LOD distances. If multiple render sets have the
+    /// same triangle count, the first one is returned. None is returned if the model
+    /// has no render set data.
+    pub fn lowest_detail_render_set(&self) -> Option<usize> {
+        self.render_sets_data.iter()
+            .enumerate()
+            .min_by_key(|&(index, data)| (data.triangle_count(), index))
+            .map(|(index, _)| index)
+    }
+
+    /// Return the index of the render set with the most triangles, see 
+    /// [`Self::lowest_detail_render_set`]. If multiple render sets have the same
+    /// triangle count, the first one is also returned.
+    pub fn highest_detail_render_set(&self) -> Option<usize> {
+        self.render_sets_data.iter()
+            .enumerate()
+            .max_by_key(|&(index, data)| (data.triangle_count(), std::cmp::Reverse(index)))
+            .map(|(index, _)| index)
+    }
+
+    /// Iterate over all triangles of this model in world space, lazily. All groups of
+    /// all render sets are iterated in order, and the vertices' positions are 
+    /// transformed by the world transform of the render set's node if found in the
+    /// visual, see [`Visual::nodes`]. Out of bounds groups and vertex indices are 
+    /// skipped, see [`Self::validate`].
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.visual.render_sets.iter()
+            .zip(&self.render_sets_data)
+            .flat_map(|(render_set, data)| {
+
+                let transform = self.visual.nodes()
+                    .find(|(node, _)| node.identifier == render_set.node)
+                    .map(|(_, transform)| transform)
+                    .unwrap_or(Affine3A::IDENTITY);
+
+                (0..data.groups.len())
+                    .filter_map(|index| data.get_group(index))
+                    .flat_map(|(_, primitives)| primitives)
+                    .filter_map(move |primitive| {
+                        let [a, b, c] = [primitive.a, primitive.b, primitive.c]
+                            .map(|index| data.vertices.get(index as usize));
+                        Some([a?, b?, c?].map(|vertex| transform.transform_point3(vertex.position)))
+                    })
+
+            })
+    }
+
+    /// Group render sets that reference the same geometry, that is the same vertices
+    /// and indices sections of the primitives file, this can be used to upload shared
+    /// geometry once and draw it as instances, using the transform of each render 
+    /// set's node. Render sets sharing only one of the two sections are not grouped
+    /// together. Groups are ordered by their first render set.
+    pub fn geometry_groups(&self) -> Vec<GeometryGroup<'_>> {
+
+        let mut groups = Vec::<GeometryGroup>::new();
+
+        for (index, render_set) in self.visual.render_sets.iter().enumerate() {
+
+            let geometry = &render_set.geometry;
+            let group = groups.iter_mut().find(|group| 
+                group.vertices_section == geometry.vertices_section && 
+                group.indices_section == geometry.indices_section);
+
+            if let Some(group) = group {
+                group.render_sets.push(index);
+            } else {
+                groups.push(GeometryGroup {
+                    vertices_section: &geometry.vertices_section,
+                    indices_section: &geometry.indices_section,
+                    render_sets: vec![index],
+                });
+            }
+
+        }
+
+        groups
+
+    }
+
+    /// Validate the coherency of this model, this can be used to check a model before
+    /// exporting it. All issues found are returned, so an empty vector means that the
+    /// model is valid. Vertex indices of primitives are checked against all vertices
+    /// of their render set.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+
+        let mut issues = Vec::new();
+
+        if self.render_sets_data.len() > self.visual.render_sets.len() {
+            issues.push(ValidationIssue::UnusedRenderSetData { 
+                count: self.render_sets_data.len() - self.visual.render_sets.len(),
+            });
+        }
+
+        for (render_set_index, render_set) in self.visual.render_sets.iter().enumerate() {
+
+            let Some(data) = self.render_sets_data.get(render_set_index) else {
+                issues.push(ValidationIssue::MissingRenderSetData { render_set: render_set_index });
+                continue;
+            };
+
+            for primitive_group in &render_set.geometry.primitive_groups {
+                if primitive_group.index as usize >= data.groups.len() {
+                    issues.push(ValidationIssue::MissingGroup { 
+                        render_set: render_set_index, 
+                        group: primitive_group.index,
+                    });
+                }
+            }
+
+            for (group_index, group) in data.groups.iter().enumerate() {
+
+                let vertices_end = group.vertices_offset as u64 + group.vertices_count as u64;
+                if vertices_end > data.vertices.len() as u64 {
+                    issues.push(ValidationIssue::GroupVerticesOutOfBounds { 
+                        render_set: render_set_index, 
+                        group: group_index, 
+                        end: vertices_end, 
+                        len: data.vertices.len(),
+                    });
+                }
+
+                let primitives_end = group.primitives_offset as u64 + group.primitives_count as u64;
+                if primitives_end > data.primitives.len() as u64 {
+                    issues.push(ValidationIssue::GroupPrimitivesOutOfBounds { 
+                        render_set: render_set_index, 
+                        group: group_index, 
+                        end: primitives_end, 
+                        len: data.primitives.len(),
+                    });
+                }
+
+            }
+
+            for (primitive_index, primitive) in data.primitives.iter().enumerate() {
+                for index in [primitive.a, primitive.b, primitive.c] {
+                    if index as usize >= data.vertices.len() {
+                        issues.push(ValidationIssue::InvalidVertexIndex { 
+                            render_set: render_set_index, 
+                            primitive: primitive_index, 
+                            index, 
+                            len: data.vertices.len(),
+                        });
+                    }
+                }
+            }
+
+        }
+
+        issues
+
+    }
+
+}
+
+/// A group of render sets sharing the same geometry, see [`Model::geometry_groups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeometryGroup<'a> {
+    /// Identifier of the shared vertices section in the primitive binary file.
+    pub vertices_section: &'a str,
+    /// Identifier of the shared indices section in the primitive binary file.
+    pub indices_section: &'a str,
+    /// Indices of the render sets using this geometry, in order, the data of any of
+    /// these render sets can be used for all instances because it has been decoded
+    /// from the same sections.
+    pub render_sets: Vec<usize>,
 }
 
 impl RenderSetData {
 
+    /// Return the number of triangles of this render set.
+    #[inline]
+    pub fn triangle_count(&self) -> usize {
+        self.primitives.len()
+    }
+
     /// Get a specific primitive group. Only its vertices and primitives are
-    /// returned.
+    /// returned. None is also returned if the group is out of bounds, see 
+    /// [`Model::validate`].
     pub fn get_group(&self, index: usize) -> Option<(&[Vertex], &[Primitive])> {
         let group = self.groups.get(index)?;
         Some((
-            &self.vertices[group.vertices_offset as usize..][..group.vertices_count as usize],
-            &self.primitives[group.primitives_offset as usize..][..group.primitives_count as usize],
+            self.vertices.get(group.vertices_offset as usize..)?.get(..group.vertices_count as usize)?,
+            self.primitives.get(group.primitives_offset as usize..)?.get(..group.primitives_count as usize)?,
         ))
     }
 
+    /// Get the texture coordinates of the vertices of a specific primitive group, in
+    /// the same order as vertices returned by [`Self::get_group`], see [`Vertex::uv`]
+    /// for their orientation. None is returned if the group is out of bounds or if the
+    /// vertices have no texture coordinates.
+    pub fn get_group_uvs(&self, index: usize) -> Option<impl Iterator<Item = Vec2> + '_> {
+        if !self.has_uv {
+            return None;
+        }
+        let (vertices, _) = self.get_group(index)?;
+        Some(vertices.iter().filter_map(Vertex::uv))
+    }
+
 }
 
 
+/// An issue found when validating a model, see [`Model::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ValidationIssue {
+    /// A render set of the visual has no associated data.
+    #[error("render set #{render_set} has no data")]
+    MissingRenderSetData {
+        render_set: usize,
+    },
+    /// There are more render set data than render sets in the visual.
+    #[error("{count} render set data are not used by the visual")]
+    UnusedRenderSetData {
+        count: usize,
+    },
+    /// A primitive group of a render set references a group that doesn't exist.
+    #[error("render set #{render_set} references missing group #{group}")]
+    MissingGroup {
+        render_set: usize,
+        group: u32,
+    },
+    /// The vertices of a group are out of the render set's vertices.
+    #[error("render set #{render_set} group #{group} vertices end at {end} but there are {len} vertices")]
+    GroupVerticesOutOfBounds {
+        render_set: usize,
+        group: usize,
+        end: u64,
+        len: usize,
+    },
+    /// The primitives of a group are out of the render set's primitives.
+    #[error("render set #{render_set} group #{group} primitives end at {end} but there are {len} primitives")]
+    GroupPrimitivesOutOfBounds {
+        render_set: usize,
+        group: usize,
+        end: u64,
+        len: usize,
+    },
+    /// A primitive references a vertex that doesn't exist.
+    #[error("render set #{render_set} primitive #{primitive} references vertex {index} but there are {len} vertices")]
+    InvalidVertexIndex {
+        render_set: usize,
+        primitive: usize,
+        index: u32,
+        len: usize,
+    },
+}
+
 /// Deserialization errors that can happen while read a whole compiled model.
 #[derive(Debug, Error)]
 pub enum DeError {
+    #[error("the model has no visual")]
+    MissingVisual,
+    #[error("the visual '{0}' is not found")]
+    VisualNotFound(String),
     #[error("the vertices section '{0}' is missing")]
     MissingVerticesSection(String),
     #[error("the indices section '{0}' is missing")]
@@ -114,4 +420,333 @@ pub enum DeError {
     Visual(#[from] visual::DeError),
     #[error("primitive error: {0}")]
     Primitive(#[from] primitive::DeError),
+    #[error("model pxml error: {0}")]
+    Pxml(#[from] pxml::DeError),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+    use std::fs;
+
+    use glam::{Affine3A, Vec2, Vec3};
+    use smallvec::{smallvec, SmallVec};
+
+    use crate::pxml::{Element, Vector};
+    use crate::util::TempDir;
+
+    use super::*;
+    use super::visual::{Node, Geometry};
+
+    fn test_vertices(count: usize) -> Vec<Vertex> {
+        (0..count).map(|_| Vertex {
+            position: Vec3::ZERO,
+            normal: Vec3::Y,
+            uv: Some(Vec2::ZERO),
+            index: [0; 3],
+            index2: [0; 3],
+            weight: [0.0; 3],
+            tangent: 0,
+            binormal: 0,
+        }).collect()
+    }
+
+    fn test_visual() -> Box<Visual> {
+        Box::new(Visual {
+            root_node: Node { 
+                identifier: "Scene Root".to_string(), 
+                transform: Affine3A::IDENTITY, 
+                children: Vec::new(),
+            },
+            render_sets: smallvec![RenderSet {
+                node: "Scene Root".to_string(),
+                geometry: Geometry {
+                    vertices_section: "vertices".to_string(),
+                    indices_section: "indices".to_string(),
+                    primitive_groups: SmallVec::new(),
+                },
+                treat_as_world_space_object: false,
+            }],
+            bb_min: Vec3::ZERO,
+            bb_max: Vec3::ZERO,
+            geometry_size: 0,
+            min_uv_density: 0.0,
+        })
+    }
+
+    #[test]
+    fn validate_group_out_of_range() {
+
+        let model = Model {
+            visual: test_visual(),
+            render_sets_data: vec![RenderSetData {
+                vertices: test_vertices(3),
+                has_uv: true,
+                primitives: vec![Primitive { a: 0, b: 1, c: 2 }],
+                index_width: IndexWidth::U16,
+                groups: vec![Group { 
+                    primitives_offset: 0, 
+                    primitives_count: 1, 
+                    vertices_offset: 1, 
+                    vertices_count: 3,
+                }],
+            }],
+        };
+
+        assert_eq!(model.validate(), vec![ValidationIssue::GroupVerticesOutOfBounds { 
+            render_set: 0, 
+            group: 0, 
+            end: 4, 
+            len: 3,
+        }]);
+
+        assert!(model.render_sets_data[0].get_group(0).is_none());
+
+    }
+
+    #[test]
+    fn group_uvs() {
+
+        let mut data = RenderSetData {
+            vertices: test_vertices(3),
+            has_uv: true,
+            primitives: vec![Primitive { a: 0, b: 1, c: 2 }],
+            index_width: IndexWidth::U16,
+            groups: vec![Group { 
+                primitives_offset: 0, 
+                primitives_count: 1, 
+                vertices_offset: 0, 
+                vertices_count: 3,
+            }],
+        };
+
+        assert_eq!(data.get_group_uvs(0).unwrap().count(), 3);
+        assert!(data.get_group_uvs(1).is_none());
+
+        data.has_uv = false;
+        assert!(data.get_group_uvs(0).is_none());
+
+    }
+
+    #[test]
+    fn detail_render_set() {
+
+        let empty = Model { visual: test_visual(), render_sets_data: Vec::new() };
+        assert_eq!(empty.lowest_detail_render_set(), None);
+        assert_eq!(empty.highest_detail_render_set(), None);
+
+        let model = Model {
+            visual: test_visual(),
+            render_sets_data: [3, 1, 3, 1].into_iter().map(|count| RenderSetData {
+                vertices: test_vertices(3),
+                has_uv: true,
+                primitives: (0..count).map(|_| Primitive { a: 0, b: 1, c: 2 }).collect(),
+                index_width: IndexWidth::U16,
+                groups: Vec::new(),
+            }).collect(),
+        };
+
+        assert_eq!(model.lowest_detail_render_set(), Some(1));
+        assert_eq!(model.highest_detail_render_set(), Some(0));
+
+    }
+
+    #[test]
+    fn triangles() {
+
+        let mut visual = test_visual();
+        visual.root_node.children.push(Node {
+            identifier: "hull".to_string(),
+            transform: Affine3A::from_translation(Vec3::new(0.0, 2.0, 0.0)),
+            children: Vec::new(),
+        });
+        visual.root_node.transform = Affine3A::from_translation(Vec3::new(1.0, 0.0, 0.0));
+        visual.render_sets[0].node = "hull".to_string();
+
+        let mut vertices = test_vertices(4);
+        vertices[1].position = Vec3::X;
+        vertices[2].position = Vec3::Z;
+        vertices[3].position = Vec3::ONE;
+
+        let groups = vec![
+            Group { primitives_offset: 0, primitives_count: 2, vertices_offset: 0, vertices_count: 4 },
+            Group { primitives_offset: 2, primitives_count: 1, vertices_offset: 0, vertices_count: 4 },
+        ];
+
+        let model = Model {
+            visual,
+            render_sets_data: vec![RenderSetData {
+                vertices,
+                has_uv: false,
+                primitives: vec![
+                    Primitive { a: 0, b: 1, c: 2 },
+                    Primitive { a: 1, b: 3, c: 2 },
+                    Primitive { a: 3, b: 2, c: 0 },
+                ],
+                index_width: IndexWidth::U16,
+                groups,
+            }],
+        };
+
+        let primitives_count = model.render_sets_data[0].groups.iter()
+            .map(|group| group.primitives_count as usize)
+            .sum::<usize>();
+        assert_eq!(model.triangles().count(), primitives_count);
+
+        let first = model.triangles().next().unwrap();
+        assert_eq!(first, [Vec3::new(1.0, 2.0, 0.0), Vec3::new(2.0, 2.0, 0.0), Vec3::new(1.0, 2.0, 1.0)]);
+
+    }
+
+    #[test]
+    fn geometry_groups() {
+
+        let mut visual = test_visual();
+        for (node, vertices_section, indices_section) in [
+            ("wheel_R", "vertices", "indices"),
+            ("track", "track_vertices", "indices"),
+            ("wheel_L2", "vertices", "indices"),
+        ] {
+            visual.render_sets.push(RenderSet {
+                node: node.to_string(),
+                geometry: Geometry {
+                    vertices_section: vertices_section.to_string(),
+                    indices_section: indices_section.to_string(),
+                    primitive_groups: SmallVec::new(),
+                },
+                treat_as_world_space_object: false,
+            });
+        }
+
+        let model = Model { visual, render_sets_data: Vec::new() };
+        assert_eq!(model.geometry_groups(), [
+            GeometryGroup { vertices_section: "vertices", indices_section: "indices", render_sets: vec![0, 1, 3] },
+            GeometryGroup { vertices_section: "track_vertices", indices_section: "indices", render_sets: vec![2] },
+        ]);
+
+    }
+
+    #[test]
+    fn visual_paths() {
+        assert_eq!(visual_base_paths("vehicles/tank/chassis.model", "chassis"), ["vehicles/tank/chassis", "chassis"]);
+        assert_eq!(visual_base_paths("vehicles/tank/chassis.model", "vehicles/tank/lod0/chassis.visual"), ["vehicles/tank/vehicles/tank/lod0/chassis", "vehicles/tank/lod0/chassis"]);
+        assert_eq!(visual_base_paths("chassis.model", "/chassis"), ["chassis"]);
+    }
+
+    fn pxml_bytes(element: &Element) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        pxml::to_writer(&mut buf, element).unwrap();
+        buf.into_inner()
+    }
+
+    fn pxml_element(children: Vec<(&str, Value)>) -> Value {
+        let mut element = Element::new();
+        for (key, value) in children {
+            element.add_children(key, value);
+        }
+        Value::Element(Box::new(element))
+    }
+
+    /// Encode a visual with a single render set using the "vertices" and "indices" 
+    /// sections, attached to the root node.
+    fn visual_bytes() -> Vec<u8> {
+        let Value::Element(visual) = pxml_element(vec![
+            ("node", pxml_element(vec![
+                ("identifier", Value::String("Scene Root".to_string())),
+                ("transform", Value::Vector(Vector(SmallVec::from_slice(&Affine3A::IDENTITY.to_cols_array())))),
+            ])),
+            ("renderSet", pxml_element(vec![
+                ("treatAsWorldSpaceObject", Value::Boolean(false)),
+                ("node", Value::String("Scene Root".to_string())),
+                ("geometry", pxml_element(vec![
+                    ("vertices", Value::String("vertices".to_string())),
+                    ("primitive", Value::String("indices".to_string())),
+                ])),
+            ])),
+            ("boundingBox", pxml_element(vec![
+                ("min", Value::String("0 0 0".to_string())),
+                ("max", Value::String("1 0 1".to_string())),
+            ])),
+            ("geometrySize", Value::Integer(0)),
+            ("minUVDensity", Value::String("1.0".to_string())),
+        ]) else { unreachable!() };
+        pxml_bytes(&visual)
+    }
+
+    /// Encode a primitives file with a single triangle.
+    fn primitives_bytes() -> Vec<u8> {
+
+        let mut vertices = Vec::new();
+        vertices.extend_from_slice(b"xyznuv");
+        vertices.resize(64, 0);
+        vertices.extend_from_slice(&3u32.to_le_bytes());
+        for position in [Vec3::ZERO, Vec3::X, Vec3::Z] {
+            for value in position.to_array().into_iter().chain([0.0, position.x, position.z]) {
+                vertices.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let mut indices = Vec::new();
+        indices.extend_from_slice(b"list");
+        indices.resize(64, 0);
+        indices.extend_from_slice(&3u32.to_le_bytes());
+        indices.extend_from_slice(&1u32.to_le_bytes());
+        for index in [0u16, 1, 2] {
+            indices.extend_from_slice(&index.to_le_bytes());
+        }
+        for value in [0u32, 1, 0, 3] {
+            indices.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut data = primitive::MAGIC.to_vec();
+        let mut table = Vec::new();
+        for (name, section) in [("vertices", vertices), ("indices", indices)] {
+            data.extend_from_slice(&section);
+            data.resize(data.len().next_multiple_of(4), 0);
+            table.extend_from_slice(&(section.len() as u32).to_le_bytes());
+            table.extend_from_slice(&[0; 16]);
+            table.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            table.extend_from_slice(name.as_bytes());
+            table.resize(table.len().next_multiple_of(4), 0);
+        }
+
+        data.extend_from_slice(&table);
+        data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        data
+
+    }
+
+    #[test]
+    fn load_from_res() {
+
+        let dir_path = TempDir::new("res-model");
+        fs::create_dir_all(dir_path.join("vehicles/tank")).unwrap();
+        fs::create_dir_all(dir_path.join("packages")).unwrap();
+
+        let Value::Element(model) = pxml_element(vec![
+            ("nodefullVisual", Value::String("chassis".to_string())),
+        ]) else { unreachable!() };
+        fs::write(dir_path.join("vehicles/tank/chassis.model"), pxml_bytes(&model)).unwrap();
+
+        // The visual next to the model has no primitives, it must be skipped.
+        fs::write(dir_path.join("vehicles/tank/chassis.visual_processed"), visual_bytes()).unwrap();
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        assert!(matches!(from_res(&res, "vehicles/tank/chassis.model"), Err(DeError::VisualNotFound(name)) if name == "chassis"));
+
+        fs::write(dir_path.join("chassis.visual_processed"), visual_bytes()).unwrap();
+        fs::write(dir_path.join("chassis.primitives_processed"), primitives_bytes()).unwrap();
+
+        let model = from_res(&res, "vehicles/tank/chassis.model").unwrap();
+        assert_eq!(model.visual.render_sets.len(), 1);
+        assert_eq!(model.render_sets_data[0].vertices.len(), 3);
+        assert_eq!(model.render_sets_data[0].groups.len(), 1);
+        assert_eq!(model.triangles().collect::<Vec<_>>(), [[Vec3::ZERO, Vec3::X, Vec3::Z]]);
+
+    }
+
 }
@@ -29,6 +29,8 @@ pub trait Section: Sized {
 pub struct PrimitiveReader<R> {
     inner: R,
     sections: HashMap<String, SectionMeta>,
+    /// Buffer reused for reading raw sections' bytes.
+    buf: Vec<u8>,
 }
 
 impl<R> PrimitiveReader<R> {
@@ -98,6 +100,7 @@ impl<R: Read + Seek> PrimitiveReader<R> {
         Ok(Self {
             inner,
             sections,
+            buf: Vec::new(),
         })
 
     }
@@ -121,6 +124,21 @@ impl<R: Read + Seek> PrimitiveReader<R> {
         }
     }
 
+    /// Read the raw bytes of the given section, without decoding them, this can be used
+    /// to copy sections verbatim. The bytes are read into a buffer owned by this reader
+    /// and reused between calls, so no allocation is done once the buffer is large 
+    /// enough. This can be used alongside [`Self::read_section`].
+    pub fn section_bytes(&mut self, name: &str) -> Option<Result<&[u8], DeError>> {
+        let &SectionMeta { off, len, .. } = self.get_section_meta(name)?;
+        self.buf.resize(len, 0);
+        let res = self.inner.seek(SeekFrom::Start(off as u64))
+            .and_then(|_| self.inner.read_exact(&mut self.buf));
+        Some(match res {
+            Ok(()) => Ok(&self.buf[..]),
+            Err(e) => Err(e.into()),
+        })
+    }
+
 }
 
 
@@ -137,6 +155,9 @@ pub struct SectionMeta {
 #[derive(Debug)]
 pub struct Vertices {
     pub vertices: Vec<Vertex>,
+    /// True if the vertex format stores texture coordinates (its name contains `uv`),
+    /// if not then all vertices have no texture coordinates.
+    pub has_uv: bool,
 }
 
 impl Section for Vertices {
@@ -163,6 +184,7 @@ impl Section for Vertices {
         let mut ty_skinned = false;
         let mut ty_tb = false;
         let mut ty_iiiww = false;
+        let ty_uv = ty_name.contains("uv");
 
         match &ty_name[..] {
             "set3/xyznuvtbpc" => {
@@ -237,10 +259,13 @@ impl Section for Vertices {
                 }
             };
 
-            let uv = {
+            // The V coordinate is stored with the origin at the top of the texture.
+            let uv = if ty_uv {
                 let u = reader.read_f32()?;
                 let v = reader.read_f32()?;
-                Vec2::new(u, 1.0 - v)
+                Some(Vec2::new(u, 1.0 - v))
+            } else {
+                None
             };
 
             let mut index = [0; 3];
@@ -285,7 +310,7 @@ impl Section for Vertices {
 
         }
 
-        Ok(Self { vertices })
+        Ok(Self { vertices, has_uv: ty_uv })
 
     }
 
@@ -294,7 +319,8 @@ impl Section for Vertices {
 pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
-    pub uv: Vec2,
+    /// Texture coordinates, if the vertex format has them, see [`Self::uv()`].
+    pub uv: Option<Vec2>,
     pub index: [u8; 3],
     pub index2: [u8; 3],
     pub weight: [f32; 3],
@@ -302,12 +328,30 @@ pub struct Vertex {
     pub binormal: u32,
 }
 
+impl Vertex {
+
+    /// Return the texture coordinates of this vertex, none if its vertex format has no
+    /// texture coordinates. The V coordinate is flipped when
+    /// decoded (`1 - v`), so the origin is at the bottom-left of the texture, like in
+    /// OpenGL, exporters to formats with a top-left origin (such as glTF) must flip it 
+    /// back. Coordinates are usually in `0.0..=1.0` but may exceed it for repeating
+    /// textures.
+    #[inline]
+    pub fn uv(&self) -> Option<Vec2> {
+        self.uv
+    }
+
+}
+
 impl fmt::Debug for Vertex {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Vertex {{ pos: {}/{}/{}, norm: {}/{}/{}, uv: {}/{}, index: {:?}, weight: {:?}, tan: {}, binorm: {} }}",
+        let uv = match self.uv {
+            Some(uv) => format!("{}/{}", uv.x, uv.y),
+            None => "none".to_string(),
+        };
+        write!(f, "Vertex {{ pos: {}/{}/{}, norm: {}/{}/{}, uv: {uv}, index: {:?}, weight: {:?}, tan: {}, binorm: {} }}",
             self.position.x, self.position.y, self.position.z,
             self.normal.x, self.normal.y, self.normal.z,
-            self.uv.x, self.uv.y,
             self.index,
             self.weight,
             self.tangent,
@@ -320,6 +364,12 @@ impl fmt::Debug for Vertex {
 /// A section that contains indices and groups.
 #[derive(Debug)]
 pub struct Indices {
+    /// Topology of the indices as stored in the section, note that strips are always
+    /// converted to triangle lists, so this is just informative.
+    pub topology: Topology,
+    /// Width of the indices as stored in the section, primitives always use 32-bit
+    /// indices, this is kept in order to re-encode the indices with the same width.
+    pub width: IndexWidth,
     /// Listing of all primitives (triangles).
     pub primitives: Vec<Primitive>,
     /// Listing of all groups of primitives.
@@ -332,33 +382,27 @@ impl Section for Indices {
         
         // Get the type name and the indices' width.
         let ty_name = reader.read_cstring(64)?;
-        let ty_long = match &ty_name[..] {
-            "list" => false,
-            "list32" => true,
+        let (width, topology) = match &ty_name[..] {
+            "list" => (IndexWidth::U16, Topology::List),
+            "list32" => (IndexWidth::U32, Topology::List),
+            "strip" => (IndexWidth::U16, Topology::Strip),
+            "strip32" => (IndexWidth::U32, Topology::Strip),
             _ => return Err(DeError::InvalidType(ty_name))
         };
 
-        // Read number of vertices and groups.
-        let vertices_count = reader.read_u32()? / 3;
+        // Read number of indices and groups.
+        let indices_count = reader.read_u32()?;
         let groups_count = reader.read_u32()?;
 
         // Read all indices.
-        let mut indices = Vec::new();
-        if ty_long {
-            for _ in 0..vertices_count {
-                indices.push(Primitive {
-                    a: reader.read_u32()?,
-                    b: reader.read_u32()?,
-                    c: reader.read_u32()?,
-                });
+        let mut indices = Vec::with_capacity(indices_count as usize);
+        if width == IndexWidth::U32 {
+            for _ in 0..indices_count {
+                indices.push(reader.read_u32()?);
             }
         } else {
-            for _ in 0..vertices_count {
-                indices.push(Primitive {
-                    a: reader.read_u16()? as u32,
-                    b: reader.read_u16()? as u32,
-                    c: reader.read_u16()? as u32,
-                });
+            for _ in 0..indices_count {
+                indices.push(reader.read_u16()? as u32);
             }
         }
 
@@ -372,12 +416,78 @@ impl Section for Indices {
             });
         }
 
-        Ok(Self { primitives: indices, groups })
+        // In both topologies, the group's primitive offset is stored as the offset of
+        // its first index, it's rewritten to be the offset of its first primitive.
+        let mut primitives = Vec::new();
+        match topology {
+            Topology::List => {
+                for chunk in indices.chunks_exact(3) {
+                    primitives.push(Primitive { a: chunk[0], b: chunk[1], c: chunk[2] });
+                }
+                for group in &mut groups {
+                    if group.primitives_offset % 3 != 0 {
+                        return Err(DeError::InvalidGroup);
+                    }
+                    group.primitives_offset /= 3;
+                }
+            }
+            Topology::Strip => {
+                // For strips, the count is the number of triangles, including the 
+                // degenerate ones. We convert each group to a list and rewrite the 
+                // group to point to it.
+                for group in &mut groups {
+
+                    let offset = group.primitives_offset as usize;
+                    let len = group.primitives_count as usize + 2;
+                    let strip = indices.get(offset..offset + len)
+                        .ok_or(DeError::InvalidGroup)?;
+
+                    group.primitives_offset = primitives.len() as u32;
+
+                    for (i, window) in strip.windows(3).enumerate() {
+                        let (a, b, c) = (window[0], window[1], window[2]);
+                        // Degenerate triangles are only used to join strips.
+                        if a == b || b == c || a == c {
+                            continue;
+                        }
+                        // Every odd triangle has its winding reversed in a strip.
+                        primitives.push(if i % 2 == 0 {
+                            Primitive { a, b, c }
+                        } else {
+                            Primitive { a: b, b: a, c }
+                        });
+                    }
+
+                    group.primitives_count = primitives.len() as u32 - group.primitives_offset;
+
+                }
+            }
+        }
+
+        Ok(Self { topology, width, primitives, groups })
 
     }
 
 }
 
+/// Topology of the indices stored in an indices section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Each 3 indices form a triangle.
+    List,
+    /// Each index forms a triangle with the two previous ones.
+    Strip,
+}
+
+/// Width of the indices stored in an indices section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexWidth {
+    /// 16-bit indices, for meshes with at most 65536 vertices.
+    U16,
+    /// 32-bit indices, used by large meshes.
+    U32,
+}
+
 /// A primitive (triangle) of indices, referencing vertices.
 #[derive(Debug)]
 pub struct Primitive {
@@ -388,7 +498,9 @@ pub struct Primitive {
 
 #[derive(Debug)]
 pub struct Group {
-    /// Offset of the first primitive of this group.
+    /// Offset of the first primitive of this group in the primitives, for both list
+    /// and strip topologies, it's stored as the offset of the group's first index and 
+    /// converted when decoding.
     pub primitives_offset: u32,
     /// Number of primitives in the group.
     pub primitives_count: u32,
@@ -407,6 +519,9 @@ pub enum DeError {
     /// Any section's type begins with a type describing data layout for
     /// the section. This error is returned if such type cannot be resolved.
     InvalidType(String),
+    /// A group of an indices section is pointing outside of the indices, or in the 
+    /// middle of a primitive.
+    InvalidGroup,
     /// Unhandled underlying I/O error.
     Io(io::Error),
 }
@@ -416,6 +531,7 @@ impl fmt::Display for DeError {
         match *self {
             Self::InvalidMagic => write!(f, "invalid magic"),
             Self::InvalidType(ref s) => write!(f, "invalid type '{s}'"),
+            Self::InvalidGroup => write!(f, "invalid group"),
             Self::Io(ref err) => write!(f, "io error: {err}"),
         }
     }
@@ -435,3 +551,167 @@ impl From<io::Error> for DeError {
         Self::Io(e)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn indices_32() {
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"list32");
+        data.resize(64, 0);
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        for index in [0u32, 70_000, 100_000] {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+        for value in [0u32, 1, 0, 100_001] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let len = data.len();
+        let indices = Indices::read(Cursor::new(data), len).unwrap();
+        assert_eq!(indices.width, IndexWidth::U32);
+        assert_eq!(indices.primitives.len(), 1);
+        assert_eq!(indices.primitives[0].b, 70_000);
+        assert_eq!(indices.primitives[0].c, 100_000);
+
+    }
+
+    /// Encode a 16-bit indices section with the given groups, as (first index, count).
+    fn indices_section(ty_name: &[u8], indices: &[u16], groups: &[(u32, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(ty_name);
+        data.resize(64, 0);
+        data.extend_from_slice(&(indices.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(groups.len() as u32).to_le_bytes());
+        for index in indices {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+        for &(offset, count) in groups {
+            for value in [offset, count, 0, 8] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn groups_list() {
+
+        let data = indices_section(b"list", &[0, 1, 2, 2, 1, 3, 4, 5, 6], &[(0, 2), (6, 1)]);
+        let len = data.len();
+        let indices = Indices::read(Cursor::new(data), len).unwrap();
+        assert_eq!(indices.primitives.len(), 3);
+        assert_eq!((indices.groups[0].primitives_offset, indices.groups[0].primitives_count), (0, 2));
+        assert_eq!((indices.groups[1].primitives_offset, indices.groups[1].primitives_count), (2, 1));
+        assert_eq!(indices.primitives[2].a, 4);
+
+        // The first index of a group must be the first index of a primitive.
+        let data = indices_section(b"list", &[0, 1, 2, 2, 1, 3], &[(1, 1)]);
+        let len = data.len();
+        assert!(matches!(Indices::read(Cursor::new(data), len), Err(DeError::InvalidGroup)));
+
+    }
+
+    #[test]
+    fn groups_strip() {
+
+        // Two strips joined with degenerate triangles, the second group starts at the 
+        // index 6, its first triangle is (4, 5, 6).
+        let data = indices_section(b"strip", &[0, 1, 2, 3, 3, 4, 4, 5, 6, 7], &[(0, 2), (6, 2)]);
+        let len = data.len();
+        let indices = Indices::read(Cursor::new(data), len).unwrap();
+        assert_eq!(indices.primitives.len(), 4);
+        assert_eq!((indices.groups[0].primitives_offset, indices.groups[0].primitives_count), (0, 2));
+        assert_eq!((indices.groups[1].primitives_offset, indices.groups[1].primitives_count), (2, 2));
+        let first = &indices.primitives[2];
+        assert_eq!((first.a, first.b, first.c), (4, 5, 6));
+        // Odd triangles of the strip have their winding reversed.
+        let second = &indices.primitives[3];
+        assert_eq!((second.a, second.b, second.c), (6, 5, 7));
+
+    }
+
+    #[test]
+    fn vertices_uv() {
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"xyznuv");
+        data.resize(64, 0);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        for (u, v) in [(0.0f32, 0.25f32), (1.0, 1.0)] {
+            for comp in [1.0f32, 2.0, 3.0] {
+                data.extend_from_slice(&comp.to_le_bytes());
+            }
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&u.to_le_bytes());
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let len = data.len();
+        let vertices = Vertices::read(Cursor::new(data), len).unwrap();
+        assert!(vertices.has_uv);
+        assert_eq!(vertices.vertices.len(), 2);
+        assert_eq!(vertices.vertices[0].uv(), Some(Vec2::new(0.0, 0.75)));
+        assert_eq!(vertices.vertices[1].uv(), Some(Vec2::new(1.0, 0.0)));
+
+    }
+
+    #[test]
+    fn vertices_unknown_type() {
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"xyzn");
+        data.resize(64, 0);
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let len = data.len();
+        assert!(matches!(Vertices::read(Cursor::new(data), len), Err(DeError::InvalidType(ty)) if ty == "xyzn"));
+
+    }
+
+    #[test]
+    fn section_bytes() {
+
+        let mut vertices = Vec::new();
+        vertices.extend_from_slice(b"xyznuv");
+        vertices.resize(64, 0);
+        vertices.extend_from_slice(&1u32.to_le_bytes());
+        vertices.resize(vertices.len() + 32, 0);
+
+        let mut data = MAGIC.to_vec();
+        let vertices_off = data.len();
+        data.extend_from_slice(&vertices);
+        data.extend_from_slice(&[0xAA; 6]);  // Another section, padded to 8.
+        data.extend_from_slice(&[0; 2]);
+
+        let mut table = Vec::new();
+        for (name, len) in [("x.vertices", vertices.len()), ("x.other", 6)] {
+            table.extend_from_slice(&(len as u32).to_le_bytes());
+            table.extend_from_slice(&[0; 16]);
+            table.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            table.extend_from_slice(name.as_bytes());
+            table.resize(table.len().next_multiple_of(4), 0);
+        }
+        data.extend_from_slice(&table);
+        data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+
+        let mut reader = PrimitiveReader::open(Cursor::new(&data)).unwrap();
+        let bytes = reader.section_bytes("x.vertices").unwrap().unwrap();
+        assert_eq!(bytes, &data[vertices_off..vertices_off + vertices.len()]);
+        assert_eq!(reader.section_bytes("x.other").unwrap().unwrap(), [0xAA; 6]);
+        assert!(reader.section_bytes("x.indices").is_none());
+
+        let decoded = reader.read_section::<Vertices>("x.vertices").unwrap().unwrap();
+        assert_eq!(decoded.vertices.len(), 1);
+        assert_eq!(reader.section_bytes("x.vertices").unwrap().unwrap(), vertices);
+
+    }
+
+}
@@ -19,7 +19,7 @@ pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Box<Visual>, DeError> {
     
     let root_node_value = root_elt.get_child("node").ok_or(DeError::MissingRootNode)?;
     let root_node_elt = root_node_value.as_element().ok_or(DeError::MissingRootNode)?;
-    let root_node = read_node(root_node_elt).ok_or(DeError::InvalidNode)?;
+    let root_node = read_node(root_node_elt)?;
 
     let bb_elt = root_elt
         .get_child("boundingBox").ok_or(DeError::MissingBoundingBox)?
@@ -60,10 +60,17 @@ pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Box<Visual>, DeError> {
 }
 
 
-fn read_node(element: &Element) -> Option<Node> {
+/// Read a node and its children, the recursion is bounded by the maximum nesting
+/// depth of the packed XML decoder, see [`pxml::MAX_DEPTH`].
+fn read_node(element: &Element) -> Result<Node, DeError> {
     
-    let identifier = element.get_child("identifier")?.as_string()?;
-    let transform = element.get_child("transform")?.as_affine3()?;
+    let identifier = element.get_child("identifier")
+        .and_then(Value::as_string)
+        .ok_or(DeError::InvalidNode)?;
+
+    let transform = element.get_child("transform")
+        .and_then(Value::as_affine3)
+        .ok_or(DeError::InvalidNode)?;
     
     let mut children = Vec::new();
     for child in element.iter_children("node") {
@@ -72,7 +79,7 @@ fn read_node(element: &Element) -> Option<Node> {
         }
     }
 
-    Some(Node {
+    Ok(Node {
         identifier: identifier.to_string(),
         transform,
         children,
@@ -172,6 +179,19 @@ pub struct Visual {
     pub min_uv_density: f32,
 }
 
+impl Visual {
+
+    /// Iterate over all nodes of the hierarchy, depth-first and starting with the root
+    /// node, each node is returned with its world transform, which is the product of
+    /// all its parents' transforms and its own transform.
+    pub fn nodes(&self) -> NodesIter<'_> {
+        NodesIter {
+            stack: vec![(&self.root_node, self.root_node.transform)],
+        }
+    }
+
+}
+
 /// Represent a node in the visual tree.
 #[derive(Debug)]
 pub struct Node {
@@ -183,6 +203,27 @@ pub struct Node {
     pub children: Vec<Node>,
 }
 
+/// Iterator over all nodes of a visual with their world transform, see [`Visual::nodes`].
+#[derive(Debug)]
+pub struct NodesIter<'a> {
+    stack: Vec<(&'a Node, Affine3A)>,
+}
+
+impl<'a> Iterator for NodesIter<'a> {
+
+    type Item = (&'a Node, Affine3A);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, world_transform) = self.stack.pop()?;
+        // Children are pushed in reverse order so that they are iterated in order.
+        for child in node.children.iter().rev() {
+            self.stack.push((child, world_transform * child.transform));
+        }
+        Some((node, world_transform))
+    }
+
+}
+
 /// Represent a render set for a model's visual.
 #[derive(Debug)]
 pub struct RenderSet {
@@ -264,3 +305,47 @@ pub enum DeError {
     #[error("pxml error: {0}")]
     Pxml(#[from] pxml::DeError),
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn node(identifier: &str, transform: Affine3A, children: Vec<Node>) -> Node {
+        Node { identifier: identifier.to_string(), transform, children }
+    }
+
+    #[test]
+    fn nodes_world_transforms() {
+
+        let root = node("Scene Root", Affine3A::from_translation(Vec3::X), vec![
+            node("a", Affine3A::from_translation(Vec3::Y), vec![
+                node("b", Affine3A::from_scale(Vec3::splat(2.0)), vec![]),
+            ]),
+            node("c", Affine3A::from_translation(Vec3::Z), vec![]),
+        ]);
+
+        let visual = Visual {
+            root_node: root,
+            render_sets: SmallVec::new(),
+            bb_min: Vec3::ZERO,
+            bb_max: Vec3::ZERO,
+            geometry_size: 0,
+            min_uv_density: 0.0,
+        };
+
+        let nodes = visual.nodes()
+            .map(|(node, world)| (node.identifier.as_str(), world.transform_point3(Vec3::ONE)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(nodes, [
+            ("Scene Root", Vec3::new(2.0, 1.0, 1.0)),
+            ("a", Vec3::new(2.0, 2.0, 1.0)),
+            ("b", Vec3::new(3.0, 3.0, 2.0)),
+            ("c", Vec3::new(2.0, 1.0, 2.0)),
+        ]);
+
+    }
+
+}
@@ -4,9 +4,13 @@
 //! replies to such elements if they are requests.
 
 use std::io::{self, Read, Write};
+use std::fmt;
 
-use crate::net::element::{ElementLength, Element, SimpleElement};
+use crate::net::element::{ElementLength, Element, ElementContext, SimpleElement};
+use crate::net::codec::SimpleCodec;
 use crate::net::app::common::entity::Method;
+use crate::util::io::*;
+use crate::util::AsciiFmt;
 
 
 /// Internal module containing all raw elements numerical ids.
@@ -35,6 +39,28 @@ pub mod id {
 }
 
 
+/// The element context of the base application, used to debug bundles sent by the 
+/// client to the base application, see [`ElementContext`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BaseContext;
+
+impl ElementContext for BaseContext {
+
+    fn element_info(&self, id: u8) -> Option<(&'static str, Option<ElementLength>)> {
+        Some(match id {
+            id::LOGIN_KEY                               => ("LoginKey", Some(LoginKey::LEN)),
+            id::SESSION_KEY                             => ("SessionKey", Some(SessionKey::LEN)),
+            id::ENABLE_ENTITIES                         => ("EnableEntities", Some(EnableEntities::LEN)),
+            id::DISCONNECT_CLIENT                       => ("DisconnectClient", Some(DisconnectClient::LEN)),
+            _ if id::CELL_ENTITY_METHOD.contains(id)    => ("CellEntityMethod", Some(ElementLength::Variable16)),
+            _ if id::BASE_ENTITY_METHOD.contains(id)    => ("BaseEntityMethod", Some(ElementLength::Variable16)),
+            _                                           => return None,
+        })
+    }
+
+}
+
+
 crate::__struct_simple_codec! {
     /// Sent by the client to the server without encryption in order to authenticate,
     /// the server then compares with its internal login keys from past successful
@@ -42,14 +68,24 @@ crate::__struct_simple_codec! {
     /// 
     /// This element is usually a request, in such case a [`SessionKey`] must be sent as 
     /// a reply, which is the server session key (not the same as login key).
+    /// 
+    /// This is the equivalent of BigWorld's `baseAppLogin` arguments, which only
+    /// contains the login key and the attempt number, the trailing 16-bit value is
+    /// specific to this client.
     #[derive(Debug, Clone)]
     pub struct LoginKey {
-        /// The login key that was sent by the login application, part of the  element
-        /// [`super::login::LoginSuccess`].
+        /// The login key that was sent by the login application, part of the element
+        /// [`LoginSuccess`](crate::net::app::login::element::LoginSuccess). If the 
+        /// server doesn't know this key, the login is usually rejected as invalid.
         pub login_key: u32,
-        /// The current number of attempts.
+        /// The attempt number, starting at zero and incremented by the client each 
+        /// time it resends this element because no session key has been received, 
+        /// the login key stays the same between attempts. This was previously named
+        /// `attempts_count`.
         pub attempt_num: u8,
-        /// Unknown 16-bits value at the end.
+        /// Unknown 16-bit value at the end, its meaning is not known yet and it is
+        /// ignored by the base application, it should be sent back as-is when 
+        /// forwarding this element.
         pub unk: u16,
     }
 }
@@ -93,12 +129,70 @@ impl SimpleElement for EnableEntities {
 
 
 crate::__struct_simple_codec! {
-    /// This is sent by the client to the base application as an acknowledgment of a
-    /// reset entity request sent to the client.
+    /// This is sent by the client to the base application when it disconnects.
     #[derive(Debug, Clone)]
     pub struct DisconnectClient {
-        pub reason: u8,
+        /// The reason of the disconnection.
+        pub reason: DisconnectReason,
+    }
+}
+
+/// The reason given by the client when disconnecting, see [`DisconnectClient`]. Known
+/// values follow BigWorld's client disconnect reasons, other values are kept as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client requested the disconnection, this is the usual reason.
+    ClientRequested,
+    /// The client has been given to another proxy.
+    GivenToOtherProxy,
+    /// The connection timed out.
+    Timeout,
+    /// The client exceeded rate limits.
+    RateLimitsExceeded,
+    /// The proxy entity has been destroyed.
+    ProxyDestroyed,
+    /// Unknown reason, the raw value is kept.
+    Unknown(u8),
+}
+
+impl DisconnectReason {
+
+    /// Get the reason from its raw value.
+    pub fn from_raw(raw: u8) -> Self {
+        match raw {
+            0 => Self::ClientRequested,
+            1 => Self::GivenToOtherProxy,
+            2 => Self::Timeout,
+            3 => Self::RateLimitsExceeded,
+            4 => Self::ProxyDestroyed,
+            raw => Self::Unknown(raw),
+        }
+    }
+
+    /// Get the raw value of this reason.
+    pub fn to_raw(self) -> u8 {
+        match self {
+            Self::ClientRequested => 0,
+            Self::GivenToOtherProxy => 1,
+            Self::Timeout => 2,
+            Self::RateLimitsExceeded => 3,
+            Self::ProxyDestroyed => 4,
+            Self::Unknown(raw) => raw,
+        }
+    }
+
+}
+
+impl SimpleCodec for DisconnectReason {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_u8(self.to_raw())
+    }
+
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        read.read_u8().map(Self::from_raw)
     }
+
 }
 
 impl SimpleElement for DisconnectClient {
@@ -123,10 +217,8 @@ impl<M: Method> Element<()> for BaseEntityMethod<M> {
 
     fn write(&self, write: &mut dyn Write, _config: &()) -> io::Result<u8> {
         let exposed_id = self.inner.write(write)?;
-        if exposed_id >= id::BASE_ENTITY_METHOD.slots_count() as u16 {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "missing support for sub-id"));
-        }
-        Ok(id::BASE_ENTITY_METHOD.first + exposed_id as u8)
+        id::BASE_ENTITY_METHOD.id_of(exposed_id as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing support for sub-id"))
     }
 
     fn read_length(_config: &(), _id: u8) -> io::Result<ElementLength> {
@@ -134,13 +226,161 @@ impl<M: Method> Element<()> for BaseEntityMethod<M> {
     }
 
     fn read(read: &mut dyn Read, _config: &(), _len: usize, id: u8) -> io::Result<Self> {
-        if !id::BASE_ENTITY_METHOD.contains(id) {
+        let Some(exposed_id) = id::BASE_ENTITY_METHOD.index_of(id) else {
             return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected base entity method element id: {id:02X}")));
-        }
-        let inner = M::read(read, (id - id::BASE_ENTITY_METHOD.first) as u16)?;
+        };
+        let inner = M::read(read, exposed_id as u16)?;
         Ok(Self {
             inner,
         })
     }
 
 }
+
+
+/// Codec for a cell entity method call, the exposed id of the method is the index of
+/// the element id in [`id::CELL_ENTITY_METHOD`], as the cell methods enumerations of 
+/// generated entities.
+///
+/// FIXME: For now, this doesn't support sub message id.
+#[derive(Debug, Clone)]
+pub struct CellEntityMethod<M: Method> {
+    pub inner: M,
+}
+
+impl<M: Method> Element<()> for CellEntityMethod<M> {
+
+    fn write_length(&self, _config: &()) -> io::Result<ElementLength> {
+        Ok(ElementLength::Variable16)
+    }
+
+    fn write(&self, write: &mut dyn Write, _config: &()) -> io::Result<u8> {
+        let exposed_id = self.inner.write(write)?;
+        id::CELL_ENTITY_METHOD.id_of(exposed_id as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing support for sub-id"))
+    }
+
+    fn read_length(_config: &(), _id: u8) -> io::Result<ElementLength> {
+        Ok(ElementLength::Variable16)
+    }
+
+    fn read(read: &mut dyn Read, _config: &(), _len: usize, id: u8) -> io::Result<Self> {
+        let Some(exposed_id) = id::CELL_ENTITY_METHOD.index_of(id) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected cell entity method element id: {id:02X}")));
+        };
+        let inner = M::read(read, exposed_id as u16)?;
+        Ok(Self {
+            inner,
+        })
+    }
+
+}
+
+
+/// Codec for a base entity method call that is not decoded, the method index is the
+/// exposed id of the method and the body is kept raw. This can be used when the type
+/// of the entity receiving the call is not statically known.
+///
+/// FIXME: For now, this doesn't support sub message id.
+#[derive(Clone)]
+pub struct RawBaseEntityMethod {
+    /// The exposed index of the method being called.
+    pub method_index: u16,
+    /// The raw encoded arguments of the method.
+    pub body: Vec<u8>,
+}
+
+impl Element<()> for RawBaseEntityMethod {
+
+    fn write_length(&self, _config: &()) -> io::Result<ElementLength> {
+        Ok(ElementLength::Variable16)
+    }
+
+    fn write(&self, write: &mut dyn Write, _config: &()) -> io::Result<u8> {
+        let id = id::BASE_ENTITY_METHOD.id_of(self.method_index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing support for sub-id"))?;
+        write.write_all(&self.body)?;
+        Ok(id)
+    }
+
+    fn read_length(_config: &(), _id: u8) -> io::Result<ElementLength> {
+        Ok(ElementLength::Variable16)
+    }
+
+    fn read(read: &mut dyn Read, _config: &(), _len: usize, id: u8) -> io::Result<Self> {
+        let Some(exposed_id) = id::BASE_ENTITY_METHOD.index_of(id) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected base entity method element id: {id:02X}")));
+        };
+        Ok(Self {
+            method_index: exposed_id as u16,
+            body: read.read_blob_to_end()?,
+        })
+    }
+
+}
+
+impl fmt::Debug for RawBaseEntityMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawBaseEntityMethod")
+            .field("method_index", &self.method_index)
+            .field("body", &AsciiFmt(&self.body))
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use crate::net::bundle::{Bundle, NextElementReader};
+    use super::*;
+
+    #[test]
+    fn disconnect_reason() {
+
+        let dc = <DisconnectClient as SimpleCodec>::read(&mut &[0x02][..]).unwrap();
+        assert_eq!(dc.reason, DisconnectReason::Timeout);
+
+        for raw in [0x00, 0x04, 0x2A, 0xFF] {
+            let mut buf = Vec::new();
+            let dc = <DisconnectClient as SimpleCodec>::read(&mut &[raw][..]).unwrap();
+            SimpleCodec::write(&dc, &mut buf).unwrap();
+            assert_eq!(buf, [raw]);
+        }
+
+        assert_eq!(DisconnectReason::from_raw(0x2A), DisconnectReason::Unknown(0x2A));
+
+    }
+
+    #[test]
+    #[allow(unreachable_patterns)]  // Generated by the methods macro.
+    fn cell_entity_method() {
+
+        crate::__struct_simple_codec! {
+            #[derive(Debug)]
+            struct Shoot { }
+            #[derive(Debug)]
+            struct SetMarker { marker: u8 }
+        }
+
+        crate::__enum_entity_methods! {
+            #[derive(Debug)]
+            enum TestCell {
+                Shoot(0x00, 0),
+                SetMarker(0x01, 1),
+            }
+        }
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(CellEntityMethod { inner: TestCell::SetMarker(SetMarker { marker: 42 }) });
+        
+        let mut reader = bundle.element_reader();
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        assert_eq!(elt.id(), id::CELL_ENTITY_METHOD.first + 1);
+        let method = elt.read_simple::<CellEntityMethod<TestCell>>().unwrap();
+        let TestCell::SetMarker(marker) = method.element.inner else { panic!() };
+        assert_eq!(marker.marker, 42);
+
+    }
+
+}
@@ -3,11 +3,13 @@
 pub mod element;
 
 use core::fmt;
+use std::any::Any;
 use std::collections::{HashMap, VecDeque};
 use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::num::Wrapping;
-use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
 use std::io;
 
 use blowfish::Blowfish;
@@ -15,17 +17,23 @@ use blowfish::Blowfish;
 use rand::rngs::OsRng;
 use rand::RngCore;
 
-use crate::net::bundle::{Bundle, NextElementReader, ElementReader};
+use crate::net::bundle::{Bundle, NextElementReader, ElementReader, RawElement};
 use crate::net::element::SimpleElement;
 use crate::net::socket::PacketSocket;
 use crate::net::proto::Protocol;
 
+use super::client::element::{UpdateFrequencyNotification, TickSync, SelectPlayerEntity, CreateBasePlayerHeader, write_create_base_player};
 use super::common::entity::Entity;
 use super::io_invalid_data;
 
-use element::{LoginKey, SessionKey};
+use element::{LoginKey, SessionKey, RawBaseEntityMethod};
 
 
+/// Maximum receive timeout while a client may time out, the socket's timeout is only
+/// updated when the next client deadline changes, so this bounds the delay to detect
+/// that a client has timed out.
+const MAX_RECV_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// The base application.
 #[derive(Debug)]
 pub struct App {
@@ -37,51 +45,206 @@ pub struct App {
     events: VecDeque<Event>,
     /// A temporary bundle for sending.
     bundle: Bundle,
-    /// Clients that have made an initial client connection, associated to the request id.
-    pending_clients: HashMap<SocketAddr, u32>,
+    /// Registry of login keys given to clients by the login app.
+    login_keys: LoginKeys,
+    /// Clients that have made an initial client connection, associated to the request id
+    /// and the login key used.
+    pending_clients: HashMap<SocketAddr, (u32, u32)>,
     /// Map of clients.
     clients: HashMap<SocketAddr, Client>,
+    /// The instant of the last packet received from each tracked client, a client is
+    /// tracked from its login until it times out.
+    clients_activity: HashMap<SocketAddr, Instant>,
+    /// The inactivity timeout of clients, if any.
+    client_timeout: Option<Duration>,
+    /// The earliest instant at which a tracked client may time out, clients are only
+    /// checked once it's reached.
+    clients_deadline: Option<Instant>,
+    /// The clients deadline that the socket's receive timeout has been set for.
+    recv_deadline: Option<Instant>,
+    /// The entities of each client and the selected one, used to resolve the entity 
+    /// receiving base entity method calls.
+    client_entities: HashMap<SocketAddr, ClientEntities>,
     /// Map of all currently alive entities.
     entities: HashMap<u32, EntityGeneric>,
     /// The next id for entities, this is wrapping around and we ensure that the same id
     /// isn't used twice!
     entities_next_id: Wrapping<u32>,
+    /// The update frequency (in hertz) advertised to clients.
+    update_frequency: u8,
+    /// Origin of the default game time clock, the instant and the game time at this
+    /// instant, it is moved when the update frequency changes.
+    time_origin: (Instant, u32),
+    /// Custom clock returning the game time, if any.
+    clock: Option<Clock>,
+}
+
+/// A custom clock returning the game time, see [`App::set_clock`].
+struct Clock(Box<dyn Fn() -> u32 + Send>);
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Clock")
+    }
 }
 
 impl App {
 
+    /// Recommended inactivity timeout of clients, client timeouts are disabled by 
+    /// default, see [`Self::set_client_timeout`].
+    pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
     pub fn new(addr: SocketAddr) -> io::Result<Self> {
         Ok(Self {
             socket: PacketSocket::bind(addr)?,
             protocol: Protocol::new(),
             events: VecDeque::new(),
             bundle: Bundle::new(),
+            login_keys: LoginKeys::new(),
             pending_clients: HashMap::new(),
             clients: HashMap::new(),
+            clients_activity: HashMap::new(),
+            client_timeout: None,
+            clients_deadline: None,
+            recv_deadline: None,
+            client_entities: HashMap::new(),
             entities: HashMap::new(),
             entities_next_id: Wrapping(OsRng.next_u32()),
+            update_frequency: 10,
+            time_origin: (Instant::now(), 0),
+            clock: None,
         })
     }
 
+    /// Get the update frequency (in hertz) advertised to clients, 10 Hz by default.
+    #[inline]
+    pub fn update_frequency(&self) -> u8 {
+        self.update_frequency
+    }
+
+    /// Set the update frequency (in hertz) advertised to clients, it is also used by
+    /// the default clock to derive the game time, which continues from its current 
+    /// value at the new frequency. This function panics if the given frequency is zero.
+    pub fn set_update_frequency(&mut self, hz: u8) {
+        assert_ne!(hz, 0, "update frequency must not be zero");
+        self.time_origin = (Instant::now(), self.default_game_time());
+        self.update_frequency = hz;
+    }
+
+    /// Set a custom clock returning the game time, in ticks. By default the game time
+    /// is the number of ticks elapsed since the creation of the app, at the update 
+    /// frequency that was set for each period.
+    pub fn set_clock(&mut self, clock: impl Fn() -> u32 + Send + 'static) {
+        self.clock = Some(Clock(Box::new(clock)));
+    }
+
+    /// Remove the custom clock and go back to the default one, see [`Self::set_clock`].
+    pub fn remove_clock(&mut self) {
+        self.clock = None;
+    }
+
+    /// Get the current game time, in ticks, from the clock.
+    pub fn game_time(&self) -> u32 {
+        match self.clock {
+            Some(ref clock) => (clock.0)(),
+            None => self.default_game_time(),
+        }
+    }
+
+    /// Get the game time from the default clock.
+    fn default_game_time(&self) -> u32 {
+        let (origin_instant, origin_time) = self.time_origin;
+        let ticks = (origin_instant.elapsed().as_secs_f64() * self.update_frequency as f64) as u32;
+        origin_time.wrapping_add(ticks)
+    }
+
+    /// Create the update frequency notification element to send to a client that has
+    /// just logged in, with the current update frequency and game time.
+    pub fn update_frequency_notification(&self) -> UpdateFrequencyNotification {
+        UpdateFrequencyNotification {
+            frequency: self.update_frequency,
+            unknown: 0,
+            game_time: self.game_time(),
+        }
+    }
+
+    /// Create the tick sync element for the current game time, it is expected to be
+    /// used as a timestamp at the beginning of each bundle. The tick is the game time
+    /// wrapped to a byte, so the tick following 255 is 0, as expected by clients.
+    pub fn tick_sync(&self) -> TickSync {
+        TickSync {
+            tick: self.game_time() as u8,
+        }
+    }
+
+    /// Get the inactivity timeout of clients, if any, see [`Self::set_client_timeout`].
+    #[inline]
+    pub fn client_timeout(&self) -> Option<Duration> {
+        self.client_timeout
+    }
+
+    /// Set the inactivity timeout of clients, none by default, 
+    /// [`Self::DEFAULT_CLIENT_TIMEOUT`] is a good value to start with. A client that 
+    /// hasn't sent any packet for this duration since its login is forgotten, along 
+    /// with its entities, and an [`Event::ClientTimeout`] is returned. Timeouts are 
+    /// detected while polling, even if no packet is received.
+    pub fn set_client_timeout(&mut self, timeout: Option<Duration>) {
+        self.client_timeout = timeout;
+        self.update_clients_deadline();
+    }
+
     /// Get the address this app is bound to.
     pub fn addr(&self) -> io::Result<SocketAddr> {
         self.socket.addr()
     }
 
+    /// Get the registry of login keys accepted by this app, it can be cloned and shared
+    /// with the thread running the login app in order to register the login keys given
+    /// to clients.
+    #[inline]
+    pub fn login_keys(&self) -> &LoginKeys {
+        &self.login_keys
+    }
+
     /// Poll for the next event of this login app, blocking.
     pub fn poll(&mut self) -> Event {
+
+        let mut recv_timed_out = false;
+
         loop {
 
+            if self.clients_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.check_clients_timeout();
+            }
+
             // Empty the events before.
             while let Some(event) = self.events.pop_front() {
                 return event;
             }
 
+            // The receive timeout allows detecting timed out clients without packets,
+            // it's relative to the receive so it's also updated after it expired.
+            if recv_timed_out || self.recv_deadline != self.clients_deadline {
+                if let Err(error) = self.update_recv_timeout() {
+                    return Event::IoError(IoErrorEvent { error, addr: None });
+                }
+            }
+
             let (packet, addr) = match self.socket.recv() {
                 Ok(ret) => ret,
+                Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {
+                    recv_timed_out = true;
+                    continue;
+                }
                 Err(error) => return Event::IoError(IoErrorEvent { error, addr: None }),
             };
 
+            recv_timed_out = false;
+
+            if let Some(last_activity) = self.clients_activity.get_mut(&addr) {
+                *last_activity = Instant::now();
+            }
+
             let Some(mut channel) = self.protocol.accept(packet, addr) else {
                 continue;
             };
@@ -111,11 +274,83 @@ impl App {
         }
     }
 
+    /// Forget about all clients that have not sent any packet since the client timeout,
+    /// an event is pushed for each of them, the clients deadline is then updated.
+    fn check_clients_timeout(&mut self) {
+
+        let Some(timeout) = self.client_timeout else {
+            self.clients_deadline = None;
+            return;
+        };
+
+        let now = Instant::now();
+        let timed_out = self.clients_activity.iter()
+            .filter(|&(_, &last_activity)| now - last_activity >= timeout)
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+
+        for addr in timed_out {
+            let entity_ids = self.remove_client(addr);
+            self.events.push_back(Event::ClientTimeout(ClientTimeoutEvent {
+                addr,
+                entity_ids,
+            }));
+        }
+
+        self.update_clients_deadline();
+
+    }
+
+    /// Compute the earliest instant at which a tracked client may time out.
+    fn update_clients_deadline(&mut self) {
+        self.clients_deadline = self.client_timeout.and_then(|timeout| {
+            self.clients_activity.values().min().map(|&last_activity| last_activity + timeout)
+        });
+    }
+
+    /// Set the socket's receive timeout to wake up at the clients deadline, if any, but
+    /// never later than [`MAX_RECV_TIMEOUT`], without deadline the receive is blocking.
+    fn update_recv_timeout(&mut self) -> io::Result<()> {
+
+        let timeout = self.clients_deadline.map(|deadline| {
+            deadline.saturating_duration_since(Instant::now())
+                .clamp(Duration::from_millis(1), MAX_RECV_TIMEOUT)
+        });
+
+        self.socket.set_recv_timeout(timeout)?;
+        self.recv_deadline = self.clients_deadline;
+        Ok(())
+
+    }
+
+    /// Forget everything about the client at the given address, its entities are also
+    /// removed and their ids are returned.
+    fn remove_client(&mut self, addr: SocketAddr) -> Vec<u32> {
+
+        self.clients_activity.remove(&addr);
+        self.pending_clients.remove(&addr);
+        self.clients.remove(&addr);
+        self.protocol.remove_addr(addr);
+        self.socket.remove_encryption(addr);
+
+        let entity_ids = self.client_entities.remove(&addr)
+            .map(|client_entities| client_entities.entities)
+            .unwrap_or_default();
+
+        for entity_id in &entity_ids {
+            self.entities.remove(entity_id);
+        }
+
+        entity_ids
+
+    }
+
     /// Handle an element read from the given address.
     fn handle_element(&mut self, addr: SocketAddr, reader: ElementReader) -> io::Result<()> {
         match reader.id() {
             LoginKey::ID => self.handle_client_auth(addr, reader),
             SessionKey::ID => self.handle_client_session_key(addr, reader),
+            id if element::id::BASE_ENTITY_METHOD.contains(id) => self.handle_base_entity_method(addr, reader),
             id => Err(io_invalid_data(format_args!("unexpected element #{id}"))),
         }
     }
@@ -126,13 +361,43 @@ impl App {
         let request_id = auth.request_id
             .ok_or_else(|| io_invalid_data(format_args!("auth should be a request")))?;
 
-        self.events.push_back(Event::Login(LoginEvent {
-            addr,
-            login_key: auth.element.login_key,
-            attempt_num: auth.element.attempt_num,
-        }));
+        let login_key = auth.element.login_key;
+
+        // The client is resending its login key because it has not been answered yet,
+        // we just update the request id to answer to.
+        if let Some(pending) = self.pending_clients.get_mut(&addr) {
+            if pending.1 == login_key {
+                pending.0 = request_id;
+                return Ok(());
+            }
+        }
 
-        self.pending_clients.insert(addr, request_id);
+        match self.login_keys.take(login_key, addr, Instant::now()) {
+            Ok(blowfish) => {
+                self.events.push_back(Event::Login(LoginEvent {
+                    addr,
+                    login_key,
+                    attempt_num: auth.element.attempt_num,
+                    unk: auth.element.unk,
+                    blowfish,
+                }));
+                self.pending_clients.insert(addr, (request_id, login_key));
+                let now = Instant::now();
+                self.clients_activity.insert(addr, now);
+                // The client's deadline can only be later than the current one, if any.
+                if let Some(timeout) = self.client_timeout {
+                    self.clients_deadline = Some(self.clients_deadline.map_or(now + timeout, |deadline| deadline.min(now + timeout)));
+                }
+            }
+            Err(reason) => {
+                self.events.push_back(Event::LoginRejected(LoginRejectedEvent {
+                    addr,
+                    login_key,
+                    attempt_num: auth.element.attempt_num,
+                    reason,
+                }));
+            }
+        }
 
         Ok(())
 
@@ -143,13 +408,30 @@ impl App {
         Ok(())
     }
 
+    fn handle_base_entity_method(&mut self, addr: SocketAddr, reader: ElementReader) -> io::Result<()> {
+
+        let method = reader.read_simple::<RawBaseEntityMethod>()?;
+        let entity_id = self.player_entity_id(addr)
+            .ok_or_else(|| io_invalid_data(format_args!("base entity method without player entity")))?;
+
+        self.events.push_back(Event::BaseMethod(BaseMethodEvent {
+            addr,
+            entity_id,
+            method_index: method.element.method_index,
+            body: method.element.body,
+        }));
+
+        Ok(())
+
+    }
+
     /// Accept the login of the given user, in response to [`Event::Login`], giving the
     /// blowfish key that will be used for encryption.
     /// 
     /// This returns true if the client hasn't been answered yet.
     pub fn answer_login_success(&mut self, addr: SocketAddr, _blowfish: Arc<Blowfish>) -> bool {
         
-        let Some(_request_id) = self.pending_clients.remove(&addr) else {
+        let Some((_request_id, _login_key)) = self.pending_clients.remove(&addr) else {
             return false;
         };
 
@@ -158,8 +440,27 @@ impl App {
 
     }
 
+    /// Return the number of entities currently hosted by this base app.
+    #[inline]
+    pub fn entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Iterate over the ids of all entities currently hosted by this base app, in no
+    /// particular order.
+    pub fn entity_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entities.keys().copied()
+    }
+
+    /// Get a reference to an entity from its handle, none is returned if the entity
+    /// no longer exists.
+    pub fn entity<E: Entity + 'static>(&self, handle: Handle<E>) -> Option<&E> {
+        self.entities.get(&handle.entity_id)?.wrapper.as_any().downcast_ref::<EntityWrapperImpl<E>>()
+            .map(|wrapper| &wrapper.inner)
+    }
+
     /// Create an entity and return the handle to manage it.
-    pub fn create_entity<E: Entity + 'static>(&mut self, entity: E) -> Handle<E> {
+    pub fn create_entity<E: Entity + Send + 'static>(&mut self, entity: E) -> Handle<E> {
 
         // Generate a new unique entity id.
         let entity_id = loop {
@@ -170,16 +471,98 @@ impl App {
             }
         };
 
-        // self.entities.insert(entity_id, EntityGeneric {
-        //     wrapper: Box::new(EntityWrapperImpl {
-        //         inner: entity,
-        //     })
-        // });
+        self.entities.insert(entity_id, EntityGeneric {
+            wrapper: Box::new(EntityWrapperImpl {
+                inner: entity,
+            })
+        });
 
-        todo!()
+        Handle {
+            entity_id,
+            _phantom: PhantomData,
+        }
 
     }
 
+    /// Add an entity to the set of entities of the client at the given address, a 
+    /// client can have many entities, such as an account and an avatar, but only the
+    /// selected one receives base entity methods, see [`Self::select_entity`]. Returns
+    /// false if the entity was already added to this client.
+    pub fn add_client_entity<E: Entity>(&mut self, addr: SocketAddr, handle: Handle<E>) -> bool {
+        let client_entities = self.client_entities.entry(addr).or_default();
+        if client_entities.entities.contains(&handle.entity_id) {
+            false
+        } else {
+            client_entities.entities.push(handle.entity_id);
+            true
+        }
+    }
+
+    /// Remove an entity from the set of entities of the client at the given address, 
+    /// if this entity was selected then no entity is selected anymore. Returns false if 
+    /// the entity was not added to this client.
+    pub fn remove_client_entity<E: Entity>(&mut self, addr: SocketAddr, handle: Handle<E>) -> bool {
+        let Some(client_entities) = self.client_entities.get_mut(&addr) else {
+            return false;
+        };
+        let Some(index) = client_entities.entities.iter().position(|&id| id == handle.entity_id) else {
+            return false;
+        };
+        client_entities.entities.remove(index);
+        if client_entities.selected == Some(handle.entity_id) {
+            client_entities.selected = None;
+        }
+        true
+    }
+
+    /// Iterate over the ids of the entities of the client at the given address, in the
+    /// order they have been added.
+    pub fn client_entity_ids(&self, addr: SocketAddr) -> impl Iterator<Item = u32> + '_ {
+        self.client_entities.get(&addr)
+            .into_iter()
+            .flat_map(|client_entities| client_entities.entities.iter().copied())
+    }
+
+    /// Set the player entity of the client at the given address, base entity methods
+    /// called by this client are then targeting this entity, see [`Event::BaseMethod`].
+    /// The entity is added to the client's entities if needed, but contrary to
+    /// [`Self::select_entity`] the client is not notified.
+    pub fn set_player_entity<E: Entity>(&mut self, addr: SocketAddr, handle: Handle<E>) {
+        self.add_client_entity(addr, handle);
+        self.client_entities.get_mut(&addr).unwrap().selected = Some(handle.entity_id);
+    }
+
+    /// Select the player entity of the client at the given address, like 
+    /// [`Self::set_player_entity`], and make it the client's player. The select player
+    /// entity element doesn't carry the entity, the client selects the entity of the
+    /// last create base player element it received, so this element is sent first for
+    /// the given entity, with the given entity type id and without entity components.
+    /// Both elements are sent on the client's channel. A not found error is returned
+    /// if the entity doesn't exist.
+    pub fn select_entity<E: Entity + 'static>(&mut self, addr: SocketAddr, handle: Handle<E>, entity_type_id: u16) -> io::Result<()> {
+        
+        let entity = self.entity(handle)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "entity not found"))?;
+        
+        let mut data = Vec::new();
+        write_create_base_player(&mut data, handle.entity_id, entity_type_id, entity, 0)?;
+
+        self.set_player_entity(addr, handle);
+        self.bundle.clear();
+        self.bundle.element_writer().write(RawElement { id: CreateBasePlayerHeader::ID, data }, &CreateBasePlayerHeader::LEN);
+        self.bundle.element_writer().write_simple(SelectPlayerEntity {});
+        self.protocol.channel(addr, None).prepare(&mut self.bundle, true);
+        self.socket.send_bundle(&self.bundle, addr)?;
+        Ok(())
+    }
+
+    /// Get the player entity id of the client at the given address, if any, this is
+    /// the selected entity of the client.
+    #[inline]
+    pub fn player_entity_id(&self, addr: SocketAddr) -> Option<u32> {
+        self.client_entities.get(&addr)?.selected
+    }
+
     /// Call a method on an entity present on the given client address and its handle.
     pub fn call_method<E: Entity>(&mut self, addr: SocketAddr, handle: Handle<E>, method: E::ClientMethod) {
         let _ = (addr, handle, method);
@@ -193,7 +576,9 @@ impl App {
 pub enum Event {
     IoError(IoErrorEvent),
     Login(LoginEvent),
-    // BaseMethod(BaseMethodEvent),
+    LoginRejected(LoginRejectedEvent),
+    BaseMethod(BaseMethodEvent),
+    ClientTimeout(ClientTimeoutEvent),
 }
 
 /// Some IO error happened internally and optionally related to a client.
@@ -205,37 +590,224 @@ pub struct IoErrorEvent {
     pub addr: Option<SocketAddr>,
 }
 
-/// A client is trying to connect.
+/// A client is trying to connect with a valid login key, it should be answered with
+/// [`App::answer_login_success`].
 #[derive(Debug)]
 pub struct LoginEvent {
     /// The address of the client that pinged the login app.
     pub addr: SocketAddr,
     /// The given client from the given address
     pub login_key: u32,
-    /// The attempt number.
+    /// The attempt number, see [`LoginKey::attempt_num`].
     pub attempt_num: u8,
+    /// The unknown trailing value, see [`LoginKey::unk`].
+    pub unk: u16,
+    /// The blowfish key registered with the login key, see [`LoginKeys::add`].
+    pub blowfish: Arc<Blowfish>,
 }
 
+/// A client tried to connect with an invalid login key, the client is not answered.
+#[derive(Debug)]
+pub struct LoginRejectedEvent {
+    /// The address of the client.
+    pub addr: SocketAddr,
+    /// The login key given by the client.
+    pub login_key: u32,
+    /// The attempt number, see [`LoginKey::attempt_num`].
+    pub attempt_num: u8,
+    /// The reason for rejecting the login key.
+    pub reason: LoginRejectReason,
+}
+
+/// The reason for rejecting a login key, see [`LoginRejectedEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginRejectReason {
+    /// The login key has not been registered.
+    Unknown,
+    /// The login key has been registered but has expired before being used.
+    Expired,
+    /// The login key has been registered for another client address.
+    WrongAddress,
+    /// The login key has already been used, this is likely a replay.
+    Replayed,
+}
+
+/// A registry of the login keys given to clients by the login app, login keys are 
+/// single-use, bound to the address of the client and expire after a timeout. This
+/// registry is shared between clones, so that it can be filled from the thread of the
+/// login app while the base app uses it to validate login keys.
+#[derive(Debug, Clone)]
+pub struct LoginKeys {
+    inner: Arc<Mutex<LoginKeysInner>>,
+}
+
+#[derive(Debug)]
+struct LoginKeysInner {
+    /// Timeout of login keys, both for registered and used keys.
+    timeout: Duration,
+    /// Login keys registered but not yet used.
+    registered: HashMap<u32, RegisteredLoginKey>,
+    /// Login keys already used, associated to the instant they are forgotten, they are
+    /// kept in order to distinguish replayed login keys from unknown ones.
+    used: HashMap<u32, Instant>,
+}
+
+#[derive(Debug)]
+struct RegisteredLoginKey {
+    addr: SocketAddr,
+    blowfish: Arc<Blowfish>,
+    expires: Instant,
+}
+
+impl LoginKeys {
+
+    /// Default timeout of login keys.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Create a new empty registry with the default timeout.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(LoginKeysInner {
+                timeout: Self::DEFAULT_TIMEOUT,
+                registered: HashMap::new(),
+                used: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Get the timeout of login keys.
+    pub fn timeout(&self) -> Duration {
+        self.inner.lock().unwrap().timeout
+    }
+
+    /// Set the timeout of login keys, this applies to login keys registered after.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.inner.lock().unwrap().timeout = timeout;
+    }
+
+    /// Register a login key given to the client at the given address, with the blowfish
+    /// key used to encrypt its communications. This returns false if the login key is
+    /// already registered or has been used recently, in such case another key should
+    /// be generated.
+    pub fn add(&self, login_key: u32, addr: SocketAddr, blowfish: Arc<Blowfish>) -> bool {
+
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        inner.purge(now);
+
+        if inner.registered.contains_key(&login_key) || inner.used.contains_key(&login_key) {
+            return false;
+        }
+
+        let expires = now + inner.timeout;
+        inner.registered.insert(login_key, RegisteredLoginKey { addr, blowfish, expires });
+        true
+
+    }
+
+    /// Take the given login key used by the client at the given address, if valid it
+    /// is marked as used and its blowfish key is returned.
+    fn take(&self, login_key: u32, addr: SocketAddr, now: Instant) -> Result<Arc<Blowfish>, LoginRejectReason> {
+
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.used.get(&login_key).is_some_and(|&forget| forget > now) {
+            return Err(LoginRejectReason::Replayed);
+        }
+
+        let Some(registered) = inner.registered.get(&login_key) else {
+            return Err(LoginRejectReason::Unknown);
+        };
+
+        if registered.expires <= now {
+            inner.registered.remove(&login_key);
+            return Err(LoginRejectReason::Expired);
+        } else if registered.addr != addr {
+            return Err(LoginRejectReason::WrongAddress);
+        }
+
+        let registered = inner.registered.remove(&login_key).unwrap();
+        let forget = now + inner.timeout;
+        inner.used.insert(login_key, forget);
+        inner.purge(now);
+        Ok(registered.blowfish)
+
+    }
+
+}
+
+impl LoginKeysInner {
+
+    /// Forget about expired registered login keys and old used login keys.
+    fn purge(&mut self, now: Instant) {
+        self.registered.retain(|_, registered| registered.expires > now);
+        self.used.retain(|_, &mut forget| forget > now);
+    }
+
+}
+
+impl Default for LoginKeys {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A client has not sent any packet for the client timeout, it has been forgotten 
+/// along with its entities, see [`App::set_client_timeout`].
+#[derive(Debug)]
+pub struct ClientTimeoutEvent {
+    /// The address of the client.
+    pub addr: SocketAddr,
+    /// The ids of the client's entities that have been removed.
+    pub entity_ids: Vec<u32>,
+}
+
+/// A client called a method on its player entity.
 #[derive(Debug)]
 pub struct BaseMethodEvent {
+    /// The address of the client that called the method.
     pub addr: SocketAddr,
+    /// The id of the entity the method is called on, this is the client's player entity,
+    /// see [`App::set_player_entity`].
     pub entity_id: u32,
-
+    /// The exposed index of the method.
+    pub method_index: u16,
+    /// The raw encoded arguments of the method.
+    pub body: Vec<u8>,
 }
 
 /// A typed handle to an entity in the base app, potentially present on client side.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub struct Handle<E> {
     entity_id: u32,
     _phantom: PhantomData<*const E>,
 }
 
+// Not derived because the entity type doesn't need to be copied.
+impl<E> Clone for Handle<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for Handle<E> {}
+
 /// A untyped handle to an entity in the base app, potentially present on client side.
 #[derive(Debug, Clone, Copy)]
 pub struct GenericHandle {
     entity_id: u32,
 }
 
+/// The entities of a client and the selected one.
+#[derive(Debug, Default)]
+struct ClientEntities {
+    /// Ids of all entities of the client, in the order they have been added.
+    entities: Vec<u32>,
+    /// Id of the selected entity, receiving base entity methods.
+    selected: Option<u32>,
+}
+
 /// An active logged in client in the base application.
 #[derive(Debug)]
 struct Client {
@@ -248,7 +820,7 @@ struct Client {
 
 
 struct EntityGeneric {
-    // wrapper: Box<dyn EntityWrapper>,
+    wrapper: Box<dyn EntityWrapper>,
 }
 
 impl fmt::Debug for EntityGeneric {
@@ -257,7 +829,9 @@ impl fmt::Debug for EntityGeneric {
     }
 }
 
-trait EntityWrapper {
+trait EntityWrapper: Send {
+
+    fn as_any(&self) -> &dyn Any;
 
 }
 
@@ -265,6 +839,287 @@ struct EntityWrapperImpl<E: Entity> {
     inner: E,
 }
 
-impl<E: Entity> EntityWrapper for EntityWrapperImpl<E> {
+impl<E: Entity + Send + 'static> EntityWrapper for EntityWrapperImpl<E> {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use crypto_common::KeyInit;
+    use super::*;
+
+    #[test]
+    fn update_frequency_and_clock() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.set_update_frequency(20);
+        assert_eq!(app.update_frequency_notification().frequency, 20);
+
+        let time = Arc::new(AtomicU32::new(255));
+        let clock_time = Arc::clone(&time);
+        app.set_clock(move || clock_time.load(Ordering::Relaxed));
+
+        let notif = app.update_frequency_notification();
+        assert_eq!(notif.game_time, 255);
+        assert_eq!(app.tick_sync().tick, 255);
+
+        time.store(256, Ordering::Relaxed);
+        assert_eq!(app.tick_sync().tick, 255u8.wrapping_add(1));
+
+    }
+
+    #[test]
+    fn update_frequency_rebase() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.time_origin.0 -= Duration::from_secs(10);
+        assert!((100..=101).contains(&app.game_time()));
+
+        // Doubling the frequency must not double the elapsed game time.
+        app.set_update_frequency(20);
+        assert!((100..=102).contains(&app.game_time()));
+
+        app.time_origin.0 -= Duration::from_secs(1);
+        assert!((120..=122).contains(&app.game_time()));
+
+    }
+
+    #[test]
+    fn login_key_replay() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = "127.0.0.1:20018".parse().unwrap();
+        let other_addr = "127.0.0.1:20019".parse().unwrap();
+
+        let blowfish = Arc::new(Blowfish::new_from_slice(&[0; 16]).unwrap());
+        assert!(app.login_keys().add(0x1234, addr, Arc::clone(&blowfish)));
+        assert!(!app.login_keys().add(0x1234, addr, Arc::clone(&blowfish)));
+
+        let auth = |app: &mut App, addr: SocketAddr, login_key: u32, request_id: u32| {
+            let mut bundle = Bundle::new();
+            bundle.element_writer().write_simple_request(LoginKey { login_key, attempt_num: 0, unk: 0 }, request_id);
+            let mut reader = bundle.element_reader();
+            let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+            app.handle_element(addr, elt).unwrap();
+            app.events.pop_front()
+        };
+
+        let Some(Event::LoginRejected(event)) = auth(&mut app, addr, 0x5678, 1) else { panic!() };
+        assert_eq!(event.reason, LoginRejectReason::Unknown);
+        let Some(Event::LoginRejected(event)) = auth(&mut app, other_addr, 0x1234, 1) else { panic!() };
+        assert_eq!(event.reason, LoginRejectReason::WrongAddress);
+
+        let Some(Event::Login(event)) = auth(&mut app, addr, 0x1234, 2) else { panic!() };
+        assert_eq!(event.login_key, 0x1234);
+        assert!(Arc::ptr_eq(&event.blowfish, &blowfish));
+
+        // Resending while not answered is not a replay.
+        assert!(auth(&mut app, addr, 0x1234, 3).is_none());
+        assert_eq!(app.pending_clients[&addr], (3, 0x1234));
+        assert!(app.answer_login_success(addr, Arc::clone(&blowfish)));
+
+        let Some(Event::LoginRejected(event)) = auth(&mut app, addr, 0x1234, 4) else { panic!() };
+        assert_eq!(event.reason, LoginRejectReason::Replayed);
+        assert!(!app.login_keys().add(0x1234, addr, Arc::clone(&blowfish)));
+
+        // Expired login key.
+        app.login_keys().set_timeout(Duration::ZERO);
+        assert!(app.login_keys().add(0x9ABC, addr, Arc::clone(&blowfish)));
+        let Some(Event::LoginRejected(event)) = auth(&mut app, addr, 0x9ABC, 5) else { panic!() };
+        assert_eq!(event.reason, LoginRejectReason::Expired);
+
+    }
+
+    #[test]
+    fn base_method() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = "127.0.0.1:20018".parse().unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(RawBaseEntityMethod {
+            method_index: 5,
+            body: vec![1, 2, 3],
+        });
+
+        let handle = |app: &mut App, bundle: &Bundle| {
+            let mut reader = bundle.element_reader();
+            let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+            app.handle_element(addr, elt)
+        };
+
+        // No player entity for this client.
+        assert!(handle(&mut app, &bundle).is_err());
+
+        app.client_entities.insert(addr, ClientEntities { entities: vec![1234], selected: Some(1234) });
+        handle(&mut app, &bundle).unwrap();
+
+        let Some(Event::BaseMethod(event)) = app.events.pop_front() else { panic!() };
+        assert_eq!(event.addr, addr);
+        assert_eq!(event.entity_id, 1234);
+        assert_eq!(event.method_index, 5);
+        assert_eq!(event.body, [1, 2, 3]);
+
+    }
+
+    /// An entity without any method.
+    #[derive(Debug)]
+    struct Dummy;
+
+    #[derive(Debug)]
+    enum NoMethod {}
+
+    impl crate::net::app::common::entity::Method for NoMethod {
+        fn write_length(&self) -> crate::net::element::ElementLength { match *self {} }
+        fn write(&self, _write: &mut dyn io::Write) -> io::Result<u16> { match *self {} }
+        fn read_length(_exposed_id: u16) -> crate::net::element::ElementLength { crate::net::element::ElementLength::ZERO }
+        fn read(_read: &mut dyn io::Read, exposed_id: u16) -> io::Result<Self> { Err(io_invalid_data(format_args!("no method #{exposed_id}"))) }
+    }
+
+    impl Entity for Dummy {
+        type ClientMethod = NoMethod;
+        type BaseMethod = NoMethod;
+        type CellMethod = NoMethod;
+        fn write(&self, _write: &mut dyn io::Write) -> io::Result<()> { Ok(()) }
+        fn read(_read: &mut dyn io::Read) -> io::Result<Self> { Ok(Self) }
+    }
+
+    /// Another entity type, to check typed access.
+    #[derive(Debug, PartialEq)]
+    struct Counter(u32);
+
+    impl Entity for Counter {
+        type ClientMethod = NoMethod;
+        type BaseMethod = NoMethod;
+        type CellMethod = NoMethod;
+        fn write(&self, _write: &mut dyn io::Write) -> io::Result<()> { Ok(()) }
+        fn read(_read: &mut dyn io::Read) -> io::Result<Self> { Ok(Self(0)) }
+    }
+
+    #[test]
+    fn entity_storage() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_eq!(app.entity_count(), 0);
+        assert_eq!(app.entity_ids().count(), 0);
+
+        let dummy = app.create_entity(Dummy);
+        let counter = app.create_entity(Counter(7));
+        assert_ne!(dummy.entity_id, counter.entity_id);
+        assert_eq!(app.entity_count(), 2);
+
+        let mut ids = app.entity_ids().collect::<Vec<_>>();
+        ids.sort_unstable();
+        let mut expected = vec![dummy.entity_id, counter.entity_id];
+        expected.sort_unstable();
+        assert_eq!(ids, expected);
+
+        assert!(app.entity(dummy).is_some());
+        assert_eq!(app.entity(counter), Some(&Counter(7)));
+
+        // A handle of the wrong type, or to an unknown entity, gives nothing.
+        let wrong = Handle::<Dummy> { entity_id: counter.entity_id, _phantom: PhantomData };
+        assert!(app.entity(wrong).is_none());
+        let unknown = Handle::<Counter> { entity_id: 0, _phantom: PhantomData };
+        assert!(!ids.contains(&0));
+        assert!(app.entity(unknown).is_none());
+
+    }
+
+    #[test]
+    fn select_entity() {
+
+        use super::super::client::element::CreateBasePlayer;
+
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let addr = client.addr().unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let account = app.create_entity(Dummy);
+        let avatar = app.create_entity(Dummy);
+        assert!(app.add_client_entity(addr, account));
+        assert!(app.clients_activity.is_empty());
+        assert!(app.add_client_entity(addr, avatar));
+        assert!(!app.add_client_entity(addr, avatar));
+        assert_eq!(app.client_entity_ids(addr).collect::<Vec<_>>(), [account.entity_id, avatar.entity_id]);
+        assert_eq!(app.player_entity_id(addr), None);
+
+        let unknown = Handle::<Dummy> { entity_id: 0, _phantom: PhantomData };
+        assert_eq!(app.select_entity(addr, unknown, 1).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(app.player_entity_id(addr), None);
+
+        app.select_entity(addr, avatar, 3).unwrap();
+        assert_eq!(app.player_entity_id(addr), Some(avatar.entity_id));
+
+        // The entity is created on the client before being selected.
+        let (packet, _) = client.recv_without_encryption().unwrap();
+        let bundle = Bundle::new_with_single(packet.read_config_locked().unwrap());
+        let mut reader = bundle.element_reader();
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        let create = elt.read_simple::<CreateBasePlayer<Dummy>>().unwrap();
+        assert_eq!((create.element.entity_id, create.element.entity_type_id), (avatar.entity_id, 3));
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        elt.read_simple::<SelectPlayerEntity>().unwrap();
+        assert!(reader.next().is_none());
+
+        // Base methods sent by the client are targeting the selected entity.
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(RawBaseEntityMethod { method_index: 1, body: vec![7] });
+        Protocol::new().off_channel(app.addr().unwrap()).prepare(&mut bundle, false);
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+        let Event::BaseMethod(event) = app.poll() else { panic!() };
+        assert_eq!((event.addr, event.entity_id, event.method_index), (addr, avatar.entity_id, 1));
+        assert_eq!(event.body, [7]);
+
+        assert!(app.remove_client_entity(addr, avatar));
+        assert_eq!(app.player_entity_id(addr), None);
+        assert_eq!(app.client_entity_ids(addr).collect::<Vec<_>>(), [account.entity_id]);
+
+    }
+
+    #[test]
+    fn client_timeout() {
+
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = client.addr().unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_eq!(app.client_timeout(), None);
+        app.set_client_timeout(Some(Duration::from_millis(100)));
+        let app_addr = app.addr().unwrap();
+
+        let blowfish = Arc::new(Blowfish::new_from_slice(&[0; 16]).unwrap());
+        assert!(app.login_keys().add(0x1234, addr, blowfish));
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple_request(LoginKey { login_key: 0x1234, attempt_num: 0, unk: 0 }, 1);
+        app.protocol.off_channel(addr).prepare(&mut bundle, false);
+        client.send_bundle_without_encryption(&bundle, app_addr).unwrap();
+
+        let Event::Login(_) = app.poll() else { panic!() };
+        let entity = app.create_entity(Dummy);
+        app.set_player_entity(addr, entity);
+        assert_eq!(app.entity_count(), 1);
+
+        // The client stops sending anything.
+        let start = Instant::now();
+        let Event::ClientTimeout(event) = app.poll() else { panic!() };
+        assert!(start.elapsed() < MAX_RECV_TIMEOUT);
+        assert_eq!(event.addr, addr);
+        assert_eq!(event.entity_ids, [entity.entity_id]);
+        assert_eq!(app.entity_count(), 0);
+        assert_eq!(app.player_entity_id(addr), None);
+        assert!(app.pending_clients.is_empty());
+        assert!(app.clients_activity.is_empty());
+
+    }
 
 }
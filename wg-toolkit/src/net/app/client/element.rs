@@ -3,12 +3,13 @@
 
 use std::fmt;
 use std::io::{self, Read, Write};
+use std::net::SocketAddrV4;
 
 use glam::Vec3;
 
 use tracing::warn;
 
-use crate::net::element::{DebugElementFixed, DebugElementVariable16, ElementLength, Element, SimpleElement};
+use crate::net::element::{DebugElementFixed, DebugElementVariable16, ElementLength, Element, ElementContext, SimpleElement};
 use crate::util::io::{WgReadExt, WgWriteExt};
 use crate::net::codec::SimpleCodec;
 use crate::util::AsciiFmt;
@@ -106,6 +107,102 @@ pub mod id {
 }
 
 
+/// The element context of the client application, used to debug bundles sent by the 
+/// base application to the client, see [`ElementContext`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientContext;
+
+impl ElementContext for ClientContext {
+
+    fn element_info(&self, id: u8) -> Option<(&'static str, Option<ElementLength>)> {
+        Some(match id {
+            id::AUTHENTICATE                                        => ("Authenticate", Some(Authenticate::LEN)),
+            id::BANDWIDTH_NOTIFICATION                              => ("BandwidthNotification", Some(BandwidthNotification::LEN)),
+            id::UPDATE_FREQUENCY_NOTIFICATION                       => ("UpdateFrequencyNotification", Some(UpdateFrequencyNotification::LEN)),
+            id::SET_GAME_TIME                                       => ("SetGameTime", Some(SetGameTime::LEN)),
+            id::RESET_ENTITIES                                      => ("ResetEntities", Some(ResetEntities::LEN)),
+            id::CREATE_BASE_PLAYER                                  => ("CreateBasePlayer", Some(CreateBasePlayerHeader::LEN)),
+            id::CREATE_CELL_PLAYER                                  => ("CreateCellPlayer", Some(CreateCellPlayer::LEN)),
+            id::DUMMY_PACKET                                        => ("DummyPacket", Some(ElementLength::Variable16)),
+            id::SPACE_PROPERTY                                      => ("SpaceProperty", Some(ElementLength::Variable16)),
+            id::ADD_SPACE_GEOMETRY_MAPPING                          => ("AddSpaceGeometryMapping", Some(ElementLength::Variable16)),
+            id::REMOVE_SPACE_GEOMETRY_MAPPING                       => ("RemoveSpaceGeometryMapping", Some(ElementLength::Variable16)),
+            id::CREATE_ENTITY                                       => ("CreateEntity", Some(ElementLength::Variable16)),
+            id::CREATE_ENTITY_DETAILED                              => ("CreateEntityDetailed", Some(ElementLength::Variable16)),
+            id::CELL_APP_SUSPENDED                                  => ("CellAppSuspended", Some(ElementLength::ZERO)),
+            id::CELL_APP_RESUMED                                    => ("CellAppResumed", Some(ElementLength::ZERO)),
+            id::CLIENT_SUSPENSION_DETECTION_ENABLED                 => ("ClientSuspensionDetectionEnabled", Some(ElementLength::Fixed(4))),
+            id::ENTER_AOI                                           => ("EnterAoi", Some(ElementLength::Fixed(5))),
+            id::ENTER_AOI_ON_VEHICLE                                => ("EnterAoiOnVehicle", Some(ElementLength::Fixed(9))),
+            id::LEAVE_AOI                                           => ("LeaveAoi", Some(ElementLength::Variable16)),
+            id::TICK_SYNC                                           => ("TickSync", Some(TickSync::LEN)),
+            id::TICK_SYNC_PERIODIC                                  => ("TickSyncPeriodic", Some(ElementLength::Fixed(2))),
+            id::RELATIVE_POSITION_REFERENCE                         => ("RelativePositionReference", Some(ElementLength::Fixed(1))),
+            id::RELATIVE_POSITION                                   => ("RelativePosition", Some(ElementLength::Fixed(12))),
+            id::SET_VEHICLE                                         => ("SetVehicle", Some(ElementLength::Fixed(8))),
+            id::SELECT_ALIASED_ENTITY                               => ("SelectAliasedEntity", Some(ElementLength::Fixed(1))),
+            id::SELECT_ENTITY                                       => ("SelectEntity", Some(ElementLength::Fixed(4))),
+            id::SELECT_PLAYER_ENTITY                                => ("SelectPlayerEntity", Some(SelectPlayerEntity::LEN)),
+            id::FORCED_POSITION                                     => ("ForcedPosition", Some(ForcedPosition::LEN)),
+            id::AVATAR_UPDATE_NO_ALIAS_DETAILED                     => ("AvatarUpdateNoAliasDetailed", Some(ElementLength::Fixed(29))),
+            id::AVATAR_UPDATE_ALIAS_DETAILED                        => ("AvatarUpdateAliasDetailed", Some(ElementLength::Fixed(26))),
+            id::AVATAR_UPDATE_PLAYER_DETAILED                       => ("AvatarUpdatePlayerDetailed", Some(ElementLength::Fixed(25))),
+            id::AVATAR_UPDATE_VOLATILE_PROPERTIES                   => ("AvatarUpdateVolatileProperties", Some(ElementLength::Variable16)),
+            id::CHANGE_VOLATILE_PACKER_TYPE                         => ("ChangeVolatilePackerType", Some(ElementLength::Variable16)),
+            id::NRL_CREATE_NODE                                     => ("NrlCreateNode", Some(ElementLength::Variable16)),
+            id::NRL_UNLINK_TREE                                     => ("NrlUnlinkTree", Some(ElementLength::Variable16)),
+            id::NRL_UPDATE_NODE                                     => ("NrlUpdateNode", Some(ElementLength::Variable16)),
+            id::NRL_UNLINK_TREE_FLAG                                => ("NrlUnlinkTreeFlag", Some(ElementLength::ZERO)),
+            id::NRL_UPDATE_NODE_FLAG                                => ("NrlUpdateNodeFlag", Some(ElementLength::ZERO)),
+            id::NRL_DATA                                            => ("NrlData", Some(ElementLength::Variable16)),
+            id::NRL_MSG_TO_CLIENT                                   => ("NrlMsgToClient", Some(ElementLength::Variable16)),
+            id::NRL_UNRELIABLE_MSG_TO_CLIENT                        => ("NrlUnreliableMsgToClient", Some(ElementLength::Variable16)),
+            id::AVATAR_UPDATE_NO_ALIAS_FULL_POS_YAW_PITCH_ROLL      => ("AvatarUpdateNoAliasFullPosYawPitchRoll", None),
+            id::AVATAR_UPDATE_NO_ALIAS_FULL_POS_YAW_PITCH           => ("AvatarUpdateNoAliasFullPosYawPitch", None),
+            id::AVATAR_UPDATE_NO_ALIAS_FULL_POS_YAW                 => ("AvatarUpdateNoAliasFullPosYaw", None),
+            id::AVATAR_UPDATE_NO_ALIAS_FULL_POS_NO_DIR              => ("AvatarUpdateNoAliasFullPosNoDir", None),
+            id::AVATAR_UPDATE_NO_ALIAS_ON_GROUND_YAW_PITCH_ROLL     => ("AvatarUpdateNoAliasOnGroundYawPitchRoll", None),
+            id::AVATAR_UPDATE_NO_ALIAS_ON_GROUND_YAW_PITCH          => ("AvatarUpdateNoAliasOnGroundYawPitch", None),
+            id::AVATAR_UPDATE_NO_ALIAS_ON_GROUND_YAW                => ("AvatarUpdateNoAliasOnGroundYaw", None),
+            id::AVATAR_UPDATE_NO_ALIAS_ON_GROUND_NO_DIR             => ("AvatarUpdateNoAliasOnGroundNoDir", None),
+            id::AVATAR_UPDATE_NO_ALIAS_NO_POS_YAW_PITCH_ROLL        => ("AvatarUpdateNoAliasNoPosYawPitchRoll", None),
+            id::AVATAR_UPDATE_NO_ALIAS_NO_POS_YAW_PITCH             => ("AvatarUpdateNoAliasNoPosYawPitch", None),
+            id::AVATAR_UPDATE_NO_ALIAS_NO_POS_YAW                   => ("AvatarUpdateNoAliasNoPosYaw", None),
+            id::AVATAR_UPDATE_NO_ALIAS_NO_POS_NO_DIR                => ("AvatarUpdateNoAliasNoPosNoDir", None),
+            id::AVATAR_UPDATE_ALIAS_FULL_POS_YAW_PITCH_ROLL         => ("AvatarUpdateAliasFullPosYawPitchRoll", None),
+            id::AVATAR_UPDATE_ALIAS_FULL_POS_YAW_PITCH              => ("AvatarUpdateAliasFullPosYawPitch", None),
+            id::AVATAR_UPDATE_ALIAS_FULL_POS_YAW                    => ("AvatarUpdateAliasFullPosYaw", None),
+            id::AVATAR_UPDATE_ALIAS_FULL_POS_NO_DIR                 => ("AvatarUpdateAliasFullPosNoDir", None),
+            id::AVATAR_UPDATE_ALIAS_ON_GROUND_YAW_PITCH_ROLL        => ("AvatarUpdateAliasOnGroundYawPitchRoll", None),
+            id::AVATAR_UPDATE_ALIAS_ON_GROUND_YAW_PITCH             => ("AvatarUpdateAliasOnGroundYawPitch", None),
+            id::AVATAR_UPDATE_ALIAS_ON_GROUND_YAW                   => ("AvatarUpdateAliasOnGroundYaw", None),
+            id::AVATAR_UPDATE_ALIAS_ON_GROUND_NO_DIR                => ("AvatarUpdateAliasOnGroundNoDir", None),
+            id::AVATAR_UPDATE_ALIAS_NO_POS_YAW_PITCH_ROLL           => ("AvatarUpdateAliasNoPosYawPitchRoll", None),
+            id::AVATAR_UPDATE_ALIAS_NO_POS_YAW_PITCH                => ("AvatarUpdateAliasNoPosYawPitch", None),
+            id::AVATAR_UPDATE_ALIAS_NO_POS_YAW                      => ("AvatarUpdateAliasNoPosYaw", None),
+            id::AVATAR_UPDATE_ALIAS_NO_POS_NO_DIR                   => ("AvatarUpdateAliasNoPosNoDir", None),
+            id::CONTROL_ENTITY                                      => ("ControlEntity", Some(ElementLength::Fixed(5))),
+            id::VOICE_DATA                                          => ("VoiceData", Some(ElementLength::Variable16)),
+            id::RESTORE_CLIENT                                      => ("RestoreClient", Some(ElementLength::Variable16)),
+            id::SWITCH_BASE_APP                                     => ("SwitchBaseApp", Some(SwitchBaseApp::LEN)),
+            id::RESOURCE_HEADER                                     => ("ResourceHeader", Some(ResourceHeader::LEN)),
+            id::RESOURCE_FRAGMENT                                   => ("ResourceFragment", Some(ResourceFragment::LEN)),
+            id::LOGGED_OFF                                          => ("LoggedOff", Some(LoggedOff::LEN)),
+            id::DETAILED_POSITION                                   => ("DetailedPosition", Some(ElementLength::Fixed(24))),
+            id::NESTED_ENTITY_PROPERTY                              => ("NestedEntityProperty", Some(ElementLength::Variable16)),
+            id::SLICE_ENTITY_PROPERTY                               => ("SliceEntityProperty", Some(ElementLength::Variable16)),
+            id::UPDATE_ENTITY                                       => ("UpdateEntity", Some(ElementLength::Variable16)),
+            id::SET_CELL_APP_EXT_ADDRESS                            => ("SetCellAppExtAddress", Some(ElementLength::Variable16)),
+            id::LAST_PROXY_MESSAGE_AFTER_DIRECT_CELL_APP_CONNECTION => ("LastProxyMessageAfterDirectCellAppConnection", Some(ElementLength::ZERO)),
+            _ if id::ENTITY_METHOD.contains(id)                     => ("EntityMethod", None),
+            _ if id::ENTITY_PROPERTY.contains(id)                   => ("EntityProperty", None),
+            _                                                       => return None,
+        })
+    }
+
+}
+
+
 crate::__struct_simple_codec! {
     #[derive(Debug, Clone)]
     pub struct Authenticate {
@@ -240,11 +337,7 @@ pub struct CreateBasePlayer<E: Entity> {
 impl<E: Entity> SimpleCodec for CreateBasePlayer<E> {
 
     fn write(&self, write: &mut dyn Write) -> io::Result<()> {
-        write.write_u32(self.entity_id)?;
-        write.write_u16(self.entity_type_id)?;
-        write.write_blob_variable(&[])?;  // Unknown blob or string?
-        self.entity_data.write(&mut *write)?;
-        write.write_u8(self.entity_components_count)
+        write_create_base_player(write, self.entity_id, self.entity_type_id, &*self.entity_data, self.entity_components_count)
     }
 
     fn read(read: &mut dyn Read) -> io::Result<Self> {
@@ -269,8 +362,70 @@ impl<E: Entity> SimpleElement for CreateBasePlayer<E> {
     const LEN: ElementLength = ElementLength::Variable16;
 }
 
+/// Write the body of a [`CreateBasePlayer`] element from a borrowed entity.
+pub(crate) fn write_create_base_player<E: Entity>(write: &mut dyn Write, entity_id: u32, entity_type_id: u16, entity: &E, entity_components_count: u8) -> io::Result<()> {
+    write.write_u32(entity_id)?;
+    write.write_u16(entity_type_id)?;
+    write.write_blob_variable(&[])?;  // Unknown blob or string?
+    entity.write(&mut *write)?;
+    write.write_u8(entity_components_count)
+}
+
+
+/// Sent from the cell when the player entity, previously created with 
+/// [`CreateBasePlayer`], enters its space. The entity id is not sent because it is 
+/// always the player entity.
+/// 
+/// The remaining data contains the player's cell properties sent to the client, it
+/// is kept raw because its layout depends on the entity type.
+/// 
+/// **Note that this layout is derived from BigWorld's `createCellPlayer` arguments and
+/// has not been verified against captured traffic yet**, the field order and the 
+/// encoding of the direction may be wrong for this client.
+#[derive(Debug, Clone)]
+pub struct CreateCellPlayer {
+    /// The space the player entity is created in.
+    pub space_id: u32,
+    /// The vehicle entity the player is on, or 0 if none, the position and direction
+    /// are relative to the vehicle if any.
+    pub vehicle_entity_id: u32,
+    /// The position, encoded as x, y, z floats.
+    pub position: Vec3,
+    /// The direction, encoded as roll, pitch, yaw floats (in radians), respectively
+    /// in x, y, z components.
+    pub direction: Vec3,
+    /// The raw cell properties of the player entity.
+    pub entity_data: Vec<u8>,
+}
+
+impl SimpleCodec for CreateCellPlayer {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_u32(self.space_id)?;
+        write.write_u32(self.vehicle_entity_id)?;
+        write.write_vec3(self.position)?;
+        write.write_vec3(self.direction)?;
+        write.write_all(&self.entity_data)
+    }
+
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        Ok(Self {
+            space_id: read.read_u32()?,
+            vehicle_entity_id: read.read_u32()?,
+            position: read.read_vec3()?,
+            direction: read.read_vec3()?,
+            entity_data: read.read_blob_to_end()?,
+        })
+    }
+
+}
+
+impl SimpleElement for CreateCellPlayer {
+    const ID: u8 = id::CREATE_CELL_PLAYER;
+    const LEN: ElementLength = ElementLength::Variable16;
+}
+
 
-pub type CreateCellPlayer = DebugElementVariable16<{ id::CREATE_CELL_PLAYER }>;
 pub type DummyPacket = DebugElementVariable16<{ id::DUMMY_PACKET }>;
 pub type SpaceProperty = DebugElementVariable16<{ id::SPACE_PROPERTY }>;
 pub type AddSpaceGeometryMapping = DebugElementVariable16<{ id::ADD_SPACE_GEOMETRY_MAPPING }>;
@@ -330,11 +485,20 @@ crate::__struct_simple_codec! {
     /// reason decided by the server (e.g. server-initiated teleport).
     #[derive(Debug, Clone)]
     pub struct ForcedPosition {
+        /// The entity being moved.
         pub entity_id: u32,
+        /// The space the entity is moved to.
         pub space_id: u32,
+        /// The vehicle entity the entity is on, or 0 if none, the position and direction
+        /// are relative to the vehicle if any.
         pub vehicle_entity_id: u32,
+        /// The position, encoded as x, y, z floats.
         pub position: Vec3,
+        /// The direction, encoded as roll, pitch, yaw floats (in radians), respectively
+        /// in x, y, z components.
         pub direction: Vec3,
+        /// Unknown value!
+        pub unknown: u16,
     }
 }
 
@@ -364,7 +528,38 @@ pub type NrlUnreliableMsgToClient = DebugElementVariable16<{ id::NRL_UNRELIABLE_
 pub type ControlEntity = DebugElementFixed<{ id::CONTROL_ENTITY }, 5>;
 pub type VoiceData = DebugElementVariable16<{ id::VOICE_DATA }>;
 pub type RestoreClient = DebugElementVariable16<{ id::RESTORE_CLIENT }>;
-pub type SwitchBaseApp = DebugElementFixed<{ id::SWITCH_BASE_APP }, 9>;
+
+
+/// The server asks the client to switch to another base application, the client 
+/// then connects to the given address with its current session key.
+#[derive(Debug, Clone)]
+pub struct SwitchBaseApp {
+    /// Address of the new base application to connect to.
+    pub base_addr: SocketAddrV4,
+    /// True if the client should reset all of its entities before switching.
+    pub reset_entities: bool,
+}
+
+impl SimpleCodec for SwitchBaseApp {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_sock_addr_v4(self.base_addr)?;
+        write.write_bool(self.reset_entities)
+    }
+
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        Ok(Self {
+            base_addr: read.read_sock_addr_v4()?,
+            reset_entities: read.read_bool()?,
+        })
+    }
+
+}
+
+impl SimpleElement for SwitchBaseApp {
+    const ID: u8 = id::SWITCH_BASE_APP;
+    const LEN: ElementLength = ElementLength::Fixed(9);
+}
 
 
 /// Header describing a resource that will be downloaded in possibly many fragments.
@@ -458,10 +653,54 @@ crate::__struct_simple_codec! {
     /// the player entity.
     #[derive(Debug, Default, Clone, Copy)]
     pub struct LoggedOff {
-        pub reason: u8,
+        /// The reason of the log off.
+        pub reason: LoggedOffReason,
     }
 }
 
+/// The reason given by the server when logging off the client, see [`LoggedOff`].
+/// Other values than the known ones are kept as-is.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LoggedOffReason {
+    /// No particular reason, this is the value always sent by BigWorld servers.
+    #[default]
+    None,
+    /// Unknown reason, the raw value is kept.
+    Unknown(u8),
+}
+
+impl LoggedOffReason {
+
+    /// Get the reason from its raw value.
+    pub fn from_raw(raw: u8) -> Self {
+        match raw {
+            0 => Self::None,
+            raw => Self::Unknown(raw),
+        }
+    }
+
+    /// Get the raw value of this reason.
+    pub fn to_raw(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Unknown(raw) => raw,
+        }
+    }
+
+}
+
+impl SimpleCodec for LoggedOffReason {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_u8(self.to_raw())
+    }
+
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        read.read_u8().map(Self::from_raw)
+    }
+
+}
+
 impl SimpleElement for LoggedOff {
     const ID: u8 = id::LOGGED_OFF;
     const LEN: ElementLength = ElementLength::Fixed(1);
@@ -495,27 +734,152 @@ impl<M: Method> Element<()> for EntityMethod<M> {
 
     fn write(&self, write: &mut dyn Write, _config: &()) -> io::Result<u8> {
         let exposed_id = self.inner.write(write)?;
-        if exposed_id >= id::ENTITY_METHOD.slots_count() as u16 {
+        let Some(id) = id::ENTITY_METHOD.id_of(exposed_id as usize) else {
             todo!("support for sub-id");
-        }
-        Ok(id::ENTITY_METHOD.first + exposed_id as u8)
+        };
+        Ok(id)
     }
 
     fn read_length(_config: &(), id: u8) -> io::Result<ElementLength> {
-        if !id::ENTITY_METHOD.contains(id) {
+        let Some(exposed_id) = id::ENTITY_METHOD.index_of(id) else {
             return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected entity method element id: {id:02X}")));
-        }
-        Ok(M::read_length((id - id::ENTITY_METHOD.first) as u16))
+        };
+        Ok(M::read_length(exposed_id as u16))
     }
 
     fn read(read: &mut dyn Read, _config: &(), _len: usize, id: u8) -> io::Result<Self> {
-        if !id::ENTITY_METHOD.contains(id) {
+        let Some(exposed_id) = id::ENTITY_METHOD.index_of(id) else {
             panic!("unexpected entity method element id: {id:02X}");
-        }
-        let inner = M::read(read, (id - id::ENTITY_METHOD.first) as u16)?;
+        };
+        let inner = M::read(read, exposed_id as u16)?;
         Ok(Self {
             inner,
         })
     }
 
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use crate::net::bundle::Bundle;
+    use crate::net::element::REPLY_ID;
+    use crate::net::packet::PacketConfig;
+    use crate::util::AsciiFmt;
+    use super::*;
+
+    #[test]
+    fn logged_off_reason() {
+
+        for raw in [0x00, 0x04, 0x2A, 0xFF] {
+            let mut buf = Vec::new();
+            let lo = <LoggedOff as SimpleCodec>::read(&mut &[raw][..]).unwrap();
+            SimpleCodec::write(&lo, &mut buf).unwrap();
+            assert_eq!(buf, [raw]);
+        }
+
+        assert_eq!(LoggedOffReason::from_raw(0), LoggedOffReason::None);
+
+    }
+
+    #[test]
+    fn debug_bundle() {
+
+        let mut bundle = Bundle::new();
+        let mut writer = bundle.element_writer();
+        writer.write_simple(TickSync { tick: 0x2A });
+        writer.write_simple(UpdateFrequencyNotification { frequency: 10, unknown: 1, game_time: 2 });
+        writer.write_simple(SelectPlayerEntity {});
+        writer.write_raw_reply(7, &[1, 2]);
+        writer.write_simple(DebugElementVariable16::<0x4E> { data: vec![3, 4] });
+
+        // Entity methods have no known length so the id and remaining bytes are given.
+        assert_eq!(format!("{:?}", bundle.debug_with(&ClientContext)), format!("{:?}", [
+            format!("TickSync {{ id: {}, data: {:?} }}", id::TICK_SYNC, AsciiFmt(&[0x2A])),
+            format!("UpdateFrequencyNotification {{ id: {}, data: {:?} }}", id::UPDATE_FREQUENCY_NOTIFICATION, AsciiFmt(&[10, 1, 0, 2, 0, 0, 0])),
+            format!("SelectPlayerEntity {{ id: {}, data: {:?} }}", id::SELECT_PLAYER_ENTITY, AsciiFmt(&[])),
+            format!("Reply {{ id: {REPLY_ID}, request_id: 7, data: {:?} }}", AsciiFmt(&[1, 2])),
+            format!("EntityMethod {{ id: 78, data: {:?} }}", AsciiFmt(&[0x4E, 2, 0, 3, 4])),
+        ]).replace('"', ""));
+
+    }
+
+    #[test]
+    fn forced_position() {
+
+        // Synthetic element body, with a f32 layout of: x, y, z, roll, pitch, yaw.
+        let mut body = Vec::new();
+        body.extend_from_slice(&0x0000_1234u32.to_le_bytes());
+        body.extend_from_slice(&2u32.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        for value in [-118.5f32, 12.25, 350.0, 0.0, 0.1, -1.5] {
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend_from_slice(&[0x01, 0x00]);
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(DebugElementFixed::<{ id::FORCED_POSITION }, 38> { data: body.try_into().unwrap() });
+        bundle.write_config(&mut PacketConfig::new());
+
+        let mut reader = bundle.element_reader();
+        let fp = reader.read::<ForcedPosition, _>(&(), true).unwrap().element;
+        assert_eq!(fp.entity_id, 0x1234);
+        assert_eq!(fp.space_id, 2);
+        assert_eq!(fp.vehicle_entity_id, 0);
+        assert_eq!(fp.position, Vec3::new(-118.5, 12.25, 350.0));
+        assert_eq!(fp.direction, Vec3::new(0.0, 0.1, -1.5));
+        assert_eq!(fp.unknown, 1);
+        assert!(reader.next_id().is_none());
+
+    }
+
+    #[test]
+    fn create_cell_player() {
+
+        // Synthetic element body, followed by some opaque cell properties.
+        let mut body = Vec::new();
+        body.extend_from_slice(&7u32.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        for value in [250.5f32, 3.75, -412.0, 0.0, 0.0, 3.1] {
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend_from_slice(&[0x2A, 0x00, 0x01]);
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(DebugElementVariable16::<{ id::CREATE_CELL_PLAYER }> { data: body });
+        bundle.write_config(&mut PacketConfig::new());
+
+        let mut reader = bundle.element_reader();
+        let ccp = reader.read::<CreateCellPlayer, _>(&(), true).unwrap().element;
+        assert_eq!(ccp.space_id, 7);
+        assert_eq!(ccp.vehicle_entity_id, 0);
+        assert_eq!(ccp.position, Vec3::new(250.5, 3.75, -412.0));
+        assert_eq!(ccp.direction, Vec3::new(0.0, 0.0, 3.1));
+        assert_eq!(ccp.entity_data, [0x2A, 0x00, 0x01]);
+        assert!(reader.next_id().is_none());
+
+    }
+
+    #[test]
+    fn switch_base_app() {
+
+        let base_addr = SocketAddrV4::new(std::net::Ipv4Addr::new(10, 0, 1, 2), 20016);
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SwitchBaseApp { base_addr, reset_entities: true });
+        bundle.write_config(&mut PacketConfig::new());
+
+        // Raw layout: address octets, big-endian port, salt and reset flag.
+        let mut reader = bundle.element_reader();
+        let raw = reader.read::<DebugElementFixed<{ id::SWITCH_BASE_APP }, 9>, _>(&(), false).unwrap().element;
+        assert_eq!(raw.data, [10, 0, 1, 2, 0x4E, 0x30, 0, 0, 1]);
+
+        let sba = reader.read::<SwitchBaseApp, _>(&(), true).unwrap().element;
+        assert_eq!(sba.base_addr, base_addr);
+        assert!(sba.reset_entities);
+        assert!(reader.next_id().is_none());
+
+    }
+
+}
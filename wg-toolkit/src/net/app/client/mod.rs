@@ -2,3 +2,185 @@
 
 pub mod element;
 
+use std::collections::{HashMap, VecDeque};
+
+use crate::util::io::serde_pickle_de_options;
+
+use element::{ResourceHeader, ResourceFragment};
+
+
+/// An assembler for resources downloaded by the client, a resource is first described
+/// by a [`ResourceHeader`] and then its data is sent in many [`ResourceFragment`] with
+/// incrementing sequence numbers. Once the last fragment is received, the total length
+/// and the CRC-32 given in the header's description are checked and the resource can
+/// be retrieved with [`Self::take_completed`].
+#[derive(Debug, Default)]
+pub struct ResourceAssembler {
+    /// Resources being downloaded, mapped to their id.
+    partials: HashMap<u16, PartialResource>,
+    /// Resources that have been fully downloaded and checked.
+    completed: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Describe a partial resource being download, a header must have been sent.
+#[derive(Debug)]
+struct PartialResource {
+    /// The byte description sent in the resource header.
+    description: Vec<u8>,
+    /// The next sequence number expected, any other sequence number abort the download
+    /// with an error.
+    sequence_num: u8,
+    /// The full assembled data.
+    data: Vec<u8>,
+}
+
+impl ResourceAssembler {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start downloading the resource described by the given header, this
+    /// intentionally overwrites any resource being downloaded with the same id.
+    pub fn push_header(&mut self, header: ResourceHeader) {
+        self.partials.insert(header.id, PartialResource {
+            description: header.description,
+            sequence_num: 0,
+            data: Vec::new(),
+        });
+    }
+
+    /// Push a fragment of a resource being downloaded, if this is the last fragment
+    /// then the resource is checked and, if valid, made available to
+    /// [`Self::take_completed`]. If any error is returned, the resource is forgotten
+    /// and must be downloaded again from its header.
+    pub fn push_fragment(&mut self, fragment: ResourceFragment) -> Result<(), ResourceError> {
+
+        let id = fragment.id;
+        let Some(partial) = self.partials.get_mut(&id) else {
+            return Err(ResourceError::MissingHeader(id));
+        };
+
+        if fragment.sequence_num != partial.sequence_num {
+            let expected = partial.sequence_num;
+            self.partials.remove(&id);
+            return Err(ResourceError::InvalidSequenceNum { id, expected, actual: fragment.sequence_num });
+        }
+
+        partial.sequence_num = partial.sequence_num.wrapping_add(1);
+        partial.data.extend_from_slice(&fragment.data);
+
+        if !fragment.last {
+            return Ok(());
+        }
+
+        let resource = self.partials.remove(&id).unwrap();
+
+        // See: scripts/client/game.py#L223
+        let (total_len, crc32) = match serde_pickle::value_from_reader(&resource.description[..], serde_pickle_de_options()) {
+            Ok(serde_pickle::Value::Tuple(values)) => {
+                if let &[serde_pickle::Value::I64(total_len), serde_pickle::Value::I64(crc32)] = &values[..] {
+                    (total_len as u32, crc32 as u32)
+                } else {
+                    return Err(ResourceError::InvalidDescription(id));
+                }
+            }
+            _ => return Err(ResourceError::InvalidDescription(id)),
+        };
+
+        let actual_len = resource.data.len();
+        if actual_len != total_len as usize {
+            return Err(ResourceError::InvalidLength { id, expected: total_len as usize, actual: actual_len });
+        }
+
+        let actual_crc32 = crc32fast::hash(&resource.data);
+        if actual_crc32 != crc32 {
+            return Err(ResourceError::InvalidCrc32 { id, expected: crc32, actual: actual_crc32 });
+        }
+
+        self.completed.push_back((resource.description, resource.data));
+        Ok(())
+
+    }
+
+    /// Take the next completed resource, in order of completion, returning its raw
+    /// description (a python pickle) and its data.
+    pub fn take_completed(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.completed.pop_front()
+    }
+
+}
+
+/// Error returned by the [`ResourceAssembler`] when pushing a fragment.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ResourceError {
+    /// The fragment has been received without any header for its resource.
+    #[error("missing header for resource {0}")]
+    MissingHeader(u16),
+    /// The fragment's sequence number is not the expected one.
+    #[error("invalid sequence number for resource {id}, expected {expected}, got {actual}")]
+    InvalidSequenceNum { id: u16, expected: u8, actual: u8 },
+    /// The description of the resource is not a pickled tuple of its total length and
+    /// CRC-32.
+    #[error("invalid description for resource {0}")]
+    InvalidDescription(u16),
+    /// The total length of the assembled data is not the one given in the description.
+    #[error("invalid length for resource {id}, expected {expected}, got {actual}")]
+    InvalidLength { id: u16, expected: usize, actual: usize },
+    /// The CRC-32 of the assembled data is not the one given in the description.
+    #[error("invalid crc32 for resource {id}, expected 0x{expected:08X}, got 0x{actual:08X}")]
+    InvalidCrc32 { id: u16, expected: u32, actual: u32 },
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Pickle a tuple of the total length and CRC-32, as done by the server.
+    fn description(total_len: i32, crc32: u32) -> Vec<u8> {
+        let mut description = vec![0x80, 0x02, b'J'];
+        description.extend_from_slice(&total_len.to_le_bytes());
+        description.push(b'J');
+        description.extend_from_slice(&crc32.to_le_bytes());
+        description.extend_from_slice(&[0x86, b'.']);
+        description
+    }
+
+    #[test]
+    fn assemble() {
+
+        let data = b"hello resource world".to_vec();
+        let crc32 = crc32fast::hash(&data);
+
+        let mut assembler = ResourceAssembler::new();
+        assembler.push_header(ResourceHeader { id: 3, description: description(data.len() as i32, crc32) });
+        for (sequence_num, chunk) in data.chunks(8).enumerate() {
+            assert!(assembler.take_completed().is_none());
+            assembler.push_fragment(ResourceFragment {
+                id: 3,
+                sequence_num: sequence_num as u8,
+                last: sequence_num == 2,
+                data: chunk.to_vec(),
+            }).unwrap();
+        }
+
+        assert_eq!(assembler.take_completed(), Some((description(data.len() as i32, crc32), data.clone())));
+        assert!(assembler.take_completed().is_none());
+
+        // Invalid sequence number forgets the resource.
+        assembler.push_header(ResourceHeader { id: 3, description: description(data.len() as i32, crc32) });
+        let fragment = ResourceFragment { id: 3, sequence_num: 1, last: true, data: data.clone() };
+        assert_eq!(assembler.push_fragment(fragment.clone()), Err(ResourceError::InvalidSequenceNum { id: 3, expected: 0, actual: 1 }));
+        assert_eq!(assembler.push_fragment(fragment), Err(ResourceError::MissingHeader(3)));
+
+        // Invalid CRC-32.
+        assembler.push_header(ResourceHeader { id: 4, description: description(data.len() as i32, !crc32) });
+        let fragment = ResourceFragment { id: 4, sequence_num: 0, last: true, data };
+        assert_eq!(assembler.push_fragment(fragment), Err(ResourceError::InvalidCrc32 { id: 4, expected: !crc32, actual: crc32 }));
+        assert!(assembler.take_completed().is_none());
+
+    }
+
+}
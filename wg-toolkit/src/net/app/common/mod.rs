@@ -7,13 +7,21 @@ pub mod entity;
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::num::NonZero;
+use std::time::Duration;
 use std::io;
 
 use crate::net::bundle::{Bundle, ElementReader, NextElementReader, ReplyReader};
+use crate::net::element::RequestIdAlloc;
 use crate::net::socket::PacketSocket;
 use crate::net::proto::Protocol;
 
 
+/// The (currently hardcoded) duration after which a request sent to a peer is 
+/// considered lost and its request id can be allocated again.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+
 /// Common abstract application that handle bundle messages.
 #[derive(Debug)]
 pub struct App {
@@ -38,32 +46,56 @@ impl App {
         })
     }
 
+    /// Get the address this app is bound to.
+    pub fn addr(&self) -> io::Result<SocketAddr> {
+        self.socket.addr()
+    }
+
     pub fn poll<H: Handler>(&mut self, mut handler: H) -> io::Result<()> {
 
         let (packet, addr) = self.socket.recv()?;
         
         let peer = self.peers.entry(addr)
-            .or_insert_with(|| InternalPeer {
-                addr,
+            .or_insert_with(|| {
+                let mut request_ids = RequestIdAlloc::default();
+                request_ids.set_timeout(Some(REQUEST_TIMEOUT));
+                InternalPeer { addr, request_ids }
             });
 
         let Some(mut channel) = self.protocol.accept(packet, addr) else {
             return Ok(());
         };
 
+        // Take all bundles before handling them, because the protocol is needed by
+        // the peer handle in order to send bundles back through the same channel.
+        let peer_channel = channel.is_on().then(|| channel.index().map(|index| index.index));
+        let mut bundles = Vec::new();
         while let Some(bundle) = channel.next_bundle() {
+            bundles.push(bundle);
+        }
+
+        for bundle in bundles {
             let mut reader = bundle.element_reader();
             while let Some(elt) = reader.next() {
-                match elt {
-                    NextElementReader::Element(elt) => {
-                        handler.handle_element(elt, Peer {
-                            internal: &mut *peer,
-                            bundle: &mut self.bundle,
-                        })?;
-                    }
-                    NextElementReader::Reply(_reply) => {
-
+                let (elt, request_id) = match elt {
+                    NextElementReader::Element(elt) => (Ok(elt), None),
+                    NextElementReader::Reply(reply) => {
+                        let request_id = reply.request_id();
+                        peer.request_ids.release(request_id);
+                        (Err(reply), Some(request_id))
                     }
+                };
+                let peer = Peer {
+                    internal: &mut *peer,
+                    bundle: &mut self.bundle,
+                    socket: &self.socket,
+                    protocol: &mut self.protocol,
+                    channel: peer_channel,
+                    request_id,
+                };
+                match elt {
+                    Ok(elt) => handler.handle_element(elt, peer)?,
+                    Err(reply) => handler.handle_reply(reply, peer)?,
                 }
             }
         }
@@ -80,6 +112,8 @@ pub trait Handler {
     /// Handle an incoming top element from the given peer.
     fn handle_element(&mut self, elt: ElementReader, peer: Peer) -> io::Result<()>;
 
+    /// Handle an incoming reply from the given peer, the request id of the reply is
+    /// also available from the peer handle.
     fn handle_reply(&mut self, reply: ReplyReader, peer: Peer) -> io::Result<()>;
 
 }
@@ -89,6 +123,13 @@ pub trait Handler {
 pub struct Peer<'a> {
     internal: &'a mut InternalPeer,
     bundle: &'a mut Bundle,
+    socket: &'a PacketSocket,
+    protocol: &'a mut Protocol,
+    /// The channel the handled element has been received from, none if off-channel,
+    /// or some with the optional index of the channel.
+    channel: Option<Option<NonZero<u32>>>,
+    /// The request id of the reply being handled, if relevant.
+    request_id: Option<u32>,
 }
 
 impl<'a> Peer<'a> {
@@ -99,10 +140,173 @@ impl<'a> Peer<'a> {
         self.internal.addr
     }
 
+    /// Get the request id of the reply being handled, this is only present when this
+    /// peer handle is given to [`Handler::handle_reply()`].
+    #[inline]
+    pub fn request_id(&self) -> Option<u32> {
+        self.request_id
+    }
+
+    /// Allocate a request id for a request to this peer, it is released when the reply
+    /// is received or after a timeout if the reply is lost.
+    #[inline]
+    pub fn alloc_request_id(&mut self) -> u32 {
+        self.internal.request_ids.alloc()
+    }
+
+    /// Get the request ids allocator of this peer.
+    #[inline]
+    pub fn request_ids(&self) -> &RequestIdAlloc {
+        &self.internal.request_ids
+    }
+
+    /// Get the bundle used to write elements to send back to this peer, this bundle is
+    /// cleared after being sent with [`Self::send()`].
+    #[inline]
+    pub fn bundle(&mut self) -> &mut Bundle {
+        &mut *self.bundle
+    }
+
+    /// Send the bundle returned by [`Self::bundle()`] to this peer, through the same
+    /// channel the handled element has been received from. The bundle is sent reliably
+    /// if it has been set reliable, see [`Bundle::set_reliable`].
+    pub fn send(&mut self) -> io::Result<()> {
+
+        let addr = self.internal.addr;
+        let mut channel = match self.channel {
+            None => self.protocol.off_channel(addr),
+            Some(index) => self.protocol.channel(addr, index),
+        };
+
+        channel.prepare(&mut *self.bundle, false);
+        let res = self.socket.send_bundle(&*self.bundle, addr);
+        self.bundle.clear();
+        res.map(|_| ())
+
+    }
+
 }
 
 /// Internal peer data that is forwarded via the peer handle given to handler.
 #[derive(Debug)]
 struct InternalPeer {
     addr: SocketAddr,
+    /// Allocator for requests sent to this peer.
+    request_ids: RequestIdAlloc,
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use crate::net::app::login::element::Ping;
+    use crate::net::packet::PacketConfig;
+
+    use super::*;
+
+    /// Echo all ping elements back to the peer.
+    struct Echo;
+
+    impl Handler for Echo {
+
+        fn handle_element(&mut self, elt: ElementReader, mut peer: Peer) -> io::Result<()> {
+            let ping = elt.read_simple::<Ping>()?;
+            peer.bundle().element_writer().write_simple(ping.element);
+            peer.send()
+        }
+
+        fn handle_reply(&mut self, _reply: ReplyReader, _peer: Peer) -> io::Result<()> {
+            panic!("unexpected reply")
+        }
+
+    }
+
+    #[test]
+    fn echo() {
+
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(Ping { num: 42 });
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+        app.poll(Echo).unwrap();
+
+        let (packet, addr) = client.recv_without_encryption().unwrap();
+        assert_eq!(addr, app.addr().unwrap());
+        let packet = packet.read_config_locked().unwrap();
+        assert!(!packet.config().on_channel());
+        let bundle = Bundle::new_with_single(packet);
+        let mut reader = bundle.element_reader();
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        assert_eq!(elt.read_simple::<Ping>().unwrap().element.num, 42);
+        assert!(reader.next().is_none());
+
+    }
+
+    /// Send a ping request in response to any element, and check replies.
+    #[derive(Default)]
+    struct Requester {
+        request_id: Option<u32>,
+        replied: bool,
+    }
+
+    impl Handler for &mut Requester {
+
+        fn handle_element(&mut self, elt: ElementReader, mut peer: Peer) -> io::Result<()> {
+            elt.read_simple::<Ping>()?;
+            let request_id = peer.alloc_request_id();
+            self.request_id = Some(request_id);
+            peer.bundle().element_writer().write_simple_request(Ping { num: 1 }, request_id);
+            peer.send()
+        }
+
+        fn handle_reply(&mut self, reply: ReplyReader, peer: Peer) -> io::Result<()> {
+            assert_eq!(peer.request_id(), self.request_id);
+            assert!(!peer.request_ids().is_in_flight(reply.request_id()));
+            assert_eq!(peer.request_ids().in_flight_count(), 0);
+            assert_eq!(reply.read_simple::<Ping>()?.num, 1);
+            self.replied = true;
+            Ok(())
+        }
+
+    }
+
+    #[test]
+    fn request_reply() {
+
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut requester = Requester::default();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(Ping { num: 42 });
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+        app.poll(&mut requester).unwrap();
+
+        let (packet, _) = client.recv_without_encryption().unwrap();
+        let bundle = Bundle::new_with_single(packet.read_config_locked().unwrap());
+        let mut reader = bundle.element_reader();
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        let ping = elt.read_simple::<Ping>().unwrap();
+        assert_eq!(ping.request_id, requester.request_id);
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple_reply(ping.element, ping.request_id.unwrap());
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+        app.poll(&mut requester).unwrap();
+        assert!(requester.replied);
+
+    }
+
 }
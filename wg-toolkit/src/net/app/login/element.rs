@@ -8,14 +8,14 @@
 //! This app also provides a way to ping test the server.
 
 use std::io::{self, Read, Write};
-use std::net::SocketAddrV4;
+use std::net::{SocketAddr, SocketAddrV4};
 use std::time::Duration;
 
 use rsa::{RsaPrivateKey, RsaPublicKey};
 use blowfish::Blowfish;
 
 use crate::net::filter::{RsaWriter, RsaReader, BlowfishWriter, BlowfishReader};
-use crate::net::element::{ElementLength, SimpleElement};
+use crate::net::element::{ElementLength, ElementContext, SimpleElement};
 use crate::net::codec::{Codec, SimpleCodec};
 use crate::util::io::*;
 
@@ -28,6 +28,25 @@ pub mod id {
 }
 
 
+/// The element context of the login application, used to debug bundles sent by the 
+/// client to the login application, see [`ElementContext`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginContext;
+
+impl ElementContext for LoginContext {
+
+    fn element_info(&self, id: u8) -> Option<(&'static str, Option<ElementLength>)> {
+        Some(match id {
+            id::LOGIN_REQUEST       => ("LoginRequest", Some(LoginRequestHeader::LEN)),
+            id::PING                => ("Ping", Some(Ping::LEN)),
+            id::CHALLENGE_RESPONSE  => ("ChallengeResponse", Some(ChallengeResponse::<()>::LEN)),
+            _                       => return None,
+        })
+    }
+
+}
+
+
 crate::__struct_simple_codec! {
     /// A ping sent from the client to the login app or replied from the
     /// login app to the client.
@@ -122,6 +141,38 @@ impl Codec<RsaPrivateKey> for LoginRequest {
 
 }
 
+/// Only the header of a [`LoginRequest`], this can be read before the full request in
+/// order to know if the rest of the request is encrypted or in clear, without decoding 
+/// it, this is used to know which decode path is taken.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginRequestHeader {
+    /// The protocol used, currently undocumented.
+    pub protocol: u32,
+    /// True if the rest of the request is encrypted with the login app's public key.
+    pub encrypted: bool,
+}
+
+impl Codec<()> for LoginRequestHeader {
+
+    fn write(&self, write: &mut dyn Write, _config: &()) -> io::Result<()> {
+        write.write_u32(self.protocol)?;
+        write.write_bool(self.encrypted)
+    }
+
+    fn read(read: &mut dyn Read, _config: &()) -> io::Result<Self> {
+        Ok(Self {
+            protocol: read.read_u32()?,
+            encrypted: read.read_bool()?,
+        })
+    }
+
+}
+
+impl SimpleElement for LoginRequestHeader {
+    const ID: u8 = id::LOGIN_REQUEST;
+    const LEN: ElementLength = ElementLength::Variable16;
+}
+
 fn write_login_request(write: &mut dyn Write, input: &LoginRequest) -> io::Result<()> {
     write.write_u8(if input.digest.is_some() { 0x01 } else { 0x00 })?;
     write.write_string_variable(&input.username)?;
@@ -171,6 +222,10 @@ pub enum LoginResponse {
     /// An error happened server-side and the login process cannot succeed. This data
     /// is expected to be a JSON string on modern version of the game.
     Error(LoginError, String),
+    /// The client is waiting in the login queue at the given position, it waits and
+    /// automatically retries to login. This is sent as a [`LoginError::RateLimited`]
+    /// error with a JSON message of the form `{"queueNumber": 42}`.
+    Queued(u64),
     /// Unknown response code.
     Unknown(u8),
 }
@@ -181,8 +236,9 @@ pub enum LoginResponse {
 #[derive(Debug, Clone)]
 pub struct LoginSuccess {
     /// The socket address of the base app server to connect after successful
-    /// login.
-    pub addr: SocketAddrV4,
+    /// login. Note that the protocol only supports IPv4 addresses, so encoding a
+    /// success with an IPv6 address will return an error.
+    pub addr: SocketAddr,
     /// Session key, it's used to authenticate to the base app.
     pub login_key: u32,
     /// Server message for successful login.
@@ -199,6 +255,55 @@ pub enum LoginChallenge {
         key_prefix: Vec<u8>,
         max_nonce: u32,
     },
+    /// A challenge of an unknown kind, its data is kept raw.
+    Unknown {
+        /// Name of the challenge kind.
+        name: String,
+        /// Raw data of the challenge, following its name.
+        data: Vec<u8>,
+    },
+}
+
+impl LoginChallenge {
+
+    /// Return the kind of this challenge.
+    pub fn kind(&self) -> ChallengeKind {
+        match self {
+            Self::CuckooCycle { .. } => ChallengeKind::CuckooCycle,
+            Self::Unknown { name, .. } => ChallengeKind::Unknown(name.clone()),
+        }
+    }
+
+}
+
+/// Kind of a challenge, identified by its name in the protocol. The kind of challenge
+/// issued determines the type of the [`ChallengeResponse`] data sent back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengeKind {
+    /// Cuckoo cycle challenge, see [`CuckooCycleResponse`].
+    CuckooCycle,
+    /// Unknown challenge kind with its name, see [`RawChallengeResponse`].
+    Unknown(String),
+}
+
+impl ChallengeKind {
+
+    /// Get the challenge kind from its name.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            CHALLENGE_CUCKOO_CYCLE => Self::CuckooCycle,
+            name => Self::Unknown(name.to_string()),
+        }
+    }
+
+    /// Get the name of this challenge kind.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::CuckooCycle => CHALLENGE_CUCKOO_CYCLE,
+            Self::Unknown(name) => name,
+        }
+    }
+
 }
 
 /// Describe a login error as a response to a login request.
@@ -237,6 +342,9 @@ pub enum LoginError {
 /// Text identifier of the cuckoo cycle challenge type.
 const CHALLENGE_CUCKOO_CYCLE: &'static str = "cuckoo_cycle";
 
+/// Key of the queue position in the JSON message of a queued login response.
+const QUEUE_NUMBER_KEY: &str = "queueNumber";
+
 impl LoginResponse {
 
     fn write_inner(&self, write: &mut dyn Write, bf: Option<&Blowfish>) -> io::Result<()> {
@@ -263,6 +371,10 @@ impl LoginResponse {
                         write.write_blob_variable(&prefix)?;
                         write.write_u64(*max_nonce as u64)?;
                     }
+                    LoginChallenge::Unknown { name, data } => {
+                        write.write_string_variable(name)?;
+                        write.write_blob(data)?;
+                    }
                 }
                 
             }
@@ -270,6 +382,10 @@ impl LoginResponse {
                 write.write_u8(*err as _)?;
                 write.write_string_variable(&message)?;
             }
+            Self::Queued(position) => {
+                write.write_u8(LoginError::RateLimited as _)?;
+                write.write_string_variable(&format!("{{\"{QUEUE_NUMBER_KEY}\": {position}}}"))?;
+            }
             Self::Unknown(code) => write.write_u8(*code)?
         }
     
@@ -295,8 +411,8 @@ impl LoginResponse {
             66 => {
                 
                 let challenge_name = read.read_string_variable()?;
-                let challenge = match &challenge_name[..] {
-                    CHALLENGE_CUCKOO_CYCLE => {
+                let challenge = match ChallengeKind::from_name(&challenge_name) {
+                    ChallengeKind::CuckooCycle => {
                         let prefix = read.read_blob_variable()?;
                         let max_nonce = read.read_u64()? as u32;
                         LoginChallenge::CuckooCycle { 
@@ -304,7 +420,12 @@ impl LoginResponse {
                             max_nonce,
                         }
                     }
-                    name => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid challenge name: {name}")))
+                    ChallengeKind::Unknown(name) => {
+                        LoginChallenge::Unknown { 
+                            name, 
+                            data: read.read_blob_to_end()?,
+                        }
+                    }
                 };
 
                 return Ok(LoginResponse::Challenge(challenge));
@@ -314,6 +435,7 @@ impl LoginResponse {
             65 => LoginError::BadProtocolVersion,
             67 => LoginError::InvalidUser,
             68 => LoginError::InvalidPassword,
+            83 => LoginError::RateLimited,
             // TODO: Implement other variants
             code => return Ok(LoginResponse::Unknown(code))
         };
@@ -324,6 +446,12 @@ impl LoginResponse {
             Err(e) => return Err(e),
         };
 
+        if error == LoginError::RateLimited {
+            if let Some(position) = parse_queue_message(&message) {
+                return Ok(LoginResponse::Queued(position));
+            }
+        }
+
         Ok(LoginResponse::Error(error, message))
 
     }
@@ -355,10 +483,21 @@ impl Codec<Blowfish> for LoginResponse {
 
 }
 
+/// Internal function to parse the queue position from the JSON message of a queued 
+/// login response, only a single key is expected.
+fn parse_queue_message(message: &str) -> Option<u64> {
+    let inner = message.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let (key, value) = inner.split_once(':')?;
+    if key.trim() != format!("\"{QUEUE_NUMBER_KEY}\"") {
+        return None;
+    }
+    value.trim().parse().ok()
+}
+
 /// Internal function for encoding login success. It is extracted here
 /// in order to be usable with optional encryption.
 fn write_login_success(write: &mut dyn Write, success: &LoginSuccess) -> io::Result<()> {
-    write.write_sock_addr_v4(success.addr)?;
+    write.write_sock_addr_v4(check_base_app_addr(success.addr)?)?;
     write.write_u32(success.login_key)?;
     if !success.server_message.is_empty() {
         write.write_string_variable(&success.server_message)?;
@@ -366,11 +505,20 @@ fn write_login_success(write: &mut dyn Write, success: &LoginSuccess) -> io::Res
     Ok(())
 }
 
+/// Check that the given base app address can be sent in a login success, the protocol
+/// only supports IPv4 addresses so an invalid input error is returned for IPv6.
+pub(crate) fn check_base_app_addr(addr: SocketAddr) -> io::Result<SocketAddrV4> {
+    match addr {
+        SocketAddr::V4(addr) => Ok(addr),
+        SocketAddr::V6(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "login success base app address must be IPv4")),
+    }
+}
+
 /// Internal function for decoding login success. It is extracted here
 /// in order to be usable with optional encryption.
 fn read_login_success(read: &mut dyn Read) -> io::Result<LoginSuccess> {
     Ok(LoginSuccess { 
-        addr: read.read_sock_addr_v4()?, 
+        addr: SocketAddr::V4(read.read_sock_addr_v4()?), 
         login_key: read.read_u32()?, 
         server_message: match read.read_string_variable() {
             Ok(msg) => msg,
@@ -454,3 +602,99 @@ impl SimpleCodec for CuckooCycleResponse {
     }
 
 }
+
+/// Describe a challenge response for challenge of unknown kind, its data is kept raw.
+#[derive(Debug, Clone)]
+pub struct RawChallengeResponse {
+    /// Raw data of the response.
+    pub data: Vec<u8>,
+}
+
+impl SimpleCodec for RawChallengeResponse {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_blob(&self.data)
+    }
+
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        Ok(RawChallengeResponse { 
+            data: read.read_blob_to_end()?,
+        })
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn login_success_addr() {
+
+        let mut success = LoginSuccess {
+            addr: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 20016)),
+            login_key: 0x12345678,
+            server_message: String::new(),
+        };
+
+        let mut data = Vec::new();
+        write_login_success(&mut data, &success).unwrap();
+        assert_eq!(data, [127, 0, 0, 1, 0x4E, 0x30, 0, 0, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(read_login_success(&mut &data[..]).unwrap().addr, success.addr);
+
+        success.addr = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 20016);
+        assert!(write_login_success(&mut Vec::new(), &success).is_err());
+
+    }
+
+    #[test]
+    fn login_queued() {
+
+        let mut data = Vec::new();
+        LoginResponse::Queued(42).write(&mut data, &()).unwrap();
+        assert_eq!(data[0], LoginError::RateLimited as u8);
+        assert!(matches!(LoginResponse::read(&mut &data[..], &()).unwrap(), LoginResponse::Queued(42)));
+
+        let mut message = Vec::new();
+        message.write_string_variable(r#"{"queueNumber": 42}"#).unwrap();
+        assert_eq!(data[1..], message);
+
+        // Other rate limit messages are kept as errors.
+        data.clear();
+        LoginResponse::Error(LoginError::RateLimited, "{}".to_string()).write(&mut data, &()).unwrap();
+        assert!(matches!(LoginResponse::read(&mut &data[..], &()).unwrap(), LoginResponse::Error(LoginError::RateLimited, msg) if msg == "{}"));
+
+    }
+
+    #[test]
+    fn login_challenge_kind() {
+
+        let mut data = Vec::new();
+        LoginResponse::Challenge(LoginChallenge::CuckooCycle { key_prefix: b"abc".to_vec(), max_nonce: 42 })
+            .write(&mut data, &()).unwrap();
+
+        let LoginResponse::Challenge(challenge) = LoginResponse::read(&mut &data[..], &()).unwrap() else {
+            panic!("expected a challenge");
+        };
+
+        assert_eq!(challenge.kind(), ChallengeKind::CuckooCycle);
+        assert!(matches!(challenge, LoginChallenge::CuckooCycle { max_nonce: 42, .. }));
+
+        let mut data = Vec::new();
+        LoginResponse::Challenge(LoginChallenge::Unknown { name: "noop".to_string(), data: vec![1, 2, 3] })
+            .write(&mut data, &()).unwrap();
+
+        let LoginResponse::Challenge(challenge) = LoginResponse::read(&mut &data[..], &()).unwrap() else {
+            panic!("expected a challenge");
+        };
+
+        assert_eq!(challenge.kind(), ChallengeKind::Unknown("noop".to_string()));
+        assert!(matches!(challenge, LoginChallenge::Unknown { data, .. } if data == [1, 2, 3]));
+
+    }
+
+}
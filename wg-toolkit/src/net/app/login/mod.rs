@@ -5,7 +5,7 @@ pub mod element;
 pub mod proxy;
 
 use std::collections::{HashMap, VecDeque};
-use std::net::{SocketAddr, SocketAddrV4};
+use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use std::sync::Arc;
 use std::io;
@@ -20,20 +20,25 @@ use rand::RngCore;
 use tracing::trace;
 
 use crate::net::bundle::{Bundle, NextElementReader, ElementReader};
-use crate::util::cuckoo::CuckooContext;
+use crate::util::cuckoo::{self, CuckooContext};
 use crate::net::socket::PacketSocket;
 use crate::net::proto::Protocol;
 use super::io_invalid_data;
 
 use element::{
     Ping,
-    LoginRequest,
+    LoginRequest, LoginRequestHeader,
     LoginResponse, LoginChallenge,
-    LoginSuccess, LoginError,
+    LoginSuccess, LoginError, check_base_app_addr,
     ChallengeResponse, CuckooCycleResponse,
 };
 
 
+/// Duration after which a client answered with a queue position and that didn't retry
+/// to login is forgotten, see [`App::answer_login_queue`].
+pub const QUEUE_TIMEOUT: Duration = Duration::from_secs(60);
+
+
 /// The login application.
 #[derive(Debug)]
 pub struct App {
@@ -55,8 +60,14 @@ pub struct App {
     pending_responses: VecDeque<PendingResponse>,
     /// Issued and pending challenges.
     pending_challenges: HashMap<SocketAddr, PendingChallenge>,
+    /// Graph parameters of issued cuckoo cycle challenges, edge bits and cycle length.
+    challenge_params: (u32, usize),
+    /// Clients that have been answered with a queue position.
+    queued_clients: HashMap<SocketAddr, QueuedClient>,
     /// Used for benchmarking performance.
     received_instant: Option<Instant>,
+    /// The receive timeout currently set on the socket, none if blocking.
+    recv_timeout: Option<Duration>,
 }
 impl App {
 
@@ -70,7 +81,10 @@ impl App {
             pending_requests: HashMap::new(),
             pending_responses: VecDeque::new(),
             pending_challenges: HashMap::new(),
+            queued_clients: HashMap::new(),
+            challenge_params: (cuckoo::BW_SIZE_SHIFT, cuckoo::BW_PROOF_SIZE),
             received_instant: None,
+            recv_timeout: None,
         })
     }
 
@@ -95,8 +109,32 @@ impl App {
         self.encryption_key.is_some()
     }
 
+    /// Set the graph parameters of the cuckoo cycle challenges issued by this login app,
+    /// the number of bits of the graph size and the length of the cycle, by default 20
+    /// and 42 as used by BigWorld. These parameters are not part of the challenge sent
+    /// to the client, so the client must be configured with the same ones, they are
+    /// kept with each pending challenge so it's verified with the parameters it has
+    /// been issued with. This function panics if the parameters are not valid, see
+    /// [`cuckoo::is_valid_params`].
+    pub fn set_challenge_params(&mut self, edge_bits: u32, cycle_len: usize) {
+        assert!(cuckoo::is_valid_params(edge_bits, cycle_len), "invalid cuckoo cycle parameters");
+        self.challenge_params = (edge_bits, cycle_len);
+    }
+
     /// Poll for the next event of this login app, blocking.
     pub fn poll(&mut self) -> Event {
+        self.poll_inner(None)
+    }
+
+    /// Poll for the next event of this login app, blocking until the given timeout, in
+    /// which case [`Event::Idle`] is returned. This can be used to run periodic tasks 
+    /// in the same thread. Returning on timeout doesn't drop any packet, a packet
+    /// arriving just after will be returned by the next poll.
+    pub fn poll_timeout(&mut self, timeout: Duration) -> Event {
+        self.poll_inner(Some(Instant::now() + timeout))
+    }
+
+    fn poll_inner(&mut self, deadline: Option<Instant>) -> Event {
         loop {
 
             // Empty the events before.
@@ -112,8 +150,32 @@ impl App {
                 }
             }
 
+            // The remaining time is rounded up to the millisecond so that polling again 
+            // with the same timeout doesn't need to update the socket's timeout, this may
+            // only delay the idle event by less than a millisecond.
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Event::Idle;
+                    }
+                    Some(Duration::from_millis(remaining.as_nanos().div_ceil(1_000_000) as u64))
+                }
+                None => None,
+            };
+
+            // Only update the timeout when it changes, it needs to be reset when polling
+            // without timeout after polling with one.
+            if timeout != self.recv_timeout {
+                if let Err(error) = self.socket.set_recv_timeout(timeout) {
+                    return Event::IoError(IoErrorEvent { error, addr: None });
+                }
+                self.recv_timeout = timeout;
+            }
+
             let (packet, addr) = match self.socket.recv() {
                 Ok(ret) => ret,
+                Err(e) if deadline.is_some() && matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => return Event::Idle,
                 Err(error) => return Event::IoError(IoErrorEvent { error, addr: None }),
             };
             
@@ -181,7 +243,11 @@ impl App {
     }
 
     /// Handle a login request to the login node.
-    fn handle_login_request(&mut self, elt: ElementReader, addr: SocketAddr) -> io::Result<()> {
+    fn handle_login_request(&mut self, mut elt: ElementReader, addr: SocketAddr) -> io::Result<()> {
+
+        // Read the header first without going to the next element, only to know if the
+        // request is actually encrypted, even if encryption is disabled on our side.
+        let encrypted = elt.read_simple_stable::<LoginRequestHeader>()?.element.encrypted;
 
         let login;
         if let Some(encryption_key) = self.encryption_key.as_deref() {
@@ -196,6 +262,10 @@ impl App {
         let blowfish = Arc::new(Blowfish::new_from_slice(&login.element.blowfish_key)
             .map_err(|_| io_invalid_data(format_args!("login has invalid blowfish key: {:?}", login.element.blowfish_key)))?);
 
+        // The client retried to login, its previous queue position is outdated.
+        self.queued_clients.remove(&addr);
+        self.purge_queued_clients();
+
         // Update or insert the login tracker... 
         self.pending_requests.insert(addr, PendingRequest {
             blowfish,
@@ -205,6 +275,7 @@ impl App {
         self.events.push_back(Event::Login(LoginEvent {
             addr,
             request: login.element,
+            encrypted,
         }));
 
         Ok(())
@@ -232,7 +303,8 @@ impl App {
             challenge.element.data.solution, 
             pending_challenge.max_nonce);
 
-        let cuckoo = CuckooContext::new(pending_challenge.max_nonce, &challenge.element.data.key);
+        let (edge_bits, cycle_len) = pending_challenge.params;
+        let cuckoo = CuckooContext::with_params(pending_challenge.max_nonce, &challenge.element.data.key, edge_bits, cycle_len);
         if !cuckoo.verify_bw(&challenge.element.data.solution) {
             return Err(io_invalid_data(format_args!("challenge has invalid solution")));
         }
@@ -250,18 +322,25 @@ impl App {
     /// register itself.
     /// 
     /// This returns the blowfish encryption instance if a client was effectively 
-    /// waiting for a response.
+    /// waiting for a response. The base app address must be IPv4 because the protocol
+    /// doesn't support IPv6, an invalid input error is returned otherwise and the
+    /// client is still waiting for a response.
+    /// 
+    /// The login key should be registered to the base app before, see 
+    /// [`LoginKeys::add`](crate::net::app::base::LoginKeys::add), so that it is 
+    /// single-use and bound to the client's address.
     pub fn answer_login_success(&mut self, 
         addr: SocketAddr, 
-        app_addr: SocketAddrV4, 
+        app_addr: SocketAddr, 
         login_key: u32,
         server_message: String
-    ) -> Option<Arc<Blowfish>> {
-        self.answer_login_response(addr, LoginResponse::Success(LoginSuccess {
+    ) -> io::Result<Option<Arc<Blowfish>>> {
+        check_base_app_addr(app_addr)?;
+        Ok(self.answer_login_response(addr, LoginResponse::Success(LoginSuccess {
             addr: app_addr,
             login_key,
             server_message,
-        }))
+        })))
     }
 
     /// In response to a [`LoginRequestEvent`], authorize a client to log into the base
@@ -286,10 +365,11 @@ impl App {
     ) -> bool {
 
         let easiness = 0.9;
+        let (edge_bits, _) = self.challenge_params;
 
         let key_prefix_value = OsRng.next_u64();
         let key_prefix = format!("{key_prefix_value:>02X}").into_bytes();
-        let max_nonce = ((1 << 20) as f32 * easiness) as u32;
+        let max_nonce = ((1u64 << edge_bits) as f32 * easiness) as u32;
 
         let success = self.answer_login_response(addr, LoginResponse::Challenge(LoginChallenge::CuckooCycle { 
             key_prefix: key_prefix.clone(), 
@@ -303,12 +383,64 @@ impl App {
         self.pending_challenges.insert(addr, PendingChallenge {
             key_prefix: key_prefix.clone(),
             max_nonce,
+            params: self.challenge_params,
+        });
+
+        true
+
+    }
+
+    /// In response to a [`LoginRequestEvent`], tell a client that it is waiting in a
+    /// login queue at the given position, see [`LoginResponse::Queued`], so it waits 
+    /// and automatically retries to login. The client stays pending, so it can be
+    /// answered later with any other response, even before it retries. If the client
+    /// doesn't retry within [`QUEUE_TIMEOUT`], it is forgotten.
+    /// 
+    /// This returns true if a client was effectively waiting for a response.
+    pub fn answer_login_queue(&mut self,
+        addr: SocketAddr,
+        position: u64,
+    ) -> bool {
+
+        let Some(request) = self.pending_requests.get(&addr) else {
+            return false;
+        };
+
+        self.pending_responses.push_back(PendingResponse {
+            request: request.clone(),
+            addr,
+            inner: LoginResponse::Queued(position),
+        });
+
+        self.queued_clients.insert(addr, QueuedClient {
+            position,
+            time: Instant::now(),
         });
 
         true
 
     }
 
+    /// Return the last queue position given to the client with the given address, if
+    /// it is still queued, see [`Self::answer_login_queue`]. The client is no longer
+    /// queued when it retries to login or when it timed out.
+    pub fn queue_position(&self, addr: SocketAddr) -> Option<u64> {
+        self.queued_clients.get(&addr).map(|client| client.position)
+    }
+
+    /// Forget the queued clients that didn't retry to login in time, with their 
+    /// pending request.
+    fn purge_queued_clients(&mut self) {
+        self.queued_clients.retain(|addr, client| {
+            if client.time.elapsed() < QUEUE_TIMEOUT {
+                true
+            } else {
+                self.pending_requests.remove(addr);
+                false
+            }
+        });
+    }
+
     /// Internal wrapper for answering a login response.
     #[inline]
     fn answer_login_response(&mut self, addr: SocketAddr, response: LoginResponse) -> Option<Arc<Blowfish>> {
@@ -317,6 +449,8 @@ impl App {
             return None;
         };
 
+        self.queued_clients.remove(&addr);
+
         let bf = Arc::clone(&request.blowfish);
 
         self.pending_responses.push_back(PendingResponse {
@@ -347,6 +481,8 @@ impl App {
 /// An event that happened in the login app regarding the login process.
 #[derive(Debug)]
 pub enum Event {
+    /// No packet has been received before the timeout, see [`App::poll_timeout`].
+    Idle,
     IoError(IoErrorEvent),
     Ping(PingEvent),
     Login(LoginEvent),
@@ -376,12 +512,18 @@ pub struct PingEvent {
 /// - [`App::answer_login_success`]
 /// - [`App::answer_login_error`]
 /// - [`App::answer_login_challenge`]
+/// - [`App::answer_login_queue`]
 #[derive(Debug)]
 pub struct LoginEvent {
     /// The address of the client that request a login.
     pub addr: SocketAddr,
     /// The request received.
     pub request: LoginRequest,
+    /// True if the request was received encrypted with the login app's public key, 
+    /// false if it was received in clear. This is useful to audit misconfigured clients
+    /// and it's accurate even if encryption is disabled on the login app, in which case
+    /// encrypted requests are rejected.
+    pub encrypted: bool,
 }
 
 /// A challenge has been answered by the client, this will usually followed by another
@@ -393,7 +535,7 @@ pub struct ChallengeEvent {
 }
 
 /// Describe a client trying to log into the server.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct PendingRequest {
     /// This is the blowfish key as sent by the client when requesting login.
     blowfish: Arc<Blowfish>,
@@ -401,6 +543,15 @@ struct PendingRequest {
     request_id: u32,
 }
 
+/// Describe a client that has been answered with a queue position.
+#[derive(Debug)]
+struct QueuedClient {
+    /// The last queue position given.
+    position: u64,
+    /// Instant when the queue position has been given.
+    time: Instant,
+}
+
 /// Describe a response pending to be sent to an address.
 #[derive(Debug)]
 struct PendingResponse {
@@ -419,4 +570,132 @@ struct PendingChallenge {
     key_prefix: Vec<u8>,
     /// The configured max nonce.
     max_nonce: u32,
+    /// The graph parameters, edge bits and cycle length.
+    params: (u32, usize),
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn queue_then_success() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = "127.0.0.1:20016".parse().unwrap();
+
+        assert!(!app.answer_login_queue(addr, 3));
+
+        app.pending_requests.insert(addr, PendingRequest {
+            blowfish: Arc::new(Blowfish::new_from_slice(&[0; 16]).unwrap()),
+            request_id: 1,
+        });
+
+        assert!(app.answer_login_queue(addr, 3));
+        assert!(app.answer_login_queue(addr, 2));
+        assert_eq!(app.queue_position(addr), Some(2));
+        assert!(matches!(app.pending_responses.back().unwrap().inner, LoginResponse::Queued(2)));
+
+        let app_addr = "127.0.0.1:20017".parse().unwrap();
+        let app_addr_v6 = "[::1]:20017".parse().unwrap();
+        let error = app.answer_login_success(addr, app_addr_v6, 0, String::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(app.queue_position(addr), Some(2));
+
+        assert!(app.answer_login_success(addr, app_addr, 0, String::new()).unwrap().is_some());
+        assert_eq!(app.queue_position(addr), None);
+        assert!(matches!(app.pending_responses.back().unwrap().inner, LoginResponse::Success(_)));
+        assert!(app.answer_login_success(addr, app_addr, 0, String::new()).unwrap().is_none());
+
+    }
+
+    #[test]
+    fn queue_timeout() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addrs: [SocketAddr; 2] = ["127.0.0.1:20016".parse().unwrap(), "127.0.0.1:20017".parse().unwrap()];
+
+        for addr in addrs {
+            app.pending_requests.insert(addr, PendingRequest {
+                blowfish: Arc::new(Blowfish::new_from_slice(&[0; 16]).unwrap()),
+                request_id: 1,
+            });
+            assert!(app.answer_login_queue(addr, 1));
+        }
+
+        // The first client didn't retry in time.
+        app.queued_clients.get_mut(&addrs[0]).unwrap().time -= QUEUE_TIMEOUT;
+        app.purge_queued_clients();
+        assert_eq!(app.queue_position(addrs[0]), None);
+        assert!(!app.pending_requests.contains_key(&addrs[0]));
+        assert_eq!(app.queue_position(addrs[1]), Some(1));
+        assert!(app.pending_requests.contains_key(&addrs[1]));
+
+    }
+
+    #[test]
+    fn poll_timeout_idle() {
+
+        use crate::net::packet::PacketConfig;
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(matches!(app.poll_timeout(Duration::from_millis(20)), Event::Idle));
+        assert!(matches!(app.poll_timeout(Duration::from_millis(20)), Event::Idle));
+        assert_eq!(app.recv_timeout, Some(Duration::from_millis(20)));
+
+        // A packet received after an idle event is still returned.
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple_request(Ping { num: 42 }, 1);
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+        let event = app.poll_timeout(Duration::from_secs(5));
+        assert!(matches!(event, Event::Ping(PingEvent { addr, .. }) if addr == client.addr().unwrap()));
+        assert!(matches!(app.poll_timeout(Duration::from_millis(20)), Event::Idle));
+
+    }
+
+    #[test]
+    fn login_encrypted() {
+
+        use crate::net::packet::PacketConfig;
+
+        // A small key is enough for the test and fast to generate.
+        let key = Arc::new(RsaPrivateKey::new(&mut OsRng, 512).unwrap());
+        let request = LoginRequest {
+            blowfish_key: vec![0; 16],
+            ..Default::default()
+        };
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.set_encryption(Arc::clone(&key));
+        let app_addr = app.addr().unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let send = |encrypted: bool| {
+            let mut bundle = Bundle::new();
+            if encrypted {
+                bundle.element_writer().write_request(request.clone(), 1, &key.to_public_key());
+            } else {
+                bundle.element_writer().write_simple_request(request.clone(), 1);
+            }
+            bundle.write_config(&mut PacketConfig::new());
+            client.send_bundle_without_encryption(&bundle, app_addr).unwrap();
+        };
+
+        send(true);
+        assert!(matches!(app.poll_timeout(Duration::from_secs(5)), Event::Login(LoginEvent { encrypted: true, .. })));
+        send(false);
+        assert!(matches!(app.poll_timeout(Duration::from_secs(5)), Event::Login(LoginEvent { encrypted: false, .. })));
+
+        // Clear requests are still accepted when encryption is disabled.
+        app.remove_encryption();
+        send(false);
+        assert!(matches!(app.poll_timeout(Duration::from_secs(5)), Event::Login(LoginEvent { encrypted: false, .. })));
+
+    }
+
 }
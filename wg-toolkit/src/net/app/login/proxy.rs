@@ -1,8 +1,8 @@
 use std::collections::{hash_map, HashMap, VecDeque};
-use std::net::{SocketAddr, SocketAddrV4};
+use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use std::sync::Arc;
-use std::io;
+use std::{fmt, io};
 
 use rsa::{RsaPrivateKey, RsaPublicKey};
 use crypto_common::KeyInit;
@@ -11,7 +11,7 @@ use blowfish::Blowfish;
 use tracing::{trace, trace_span};
 
 use crate::net::bundle::{Bundle, NextElementReader, ReplyReader, ElementReader};
-use crate::net::app::login::element::{ChallengeResponse, CuckooCycleResponse};
+use crate::net::app::login::element::{ChallengeResponse, RawChallengeResponse};
 use crate::net::app::proxy::{UNSPECIFIED_ADDR, RECV_TIMEOUT};
 use crate::net::socket::PacketSocket;
 use crate::net::proto::Protocol;
@@ -19,7 +19,7 @@ use crate::net::packet::Packet;
 
 use crate::util::thread::{ThreadPoll, ThreadPollHandle};
 
-use super::element::{self, LoginError, LoginRequest, LoginResponse, Ping};
+use super::element::{self, check_base_app_addr, LoginError, LoginRequest, LoginResponse, Ping};
 use super::io_invalid_data;
 
 
@@ -48,11 +48,19 @@ struct Inner {
     /// order to validate.
     encryption_key: Option<Arc<RsaPrivateKey>>,
     /// Allows modifying the base app address returned to the client.
-    forced_base_app_addr: Option<SocketAddrV4>,
+    forced_base_app_addr: Option<SocketAddr>,
     /// The address of the real application where we proxy all packets.
     real_addr: SocketAddr,
     /// Encryption key for sending to the real login application.
     real_encryption_key: Option<Arc<RsaPublicKey>>,
+    /// Other real applications, mapped to their periphery id, that can be selected for
+    /// each login request instead of the default real application.
+    peripheries: HashMap<u32, RealApp>,
+    /// If present, used to select the periphery to forward each login request to.
+    periphery_selector: Option<Box<dyn PeripherySelector>>,
+    /// If present, requests are no longer forwarded to the real application and this
+    /// source is used to answer them instead.
+    replay_source: Option<Box<dyn ReplaySource>>,
     /// Protocol for accepting out packets and preparing in packets.
     out_protocol: Protocol,
     /// Protocol for accepting in packets and preparing out packets.
@@ -70,12 +78,22 @@ struct Peer {
     socket: PacketSocket,
     /// The address to send packets to the peer when receiving from real application.
     addr: SocketAddr,
+    /// The address of the real application this peer is forwarded to, this is the
+    /// default real application until a periphery is selected on login.
+    real_addr: SocketAddr,
     /// Last time a paquet was received from this peer.
     last_time: Instant,
     /// Information about the last request made by the client, if any.
     last_request: Option<PeerLastRequest>,
 }
 
+/// A real login application of a periphery.
+#[derive(Debug)]
+struct RealApp {
+    addr: SocketAddr,
+    encryption_key: Option<Arc<RsaPublicKey>>,
+}
+
 #[derive(Debug)]
 struct PeerLastRequest {
     request_id: u32,
@@ -108,7 +126,7 @@ impl App {
         socket.set_recv_timeout(Some(RECV_TIMEOUT))?;
 
         let thread_socket = socket.clone();
-        socket_poll.spawn(move || Some(SocketPollRet {
+        socket_poll.spawn_named(format!("lp-{}-{}", addr.port(), addr.ip()), move || Some(SocketPollRet {
             res: thread_socket.recv_without_encryption(),
             peer: None,
         }));
@@ -122,6 +140,9 @@ impl App {
                 forced_base_app_addr: None,
                 real_addr,
                 real_encryption_key,
+                peripheries: HashMap::new(),
+                periphery_selector: None,
+                replay_source: None,
                 out_protocol: Protocol::new(),
                 in_protocol: Protocol::new(),
                 bundle: Bundle::new(),
@@ -153,17 +174,78 @@ impl App {
     }
 
     /// Forcing the base app address allow redirecting clients that successfully login
-    /// into a given base app.
-    pub fn set_forced_base_app_addr(&mut self, addr: SocketAddrV4) {
+    /// into a given base app. This address must be IPv4 because the protocol doesn't
+    /// support IPv6, an invalid input error is returned otherwise.
+    pub fn set_forced_base_app_addr(&mut self, addr: SocketAddr) -> io::Result<()> {
+        check_base_app_addr(addr)?;
         self.inner.forced_base_app_addr = Some(addr);
+        Ok(())
     }
 
     pub fn remove_forced_base_app_addr(&mut self) {
         self.inner.forced_base_app_addr = None;
     }
 
+    /// Add a periphery, that is another real login application, given its address and
+    /// the optional encryption key for sending to it. Login requests are forwarded to
+    /// this periphery if the selector returns its id, see [`Self::set_periphery_selector`].
+    /// Any previous periphery with the same id is replaced.
+    pub fn add_periphery(&mut self, id: u32, real_addr: SocketAddr, real_encryption_key: Option<Arc<RsaPublicKey>>) {
+        self.inner.peripheries.insert(id, RealApp { addr: real_addr, encryption_key: real_encryption_key });
+    }
+
+    /// Remove the periphery with the given id, returning true if it was present. Peers
+    /// already forwarded to this periphery are not affected.
+    pub fn remove_periphery(&mut self, id: u32) -> bool {
+        self.inner.peripheries.remove(&id).is_some()
+    }
+
+    /// Set the selector used to choose, for each login request, the periphery to 
+    /// forward to. The peer's requests are then forwarded to the selected periphery,
+    /// the default real application is used if no periphery is selected.
+    pub fn set_periphery_selector(&mut self, selector: Box<dyn PeripherySelector>) {
+        self.inner.periphery_selector = Some(selector);
+    }
+
+    /// As opposed to [`Self::set_periphery_selector`], forward all login requests to
+    /// the default real application.
+    pub fn remove_periphery_selector(&mut self) {
+        self.inner.periphery_selector = None;
+    }
+
+    /// Stop forwarding requests to the real application and answer them using the 
+    /// given replay source instead. Events are still produced as if the responses came
+    /// from the real application and the client's blowfish key is still used to
+    /// encrypt login responses.
+    pub fn set_replay_source(&mut self, source: Box<dyn ReplaySource>) {
+        self.inner.replay_source = Some(source);
+    }
+
+    /// As opposed to [`Self::set_replay_source`], forward requests to the real 
+    /// application again.
+    pub fn remove_replay_source(&mut self) {
+        self.inner.replay_source = None;
+    }
+
+    /// Return true if requests are answered by a replay source instead of the real
+    /// application.
+    pub fn has_replay_source(&self) -> bool {
+        self.inner.replay_source.is_some()
+    }
+
     /// Poll for the next event of this login app, blocking.
     pub fn poll(&mut self) -> Event {
+        self.poll_inner(None)
+    }
+
+    /// Poll for the next event of this login app, blocking until the given timeout, in
+    /// which case [`Event::Idle`] is returned. Returning on timeout doesn't drop any
+    /// packet, a packet arriving just after will be returned by the next poll.
+    pub fn poll_timeout(&mut self, timeout: Duration) -> Event {
+        self.poll_inner(Some(Instant::now() + timeout))
+    }
+
+    fn poll_inner(&mut self, deadline: Option<Instant>) -> Event {
         loop {
 
             // Dropping dead peers, this will also terminate poll threads.
@@ -183,7 +265,16 @@ impl App {
                 return event;
             }
             
-            let socket_poll_ret = self.inner.socket_poll.poll();
+            let socket_poll_ret = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match self.inner.socket_poll.poll_timeout(remaining) {
+                        Some(ret) => ret,
+                        None => return Event::Idle,
+                    }
+                }
+                None => self.inner.socket_poll.poll(),
+            };
 
             let (packet, addr) = match socket_poll_ret.res {
                 Ok(ret) => ret,
@@ -234,7 +325,7 @@ impl App {
                         };
 
                         let thread_socket = socket.clone();
-                        let socket_poll_handle = self.inner.socket_poll.spawn_with_handle(move || Some(SocketPollRet {
+                        let socket_poll_handle = self.inner.socket_poll.spawn_with_handle_named(format!("lpp-{}-{}", addr.port(), addr.ip()), move || Some(SocketPollRet {
                             res: thread_socket.recv_without_encryption(),
                             peer: Some(addr),
                         }));
@@ -243,6 +334,7 @@ impl App {
                             socket_poll_handle,
                             socket,
                             addr,
+                            real_addr: self.inner.real_addr,
                             last_time: now,
                             last_request: None,
                         })
@@ -261,10 +353,15 @@ impl App {
                 continue;
             };
 
-            if socket_poll_ret.peer.is_some() {
-                self.inner.handle_in(bundle, peer).unwrap();
+            let addr = peer.addr;
+            let res = if socket_poll_ret.peer.is_some() {
+                self.inner.handle_in(bundle, peer)
             } else {
-                self.inner.handle_out(bundle, peer).unwrap();
+                self.inner.handle_out(bundle, peer)
+            };
+
+            if let Err(error) = res {
+                return Event::IoError(IoErrorEvent { error, addr: Some(addr) });
             }
 
         }
@@ -289,11 +386,17 @@ impl Inner {
         }
 
         if !self.bundle.is_empty() {
-            self.in_protocol.off_channel(peer.addr).prepare(&mut self.bundle, false);
-            // for packet in self.bundle.packets() {
-            //     debug!(">{}: [{:08X}] {:?}", self.real_addr, packet.raw().read_prefix(), packet.raw());
-            // }
-            peer.socket.send_bundle_without_encryption(&self.bundle, self.real_addr)?;
+            if self.replay_source.is_some() {
+                // The bundle contains replies to send back to the client.
+                self.out_protocol.off_channel(peer.addr).prepare(&mut self.bundle, false);
+                self.socket.send_bundle_without_encryption(&self.bundle, peer.addr)?;
+            } else {
+                self.in_protocol.off_channel(peer.addr).prepare(&mut self.bundle, false);
+                // for packet in self.bundle.packets() {
+                //     debug!(">{}: [{:08X}] {:?}", self.real_addr, packet.raw().read_prefix(), packet.raw());
+                // }
+                peer.socket.send_bundle_without_encryption(&self.bundle, peer.real_addr)?;
+            }
         }
 
         Ok(())
@@ -316,6 +419,15 @@ impl Inner {
         let request_id = ping.request_id
             .ok_or_else(|| io_invalid_data(format_args!("ping should be a request")))?;
 
+        if self.replay_source.is_some() {
+            self.events.push_back(Event::Ping(PingEvent {
+                addr: peer.addr,
+                latency: Duration::ZERO,
+            }));
+            self.bundle.element_writer().write_simple_reply(ping.element, request_id);
+            return Ok(());
+        }
+
         peer.last_request = Some(PeerLastRequest {
             request_id,
             time: Instant::now(),
@@ -344,13 +456,34 @@ impl Inner {
         let blowfish = Arc::new(Blowfish::new_from_slice(&login.element.blowfish_key)
             .map_err(|_| io_invalid_data(format_args!("login has invalid blowfish key: {:?}", login.element.blowfish_key)))?);
 
+        if let Some(source) = &mut self.replay_source {
+            let mut response = source.login(peer.addr, &login.element);
+            // No prefix to inherit because there is no real application.
+            self.handle_login_response(&mut response, peer, &blowfish)?;
+            self.bundle.element_writer().write_reply(response, request_id, &*blowfish);
+            return Ok(());
+        }
+
+        let periphery = self.periphery_selector.as_mut()
+            .and_then(|selector| selector.select(peer.addr, &login.element))
+            .and_then(|id| self.peripheries.get(&id));
+
+        let real_encryption_key;
+        if let Some(periphery) = periphery {
+            peer.real_addr = periphery.addr;
+            real_encryption_key = periphery.encryption_key.as_deref();
+        } else {
+            peer.real_addr = self.real_addr;
+            real_encryption_key = self.real_encryption_key.as_deref();
+        }
+
         peer.last_request = Some(PeerLastRequest {
             request_id,
             time: Instant::now(),
             kind: PeerLastRequestKind::Login { blowfish },
         });
 
-        if let Some(encryption_key) = self.real_encryption_key.as_deref() {
+        if let Some(encryption_key) = real_encryption_key {
             self.bundle.element_writer().write_request(login.element.clone(), request_id, encryption_key);
         } else {
             self.bundle.element_writer().write_simple_request(login.element.clone(), request_id);
@@ -361,8 +494,12 @@ impl Inner {
     }
 
     fn handle_challenge_response(&mut self, elt: ElementReader, _peer: &mut Peer) -> io::Result<()> {
-        let challenge = elt.read_simple::<ChallengeResponse<CuckooCycleResponse>>()?;
-        self.bundle.element_writer().write_simple(challenge.element);
+        // The response is forwarded as-is, so it works with any kind of challenge.
+        let challenge = elt.read_simple::<ChallengeResponse<RawChallengeResponse>>()?;
+        // Challenge responses are not answered, so we just drop them when replaying.
+        if self.replay_source.is_none() {
+            self.bundle.element_writer().write_simple(challenge.element);
+        }
         Ok(())
     }
 
@@ -433,32 +570,8 @@ impl Inner {
             PeerLastRequestKind::Login { blowfish } => {
 
                 let mut login = elt.read::<LoginResponse, _>(&*blowfish)?;
-                
-                if let LoginResponse::Success(success) = &mut login {
-
+                if self.handle_login_response(&mut login, peer, &blowfish)? {
                     *inherit_prefix = true;
-                    self.events.push_back(Event::LoginSuccess(LoginSuccessEvent {
-                        addr: peer.addr,
-                        blowfish: Arc::clone(&blowfish),
-                        real_base_app_addr: success.addr,
-                        login_key: success.login_key,
-                        server_message: success.server_message.clone(),
-                    }));
-
-                    // Change the base app just after the event, so the event still get the
-                    // non-forced address.
-                    if let Some(base_app_addr) = self.forced_base_app_addr {
-                        success.addr = base_app_addr;
-                    }
-                    
-                } else if let LoginResponse::Error(error, data) = &login {
-                    
-                    self.events.push_back(Event::LoginError(LoginErrorEvent {
-                        addr: peer.addr,
-                        error: *error,
-                        data: data.clone(),
-                    }));
-
                 }
 
                 self.bundle.element_writer().write_reply(login, request_id, &*blowfish);
@@ -470,11 +583,76 @@ impl Inner {
 
     }
 
+    /// Handle a login response before sending it to the client, this pushes the 
+    /// associated event and apply the forced base app address, if any. This returns
+    /// true if the login is successful, or an error if the base app address can't be
+    /// sent to the client, this can only happen with a replay source.
+    fn handle_login_response(&mut self, login: &mut LoginResponse, peer: &mut Peer, blowfish: &Arc<Blowfish>) -> io::Result<bool> {
+
+        if let LoginResponse::Success(success) = login {
+
+            check_base_app_addr(success.addr)?;
+
+            self.events.push_back(Event::LoginSuccess(LoginSuccessEvent {
+                addr: peer.addr,
+                blowfish: Arc::clone(blowfish),
+                real_base_app_addr: success.addr,
+                login_key: success.login_key,
+                server_message: success.server_message.clone(),
+            }));
+
+            // Change the base app just after the event, so the event still get the
+            // non-forced address.
+            if let Some(base_app_addr) = self.forced_base_app_addr {
+                success.addr = base_app_addr;
+            }
+
+            Ok(true)
+            
+        } else {
+
+            if let LoginResponse::Error(error, data) = &*login {
+                self.events.push_back(Event::LoginError(LoginErrorEvent {
+                    addr: peer.addr,
+                    error: *error,
+                    data: data.clone(),
+                }));
+            }
+
+            Ok(false)
+
+        }
+
+    }
+
+}
+
+/// A source of canned responses used by the proxy instead of forwarding requests to
+/// the real login application, see [`App::set_replay_source`].
+pub trait ReplaySource: fmt::Debug + Send {
+
+    /// Return the response to the login request of the client at the given address.
+    /// The response is encrypted with the blowfish key of the request if successful.
+    fn login(&mut self, addr: SocketAddr, request: &LoginRequest) -> LoginResponse;
+
+}
+
+/// A selector of the periphery to forward login requests to, see 
+/// [`App::set_periphery_selector`].
+pub trait PeripherySelector: fmt::Debug + Send {
+
+    /// Return the id of the periphery to forward the login request of the client at the
+    /// given address to, the default real application is used if none is returned or
+    /// if the periphery is unknown.
+    fn select(&mut self, addr: SocketAddr, request: &LoginRequest) -> Option<u32>;
+
 }
 
 /// An event that happened in the proxy login app regarding the login process.
 #[derive(Debug)]
 pub enum Event {
+    /// No packet has been received before the timeout, see [`App::poll_timeout`].
+    Idle,
     IoError(IoErrorEvent),
     Ping(PingEvent),
     LoginSuccess(LoginSuccessEvent),
@@ -511,7 +689,7 @@ pub struct LoginSuccessEvent {
     pub blowfish: Arc<Blowfish>,
     /// The address of the base app that was answered by the real server, if any base
     /// app address is forced then this value is still the value of the real server.
-    pub real_base_app_addr: SocketAddrV4,
+    pub real_base_app_addr: SocketAddr,
     /// The login key returned, used to authenticate to the base app.
     pub login_key: u32,
     /// The server message returned with the login success, usually a stringified JSON.
@@ -525,3 +703,146 @@ pub struct LoginErrorEvent {
     pub error: LoginError,
     pub data: String,
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use crate::net::packet::PacketConfig;
+    use crate::net::app::login;
+    use super::*;
+
+    /// Select the periphery from the username, for testing purpose.
+    #[derive(Debug)]
+    struct UsernameSelector;
+
+    impl PeripherySelector for UsernameSelector {
+        fn select(&mut self, _addr: SocketAddr, request: &LoginRequest) -> Option<u32> {
+            request.username.parse().ok()
+        }
+    }
+
+    #[test]
+    fn periphery() {
+
+        let mut real_1 = login::App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut real_2 = login::App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap(), real_1.addr().unwrap(), None).unwrap();
+        app.add_periphery(2, real_2.addr().unwrap(), None);
+        app.set_periphery_selector(Box::new(UsernameSelector));
+        let app_addr = app.addr().unwrap();
+
+        for username in ["2", "1"] {
+            let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let mut bundle = Bundle::new();
+            bundle.element_writer().write_simple_request(LoginRequest {
+                username: username.to_string(),
+                blowfish_key: vec![0; 16],
+                ..Default::default()
+            }, 1);
+            bundle.write_config(&mut PacketConfig::new());
+            client.send_bundle_without_encryption(&bundle, app_addr).unwrap();
+        }
+
+        // Forward both requests, the proxy is idle once both have been handled.
+        while !matches!(app.poll_timeout(Duration::from_millis(200)), Event::Idle) {}
+
+        let login::Event::Login(event) = real_2.poll_timeout(Duration::from_secs(5)) else { panic!() };
+        assert_eq!(event.request.username, "2");
+        let login::Event::Login(event) = real_1.poll_timeout(Duration::from_secs(5)) else { panic!() };
+        assert_eq!(event.request.username, "1");
+
+    }
+
+
+    /// Answer all login requests with a success, the login key is the username.
+    #[derive(Debug)]
+    struct SuccessSource;
+
+    impl ReplaySource for SuccessSource {
+        fn login(&mut self, _addr: SocketAddr, request: &LoginRequest) -> LoginResponse {
+            LoginResponse::Success(element::LoginSuccess {
+                addr: "127.0.0.1:20016".parse().unwrap(),
+                login_key: request.username.parse().unwrap(),
+                server_message: "replay".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn replay_blowfish() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap(), "127.0.0.1:1".parse().unwrap(), None).unwrap();
+        app.set_replay_source(Box::new(SuccessSource));
+        assert!(app.has_replay_source());
+
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let blowfish_key = (1..=16).collect::<Vec<u8>>();
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple_request(LoginRequest {
+            username: "1234".to_string(),
+            blowfish_key: blowfish_key.clone(),
+            ..Default::default()
+        }, 1);
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+        let Event::LoginSuccess(event) = app.poll_timeout(Duration::from_secs(5)) else { panic!() };
+        assert_eq!(event.addr, client.addr().unwrap());
+        assert_eq!(event.login_key, 1234);
+
+        // The response is encrypted with the client's key, which is the event's key.
+        let (packet, _) = client.recv_without_encryption().unwrap();
+        let bundle = Bundle::new_with_single(packet.read_config_locked().unwrap());
+        let client_blowfish = Blowfish::new_from_slice(&blowfish_key).unwrap();
+        for blowfish in [&client_blowfish, &*event.blowfish] {
+            let mut reader = bundle.element_reader();
+            let Some(NextElementReader::Reply(reply)) = reader.next() else { panic!() };
+            assert_eq!(reply.request_id(), 1);
+            let LoginResponse::Success(success) = reply.read::<LoginResponse, _>(blowfish).unwrap() else { panic!() };
+            assert_eq!((success.login_key, success.server_message.as_str()), (1234, "replay"));
+        }
+
+    }
+
+    /// Answer all login requests with a success to an IPv6 base app.
+    #[derive(Debug)]
+    struct Ipv6Source;
+
+    impl ReplaySource for Ipv6Source {
+        fn login(&mut self, _addr: SocketAddr, _request: &LoginRequest) -> LoginResponse {
+            LoginResponse::Success(element::LoginSuccess {
+                addr: "[::1]:20016".parse().unwrap(),
+                login_key: 0,
+                server_message: String::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn ipv6_base_app() {
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap(), "127.0.0.1:1".parse().unwrap(), None).unwrap();
+        let error = app.set_forced_base_app_addr("[::1]:20016".parse().unwrap()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        // An IPv6 address from the replay source is reported instead of being sent.
+        app.set_replay_source(Box::new(Ipv6Source));
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple_request(LoginRequest {
+            blowfish_key: vec![0; 16],
+            ..Default::default()
+        }, 1);
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+        let Event::IoError(event) = app.poll_timeout(Duration::from_secs(5)) else { panic!() };
+        assert_eq!(event.error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(event.addr, Some(client.addr().unwrap()));
+
+    }
+
+}
@@ -2,23 +2,30 @@
 //! to another application while providing debugging capabilities to inspect the network
 //! without being blocking by blowfish cipher.
 
+mod shaper;
+
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
-use std::collections::HashMap;
-use std::time::Duration;
-use std::sync::Arc;
-use std::io;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::{fmt, io};
 
 use blowfish::Blowfish;
+use indexmap::IndexMap;
 
-use tracing::{trace, trace_span};
+use tracing::{info, trace, trace_span, warn};
 
 use crate::net::packet::Packet;
 use crate::util::thread::ThreadPoll;
 use crate::net::proto::{ChannelIndex, Protocol};
-use crate::net::socket::{PacketSocket, decrypt_packet};
-use crate::net::bundle::Bundle;
+use crate::net::socket::{PacketSocket, decrypt_packet, encrypt_packet};
+use crate::net::bundle::{Bundle, BundleElement, RawElement};
+use crate::net::element::{ElementContext, ElementLength, REPLY_ID};
+use super::client::element::{id as client_id, ClientContext, SwitchBaseApp};
 use super::io_invalid_data;
 
+use shaper::Shaper;
+
 
 /// The unspecified address used to let the socket allocate its own address.
 pub(crate) const UNSPECIFIED_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
@@ -27,25 +34,73 @@ pub(crate) const UNSPECIFIED_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new
 /// continue running.
 pub(crate) const RECV_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Number of consecutive packets that failed to decrypt for a peer before a warning is 
+/// emitted, this usually means that the peer's encryption key is wrong.
+const DECRYPT_FAILURES_WARN_THRESHOLD: u32 = 16;
+
+/// Interval between two rounds of receive on all sockets when blocking on 
+/// [`App::poll`] in synchronous mode.
+const SYNC_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
 
 /// The generic proxy application.
+/// 
+/// By default, the listening socket and each peer's upstream socket are received from
+/// in their own thread. In synchronous mode, see [`App::new_sync`], no thread is 
+/// spawned and all sockets are received from, without blocking, by the thread calling
+/// [`App::poll`] or [`App::poll_once`].
 #[derive(Debug)]
 pub struct App {
-    /// Thread poll for socket result.
-    socket_poll: ThreadPoll<SocketPollRet>,
+    /// Thread poll for socket result, none in synchronous mode.
+    socket_poll: Option<ThreadPoll<SocketPollRet>>,
     /// The main socket receiving peer packets.
     socket: PacketSocket,
     /// Channel tracker for out packets.
     out_protocol: Protocol,
     /// Channel tracker for in packets.
     in_protocol: Protocol,
-    /// Each peer connected and forwarded. Using an index map because we use the peer's
-    /// index as the mio token (-1).
-    peers: HashMap<SocketAddr, Arc<Peer>>,
+    /// Each peer connected and forwarded. Using an index map because peers are polled
+    /// by index and in a stable order when receiving synchronously.
+    peers: IndexMap<SocketAddr, Arc<Peer>>,
     /// Filled when a peer is rejected and a Rejection event is returned, it allows the
     /// handler of that event to bind the missing peer and allow it to be accepted on
     /// next poll. 
     last_rejection: Option<(Packet, SocketAddr)>,
+    /// Optional handler called when the real application asks a peer to switch to
+    /// another base application.
+    switch_handler: Option<Box<dyn SwitchHandler>>,
+    /// Optional handler called to rewrite bundles before forwarding them.
+    transform_handler: Option<Box<dyn TransformHandler>>,
+    /// The local address to bind upstream sockets created for peers.
+    upstream_bind: SocketAddr,
+    /// In synchronous mode, index of the socket to receive from first on the next round,
+    /// zero being the listening socket and the following being each peer's socket.
+    sync_cursor: usize,
+}
+
+/// A handler that can be used to validate and override the base application a peer
+/// is asked to switch to by the real application, see [`App::set_switch_handler`].
+pub trait SwitchHandler: fmt::Debug + Send {
+
+    /// Called when the real application of the given peer, at address `from`, asks the
+    /// peer to switch to the base application at address `to`. Returning some address
+    /// rewrites the target sent to the peer, this can be used to redirect the peer to
+    /// another proxy port, while returning none keeps the given target.
+    fn on_base_switch(&mut self, peer: SocketAddr, from: SocketAddr, to: SocketAddr) -> Option<SocketAddr>;
+
+}
+
+/// A handler that can be used to rewrite bundles in transit between a peer and its real
+/// application, see [`App::set_transform_handler`].
+pub trait TransformHandler: fmt::Debug + Send {
+
+    /// Called with each intercepted bundle of the given peer, in the given direction,
+    /// before it is forwarded. Returning some bundle forwards it instead of the original
+    /// one, the returned bundle must fit in a single packet, and it will be sent with 
+    /// the original packet's configuration (sequence number, acks, channel). Returning
+    /// none forwards the original packet unchanged.
+    fn transform_bundle(&mut self, peer: SocketAddr, bundle: &Bundle, direction: PacketDirection) -> Option<Bundle>;
+
 }
 
 /// A registered peer that can forward and receive packets from the real application.
@@ -57,8 +112,40 @@ struct Peer {
     addr: SocketAddr,
     /// Real address of the base server to communicate with.
     real_addr: SocketAddr,
-    /// Encryption key for this peer.
-    blowfish: Option<Arc<Blowfish>>,
+    /// Encryption key for this peer, it can be changed while the peer is bound, the
+    /// same key is used in both directions.
+    blowfish: RwLock<Option<Arc<Blowfish>>>,
+    /// Optional shaper for packets sent to the real application.
+    out_shaper: Option<Shaper>,
+    /// Optional shaper for packets sent back to the peer.
+    in_shaper: Option<Shaper>,
+    /// Instant when this peer has been bound.
+    connected_at: Instant,
+    /// Instant of the last packet received from the peer or its real application.
+    last_activity: Mutex<Instant>,
+    /// Number of consecutive packets that failed to decrypt, reset on the first packet
+    /// successfully decrypted.
+    decrypt_failures: AtomicU32,
+}
+
+/// Configuration of the network conditions to emulate for a peer, each limit applies
+/// independently to both directions, packets are never reordered.
+#[derive(Debug, Clone, Default)]
+pub struct PeerConfig {
+    /// Maximum number of bytes per second forwarded in each direction.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Latency added to every forwarded packet.
+    pub added_latency: Option<Duration>,
+}
+
+impl PeerConfig {
+
+    /// Return true if this config requires shaping the peer's traffic.
+    #[inline]
+    fn is_shaping(&self) -> bool {
+        self.max_bytes_per_sec.is_some() || self.added_latency.is_some()
+    }
+
 }
 
 /// Type of return value for our socket poll. 
@@ -82,20 +169,51 @@ impl App {
         socket.set_recv_timeout(Some(RECV_TIMEOUT))?;
 
         let thread_socket = socket.clone();
-        socket_poll.spawn(move || Some(SocketPollRet {
+        socket_poll.spawn_named(format!("p-{}-{}", addr.port(), addr.ip()), move || Some(SocketPollRet {
             peer: None,
             res: thread_socket.recv_without_encryption(),
         }));
 
-        Ok(Self {
+        Ok(Self::with_socket(Some(socket_poll), socket))
+
+    }
+
+    /// Create a new proxy application in synchronous mode, with the given listening 
+    /// address. In this mode, no thread is spawned, neither for the listening socket nor
+    /// for peers, all sockets are non-blocking and are received from by the thread that
+    /// polls this application. 
+    /// 
+    /// Each peer still has its own upstream socket, this is required for the real 
+    /// application to distinguish peers (it identifies them by address) and it allows
+    /// packets received from upstream to be associated to their peer without ambiguity,
+    /// even when many peers share the same real application. Traffic shaping is not 
+    /// supported in this mode because it requires its own threads.
+    pub fn new_sync(addr: SocketAddr) -> io::Result<Self> {
+        let socket = PacketSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self::with_socket(None, socket))
+    }
+
+    fn with_socket(socket_poll: Option<ThreadPoll<SocketPollRet>>, socket: PacketSocket) -> Self {
+        Self {
             socket_poll,
             socket,
             out_protocol: Protocol::new(),
             in_protocol: Protocol::new(),
-            peers: HashMap::new(),
+            peers: IndexMap::new(),
             last_rejection: None,
-        })
+            switch_handler: None,
+            transform_handler: None,
+            upstream_bind: UNSPECIFIED_ADDR,
+            sync_cursor: 0,
+        }
+    }
 
+    /// Return true if this application has been created in synchronous mode, with
+    /// [`Self::new_sync`].
+    #[inline]
+    pub fn is_sync(&self) -> bool {
+        self.socket_poll.is_none()
     }
 
     /// Get the address this app is bound to.
@@ -103,32 +221,112 @@ impl App {
         self.socket.addr()
     }
 
+    /// Set the handler called when the real application asks a peer to switch to 
+    /// another base application. Only switches sent in a single-packet bundle can be
+    /// intercepted and rewritten. Note that packets sent to peers are decrypted to be
+    /// inspected when a handler is set.
+    pub fn set_switch_handler(&mut self, handler: Box<dyn SwitchHandler>) {
+        self.switch_handler = Some(handler);
+    }
+
+    /// Remove the switch handler, if any.
+    pub fn remove_switch_handler(&mut self) {
+        self.switch_handler = None;
+    }
+
+    /// Set the handler called to rewrite bundles before forwarding them. Only bundles
+    /// made of a single packet can be rewritten, others are forwarded unchanged. When no
+    /// handler is set, packets are forwarded without being decoded. The bundle events 
+    /// are still produced from the original packets.
+    pub fn set_transform_handler(&mut self, handler: Box<dyn TransformHandler>) {
+        self.transform_handler = Some(handler);
+    }
+
+    /// Remove the transform handler, if any.
+    pub fn remove_transform_handler(&mut self) {
+        self.transform_handler = None;
+    }
+
+    /// Set the local address to bind the upstream socket of peers bound after this 
+    /// call, when no socket is given to [`Self::bind_peer`]. This can be used on 
+    /// multi-homed hosts to choose the interface used to reach the real application, 
+    /// by default the unspecified address is used. This doesn't affect the listening
+    /// socket of this application.
+    pub fn set_upstream_bind(&mut self, addr: SocketAddr) {
+        self.upstream_bind = addr;
+    }
+
+    /// Get the local address used to bind the upstream socket of new peers.
+    #[inline]
+    pub fn upstream_bind(&self) -> SocketAddr {
+        self.upstream_bind
+    }
+
     pub fn bind_peer(&mut self, 
         addr: SocketAddr, 
         real_addr: SocketAddr, 
         blowfish: Option<Arc<Blowfish>>,
         socket: Option<PacketSocket>, 
     ) -> io::Result<()> {
+        self.bind_peer_with_config(addr, real_addr, blowfish, socket, PeerConfig::default())
+    }
+
+    /// Same as [`Self::bind_peer`] but with a config to emulate network conditions
+    /// for the forwarded traffic of this peer. Shaping the traffic is not supported in
+    /// synchronous mode and returns an unsupported error, it also returns an error if
+    /// the shapers' threads can't be spawned.
+    pub fn bind_peer_with_config(&mut self, 
+        addr: SocketAddr, 
+        real_addr: SocketAddr, 
+        blowfish: Option<Arc<Blowfish>>,
+        socket: Option<PacketSocket>, 
+        config: PeerConfig,
+    ) -> io::Result<()> {
+
+        if self.is_sync() && config.is_shaping() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "traffic shaping is not supported in synchronous mode"));
+        }
 
         let socket = match socket {
             Some(socket) => socket,
-            None => PacketSocket::bind(UNSPECIFIED_ADDR)?
+            None => PacketSocket::bind(self.upstream_bind)?
         };
 
-        socket.set_recv_timeout(Some(RECV_TIMEOUT))?;
+        if self.is_sync() {
+            socket.set_nonblocking(true)?;
+        } else {
+            socket.set_recv_timeout(Some(RECV_TIMEOUT))?;
+        }
+
+        let (out_shaper, in_shaper) = if config.is_shaping() {
+            (
+                Some(Shaper::new(socket.clone(), real_addr, config.max_bytes_per_sec, config.added_latency)?),
+                Some(Shaper::new(self.socket.clone(), addr, config.max_bytes_per_sec, config.added_latency)?),
+            )
+        } else {
+            (None, None)
+        };
 
+        let now = Instant::now();
         let peer = Arc::new(Peer {
             socket,
             addr,
             real_addr,
-            blowfish,
+            blowfish: RwLock::new(blowfish),
+            out_shaper,
+            in_shaper,
+            connected_at: now,
+            last_activity: Mutex::new(now),
+            decrypt_failures: AtomicU32::new(0),
         });
 
-        let thread_peer = Arc::clone(&peer);
-        self.socket_poll.spawn(move || Some(SocketPollRet {
-            peer: Some(Arc::clone(&thread_peer)),
-            res: thread_peer.socket.recv_without_encryption(),
-        }));
+        if let Some(socket_poll) = &self.socket_poll {
+            let thread_peer = Arc::clone(&peer);
+            socket_poll.spawn_named(format!("pp-{}-{}", addr.port(), addr.ip()), move || Some(SocketPollRet {
+                peer: Some(Arc::clone(&thread_peer)),
+                res: thread_peer.socket.recv_without_encryption(),
+            }));
+        }
 
         self.peers.insert(addr, peer);
 
@@ -136,8 +334,67 @@ impl App {
         
     }
 
-    /// Poll for the next event of this login app, blocking.
+    /// Change the encryption key of the given peer, for both directions at once. This
+    /// can be used when a session renegotiates its key, the new key is used starting 
+    /// with the next packet polled, and the decrypt failures counter is reset. Returns 
+    /// false if the peer is not bound.
+    pub fn set_peer_blowfish(&mut self, addr: SocketAddr, blowfish: Option<Arc<Blowfish>>) -> bool {
+        let Some(peer) = self.peers.get(&addr) else {
+            return false;
+        };
+        *peer.blowfish.write().unwrap() = blowfish;
+        peer.decrypt_failures.store(0, Ordering::Relaxed);
+        true
+    }
+
+    /// Get the current encryption key of the given peer, if bound and encrypted.
+    pub fn peer_blowfish(&self, addr: SocketAddr) -> Option<Arc<Blowfish>> {
+        self.peers.get(&addr)?.blowfish()
+    }
+
+    /// Get the instant when the given peer has been bound, if bound.
+    pub fn peer_connected_at(&self, addr: SocketAddr) -> Option<Instant> {
+        Some(self.peers.get(&addr)?.connected_at)
+    }
+
+    /// Get the duration since the last packet received from the given peer or from its
+    /// real application, if bound. This is the duration since the peer has been bound
+    /// if no packet has been received yet.
+    pub fn peer_idle_for(&self, addr: SocketAddr) -> Option<Duration> {
+        Some(self.peers.get(&addr)?.last_activity.lock().unwrap().elapsed())
+    }
+
+    /// Get the number of consecutive packets of the given peer, in any direction, that
+    /// failed to decrypt, if bound. The counter is reset when a packet is successfully
+    /// decrypted, so a growing counter usually means that the peer's key is wrong.
+    pub fn peer_decrypt_failures(&self, addr: SocketAddr) -> Option<u32> {
+        Some(self.peers.get(&addr)?.decrypt_failures.load(Ordering::Relaxed))
+    }
+
+    /// Poll for the next event of this proxy app, blocking. In synchronous mode, this
+    /// receives from all sockets in turn until an event is produced, sleeping 1 ms 
+    /// between two rounds where no socket had a pending packet, so an idle proxy still
+    /// wakes up 1000 times per second and each packet may be delayed by up to 1 ms. If
+    /// this is not acceptable, use [`Self::poll_once`] from an existing event loop.
     pub fn poll(&mut self) -> Event {
+        loop {
+            if let Some(event) = self.poll_inner(true) {
+                break event;
+            }
+        }
+    }
+
+    /// Poll for the next event of this proxy app, without blocking, returning none if 
+    /// no event is available. In synchronous mode, this receives from all sockets until
+    /// an event is produced or no more packet is pending, this can be used to integrate
+    /// the proxy into an existing event loop.
+    pub fn poll_once(&mut self) -> Option<Event> {
+        self.poll_inner(false)
+    }
+
+    /// Internal poll function, only blocking if requested, if not blocking then none is
+    /// returned if no more packet is pending.
+    fn poll_inner(&mut self, block: bool) -> Option<Event> {
         loop {
 
             let ignore_rejection;
@@ -150,61 +407,92 @@ impl App {
                 };
             } else {
                 ignore_rejection = false;
-                socket_poll_ret = self.socket_poll.poll();
+                socket_poll_ret = match (&self.socket_poll, block) {
+                    (Some(socket_poll), true) => socket_poll.poll(),
+                    (Some(socket_poll), false) => socket_poll.try_poll()?,
+                    (None, _) => match self.recv_sync() {
+                        Some(ret) => ret,
+                        None if block => {
+                            std::thread::sleep(SYNC_POLL_INTERVAL);
+                            continue;
+                        }
+                        None => return None,
+                    }
+                };
             }
 
             let (cipher_packet, addr) = match socket_poll_ret.res {
                 Ok(ret) => ret,
                 Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => continue,
                 Err(e) => {
-                    return Event::IoError(IoErrorEvent {
+                    return Some(Event::IoError(IoErrorEvent {
                         error: e,
                         addr: None,
-                    });
+                    }));
                 }
             };
 
             let peer;
             let direction;
-            let res;
             if let Some(peer_) = &socket_poll_ret.peer {
                 peer = &**peer_;
                 direction = PacketDirection::In;
-                res = self.socket.send_without_encryption(&cipher_packet, peer.addr);
             } else if let Some(peer_) = self.peers.get(&addr) {
                 peer = &**peer_;
                 direction = PacketDirection::Out;
-                res = peer.socket.send_without_encryption(&cipher_packet, peer.real_addr);
             } else {
                 if ignore_rejection {
                     continue;
                 } else {
                     self.last_rejection = Some((cipher_packet, addr));
-                    return Event::Rejection(RejectionEvent {
+                    return Some(Event::Rejection(RejectionEvent {
                         addr,
-                    });
+                    }));
+                }
+            }
+
+            *peer.last_activity.lock().unwrap() = Instant::now();
+
+            // The switch and transform handlers may rewrite the packet forwarded, the event
+            // is still produced from the original packet.
+            let mut forward_packet = None;
+            if direction == PacketDirection::In {
+                if let Some(handler) = self.switch_handler.as_deref_mut() {
+                    forward_packet = rewrite_base_switch(handler, peer, &cipher_packet);
+                }
+            }
+
+            if let Some(handler) = self.transform_handler.as_deref_mut() {
+                let packet = forward_packet.as_ref().unwrap_or(&cipher_packet);
+                if let Some(packet) = transform_bundle(handler, peer, packet, direction) {
+                    forward_packet = Some(packet);
                 }
             }
 
-            if let Err(e) = res {
-                return Event::IoError(IoErrorEvent {
+            if let Err(e) = peer.send_packet(&self.socket, forward_packet.as_ref().unwrap_or(&cipher_packet), direction) {
+                return Some(Event::IoError(IoErrorEvent {
                     error: e,
                     addr: Some(peer.addr),
-                });
+                }));
             }
 
             let packet;
-            if let Some(blowfish) = peer.blowfish.as_deref() {
-                packet = match decrypt_packet(cipher_packet, blowfish) {
-                    Ok(ret) => ret,
+            if let Some(blowfish) = peer.blowfish() {
+                packet = match decrypt_packet(cipher_packet, &blowfish) {
+                    Ok(ret) => {
+                        peer.decrypt_failures.store(0, Ordering::Relaxed);
+                        ret
+                    }
                     Err(_cipher_packet) => {
-                        // warn!("invalid encryption, continuing without it...");
-                        // cipher_packet
-                        // warn!(direction = ?direction, "Cipher packet: {:?}", cipher_packet.raw());
-                        return Event::IoError(IoErrorEvent {
+                        // Only warn once when reaching the threshold, until reset.
+                        let failures = peer.decrypt_failures.fetch_add(1, Ordering::Relaxed) + 1;
+                        if failures == DECRYPT_FAILURES_WARN_THRESHOLD {
+                            warn!(addr = %peer.addr, "{failures} consecutive packets failed to decrypt, the encryption key may be wrong");
+                        }
+                        return Some(Event::IoError(IoErrorEvent {
                             error: io_invalid_data(format_args!("invalid packet encryption")),
                             addr: Some(addr),
-                        });
+                        }));
                     }
                 };
             } else {
@@ -240,19 +528,227 @@ impl App {
                 continue;
             };
 
-            return Event::Bundle(BundleEvent {
+            return Some(Event::Bundle(BundleEvent {
                 addr: peer.addr,
                 bundle,
                 direction,
                 channel: channel.is_on().then(|| PacketChannel {
                     index: channel.index(),
                 }),
-            })
+            }))
 
         }
 
     }
 
+    /// Receive the next pending packet in synchronous mode, from the listening socket 
+    /// and each peer's upstream socket, returning none if no socket has a pending packet.
+    /// The first socket received from is rotated after each packet, so a busy socket 
+    /// cannot starve the others.
+    fn recv_sync(&mut self) -> Option<SocketPollRet> {
+
+        let sockets_count = 1 + self.peers.len();
+        let start = self.sync_cursor % sockets_count;
+
+        for offset in 0..sockets_count {
+
+            let index = (start + offset) % sockets_count;
+            let (socket, peer) = match index {
+                0 => (&self.socket, None),
+                _ => {
+                    let (_, peer) = self.peers.get_index(index - 1).unwrap();
+                    (&peer.socket, Some(peer))
+                }
+            };
+
+            match socket.recv_without_encryption() {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => {
+                    self.sync_cursor = index + 1;
+                    return Some(SocketPollRet { res, peer: peer.map(Arc::clone) });
+                }
+            }
+
+        }
+
+        None
+
+    }
+
+}
+
+impl Peer {
+
+    /// Get the current encryption key of this peer.
+    #[inline]
+    fn blowfish(&self) -> Option<Arc<Blowfish>> {
+        self.blowfish.read().unwrap().clone()
+    }
+
+    /// Forward the given packet in the given direction, the packet is queued in the
+    /// direction's shaper if any.
+    fn send_packet(&self, socket: &PacketSocket, packet: &Packet, direction: PacketDirection) -> io::Result<()> {
+        match direction {
+            PacketDirection::Out => {
+                if let Some(shaper) = &self.out_shaper {
+                    shaper.send(packet.clone());
+                } else {
+                    self.socket.send_without_encryption(packet, self.real_addr)?;
+                }
+            }
+            PacketDirection::In => {
+                if let Some(shaper) = &self.in_shaper {
+                    shaper.send(packet.clone());
+                } else {
+                    socket.send_without_encryption(packet, self.addr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+}
+
+/// Look for a switch base app element in the given packet sent by the real application
+/// to the peer, and return the re-encoded packet if the handler changed its target. 
+fn rewrite_base_switch(handler: &mut dyn SwitchHandler, peer: &Peer, cipher_packet: &Packet) -> Option<Packet> {
+
+    let blowfish = peer.blowfish();
+    let packet = match blowfish.as_deref() {
+        Some(blowfish) => decrypt_packet(cipher_packet.clone(), blowfish).ok()?,
+        None => cipher_packet.clone(),
+    };
+
+    let prefix = packet.read_prefix();
+    let packet = packet.read_config_locked().ok()?;
+
+    // Multi-packet bundles can't be rewritten because their sequence range is already
+    // allocated, we can only warn if the switch is at the start of the first fragment.
+    if let Some((first_num, _)) = packet.config().sequence_range() {
+        if packet.config().sequence_num() == first_num {
+            let bundle = Bundle::new_with_single(packet);
+            if bundle.element_reader().next_id() == Some(client_id::SWITCH_BASE_APP) {
+                warn!(addr = %peer.addr, "Base app switch in a multi-packet bundle can't be rewritten");
+            }
+        }
+        return None;
+    }
+
+    let mut config = packet.config().clone();
+    let bundle = Bundle::new_with_single(packet);
+    let mut reader = bundle.element_reader();
+    let mut elements = Vec::new();
+    let mut switch: Option<(usize, SwitchBaseApp)> = None;
+
+    while let Some(id) = reader.next_id() {
+
+        let len = if id == REPLY_ID {
+            ElementLength::Variable32
+        } else if let Some((_, Some(len))) = ClientContext.element_info(id) {
+            len
+        } else if let Some((_, switch)) = &switch {
+            // The remaining elements can't be copied, so the bundle can't be rebuilt.
+            warn!(addr = %peer.addr, "Base app switch to {} can't be rewritten, unknown element #{id} after it", switch.base_addr);
+            return None;
+        } else {
+            return None;
+        };
+
+        if id == client_id::SWITCH_BASE_APP && switch.is_none() {
+            let elt = reader.read::<SwitchBaseApp, _>(&(), false).ok()?;
+            switch = Some((elements.len(), elt.element));
+        }
+
+        elements.push((reader.read::<RawElement, _>(&len, true).ok()?, len));
+
+    }
+
+    let (switch_index, switch) = switch?;
+    let to = SocketAddr::V4(switch.base_addr);
+
+    let new_to = handler.on_base_switch(peer.addr, peer.real_addr, to);
+    let target = new_to.unwrap_or(to);
+    
+    info!(addr = %peer.addr, "Base app switch from {} to {to}, peer target: {target}", peer.real_addr);
+    if target.ip().is_unspecified() || target.port() == 0 {
+        warn!(addr = %peer.addr, "Base app switch target is unreachable: {target}");
+    }
+
+    let SocketAddr::V4(new_to) = new_to? else {
+        warn!(addr = %peer.addr, "Base app switch target must be IPv4, keeping: {to}");
+        return None;
+    };
+
+    let mut new_bundle = Bundle::new();
+    let mut writer = new_bundle.element_writer();
+    for (index, (elt, len)) in elements.into_iter().enumerate() {
+        if index == switch_index {
+            writer.write_raw(BundleElement {
+                element: SwitchBaseApp { base_addr: new_to, reset_entities: switch.reset_entities },
+                request_id: elt.request_id,
+            }, &());
+        } else {
+            writer.write_raw(elt, &len);
+        }
+    }
+
+    if new_bundle.len() > 1 {
+        warn!(addr = %peer.addr, "Rewritten base app switch doesn't fit in a single packet, forwarding the original one");
+        return None;
+    }
+
+    // The packet's config is written again, this also recomputes the checksum.
+    new_bundle.write_config(&mut config);
+    new_bundle.write_prefix(prefix);
+    let packet = new_bundle.into_iter().next()?;
+
+    Some(match blowfish.as_deref() {
+        Some(blowfish) => encrypt_packet(packet, blowfish),
+        None => packet,
+    })
+
+}
+
+/// Decode the given single-packet bundle and give it to the transform handler, if the 
+/// handler returns a new bundle then it's re-encoded with the original packet's config.
+fn transform_bundle(handler: &mut dyn TransformHandler, peer: &Peer, cipher_packet: &Packet, direction: PacketDirection) -> Option<Packet> {
+
+    let blowfish = peer.blowfish();
+    let packet = match blowfish.as_deref() {
+        Some(blowfish) => decrypt_packet(cipher_packet.clone(), blowfish).ok()?,
+        None => cipher_packet.clone(),
+    };
+
+    let prefix = packet.read_prefix();
+    let packet = packet.read_config_locked().ok()?;
+
+    // Multi-packet bundles can't be rewritten because their sequence range is already
+    // allocated, and it's not possible to rewrite a single fragment.
+    if packet.config().sequence_range().is_some() {
+        return None;
+    }
+
+    let mut config = packet.config().clone();
+    let bundle = Bundle::new_with_single(packet);
+    let mut new_bundle = handler.transform_bundle(peer.addr, &bundle, direction)?;
+
+    if new_bundle.len() > 1 {
+        warn!(addr = %peer.addr, "Transformed bundle doesn't fit in a single packet, forwarding the original one");
+        return None;
+    } else if new_bundle.is_empty() {
+        // All elements have been removed, we still need to forward the packet's config.
+        new_bundle.push_empty();
+    }
+
+    new_bundle.write_config(&mut config);
+    new_bundle.write_prefix(prefix);
+    let packet = new_bundle.into_iter().next()?;
+
+    Some(match blowfish.as_deref() {
+        Some(blowfish) => encrypt_packet(packet, blowfish),
+        None => packet,
+    })
+
 }
 
 /// An event that happened in the login app regarding the login process.
@@ -303,3 +799,431 @@ pub enum PacketDirection {
 pub struct PacketChannel {
     pub index: Option<ChannelIndex>,
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use crate::net::app::base::element::{SessionKey, DisconnectClient, DisconnectReason};
+    use crate::net::app::client::element::TickSyncPeriodic;
+    use crate::net::bundle::NextElementReader;
+    use crate::net::element::{SimpleElement, DebugElementVariable16};
+    use crate::net::packet::PacketConfig;
+
+    use super::*;
+
+    /// Drop all disconnect elements in bundles sent to the real application.
+    #[derive(Debug)]
+    struct DropDisconnect;
+
+    impl TransformHandler for DropDisconnect {
+        fn transform_bundle(&mut self, _peer: SocketAddr, bundle: &Bundle, direction: PacketDirection) -> Option<Bundle> {
+            assert_eq!(direction, PacketDirection::Out);
+            let mut new_bundle = Bundle::new();
+            let mut reader = bundle.element_reader();
+            while let Some(NextElementReader::Element(elt)) = reader.next() {
+                match elt.id() {
+                    SessionKey::ID => new_bundle.element_writer().write_simple(elt.read_simple::<SessionKey>().unwrap().element),
+                    DisconnectClient::ID => { elt.read_simple::<DisconnectClient>().unwrap(); }
+                    id => panic!("unexpected element #{id}"),
+                }
+            }
+            Some(new_bundle)
+        }
+    }
+
+    /// Redirect all base app switches to the given address.
+    #[derive(Debug)]
+    struct RedirectSwitch(SocketAddr);
+
+    impl SwitchHandler for RedirectSwitch {
+        fn on_base_switch(&mut self, _peer: SocketAddr, _from: SocketAddr, _to: SocketAddr) -> Option<SocketAddr> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn peer_activity() {
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let client_addr = client.addr().unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(app.peer_idle_for(client_addr).is_none());
+        app.bind_peer(client_addr, real.addr().unwrap(), None, None).unwrap();
+        let connected_at = app.peer_connected_at(client_addr).unwrap();
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(app.peer_idle_for(client_addr).unwrap() >= Duration::from_millis(20));
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        real.recv_without_encryption().unwrap();
+        assert!(app.peer_idle_for(client_addr).unwrap() < connected_at.elapsed());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(app.peer_idle_for(client_addr).unwrap() >= Duration::from_millis(20));
+        assert_eq!(app.peer_connected_at(client_addr), Some(connected_at));
+
+    }
+
+    #[test]
+    fn upstream_bind() {
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        // Find a free local port to bind the upstream socket to.
+        let upstream_addr = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap().addr().unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let app_addr = app.addr().unwrap();
+        assert_eq!(app.upstream_bind(), UNSPECIFIED_ADDR);
+        app.set_upstream_bind(upstream_addr);
+        app.bind_peer(client.addr().unwrap(), real.addr().unwrap(), None, None).unwrap();
+        assert_eq!(app.addr().unwrap(), app_addr);
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app_addr).unwrap();
+
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        let (_, from) = real.recv_without_encryption().unwrap();
+        assert_eq!(from, upstream_addr);
+
+    }
+
+    #[test]
+    fn decrypt_failures() {
+
+        use crypto_common::KeyInit;
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let client_addr = client.addr().unwrap();
+        let blowfish = Arc::new(Blowfish::new_from_slice(&[0x42; 16]).unwrap());
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.bind_peer(client_addr, real.addr().unwrap(), Some(Arc::clone(&blowfish)), None).unwrap();
+        assert_eq!(app.peer_decrypt_failures(client_addr), Some(0));
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+        let packet = bundle.iter().next().unwrap().clone();
+
+        // Packets encrypted with another key are garbage for the proxy.
+        let wrong_blowfish = Blowfish::new_from_slice(&[0x24; 16]).unwrap();
+        for failures in 1..=3 {
+            client.send_without_encryption(&encrypt_packet(packet.clone(), &wrong_blowfish), app.addr().unwrap()).unwrap();
+            let Event::IoError(_) = app.poll() else { panic!() };
+            assert_eq!(app.peer_decrypt_failures(client_addr), Some(failures));
+        }
+
+        client.send_without_encryption(&encrypt_packet(packet, &blowfish), app.addr().unwrap()).unwrap();
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        assert_eq!(app.peer_decrypt_failures(client_addr), Some(0));
+
+    }
+
+    #[test]
+    fn change_blowfish() {
+
+        use crypto_common::KeyInit;
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client_addr = client.addr().unwrap();
+        let old_blowfish = Arc::new(Blowfish::new_from_slice(&[0x42; 16]).unwrap());
+        let new_blowfish = Arc::new(Blowfish::new_from_slice(&[0x24; 16]).unwrap());
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.bind_peer(client_addr, real.addr().unwrap(), Some(Arc::clone(&old_blowfish)), None).unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+        let packet = bundle.iter().next().unwrap().clone();
+
+        client.send_without_encryption(&encrypt_packet(packet.clone(), &old_blowfish), app.addr().unwrap()).unwrap();
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        let (_, upstream_addr) = real.recv_without_encryption().unwrap();
+
+        assert!(app.set_peer_blowfish(client_addr, Some(Arc::clone(&new_blowfish))));
+        assert!(Arc::ptr_eq(&app.peer_blowfish(client_addr).unwrap(), &new_blowfish));
+
+        // The old key is no longer accepted.
+        client.send_without_encryption(&encrypt_packet(packet.clone(), &old_blowfish), app.addr().unwrap()).unwrap();
+        let Event::IoError(_) = app.poll() else { panic!() };
+        assert_eq!(app.peer_decrypt_failures(client_addr), Some(1));
+
+        // The next packet is decrypted with the new key, in both directions.
+        for direction in [PacketDirection::Out, PacketDirection::In] {
+
+            let cipher_packet = encrypt_packet(packet.clone(), &new_blowfish);
+            match direction {
+                PacketDirection::Out => client.send_without_encryption(&cipher_packet, app.addr().unwrap()).unwrap(),
+                PacketDirection::In => real.send_without_encryption(&cipher_packet, upstream_addr).unwrap(),
+            };
+
+            let Event::Bundle(event) = app.poll() else { panic!() };
+            assert_eq!(event.direction, direction);
+            let mut reader = event.bundle.element_reader();
+            let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+            assert_eq!(elt.read_simple::<SessionKey>().unwrap().element.session_key, 0x12345678);
+
+        }
+
+        assert_eq!(app.peer_decrypt_failures(client_addr), Some(0));
+
+    }
+
+    #[test]
+    fn shaping() {
+
+        const LATENCY: Duration = Duration::from_millis(100);
+        const RATE: u64 = 10_000;
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        let config = PeerConfig { max_bytes_per_sec: Some(RATE), added_latency: Some(LATENCY) };
+        app.bind_peer_with_config(client.addr().unwrap(), real.addr().unwrap(), None, None, config).unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+        let packet = bundle.iter().next().unwrap().clone();
+        let mut packets = Vec::new();
+        for _ in 0..5 {
+            let mut bundle = Bundle::new();
+            bundle.element_writer().write_simple(DebugElementVariable16::<0> { data: vec![0; 1000] });
+            bundle.write_config(&mut PacketConfig::new());
+            packets.push(bundle.iter().next().unwrap().clone());
+        }
+
+        // A single packet is delayed by the added latency.
+        let start = Instant::now();
+        client.send_without_encryption(&packet, app.addr().unwrap()).unwrap();
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        let (received, _) = real.recv_without_encryption().unwrap();
+        assert!(start.elapsed() >= LATENCY);
+        assert_eq!(received.len(), packet.len());
+
+        // Packets sent in a burst are rationed to the bandwidth, the last one can only
+        // be sent when all the previous ones have been transmitted.
+        let start = Instant::now();
+        for packet in &packets {
+            client.send_without_encryption(packet, app.addr().unwrap()).unwrap();
+            let Event::Bundle(_) = app.poll() else { panic!() };
+        }
+        for packet in &packets {
+            let (received, _) = real.recv_without_encryption().unwrap();
+            assert_eq!(received.len(), packet.len());
+        }
+        let transmit = packets[..4].iter().map(|packet| packet.len() as u64).sum::<u64>();
+        assert!(start.elapsed() >= LATENCY + Duration::from_secs_f64(transmit as f64 / RATE as f64));
+
+    }
+
+    #[test]
+    fn sync_two_peers() {
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let clients = [
+            PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap(),
+            PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap(),
+        ];
+
+        let mut app = App::new_sync("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(app.is_sync());
+        assert!(app.poll_once().is_none());
+
+        let config = PeerConfig { added_latency: Some(Duration::from_millis(10)), ..Default::default() };
+        let err = app.bind_peer_with_config(clients[0].addr().unwrap(), real.addr().unwrap(), None, None, config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+        for client in &clients {
+            app.bind_peer(client.addr().unwrap(), real.addr().unwrap(), None, None).unwrap();
+        }
+
+        let mut upstream_addrs = Vec::new();
+        for (i, client) in clients.iter().enumerate() {
+
+            let mut bundle = Bundle::new();
+            bundle.element_writer().write_simple(SessionKey { session_key: i as u32 });
+            bundle.write_config(&mut PacketConfig::new());
+            client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+            let Event::Bundle(event) = app.poll() else { panic!() };
+            assert_eq!(event.addr, client.addr().unwrap());
+            assert_eq!(event.direction, PacketDirection::Out);
+
+            let (_, from) = real.recv_without_encryption().unwrap();
+            upstream_addrs.push(from);
+
+        }
+
+        assert_ne!(upstream_addrs[0], upstream_addrs[1]);
+
+        // Reply in reverse order, each reply must be forwarded to its own peer.
+        for (i, client) in clients.iter().enumerate().rev() {
+
+            let mut bundle = Bundle::new();
+            bundle.element_writer().write_simple(SessionKey { session_key: 0x100 + i as u32 });
+            bundle.write_config(&mut PacketConfig::new());
+            real.send_bundle_without_encryption(&bundle, upstream_addrs[i]).unwrap();
+
+            let Event::Bundle(event) = app.poll() else { panic!() };
+            assert_eq!(event.addr, client.addr().unwrap());
+            assert_eq!(event.direction, PacketDirection::In);
+
+            client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+            let (packet, from) = client.recv_without_encryption().unwrap();
+            assert_eq!(from, app.addr().unwrap());
+            let bundle = Bundle::new_with_single(packet.read_config_locked().unwrap());
+            let mut reader = bundle.element_reader();
+            let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+            assert_eq!(elt.read_simple::<SessionKey>().unwrap().element.session_key, 0x100 + i as u32);
+
+        }
+
+        assert!(app.poll_once().is_none());
+
+    }
+
+    #[test]
+    fn sync_fairness() {
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut app = App::new_sync("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.bind_peer(client.addr().unwrap(), real.addr().unwrap(), None, None).unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        let (_, upstream_addr) = real.recv_without_encryption().unwrap();
+
+        // Flood the listening socket, the upstream reply must not wait for all of them.
+        for _ in 0..3 {
+            client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+        }
+        real.send_bundle_without_encryption(&bundle, upstream_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let directions = (0..2)
+            .map(|_| match app.poll() {
+                Event::Bundle(event) => event.direction,
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert!(directions.contains(&PacketDirection::In));
+
+    }
+
+    #[test]
+    fn transform_drop_element() {
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.bind_peer(client.addr().unwrap(), real.addr().unwrap(), None, None).unwrap();
+        app.set_transform_handler(Box::new(DropDisconnect));
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.element_writer().write_simple(DisconnectClient { reason: DisconnectReason::ClientRequested });
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+
+        // The event contains the original bundle.
+        let Event::Bundle(event) = app.poll() else { panic!() };
+        let mut reader = event.bundle.element_reader();
+        assert!(matches!(reader.next(), Some(NextElementReader::Element(elt)) if elt.id() == SessionKey::ID));
+
+        let (packet, _) = real.recv_without_encryption().unwrap();
+        let bundle = Bundle::new_with_single(packet.read_config_locked().unwrap());
+        let mut reader = bundle.element_reader();
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        assert_eq!(elt.read_simple::<SessionKey>().unwrap().element.session_key, 0x12345678);
+        assert!(reader.next().is_none());
+
+    }
+
+    #[test]
+    fn switch_redirect() {
+
+        let real = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        real.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let client = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let redirect_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 20017);
+        let mut app = App::new("127.0.0.1:0".parse().unwrap()).unwrap();
+        app.bind_peer(client.addr().unwrap(), real.addr().unwrap(), None, None).unwrap();
+        app.set_switch_handler(Box::new(RedirectSwitch(SocketAddr::V4(redirect_addr))));
+
+        // The real application needs the peer's upstream address to answer.
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(SessionKey { session_key: 0x12345678 });
+        bundle.write_config(&mut PacketConfig::new());
+        client.send_bundle_without_encryption(&bundle, app.addr().unwrap()).unwrap();
+        let Event::Bundle(_) = app.poll() else { panic!() };
+        let (_, upstream_addr) = real.recv_without_encryption().unwrap();
+
+        // Test with and without checksum, the checksum must be recomputed.
+        for has_checksum in [false, true] {
+
+            let mut config = PacketConfig::new();
+            config.set_has_checksum(has_checksum);
+
+            let mut bundle = Bundle::new();
+            bundle.element_writer().write_simple(SwitchBaseApp { 
+                base_addr: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 20016), 
+                reset_entities: true,
+            });
+            bundle.element_writer().write_simple(TickSyncPeriodic { data: [0x12, 0x34] });
+            bundle.write_config(&mut config);
+            real.send_bundle_without_encryption(&bundle, upstream_addr).unwrap();
+
+            let Event::Bundle(_) = app.poll() else { panic!() };
+
+            let (packet, _) = client.recv_without_encryption().unwrap();
+            let packet = packet.read_config_locked().unwrap();
+            assert_eq!(packet.config().has_checksum(), has_checksum);
+            let bundle = Bundle::new_with_single(packet);
+            let mut reader = bundle.element_reader();
+            let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+            let switch = elt.read_simple::<SwitchBaseApp>().unwrap().element;
+            assert_eq!(switch.base_addr, redirect_addr);
+            assert!(switch.reset_entities);
+            let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+            assert_eq!(elt.read_simple::<TickSyncPeriodic>().unwrap().element.data, [0x12, 0x34]);
+            assert!(reader.next().is_none());
+
+        }
+
+    }
+
+}
@@ -0,0 +1,78 @@
+//! Traffic shaping used by the proxy to emulate poor network conditions.
+
+use std::time::{Duration, Instant};
+use std::net::SocketAddr;
+use std::thread;
+use std::io;
+
+use crossbeam_channel::Sender;
+use tracing::warn;
+
+use crate::net::socket::PacketSocket;
+use crate::net::packet::Packet;
+
+
+/// A traffic shaper for a single direction of a peer, packets are queued and sent in
+/// order by a dedicated thread after being delayed by the configured latency and
+/// rationed to the configured bandwidth. The thread terminates when this shaper is
+/// dropped.
+#[derive(Debug)]
+pub struct Shaper {
+    tx: Sender<(Instant, Packet)>,
+}
+
+impl Shaper {
+
+    /// Create a new shaper sending packets to the given address through the given
+    /// socket, returning an error if the shaper's thread can't be spawned.
+    pub fn new(
+        socket: PacketSocket,
+        addr: SocketAddr,
+        max_bytes_per_sec: Option<u64>,
+        added_latency: Option<Duration>,
+    ) -> io::Result<Self> {
+
+        let (tx, rx) = crossbeam_channel::unbounded::<(Instant, Packet)>();
+
+        thread::Builder::new()
+            .name(format!("ps-{}-{}", addr.port(), addr.ip()))
+            .spawn(move || {
+
+                // The instant when the previous packet has been fully transmitted,
+                // according to the bandwidth limit.
+                let mut free_instant = Instant::now();
+
+                while let Ok((queued_instant, packet)) = rx.recv() {
+
+                    let mut send_instant = queued_instant + added_latency.unwrap_or_default();
+                    send_instant = send_instant.max(free_instant);
+
+                    let now = Instant::now();
+                    if send_instant > now {
+                        thread::sleep(send_instant - now);
+                    }
+
+                    if let Some(max_bytes_per_sec) = max_bytes_per_sec {
+                        let transmit = Duration::from_secs_f64(packet.len() as f64 / max_bytes_per_sec.max(1) as f64);
+                        free_instant = send_instant + transmit;
+                    }
+
+                    if let Err(e) = socket.send_without_encryption(&packet, addr) {
+                        warn!(%addr, "Failed to send shaped packet: {e}");
+                    }
+
+                }
+
+            })?;
+
+        Ok(Self { tx })
+
+    }
+
+    /// Queue a packet to be sent when the shaping constraints allow it.
+    pub fn send(&self, packet: Packet) {
+        // The thread only terminates when the sender is dropped, so this can't fail.
+        let _ = self.tx.send((Instant::now(), packet));
+    }
+
+}
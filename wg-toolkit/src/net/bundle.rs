@@ -6,8 +6,8 @@ use std::fmt;
 use tracing::warn;
 
 use super::packet::{self, PacketConfig, PacketLocked, Packet};
-use super::element::{Element, Reply, REPLY_ID};
-use super::codec::Codec;
+use super::element::{Element, ElementContext, Reply, RequestIdAlloc, REPLY_ID};
+use super::codec::{Codec, SimpleCodec};
 
 use crate::util::io::{WgReadExt, WgWriteExt, IoCounter};
 use crate::net::element::ElementLength;
@@ -46,6 +46,9 @@ pub struct Bundle {
     /// (starting with flags, so value 0 or 1 equals "no next request"). That offset is
     /// in content space.
     last_request_link_offset: Option<u16>,
+    /// True if this bundle should be sent reliably, this is also set when a reliable
+    /// packet is pushed, so this tells if a received bundle was reliable.
+    reliable: bool,
 }
 
 impl Bundle {
@@ -55,6 +58,7 @@ impl Bundle {
             packets: Vec::new(),
             free: 0,
             last_request_link_offset: None,
+            reliable: false,
         }
     }
 
@@ -77,11 +81,25 @@ impl Bundle {
         self.packets.is_empty()
     }
 
-    /// Clear the bundle by removing all packets.
+    /// Return true if this bundle is reliable, see [`Self::set_reliable`].
+    #[inline]
+    pub fn is_reliable(&self) -> bool {
+        self.reliable
+    }
+
+    /// Set this bundle to be reliable, when prepared for sending all of its packets will
+    /// have the reliable flag and will be tracked for acknowledgement.
+    #[inline]
+    pub fn set_reliable(&mut self, reliable: bool) {
+        self.reliable = reliable;
+    }
+
+    /// Clear the bundle by removing all packets, the bundle is also no longer reliable.
     pub fn clear(&mut self) {
         self.packets.clear();
         self.free = 0;
         self.last_request_link_offset = None;
+        self.reliable = false;
     }
 
     /// Push a new packet in this bundle, the packet must be locked to ensure that the
@@ -94,6 +112,10 @@ impl Bundle {
 
         let (packet, config) = locked.destruct();
 
+        if config.reliable() {
+            self.reliable = true;
+        }
+
         self.packets.push(BundlePacket {
             packet,
             len: (config.footer_offset() - packet::PACKET_HEADER_LEN) as u16,
@@ -123,6 +145,30 @@ impl Bundle {
 
     }
 
+    /// Append all elements of the given bundle to this bundle, by appending its packets
+    /// after the packets of this bundle. Requests and replies are preserved because
+    /// request links never cross packets, and the whole bundle will be fragmented
+    /// when writing its config. Subsequent writes are done after the other bundle's
+    /// elements. The merged bundle is reliable if any of the two bundles is reliable.
+    /// 
+    /// Note that the last packet of this bundle is not filled with other bundle's 
+    /// elements, so merging many small bundles may waste some packets.
+    pub fn merge(&mut self, other: Bundle) {
+
+        if other.packets.is_empty() {
+            self.reliable |= other.reliable;
+            return;
+        }
+
+        assert!(self.packets.len() + other.packets.len() <= BUNDLE_MAX_PACKET_COUNT, "too much packets");
+
+        self.packets.extend(other.packets);
+        self.free = other.free;
+        self.last_request_link_offset = other.last_request_link_offset;
+        self.reliable |= other.reliable;
+
+    }
+
     /// Write the given configuration to all packets in this bundle. However, there will
     /// be some modifications to the packet configuration to apply the bundle's 
     /// parameters: 
@@ -187,15 +233,57 @@ impl Bundle {
     }
 
     /// See [`BundleElementReader`].
+    /// 
+    /// The returned reader only borrows this bundle and is cheap to create, each call
+    /// returns a new reader starting at the first element, so a bundle can be iterated
+    /// many times, reading elements with one reader never affects the other readers.
     pub fn element_reader(&self) -> BundleElementReader<'_> {
         BundleElementReader::new(self)
     }
 
+    /// Quickly scan the identifiers of all elements in this bundle, in order, without
+    /// decoding their bodies. The given element context is used to know the length of
+    /// each element in order to skip it, the scan stops after the first element of 
+    /// unknown length, its id is the last one returned. Replies are returned with the
+    /// [`REPLY_ID`] identifier.
+    pub fn peek_element_ids<C: ElementContext>(&self, context: &C) -> Vec<u8> {
+
+        let mut ids = Vec::new();
+        let mut reader = self.element_reader();
+
+        while let Some(id) = reader.next_id() {
+
+            ids.push(id);
+
+            let len = if id == REPLY_ID {
+                Some(ElementLength::Variable32)
+            } else {
+                context.element_info(id).and_then(|(_, len)| len)
+            };
+
+            let Some(len) = len else { break };
+            if reader.read::<SkipElement, _>(&len, true).is_err() {
+                break;
+            }
+
+        }
+
+        ids
+
+    }
+
     /// See [`BundleElementWriter`].
     pub fn element_writer(&mut self) -> BundleElementWriter<'_> {
         BundleElementWriter::new(self)
     }
 
+    /// Return a debug formatter that decodes this bundle's elements using the given 
+    /// element context, used to name elements and give their length. Elements with 
+    /// unknown length stop the decoding and the remaining raw bytes are printed.
+    pub fn debug_with<'a, C: ElementContext>(&'a self, context: &'a C) -> BundleDebug<'a, C> {
+        BundleDebug { bundle: self, context }
+    }
+
     /// Reserve a single byte.
     fn reserve_single(&mut self) -> &mut u8 {
         if self.free == 0 {
@@ -501,6 +589,22 @@ impl<'a> BundleElementWriter<'a> {
         self.write_request(element, request_id, &())
     }
 
+    /// Add a request element to this bundle, with a request ID allocated from the given
+    /// allocator, the allocated request ID is returned.
+    #[inline]
+    pub fn write_request_auto<E: Element<C>, C>(&mut self, element: E, config: &C, alloc: &mut RequestIdAlloc) -> u32 {
+        let request_id = alloc.alloc();
+        self.write_request(element, request_id, config);
+        request_id
+    }
+
+    /// Add a request element to this bundle, with a request ID allocated from the given
+    /// allocator, the allocated request ID is returned. Such elements have no config.
+    #[inline]
+    pub fn write_simple_request_auto<E: Element<()>>(&mut self, element: E, alloc: &mut RequestIdAlloc) -> u32 {
+        self.write_request_auto(element, &(), alloc)
+    }
+
     /// Add a reply element to this bundle, for a given request ID.
     /// 
     /// Such elements are special and don't require an ID, because they 
@@ -518,6 +622,14 @@ impl<'a> BundleElementWriter<'a> {
         self.write_reply(data, request_id, &())
     }
 
+    /// Add a reply element to this bundle, for a given request ID, with the given raw
+    /// body. This is mostly useful for testing peers with crafted replies, the framing
+    /// is the same as any other reply.
+    #[inline]
+    pub fn write_raw_reply(&mut self, request_id: u32, body: &[u8]) {
+        self.write_simple_reply(RawBody(body), request_id)
+    }
+
     /// Raw method to add an element to this bundle, given an ID, the 
     /// element and its config. With an optional request ID.
     pub fn write_raw<E: Element<C>, C>(&mut self, element: BundleElement<E>, config: &C) {
@@ -603,6 +715,171 @@ impl<'a> BundleElementWriter<'a> {
 }
 
 
+/// Debug formatter of a bundle that decodes elements from a given context, see
+/// [`Bundle::debug_with()`].
+pub struct BundleDebug<'a, C> {
+    bundle: &'a Bundle,
+    context: &'a C,
+}
+
+impl<C: ElementContext> fmt::Debug for BundleDebug<'_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        let mut list = f.debug_list();
+        let mut reader = self.bundle.element_reader();
+
+        while let Some(id) = reader.next_id() {
+
+            if id == REPLY_ID {
+                if let Ok(reply) = reader.read::<Reply<RawData>, ()>(&(), true) {
+                    list.entry(&RawElementDebug {
+                        name: Some("Reply"),
+                        id,
+                        request_id: Some(reply.element.request_id),
+                        data: &reply.element.data.0,
+                    });
+                    continue;
+                }
+            }
+
+            let (name, len) = match self.context.element_info(id) {
+                Some((name, len)) => (Some(name), len),
+                None => (None, None),
+            };
+
+            if let Some(len) = len {
+                if let Ok(elt) = reader.read::<RawElement, _>(&len, true) {
+                    list.entry(&RawElementDebug {
+                        name,
+                        id,
+                        request_id: elt.request_id,
+                        data: &elt.element.data,
+                    });
+                    continue;
+                }
+            }
+
+            // Unknown length or invalid element, we can't go further so we print all the
+            // remaining data, starting with the element's id.
+            let mut data = Vec::new();
+            let _ = reader.bundle_reader.clone().read_to_end(&mut data);
+            list.entry(&RawElementDebug {
+                name,
+                id,
+                request_id: None,
+                data: &data,
+            });
+            break;
+
+        }
+
+        list.finish()
+
+    }
+}
+
+/// Internal element used to read raw data of an element, with its length as config, 
+/// the element can be written back as-is with the same config.
+pub(crate) struct RawElement {
+    pub(crate) id: u8,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Element<ElementLength> for RawElement {
+
+    fn write_length(&self, config: &ElementLength) -> io::Result<ElementLength> {
+        Ok(*config)
+    }
+
+    fn write(&self, write: &mut dyn Write, _config: &ElementLength) -> io::Result<u8> {
+        write.write_all(&self.data)?;
+        Ok(self.id)
+    }
+
+    fn read_length(config: &ElementLength, _id: u8) -> io::Result<ElementLength> {
+        Ok(*config)
+    }
+
+    fn read(read: &mut dyn Read, _config: &ElementLength, _len: usize, id: u8) -> io::Result<Self> {
+        read.read_blob_to_end().map(|data| Self { id, data })
+    }
+
+}
+
+/// Internal element used to skip an element's body without decoding nor copying it, 
+/// with its length as config.
+struct SkipElement;
+
+impl Element<ElementLength> for SkipElement {
+
+    fn write_length(&self, config: &ElementLength) -> io::Result<ElementLength> {
+        Ok(*config)
+    }
+
+    fn write(&self, _write: &mut dyn Write, _config: &ElementLength) -> io::Result<u8> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "skip element cannot be written"))
+    }
+
+    fn read_length(config: &ElementLength, _id: u8) -> io::Result<ElementLength> {
+        Ok(*config)
+    }
+
+    fn read(read: &mut dyn Read, _config: &ElementLength, _len: usize, _id: u8) -> io::Result<Self> {
+        io::copy(read, &mut io::sink()).map(|_| Self)
+    }
+
+}
+
+/// Internal codec used to read raw data of a reply.
+struct RawData(Vec<u8>);
+
+impl SimpleCodec for RawData {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_all(&self.0)
+    }
+
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        read.read_blob_to_end().map(Self)
+    }
+
+}
+
+/// Internal codec used to write raw body of a reply.
+struct RawBody<'a>(&'a [u8]);
+
+impl SimpleCodec for RawBody<'_> {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_all(self.0)
+    }
+
+    fn read(_read: &mut dyn Read) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "raw body cannot be read"))
+    }
+
+}
+
+/// Internal debug of a raw element.
+struct RawElementDebug<'a> {
+    name: Option<&'static str>,
+    id: u8,
+    request_id: Option<u32>,
+    data: &'a [u8],
+}
+
+impl fmt::Debug for RawElementDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct(self.name.unwrap_or("Unknown"));
+        s.field("id", &self.id);
+        if let Some(request_id) = self.request_id {
+            s.field("request_id", &request_id);
+        }
+        s.field("data", &AsciiFmt(self.data)).finish()
+    }
+}
+
+
 /// The structure used to iterate over a bundle's elements, providing
 /// a developer-friendly API that automatically handle reply elements.
 /// 
@@ -656,6 +933,46 @@ impl<'a> BundleElementReader<'a> {
         }
     }
 
+    /// Update the next request offset if the reader has moved to a new packet.
+    fn update_next_request_offset(&mut self) {
+        let packet_index = self.bundle_reader.packet_index();
+        if self.last_packet_index != packet_index {
+            self.next_request_offset = self.bundle_reader.packet().and_then(|p| p.first_request_offset);
+            self.last_packet_index = packet_index;
+        }
+    }
+
+    /// Get a borrowed view of the current element's body, given its length kind, 
+    /// without decoding nor copying it and without going to the next element. This
+    /// returns none if the body is not contiguous in a single packet, which is the
+    /// case if the element spans over multiple packets or if it's oversized, its
+    /// length also needs to be defined.
+    pub fn body_bytes(&mut self, elt_len_kind: ElementLength) -> io::Result<Option<&'a [u8]>> {
+
+        let Some(slice) = self.bundle_reader.ensure() else {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no more element to read from in the packets"));
+        };
+
+        if elt_len_kind == ElementLength::Undefined {
+            return Ok(None);
+        }
+
+        self.update_next_request_offset();
+        let request = self.next_request_offset == Some(self.bundle_reader.content_offset());
+        let header_len = 1 + elt_len_kind.len() + if request { REQUEST_HEADER_LEN } else { 0 };
+        if slice.len() < header_len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the header of the next element is not contiguous"));
+        }
+
+        // The length is located just after the element id.
+        let Some(elt_len) = elt_len_kind.read(&slice[1..])? else {
+            return Ok(None);
+        };
+
+        Ok(slice[header_len..].get(..elt_len as usize))
+
+    }
+
     /// Try to decode the current element using a given codec. You can choose to go
     /// to the next element using the `next` argument.
     pub fn read<E: Element<C>, C>(&mut self, config: &C, next: bool) -> io::Result<BundleElement<E>> {
@@ -666,11 +983,7 @@ impl<'a> BundleElementReader<'a> {
         };
         
         // We also update the next request offset if we are on a new packet!
-        let start_packet_index = self.bundle_reader.packet_index();
-        if self.last_packet_index != start_packet_index {
-            self.next_request_offset = self.bundle_reader.packet().and_then(|p| p.first_request_offset);
-            self.last_packet_index = start_packet_index;
-        }
+        self.update_next_request_offset();
 
         // Once we have a non-empty header slice, check if it correspond to the next 
         // request that we are expecting.
@@ -690,8 +1003,10 @@ impl<'a> BundleElementReader<'a> {
             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the header of the next element is not contiguous"));
         }
 
-        // Keep a clone in order to rollback if not 'next' or any error happens.
+        // Keep a clone in order to rollback if not 'next' or any error happens, the next
+        // request offset is also saved because it's updated when reading a request.
         let reader_save = self.bundle_reader.clone();
+        let next_request_offset_save = self.next_request_offset;
 
         // After length has been checked, we can read all this for sure, so we unwrap.
         let elt_id = self.bundle_reader.read_u8().unwrap();
@@ -736,6 +1051,7 @@ impl<'a> BundleElementReader<'a> {
             Ok(ret) => ret,
             Err(e) => {
                 self.bundle_reader = reader_save;  // Rollback before going further.
+                self.next_request_offset = next_request_offset_save;
                 return Err(e);
             }
         };
@@ -763,6 +1079,7 @@ impl<'a> BundleElementReader<'a> {
         } else {
             // Not going next, only rollback the internal reader.
             self.bundle_reader = reader_save;
+            self.next_request_offset = next_request_offset_save;
         }
 
         Ok(BundleElement {
@@ -809,7 +1126,7 @@ impl NextElementReader<'_, '_> {
 /// The simple variant of element, provides direct decoding using a codec.
 pub struct ElementReader<'reader, 'bundle>(&'reader mut BundleElementReader<'bundle>, u8);
 
-impl ElementReader<'_, '_> {
+impl<'bundle> ElementReader<'_, 'bundle> {
 
     /// Get the numeric identifier of the element being read.
     #[inline]
@@ -817,6 +1134,14 @@ impl ElementReader<'_, '_> {
         self.1
     }
 
+    /// Get a borrowed view of the element's body bytes without decoding it, the
+    /// slice borrows the bundle and not this reader. This returns none if the body
+    /// is not contiguous in the bundle, see [`BundleElementReader::body_bytes`].
+    #[inline]
+    pub fn body_bytes(&mut self, len: ElementLength) -> io::Result<Option<&'bundle [u8]>> {
+        self.0.body_bytes(len)
+    }
+
     /// Same as `read` but never go to the next element *(this is why this method doesn't take
     /// self by value)*.
     pub fn read_stable<E: Element<C>, C>(&mut self, config: &C) -> io::Result<BundleElement<E>> {
@@ -902,3 +1227,257 @@ impl fmt::Debug for ReplyReader<'_, '_> {
         f.debug_struct("ReplyReader").field("request_id", &self.1).finish()
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::net::packet::flags;
+    use crate::net::proto::Protocol;
+
+    #[test]
+    fn reliable_flag() {
+
+        let addr = "127.0.0.1:20016".parse().unwrap();
+        let mut protocol = Protocol::new();
+
+        let mut bundle = Bundle::new();
+        bundle.push_empty();
+        bundle.push_empty();
+        bundle.set_reliable(true);
+        protocol.off_channel(addr).prepare(&mut bundle, false);
+        assert!(bundle.iter().all(|packet| packet.read_flags() & flags::IS_RELIABLE != 0));
+
+        let mut bundle = Bundle::new();
+        bundle.push_empty();
+        protocol.off_channel(addr).prepare(&mut bundle, false);
+        assert!(bundle.iter().all(|packet| packet.read_flags() & flags::IS_RELIABLE == 0));
+
+    }
+
+    #[test]
+    fn raw_reply() {
+
+        let body = [0x01, 0x02, 0xFF, 0x00, 0x42];
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_raw_reply(0x12345678, &body);
+
+        let mut reader = bundle.element_reader();
+        let Some(NextElementReader::Reply(reply)) = reader.next() else {
+            panic!("expected a reply");
+        };
+        assert_eq!(reply.request_id(), 0x12345678);
+        assert_eq!(reply.read_simple::<RawData>().unwrap().0, body);
+        assert!(reader.next().is_none());
+
+    }
+
+    #[test]
+    fn request_auto() {
+
+        use crate::net::element::DebugElementVariable16;
+
+        let mut alloc = RequestIdAlloc::new(10);
+        let mut bundle = Bundle::new();
+        let first = bundle.element_writer().write_simple_request_auto(DebugElementVariable16::<5> { data: vec![1] }, &mut alloc);
+        let second = bundle.element_writer().write_simple_request_auto(DebugElementVariable16::<5> { data: vec![2] }, &mut alloc);
+        assert_ne!(first, second);
+
+        let mut reader = bundle.element_reader();
+        assert_eq!(reader.read::<DebugElementVariable16<5>, _>(&(), true).unwrap().request_id, Some(first));
+        assert_eq!(reader.read::<DebugElementVariable16<5>, _>(&(), true).unwrap().request_id, Some(second));
+
+    }
+
+    #[test]
+    fn request_read_rollback() {
+
+        use crate::net::element::{DebugElementVariable16, ElementLength, SimpleElement};
+        use crate::net::codec::SimpleCodec;
+
+        /// An element that needs more data than the written one.
+        struct Wide(u32);
+
+        impl SimpleCodec for Wide {
+            fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+                write.write_u32(self.0)
+            }
+            fn read(read: &mut dyn Read) -> io::Result<Self> {
+                read.read_u32().map(Self)
+            }
+        }
+
+        impl SimpleElement for Wide {
+            const ID: u8 = 5;
+            const LEN: ElementLength = ElementLength::Variable16;
+        }
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple_request(DebugElementVariable16::<5> { data: vec![1] }, 10);
+        bundle.element_writer().write_simple_request(DebugElementVariable16::<5> { data: vec![2] }, 11);
+
+        // The failed read must not consume the request header of the first element.
+        let mut reader = bundle.element_reader();
+        assert!(reader.read::<Wide, _>(&(), true).is_err());
+        let first = reader.read::<DebugElementVariable16<5>, _>(&(), true).unwrap();
+        assert_eq!((first.request_id, first.element.data), (Some(10), vec![1]));
+        let second = reader.read::<DebugElementVariable16<5>, _>(&(), true).unwrap();
+        assert_eq!((second.request_id, second.element.data), (Some(11), vec![2]));
+        assert!(reader.next().is_none());
+
+    }
+
+    #[test]
+    fn peek_element_ids() {
+
+        use crate::net::element::{DebugElementVariable16, ElementLength};
+
+        struct Context;
+        impl ElementContext for Context {
+            fn element_info(&self, id: u8) -> Option<(&'static str, Option<ElementLength>)> {
+                match id {
+                    5 | 6 => Some(("Debug", Some(ElementLength::Variable16))),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(DebugElementVariable16::<5> { data: vec![0xAA; 2000] });
+        bundle.element_writer().write_raw_reply(200, &[4, 5]);
+        bundle.element_writer().write_simple(DebugElementVariable16::<6> { data: vec![1, 2, 3] });
+        bundle.element_writer().write_simple(DebugElementVariable16::<7> { data: vec![6] });
+        bundle.element_writer().write_simple(DebugElementVariable16::<5> { data: vec![7] });
+
+        // Element 7 has an unknown length so the scan stops there.
+        assert_eq!(bundle.peek_element_ids(&Context), [5, REPLY_ID, 6, 7]);
+
+        // Peeking doesn't prevent reading, and each reader restarts at the first element.
+        for _ in 0..2 {
+            let mut reader = bundle.element_reader();
+            assert_eq!(reader.next_id(), Some(5));
+            let elt = reader.read::<DebugElementVariable16<5>, _>(&(), true).unwrap();
+            assert_eq!(elt.element.data.len(), 2000);
+            assert_eq!(reader.next_id(), Some(REPLY_ID));
+        }
+
+    }
+
+    #[test]
+    fn body_bytes() {
+
+        use crate::net::element::{DebugElementFixed, DebugElementVariable16, ElementLength};
+
+        let mut bundle = Bundle::new();
+        let mut writer = bundle.element_writer();
+        writer.write_simple(DebugElementFixed::<1, 3> { data: [1, 2, 3] });
+        writer.write_simple_request(DebugElementVariable16::<2> { data: vec![4, 5] }, 10);
+        writer.write_simple(DebugElementVariable16::<3> { data: vec![0x42; 3000] });
+        writer.write_simple(DebugElementVariable16::<4> { data: vec![] });
+        bundle.write_config(&mut PacketConfig::new());
+        assert!(bundle.len() > 1);
+
+        let mut reader = bundle.element_reader();
+
+        let Some(NextElementReader::Element(mut elt)) = reader.next() else { panic!() };
+        let body = elt.body_bytes(ElementLength::Fixed(3)).unwrap().unwrap();
+        assert_eq!(body, elt.read_simple::<DebugElementFixed<1, 3>>().unwrap().element.data);
+
+        // The request header is not part of the body.
+        let Some(NextElementReader::Element(mut elt)) = reader.next() else { panic!() };
+        let body = elt.body_bytes(ElementLength::Variable16).unwrap().unwrap();
+        let read = elt.read_simple::<DebugElementVariable16<2>>().unwrap();
+        assert_eq!((body, read.request_id), (&read.element.data[..], Some(10)));
+
+        // Spanning over multiple packets, the element can still be decoded.
+        let Some(NextElementReader::Element(mut elt)) = reader.next() else { panic!() };
+        assert_eq!(elt.body_bytes(ElementLength::Variable16).unwrap(), None);
+        assert_eq!(elt.body_bytes(ElementLength::Undefined).unwrap(), None);
+        assert_eq!(elt.read_simple::<DebugElementVariable16<3>>().unwrap().element.data, [0x42; 3000]);
+
+        let Some(NextElementReader::Element(mut elt)) = reader.next() else { panic!() };
+        assert_eq!(elt.body_bytes(ElementLength::Variable16).unwrap(), Some(&[][..]));
+        assert!(elt.read_simple::<DebugElementVariable16<4>>().unwrap().element.data.is_empty());
+        assert!(reader.next().is_none());
+
+    }
+
+    #[test]
+    fn large_element() {
+
+        use crate::net::element::{DebugElementVariable16, DebugElementVariable32};
+
+        let data = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+
+        let mut bundle = Bundle::new();
+        let mut writer = bundle.element_writer();
+        writer.write_simple(DebugElementVariable16::<1> { data: data.clone() });
+        writer.write_simple(DebugElementVariable32::<2> { data: data.clone() });
+        writer.write_simple(DebugElementVariable16::<3> { data: vec![0x42; 4] });
+        assert!(bundle.len() > 1);
+
+        let mut reader = bundle.element_reader();
+        let elt = reader.read::<DebugElementVariable16<1>, _>(&(), true).unwrap();
+        assert!(elt.element.data == data);
+        let elt = reader.read::<DebugElementVariable32<2>, _>(&(), true).unwrap();
+        assert!(elt.element.data == data);
+        let elt = reader.read::<DebugElementVariable16<3>, _>(&(), true).unwrap();
+        assert_eq!(elt.element.data, [0x42; 4]);
+        assert!(reader.next_id().is_none());
+
+    }
+
+    #[test]
+    fn merge() {
+
+        use crate::net::element::DebugElementVariable16;
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(DebugElementVariable16::<5> { data: vec![0xAA; 2000] });
+        bundle.element_writer().write_simple_request(DebugElementVariable16::<7> { data: vec![1, 2, 3] }, 100);
+
+        let mut other = Bundle::new();
+        other.element_writer().write_raw_reply(200, &[4, 5]);
+        other.element_writer().write_simple_request(DebugElementVariable16::<6> { data: vec![6] }, 300);
+
+        let first_len = bundle.len();
+        bundle.merge(other);
+        bundle.element_writer().write_simple(DebugElementVariable16::<8> { data: vec![7] });
+        assert_eq!(bundle.len(), first_len + 1);
+
+        // Prepare the bundle and rebuild it from its packets, like on reception.
+        let addr = "127.0.0.1:20016".parse().unwrap();
+        Protocol::new().off_channel(addr).prepare(&mut bundle, false);
+        let bundle = bundle.into_iter()
+            .map(|packet| packet.read_config_locked().unwrap())
+            .collect::<Bundle>();
+
+        let mut reader = bundle.element_reader();
+
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        let elt = elt.read_simple::<DebugElementVariable16<5>>().unwrap();
+        assert_eq!((elt.element.data.len(), elt.request_id), (2000, None));
+
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        let elt = elt.read_simple::<DebugElementVariable16<7>>().unwrap();
+        assert_eq!((elt.element.data, elt.request_id), (vec![1, 2, 3], Some(100)));
+
+        let Some(NextElementReader::Reply(reply)) = reader.next() else { panic!() };
+        assert_eq!(reply.request_id(), 200);
+        assert_eq!(reply.read_simple::<RawData>().unwrap().0, [4, 5]);
+
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        let elt = elt.read_simple::<DebugElementVariable16<6>>().unwrap();
+        assert_eq!((elt.element.data, elt.request_id), (vec![6], Some(300)));
+
+        let Some(NextElementReader::Element(elt)) = reader.next() else { panic!() };
+        let elt = elt.read_simple::<DebugElementVariable16<8>>().unwrap();
+        assert_eq!((elt.element.data, elt.request_id), (vec![7], None));
+
+        assert!(reader.next().is_none());
+
+    }
+
+}
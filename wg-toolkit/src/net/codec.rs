@@ -1,11 +1,18 @@
 //! This module contains the network codec trait and builtin implementations for trivial
 //! types that are commonly used, such as ints, floats and various common blobs.
+//! 
+//! All numbers are encoded in little-endian, and glam's [`Vec2`], [`Vec3`] and [`Vec4`]
+//! are encoded as their consecutive f32 components, as used by the engine for its
+//! vector types, these codecs can be used directly in external element definitions.
 
 
 use std::io::{self, Read, Write};
+use std::net::SocketAddrV4;
 use std::borrow::Cow;
+use std::sync::OnceLock;
 use std::fmt;
 
+use flate2::read::ZlibDecoder;
 use glam::{Vec2, Vec3, Vec4};
 
 use crate::util::io::{WgReadExt, WgWriteExt, serde_pickle_de_options, serde_pickle_ser_options};
@@ -125,6 +132,133 @@ impl<C, D: Codec<C>> Codec<C> for Vec<D> {
 
 }
 
+/// The width of the length prefix of a vector, by default vectors are prefixed by a
+/// packed u24 (see [`WgReadExt::read_packed_u24`]), but some arguments use fixed-width
+/// counts instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPrefix {
+    /// Packed u24, used by default for [`Vec`].
+    Packed,
+    /// Fixed 8-bit length.
+    U8,
+    /// Fixed 16-bit length.
+    U16,
+    /// Fixed 32-bit length.
+    U32,
+}
+
+impl LengthPrefix {
+
+    /// Read a length with this prefix.
+    pub fn read_len(self, read: &mut dyn Read) -> io::Result<usize> {
+        Ok(match self {
+            Self::Packed => read.read_packed_u24()? as usize,
+            Self::U8 => read.read_u8()? as usize,
+            Self::U16 => read.read_u16()? as usize,
+            Self::U32 => read.read_u32()? as usize,
+        })
+    }
+
+    /// Write a length with this prefix, returning an error if the length cannot be
+    /// represented with this prefix.
+    pub fn write_len(self, write: &mut dyn Write, len: usize) -> io::Result<()> {
+        
+        let max = match self {
+            Self::Packed => 0xFFFFFF,
+            Self::U8 => u8::MAX as usize,
+            Self::U16 => u16::MAX as usize,
+            Self::U32 => u32::MAX as usize,
+        };
+
+        if len > max {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("length {len} is too large for {self:?} prefix")));
+        }
+
+        match self {
+            Self::Packed => write.write_packed_u24(len as u32),
+            Self::U8 => write.write_u8(len as u8),
+            Self::U16 => write.write_u16(len as u16),
+            Self::U32 => write.write_u32(len as u32),
+        }
+
+    }
+
+}
+
+/// Read a vector of values prefixed by a length of the given width, this is the same 
+/// as [`Vec`] codec but with a custom length prefix.
+pub fn read_vec_with_prefix<C, D: Codec<C>>(read: &mut dyn Read, prefix: LengthPrefix, config: &C) -> io::Result<Vec<D>> {
+    let len = prefix.read_len(&mut *read)?;
+    // Don't trust the length too much for preallocation.
+    let mut tmp = Vec::with_capacity(len.min(4096));
+    for _ in 0..len {
+        tmp.push(D::read(&mut *read, config)?);
+    }
+    Ok(tmp)
+}
+
+/// Write a vector of values prefixed by a length of the given width, see 
+/// [`read_vec_with_prefix`].
+pub fn write_vec_with_prefix<C, D: Codec<C>>(write: &mut dyn Write, prefix: LengthPrefix, values: &[D], config: &C) -> io::Result<()> {
+    prefix.write_len(&mut *write, values.len())?;
+    for comp in values {
+        comp.write(&mut *write, config)?;
+    }
+    Ok(())
+}
+
+/// A vector with a fixed-width length prefix of the given number of bits (8, 16 or 32),
+/// to be used as a field type in place of [`Vec`] when an argument is not prefixed
+/// by the default packed u24 length.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefixedVec<D, const BITS: u8>(pub Vec<D>);
+
+/// A vector with a 8-bit length prefix.
+pub type Vec8<D> = PrefixedVec<D, 8>;
+/// A vector with a 16-bit length prefix.
+pub type Vec16<D> = PrefixedVec<D, 16>;
+/// A vector with a 32-bit length prefix.
+pub type Vec32<D> = PrefixedVec<D, 32>;
+
+impl<D, const BITS: u8> PrefixedVec<D, BITS> {
+
+    /// The length prefix of this vector type.
+    pub const PREFIX: LengthPrefix = match BITS {
+        8 => LengthPrefix::U8,
+        16 => LengthPrefix::U16,
+        32 => LengthPrefix::U32,
+        _ => panic!("unsupported length prefix bits"),
+    };
+
+}
+
+impl<D, const BITS: u8> std::ops::Deref for PrefixedVec<D, BITS> {
+    type Target = Vec<D>;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<D, const BITS: u8> std::ops::DerefMut for PrefixedVec<D, BITS> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<C, D: Codec<C>, const BITS: u8> Codec<C> for PrefixedVec<D, BITS> {
+
+    fn write(&self, write: &mut dyn Write, config: &C) -> io::Result<()> {
+        write_vec_with_prefix(write, Self::PREFIX, &self.0, config)
+    }
+
+    fn read(read: &mut dyn Read, config: &C) -> io::Result<Self> {
+        read_vec_with_prefix(read, Self::PREFIX, config).map(Self)
+    }
+
+}
+
 macro_rules! impl_builtin_copy {
     ($ty:ty, $write_method:ident, $read_method:ident) => {
         impl SimpleCodec for $ty {
@@ -177,14 +311,43 @@ impl fmt::Debug for AutoString {
     }
 }
 
-impl SimpleCodec for AutoString {
+impl AutoString {
 
-    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
-        write.write_blob_variable(&*(match self {
+    /// Get the raw bytes of this string, as encoded, Python values are pickled.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
             AutoString::String(v) => Cow::Borrowed(v.as_bytes()),
             AutoString::Python(v) => Cow::Owned(serde_pickle::value_to_vec(v, serde_pickle_ser_options()).unwrap()),
             AutoString::Raw(v) => Cow::Borrowed(&v[..]),
-        }))
+        }
+    }
+
+    /// Decode the raw bytes of this string as UTF-8, invalid sequences are replaced 
+    /// with the replacement character.
+    #[inline]
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        self.to_string_encoding(StringEncoding::Utf8)
+    }
+
+    /// Decode the raw bytes of this string with the given encoding, this can be used 
+    /// when the encoding of a field is known, because strings that are not valid UTF-8 
+    /// are decoded as raw bytes.
+    pub fn to_string_encoding(&self, encoding: StringEncoding) -> Cow<'_, str> {
+        match (self, encoding) {
+            (AutoString::String(v), StringEncoding::Utf8) => Cow::Borrowed(v.as_str()),
+            _ => match self.as_bytes() {
+                Cow::Borrowed(bytes) => encoding.decode(bytes),
+                Cow::Owned(bytes) => Cow::Owned(encoding.decode(&bytes).into_owned()),
+            }
+        }
+    }
+
+}
+
+impl SimpleCodec for AutoString {
+
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_blob_variable(&self.as_bytes())
     }
 
     fn read(read: &mut dyn Read) -> io::Result<Self> {
@@ -205,52 +368,161 @@ impl SimpleCodec for AutoString {
 }
 
 
-/// The Python builtin data type.
+/// Text encoding of a string's raw bytes, see [`AutoString::to_string_encoding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringEncoding {
+    /// UTF-8, invalid sequences are replaced with the replacement character.
+    #[default]
+    Utf8,
+    /// Latin-1 (ISO 8859-1), each byte is the code point of its character.
+    Latin1,
+}
+
+impl StringEncoding {
+
+    /// Decode the given bytes into a string with this encoding, this never fails.
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes),
+            Self::Latin1 if bytes.is_ascii() => Cow::Borrowed(std::str::from_utf8(bytes).unwrap()),
+            Self::Latin1 => Cow::Owned(bytes.iter().map(|&b| b as char).collect()),
+        }
+    }
+
+}
+
+
+/// The Python builtin data type, this is a pickled Python object that may also be 
+/// compressed with zlib. The raw bytes are the only stored data and the pickle value
+/// is only parsed when requested, so decoding never fails because of an invalid pickle.
+#[derive(Clone)]
 pub struct Python {
-    /// Internal pickle value.
-    pub value: serde_pickle::Value,
+    /// Raw bytes of the pickle, possibly zlib-compressed.
+    raw: Vec<u8>,
+    /// Lazily parsed pickle value, or the parse error message.
+    value: OnceLock<Result<serde_pickle::Value, String>>,
+}
+
+impl Python {
+
+    /// Create a new Python data from the given pickle value, it is serialized 
+    /// immediately, without compression.
+    pub fn new(value: serde_pickle::Value) -> io::Result<Self> {
+        let mut ret = Self::from_raw(Vec::new());
+        ret.set_value(value)?;
+        Ok(ret)
+    }
+
+    /// Create a new Python data from its raw pickle bytes, possibly zlib-compressed.
+    pub fn from_raw(raw: Vec<u8>) -> Self {
+        Self {
+            raw,
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Get the raw bytes of the pickle, possibly zlib-compressed.
+    #[inline]
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Take the raw bytes of the pickle, possibly zlib-compressed.
+    #[inline]
+    pub fn into_raw(self) -> Vec<u8> {
+        self.raw
+    }
+
+    /// Return true if the raw bytes are compressed with zlib, this is detected using 
+    /// the zlib header.
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        is_zlib_compressed(&self.raw)
+    }
+
+    /// Get the parsed pickle value, it is parsed on first call and decompressed if 
+    /// needed, an invalid data error is returned if the pickle is invalid.
+    pub fn value(&self) -> io::Result<&serde_pickle::Value> {
+        self.value.get_or_init(|| {
+            let res = if self.is_compressed() {
+                serde_pickle::value_from_reader(ZlibDecoder::new(&self.raw[..]), serde_pickle_de_options())
+            } else {
+                serde_pickle::value_from_reader(&self.raw[..], serde_pickle_de_options())
+            };
+            res.map_err(|e| e.to_string())
+        }).as_ref().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.clone()))
+    }
+
+    /// Replace the pickle value, the raw bytes are serialized again, without 
+    /// compression. The value is left unchanged if it can't be serialized.
+    pub fn set_value(&mut self, value: serde_pickle::Value) -> io::Result<()> {
+        self.raw = serde_pickle::value_to_vec(&value, serde_pickle_ser_options())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.value = OnceLock::from(Ok(value));
+        Ok(())
+    }
+
+}
+
+/// Return true if the given bytes start with a valid zlib header.
+fn is_zlib_compressed(data: &[u8]) -> bool {
+    match data[..] {
+        [cmf @ 0x78, flg, ..] => u16::from_be_bytes([cmf, flg]) % 31 == 0,
+        _ => false,
+    }
 }
 
 impl SimpleCodec for Python {
 
     #[inline(always)]
     fn write(&self, write: &mut dyn Write) -> io::Result<()> {
-        write.write_python_pickle(&self.value)
+        write.write_blob_variable(&self.raw)
     }
 
     #[inline(always)]
     fn read(read: &mut dyn Read) -> io::Result<Self> {
-        read.read_python_pickle().map(|value| Self { value })
+        read.read_blob_variable().map(Self::from_raw)
     }
 
 }
 
 impl fmt::Debug for Python {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_tuple("Python")
-            .field(&format_args!("{}", self.value))
-            .finish()
+        match self.value() {
+            Ok(value) => f.debug_tuple("Python").field(&format_args!("{value}")).finish(),
+            Err(_) => f.debug_tuple("Python").field(&AsciiFmt(&self.raw)).finish(),
+        }
     }
 }
 
 
-/// The mailbox type used sparingly in method calls.
-#[derive(Debug)]
+/// The mailbox type used sparingly in method calls, it references an entity living on
+/// a given application (the address) and the kind of component (base, cell, client...).
+/// 
+/// On the wire, this is the entity id followed by the address, the kind being stored 
+/// in the 3 high bits of the address' salt, the 13 low bits are ignored.
+#[derive(Debug, Clone)]
 pub struct Mailbox {
     pub entity_id: u32,
-    pub address: (), // TODO: 
+    pub addr: SocketAddrV4,
+    pub kind: u8,
 }
 
 impl SimpleCodec for Mailbox {
 
-    #[inline]
-    fn write(&self, _write: &mut dyn Write) -> io::Result<()> {
-        Err(io::Error::new(io::ErrorKind::InvalidData, "mailbox write not yet supported"))
+    fn write(&self, write: &mut dyn Write) -> io::Result<()> {
+        write.write_u32(self.entity_id)?;
+        write.write_sock_addr_v4_salt(self.addr, (self.kind as u16 & 0x7) << 13)
     }
 
-    #[inline]
-    fn read(_read: &mut dyn Read) -> io::Result<Self> {
-        Err(io::Error::new(io::ErrorKind::InvalidData, "mailbox write not yet supported"))
+    fn read(read: &mut dyn Read) -> io::Result<Self> {
+        let entity_id = read.read_u32()?;
+        let (addr, salt) = read.read_sock_addr_v4_salt()?;
+        Ok(Self {
+            entity_id,
+            addr,
+            kind: (salt >> 13) as u8,
+        })
     }
 
 }
@@ -292,3 +564,99 @@ macro_rules! __struct_simple_codec {
         )*
     };
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn vec3_layout() {
+
+        // Position argument of an entity method, three little-endian f32.
+        let data = [0x00, 0x00, 0x80, 0x3F, 0x00, 0x00, 0x20, 0x40, 0x00, 0x00, 0x40, 0xC0];
+        let vec = <Vec3 as SimpleCodec>::read(&mut &data[..]).unwrap();
+        assert_eq!(vec, Vec3::new(1.0, 2.5, -3.0));
+
+        let mut buf = Vec::new();
+        SimpleCodec::write(&vec, &mut buf).unwrap();
+        assert_eq!(buf, data);
+
+    }
+
+    #[test]
+    fn vec_prefix() {
+
+        // Array argument with a 16-bit count of u32 values.
+        let data = [0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+        let vec = read_vec_with_prefix::<(), u32>(&mut &data[..], LengthPrefix::U16, &()).unwrap();
+        assert_eq!(vec, [1, 2]);
+
+        let vec = <Vec16<u32> as Codec<()>>::read(&mut &data[..], &()).unwrap();
+        assert_eq!(vec.len(), 2);
+
+        let mut buf = Vec::new();
+        Codec::write(&vec, &mut buf, &()).unwrap();
+        assert_eq!(buf, data);
+
+        let mut buf = Vec::new();
+        assert!(write_vec_with_prefix(&mut buf, LengthPrefix::U8, &[0u8; 256], &()).is_err());
+
+    }
+
+    #[test]
+    fn string_encoding() {
+
+        // A player name encoded in Latin-1, which is not valid UTF-8.
+        let data = [0x03, b'Z', b'o', 0xE9];
+        let name = <AutoString as SimpleCodec>::read(&mut &data[..]).unwrap();
+        assert!(matches!(name, AutoString::Raw(_)));
+        assert_eq!(name.as_bytes(), &data[1..]);
+        assert_eq!(name.to_string_encoding(StringEncoding::Latin1), "Zoé");
+        assert_eq!(name.to_string_lossy(), "Zo\u{FFFD}");
+
+        let name = AutoString::String("Zoé".to_string());
+        assert_eq!(name.to_string_lossy(), "Zoé");
+        assert_eq!(name.to_string_encoding(StringEncoding::Latin1), "ZoÃ©");
+
+    }
+
+    #[test]
+    fn mailbox_salt() {
+
+        // Entity 0x1234 on 10.0.0.1:20013, kind 2 in the salt's high bits.
+        let data = [0x34, 0x12, 0x00, 0x00, 10, 0, 0, 1, 0x4E, 0x2D, 0x00, 0x40];
+        let mailbox = <Mailbox as SimpleCodec>::read(&mut &data[..]).unwrap();
+        assert_eq!(mailbox.entity_id, 0x1234);
+        assert_eq!(mailbox.addr, "10.0.0.1:20013".parse().unwrap());
+        assert_eq!(mailbox.kind, 2);
+
+        let mut buf = Vec::new();
+        SimpleCodec::write(&mailbox, &mut buf).unwrap();
+        assert_eq!(buf, data);
+
+    }
+
+
+    #[test]
+    fn python_lazy() {
+
+        // Pickle of the tuple (1, 2), protocol 2.
+        let data = [0x80, 0x02, b'J', 1, 0, 0, 0, b'J', 2, 0, 0, 0, 0x86, b'.'];
+        let python = Python::from_raw(data.to_vec());
+        assert!(!python.is_compressed());
+        let value = python.value().unwrap();
+        assert_eq!(*value, serde_pickle::Value::Tuple(vec![serde_pickle::Value::I64(1), serde_pickle::Value::I64(2)]));
+
+        // Invalid pickles are reported when the value is requested, but are still
+        // written back unchanged.
+        let python = Python::from_raw(vec![0x80, 0x02, 0xFF]);
+        assert_eq!(python.value().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut buf = Vec::new();
+        SimpleCodec::write(&python, &mut buf).unwrap();
+        assert_eq!(buf, [3, 0x80, 0x02, 0xFF]);
+
+    }
+
+}
@@ -1,6 +1,9 @@
 //! Definitions for elements contained in bundles (and so in packets).
 
 use std::io::{self, Read, Write};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use std::fmt;
 
 use crate::util::AsciiFmt;
@@ -78,6 +81,14 @@ impl<E: SimpleElement<C>, C> Element<C> for E {
 
 /// Type of length used by a specific message codec.
 /// This describes how the length of an element should be encoded in the packet.
+/// 
+/// Elements larger than what the 8, 16 or 24 bits variable lengths can hold are still
+/// supported, this is the engine's *oversize* framing: the length is written full of
+/// ones (0xFF...) and the real length is written on 32 bits in place of the first 4
+/// bytes of the element, these bytes being moved at the end of the element. This is
+/// what the client expects for large elements such as `CreateBasePlayer`, so the 
+/// length kind of an element must match the client's one and not be changed to 
+/// [`Self::Variable32`] only because the element can be large.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum ElementLength {
     /// A fixed length element, the length is not written in the header.
@@ -88,7 +99,8 @@ pub enum ElementLength {
     Variable16,
     /// The length is encoded on 24 bits in the element's header.
     Variable24,
-    /// The length is encoded on 32 bits in the element's header.
+    /// The length is encoded on 32 bits in the element's header, this is never 
+    /// oversized.
     Variable32,
     /// The length is not encoded nor decode, so it's up to the element to encode and
     /// decode anything wanted, the length given to [`Element::decode`] is `u32::MAX`,
@@ -167,6 +179,20 @@ impl ElementLength {
 }
 
 
+/// A context describing the elements id space of an application (login, base, client),
+/// this is used to give a name and a length to elements when debugging bundles, see
+/// [`Bundle::debug_with()`](super::bundle::Bundle::debug_with).
+pub trait ElementContext {
+
+    /// Return the name of the element with the given id, and its length if it can be
+    /// known without more context (entity methods for example have a length that
+    /// depends on the entity type), none is returned if the id is unknown. Elements
+    /// implementing [`SimpleElement`] should return their [`SimpleElement::LEN`].
+    fn element_info(&self, id: u8) -> Option<(&'static str, Option<ElementLength>)>;
+
+}
+
+
 /// A wrapper for a reply element, with the request ID and the underlying element, use
 /// the empty element `()` as element in order to just read the request id.
 #[derive(Debug)]
@@ -208,6 +234,92 @@ impl<D: Codec<C>, C> SimpleElement<C> for Reply<D> {
 }
 
 
+/// An allocator of request IDs for a connection, IDs are allocated in increasing order
+/// and wrap around, IDs of requests that are still in flight are never allocated again
+/// until they are released when their reply is received, or when they time out if a
+/// timeout is set, see [`Self::set_timeout`].
+#[derive(Debug, Default)]
+pub struct RequestIdAlloc {
+    /// The next request ID to try to allocate.
+    next: u32,
+    /// Request IDs that have been allocated and not yet released, with the instant 
+    /// they have been allocated at.
+    in_flight: HashMap<u32, Instant>,
+    /// Duration after which a request ID in flight is released.
+    timeout: Option<Duration>,
+}
+
+impl RequestIdAlloc {
+
+    /// Create a new allocator that will start allocating from the given request ID.
+    pub fn new(next: u32) -> Self {
+        Self {
+            next,
+            in_flight: HashMap::new(),
+            timeout: None,
+        }
+    }
+
+    /// Set the duration after which a request ID in flight is released, none to keep
+    /// request IDs in flight until they are explicitly released.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Allocate a new request ID, it is in flight until released. Timed out request IDs
+    /// are released before allocating, use [`Self::release_timed_out`] before this 
+    /// function in order to know them.
+    pub fn alloc(&mut self) -> u32 {
+        self.release_timed_out();
+        assert!((self.in_flight.len() as u64) < (1 << 32), "no request id available");
+        let now = Instant::now();
+        loop {
+            let request_id = self.next;
+            self.next = request_id.wrapping_add(1);
+            if let Entry::Vacant(entry) = self.in_flight.entry(request_id) {
+                entry.insert(now);
+                return request_id;
+            }
+        }
+    }
+
+    /// Release the given request ID so that it can be allocated again, returning false
+    /// if it was not in flight. This should be called when the reply is received.
+    pub fn release(&mut self, request_id: u32) -> bool {
+        self.in_flight.remove(&request_id).is_some()
+    }
+
+    /// Release all request IDs that have been in flight for longer than the timeout, 
+    /// if any, and return them.
+    pub fn release_timed_out(&mut self) -> Vec<u32> {
+        let mut timed_out = Vec::new();
+        if let Some(timeout) = self.timeout {
+            self.in_flight.retain(|&request_id, &mut alloc_instant| {
+                let keep = alloc_instant.elapsed() < timeout;
+                if !keep {
+                    timed_out.push(request_id);
+                }
+                keep
+            });
+        }
+        timed_out
+    }
+
+    /// Return true if the given request ID is allocated and not yet released.
+    #[inline]
+    pub fn is_in_flight(&self, request_id: u32) -> bool {
+        self.in_flight.contains_key(&request_id)
+    }
+
+    /// Return the number of request IDs in flight.
+    #[inline]
+    pub fn in_flight_count(&self) -> usize {
+        self.in_flight.len()
+    }
+
+}
+
+
 /// An element of fixed sized that just buffer the data.
 #[derive(Clone)]
 pub struct DebugElementFixed<const ID: u8, const LEN: usize> {
@@ -321,7 +433,9 @@ impl_debug_element_var!(DebugElementUndefined, ElementLength::Undefined);
 /// This structure is small and therefore can be copied.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ElementIdRange {
+    /// The first id of the range, included.
     pub first: u8,
+    /// The last id of the range, included.
     pub last: u8,
 }
 
@@ -332,11 +446,38 @@ impl ElementIdRange {
         Self { first, last }
     }
 
+    /// Return true if the given id is in this range.
     #[inline]
     pub const fn contains(self, id: u8) -> bool {
         self.first <= id && id <= self.last
     }
 
+    /// Returns the number of ids in this range, unlike [`Self::slots_count`] this 
+    /// cannot overflow if the range covers all ids.
+    #[inline]
+    pub const fn len(self) -> usize {
+        if self.is_empty() { 0 } else { (self.last - self.first) as usize + 1 }
+    }
+
+    /// Return true if this range contains no id, which only happens if the first id
+    /// is greater than the last one.
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.first > self.last
+    }
+
+    /// Return the index of the given id within this range, if it is contained.
+    #[inline]
+    pub const fn index_of(self, id: u8) -> Option<usize> {
+        if self.contains(id) { Some((id - self.first) as usize) } else { None }
+    }
+
+    /// Return the id at the given index within this range, if it is in bounds.
+    #[inline]
+    pub const fn id_of(self, index: usize) -> Option<u8> {
+        if index < self.len() { Some(self.first + index as u8) } else { None }
+    }
+
     /// Returns the number of slots in this range.
     #[inline]
     pub const fn slots_count(self) -> u8 {
@@ -420,3 +561,73 @@ impl ElementIdRange {
     }
 
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+    use std::thread;
+
+    use crate::net::app::{base, client};
+    use super::{ElementIdRange, RequestIdAlloc};
+
+    #[test]
+    fn request_id_alloc() {
+
+        let mut alloc = RequestIdAlloc::new(u32::MAX - 1);
+        assert_eq!(alloc.alloc(), u32::MAX - 1);
+        assert_eq!(alloc.alloc(), u32::MAX);
+        assert_eq!(alloc.alloc(), 0);
+        assert!(alloc.release(u32::MAX));
+        assert!(!alloc.release(u32::MAX));
+
+        // After wrapping around, IDs in flight are skipped.
+        alloc.next = u32::MAX - 1;
+        assert_eq!(alloc.alloc(), u32::MAX);
+        assert_eq!(alloc.alloc(), 1);
+        assert_eq!(alloc.in_flight_count(), 4);
+        assert!(alloc.is_in_flight(u32::MAX - 1));
+
+    }
+
+    #[test]
+    fn request_id_timeout() {
+
+        let mut alloc = RequestIdAlloc::new(0);
+        assert_eq!(alloc.alloc(), 0);
+        assert_eq!(alloc.alloc(), 1);
+        assert!(alloc.release_timed_out().is_empty());
+
+        alloc.set_timeout(Some(Duration::from_millis(20)));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(alloc.alloc(), 2);
+        assert_eq!(alloc.release_timed_out(), []);
+        assert_eq!(alloc.in_flight_count(), 1);
+
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(alloc.release_timed_out(), [2]);
+        assert_eq!(alloc.in_flight_count(), 0);
+
+    }
+
+    #[test]
+    fn id_range_index() {
+        for range in [
+            base::element::id::CELL_ENTITY_METHOD,
+            base::element::id::BASE_ENTITY_METHOD,
+            client::element::id::ENTITY_METHOD,
+            client::element::id::ENTITY_PROPERTY,
+            ElementIdRange::new(0x00, 0xFF),
+        ] {
+            for index in 0..range.len() {
+                let id = range.id_of(index).unwrap();
+                assert_eq!(range.index_of(id), Some(index));
+            }
+            assert_eq!(range.id_of(range.len()), None);
+            assert_eq!(range.first.checked_sub(1).and_then(|id| range.index_of(id)), None);
+            assert_eq!(range.last.checked_add(1).and_then(|id| range.index_of(id)), None);
+        }
+    }
+
+}
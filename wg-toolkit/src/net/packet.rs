@@ -1,7 +1,7 @@
 //! Packet structure definition with synchronization methods.
 
 use std::collections::VecDeque;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read};
 use std::num::NonZero;
 use std::fmt;
 
@@ -77,6 +77,32 @@ impl Packet {
         }
     }
 
+    /// Create a new packet from the given raw bytes, as received from or sent to the 
+    /// network, including the prefix. This can be used to replay captured packets. An
+    /// error is returned if the length is smaller than [`PACKET_HEADER_LEN`] or greater
+    /// than [`PACKET_CAP`].
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+
+        if data.len() < PACKET_HEADER_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("packet too small: {} bytes", data.len())));
+        } else if data.len() > PACKET_CAP {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("packet too large: {} bytes", data.len())));
+        }
+
+        let mut packet = Self::new();
+        packet.inner.buf[..data.len()].copy_from_slice(data);
+        packet.inner.len = data.len() as u16;
+        Ok(packet)
+
+    }
+
+    /// Get the raw bytes of this packet, this is the same as [`Self::slice`] and the
+    /// reverse of [`Self::from_bytes`].
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.slice()
+    }
+
     /// Reset this packet's length, flags and prefix.
     #[inline]
     pub fn reset(&mut self) {
@@ -274,8 +300,7 @@ impl fmt::Debug for Packet {
         let mut debug = f.debug_struct("Packet");
 
         debug.field("prefix", &format_args!("{:08X}", self.read_prefix()));
-        debug.field("flags", &format_args!("{:04X}", self.read_flags()));
-        debug.field("flags", &format_args!("{}", FlagsFmt(self.read_flags())));
+        debug.field("flags", &format_args!("{:04X} ({:#})", self.read_flags(), FlagsFmt(self.read_flags())));
         debug.field("len", &format_args!("{}", self.inner.len));
         if width != 0 {
             debug.field("body", &format_args!("{}", 
@@ -826,15 +851,15 @@ impl PacketConfig {
 
             // Debug assert, and cap to the max number of acks.
             debug_assert!(available_len >= 4, "PACKET_MIN_FOOTER_LEN should ensure at least one single ack");
-            let available_len = available_len.min(u8::MAX as usize * 4);
+            let mut available_len = available_len.min(u8::MAX as usize * 4);
 
+            // Remaining acks are kept in the queue for the next packets.
             let mut count = 0u8;
-            while let Some(ack) = self.single_acks.pop_front() {
+            while available_len >= 4 {
+                let Some(ack) = self.single_acks.pop_front() else { break };
                 count += 1;
+                available_len -= 4;
                 packet.grow(4).write_u32(ack.get()).unwrap();
-                if available_len < 4 {
-                    break;
-                }
             }
 
             debug_assert!(count != 0);
@@ -1076,36 +1101,42 @@ pub mod flags {
 }
 
 
-/// Wrapper structure for displaying flags.
+/// Wrapper structure for displaying flags, each flag is displayed with a short name
+/// separated by `|`, or with the full name of its constant in [`flags`] separated by
+/// ` | ` with the alternate flag (`{:#}`). Unknown bits are displayed in hex with a
+/// trailing `?`.
 pub struct FlagsFmt(pub u16);
 
 impl fmt::Display for FlagsFmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 
-        static NAMES: [&'static str; 13] = [
-            "REQS",
-            "PIGB",
-            "ACKS",
-            "CHAN",
-            "RELI",
-            "FRAG",
-            "SEQN",
-            "INDX",
-            "CSUM",
-            "CREA",
-            "CUMU",
-            "0800",
-            "LAST",
+        static NAMES: [(&str, &str); 13] = [
+            ("REQS", "HAS_REQUESTS"),
+            ("PIGB", "HAS_PIGGYBACKS"),
+            ("ACKS", "HAS_ACKS"),
+            ("CHAN", "ON_CHANNEL"),
+            ("RELI", "IS_RELIABLE"),
+            ("FRAG", "IS_FRAGMENT"),
+            ("SEQN", "HAS_SEQUENCE_NUMBER"),
+            ("INDX", "INDEXED_CHANNEL"),
+            ("CSUM", "HAS_CHECKSUM"),
+            ("CREA", "CREATE_CHANNEL"),
+            ("CUMU", "HAS_CUMULATIVE_ACK"),
+            ("0800", "UNK_0800"),
+            ("LAST", "UNK_1000"),
         ];
 
+        let alternate = f.alternate();
+        let sep = if alternate { " | " } else { "|" };
+
         let mut flag = self.0;
         let mut prev = false;
-        for flag_name in NAMES {
+        for (short_name, name) in NAMES {
             if flag & 1 != 0 {
                 if prev {
-                    f.write_str("|")?;
+                    f.write_str(sep)?;
                 }
-                f.write_str(flag_name)?;
+                f.write_str(if alternate { name } else { short_name })?;
                 prev = true;
             }
             flag >>= 1;
@@ -1113,7 +1144,7 @@ impl fmt::Display for FlagsFmt {
 
         if flag != 0 {
             if prev {
-                f.write_str("|")?;
+                f.write_str(sep)?;
             }
             f.write_fmt(format_args!("{:04X}?", flag << NAMES.len()))?;
         }
@@ -1185,3 +1216,62 @@ pub enum PacketConfigError {
     #[error("invalid checksum")]
     InvalidChecksum
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn from_bytes() {
+
+        let mut packet = Packet::new();
+        packet.grow(5).copy_from_slice(b"hello");
+        let mut config = PacketConfig::new();
+        config.set_reliable(true);
+        config.set_sequence_num(Seq::new(1234).unwrap());
+        packet.write_config(&mut config);
+        packet.write_prefix(0x12345678);
+
+        let captured = packet.as_bytes().to_vec();
+        let replayed = Packet::from_bytes(&captured).unwrap();
+        assert_eq!(replayed.as_bytes(), &captured[..]);
+        assert_eq!(replayed.read_prefix(), 0x12345678);
+
+        let locked = replayed.read_config_locked().unwrap();
+        assert!(locked.config().reliable());
+        assert_eq!(locked.config().sequence_num().get(), 1234);
+        assert_eq!(&locked.packet().slice()[PACKET_HEADER_LEN..locked.config().footer_offset()], b"hello");
+
+        assert!(Packet::from_bytes(&[0; PACKET_HEADER_LEN - 1]).is_err());
+        assert!(Packet::from_bytes(&[0; PACKET_CAP + 1]).is_err());
+
+        // Malformed flags are rejected when reading the config.
+        let mut malformed = captured.clone();
+        malformed.truncate(PACKET_HEADER_LEN + 1);
+        assert!(Packet::from_bytes(&malformed).unwrap().read_config_locked().is_err());
+
+    }
+
+    #[test]
+    fn flags_fmt() {
+
+        let mut packet = Packet::new();
+        let mut config = PacketConfig::new();
+        config.set_on_channel(true);
+        config.set_reliable(true);
+        config.set_sequence_num(Seq::new(1).unwrap());
+        packet.write_config(&mut config);
+
+        let debug = format!("{packet:0?}");
+        assert!(debug.contains("flags: 0058 (ON_CHANNEL | IS_RELIABLE | HAS_SEQUENCE_NUMBER)"), "{debug}");
+
+        let flags = flags::HAS_REQUESTS | flags::UNK_1000 | 0x8000;
+        assert_eq!(FlagsFmt(flags).to_string(), "REQS|LAST|8000?");
+        assert_eq!(format!("{:#}", FlagsFmt(flags)), "HAS_REQUESTS | UNK_1000 | 8000?");
+        assert_eq!(format!("{:?}", FlagsFmt(0)), "Flags()");
+
+    }
+
+}
@@ -9,13 +9,19 @@ use std::num::NonZero;
 use tracing::{instrument, trace, trace_span, warn};
 
 use super::packet::{Packet, PacketConfig, PacketLocked, PacketConfigError};
-use super::seq::{Seq, SeqAlloc};
+use super::seq::{Seq, SeqAlloc, SeqStatus, SeqTracker};
 use super::bundle::Bundle;
 
+use crate::util::DurationFmt;
+
 
 /// The (currently hardcoded) timeout on bundle fragments.
 const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// The default number of buffered out-of-order reliable packets on a channel above 
+/// which a warning is emitted.
+pub const DEFAULT_REORDER_WARN_THRESHOLD: usize = 50;
+
 
 /// A protocol tracker for an interface, providing support for accepting and preparing
 /// bundles, with reliability, defragmenting and (off)channel support.
@@ -40,6 +46,10 @@ struct ProtocolShared {
     last_accepted_prefix: u32,
     /// The current prefix offset being used for updating all packets' prefixes.
     prefix_offset: u32,
+    /// The reorder window of channels created from now on.
+    reorder_window: u32,
+    /// Number of buffered reliable packets above which a warning is emitted.
+    reorder_warn_threshold: usize,
 }
 
 impl Protocol {
@@ -50,6 +60,8 @@ impl Protocol {
                 off_seq_alloc: SeqAlloc::new(Seq::ZERO + 1),
                 last_accepted_prefix: 0,
                 prefix_offset: 0,
+                reorder_window: SeqTracker::DEFAULT_WINDOW,
+                reorder_warn_threshold: DEFAULT_REORDER_WARN_THRESHOLD,
             },
             off_channels: HashMap::new(),
             channels: HashMap::new(),
@@ -86,8 +98,8 @@ impl Protocol {
             .or_insert_with(|| OnChannel {
                 off: OffChannelData::new(),
                 on: match index {
-                    None => OnChannelData::new_without_index(),
-                    Some(index) => OnChannelData::new_with_index(index),
+                    None => OnChannelData::new_without_index(self.shared.reorder_window),
+                    Some(index) => OnChannelData::new_with_index(index, self.shared.reorder_window),
                 },
             });
         
@@ -95,12 +107,54 @@ impl Protocol {
             inner: GenericChannel {
                 shared: &mut self.shared,
                 off: &mut channel.off,
-                on: None,
+                on: Some(&mut channel.on),
             }
         }
 
     }
 
+    /// Forget the off-channel and all channels associated with the given address, any
+    /// pending reliable or fragmented packet is lost. This should be used when a peer
+    /// is gone. Returns false if nothing was known about this address.
+    pub fn remove_addr(&mut self, addr: SocketAddr) -> bool {
+        let len = self.channels.len();
+        self.channels.retain(|&(channel_addr, _), _| channel_addr != addr);
+        self.off_channels.remove(&addr).is_some() || self.channels.len() != len
+    }
+
+    /// Set the reorder window of channels created after this call, this is the maximum
+    /// distance ahead of the next expected reliable sequence number for a packet to be
+    /// buffered, packets further ahead are dropped without being acknowledged so that
+    /// they are sent again later. The default is [`SeqTracker::DEFAULT_WINDOW`].
+    /// 
+    /// The window must be greater than zero and less than half the range of sequence
+    /// numbers, see [`SeqTracker::with_window`].
+    pub fn set_reorder_window(&mut self, window: u32) {
+        // Check the window early instead of when creating the channel.
+        SeqTracker::with_window(window);
+        self.shared.reorder_window = window;
+    }
+
+    /// Return the reorder window of channels created from now on.
+    #[inline]
+    pub fn reorder_window(&self) -> u32 {
+        self.shared.reorder_window
+    }
+
+    /// Set the number of buffered out-of-order reliable packets on a channel above 
+    /// which a warning is emitted, this applies to all channels and the default is 
+    /// [`DEFAULT_REORDER_WARN_THRESHOLD`].
+    #[inline]
+    pub fn set_reorder_warn_threshold(&mut self, threshold: usize) {
+        self.shared.reorder_warn_threshold = threshold;
+    }
+
+    /// Return the number of buffered reliable packets above which a warning is emitted.
+    #[inline]
+    pub fn reorder_warn_threshold(&self) -> usize {
+        self.shared.reorder_warn_threshold
+    }
+
     /// Reset the prefix offset to zero.
     #[inline]
     pub fn reset_prefix_offset(&mut self) {
@@ -168,7 +222,7 @@ impl Protocol {
                 on_channel = self.channels.entry((addr, Some(index)))
                     .or_insert_with(|| OnChannel {
                         off: OffChannelData::new(),
-                        on: OnChannelData::new_with_index_version(index, version),
+                        on: OnChannelData::new_with_index_version(index, version, self.shared.reorder_window),
                     });
 
                 // Unwrap because the channel should have index.
@@ -184,7 +238,7 @@ impl Protocol {
                 on_channel = self.channels.entry((addr, None))
                     .or_insert_with(|| OnChannel {
                         off: OffChannelData::new(),
-                        on: OnChannelData::new_without_index(),
+                        on: OnChannelData::new_without_index(self.shared.reorder_window),
                     });
             }
 
@@ -235,12 +289,21 @@ impl Protocol {
                 return None;
             }
 
+            // Packets too far ahead are not acknowledged, so they will be sent again.
+            if let Some(on) = channel.on.as_deref() {
+                if !on.is_in_reliable_window(packet.config().sequence_num()) {
+                    warn!("Reliable packet out of the reorder window, expected: {}, got: {}",
+                        on.in_reliable_expected_seq, packet.config().sequence_num());
+                    return None;
+                }
+            }
+
             channel.off.add_in_reliable_packet(packet.config().sequence_num());
 
             // When on-channel with reliable packets, we must track the cumulative ack
             // and buffer any packet that is received out-of-order!
             if let Some(on) = channel.on.as_deref_mut() {
-                on.add_in_reliable_packet(packet);
+                on.add_in_reliable_packet(packet, channel.shared.reorder_warn_threshold);
                 while let Some(bundle) = on.pop_in_reliable_bundle() {
                     channel.off.in_bundles.push_back(bundle);
                 }
@@ -301,14 +364,14 @@ impl Protocol {
                 on_channel = self.channels.entry((addr, Some(index)))
                     .or_insert_with(|| OnChannel {
                         off: OffChannelData::new(),
-                        on: OnChannelData::new_with_index_version(index, version),
+                        on: OnChannelData::new_with_index_version(index, version, self.shared.reorder_window),
                     });
             } else {
                 trace!("Is on-channel: not indexed");
                 on_channel = self.channels.entry((addr, None))
                     .or_insert_with(|| OnChannel {
                         off: OffChannelData::new(),
-                        on: OnChannelData::new_without_index(),
+                        on: OnChannelData::new_without_index(self.shared.reorder_window),
                     });
             }
 
@@ -386,9 +449,18 @@ impl Channel<'_> {
         self.inner.off.in_bundles.pop_front()
     }
 
+    /// Return true if some received reliable packets are still waiting to be 
+    /// acknowledged, in such case the next prepared bundle will carry these acks, a 
+    /// bundle with a single empty packet can be sent if there is nothing else to send.
+    pub fn has_pending_acks(&self) -> bool {
+        !self.inner.off.in_reliable_packets.is_empty()
+    }
+
     /// Prepare a bundle to be sent, adding acks and other configuration required by this
     /// tracker into all packets. After this function, all packets are ready to be sent
-    /// and the bundle should not be touched for this to remain true.
+    /// and the bundle should not be touched for this to remain true. The bundle is sent
+    /// reliably if the given reliable argument is true or if the bundle itself is set
+    /// reliable, see [`Bundle::set_reliable`].
     /// 
     /// NOTE: FIXME: It's said that external interfaces don't allow off-channel reliable 
     /// communication (see packet_receiver.cpp, line 977).
@@ -397,6 +469,7 @@ impl Channel<'_> {
 
         let time = Instant::now();
         let bundle_len = bundle.len() as u32;
+        let reliable = reliable || bundle.is_reliable();
         trace!("Count: {bundle_len}");
         
         // Create a common packet config for all the bundle.
@@ -418,6 +491,8 @@ impl Channel<'_> {
         if let Some(on) = self.inner.on.as_deref_mut() {
             packet_config.set_on_channel(true);
             packet_config.set_cumulative_ack(on.in_reliable_expected_seq);
+            // Single acks are useless for packets already covered by the cumulative ack.
+            self.inner.off.ack_in_reliable_packet_cumulative(on.in_reliable_expected_seq);
             if let Some(index) = on.index {
                 packet_config.set_indexed_channel(index.index, index.version);
                 trace!("Is on-channel: {} v{}", index.index, index.version);
@@ -576,7 +651,7 @@ impl OffChannelData {
 
         if let Some(index) = index {
             let reliable_packet = self.out_reliable_packets.remove(index);
-            trace!("Single ack for reliable packet: {sequence_num} after {:?}", reliable_packet.time.elapsed());
+            trace!("Single ack for reliable packet: {sequence_num} after {}", DurationFmt(reliable_packet.time.elapsed()));
         }
 
     }
@@ -594,8 +669,8 @@ impl OffChannelData {
 
         trace!("Cumulative ack for reliable packets: ..{sequence_num}");
         for reliable_packet in self.out_reliable_packets.drain(..drain_len) {
-            trace!("Cumulative ack for a previous packet: {}, after: {:?}", 
-                reliable_packet.sequence_num, reliable_packet.time.elapsed());
+            trace!("Cumulative ack for a previous packet: {}, after: {}", 
+                reliable_packet.sequence_num, DurationFmt(reliable_packet.time.elapsed()));
         }
 
     }
@@ -688,32 +763,43 @@ struct OnChannelData {
     /// 
     /// NOTE: This is the same as `inSeqAt_` in BW source.
     in_reliable_expected_seq: Seq,
+    /// Tracker of received reliable sequence numbers, used to detect duplicates of
+    /// buffered packets, its window is the reorder window.
+    in_reliable_tracker: SeqTracker,
     in_reliable_contiguous_packets: VecDeque<PacketLocked>,
     in_reliable_packets: VecDeque<PacketLocked>,
 }
 
 impl OnChannelData {
 
-    fn new(index: Option<ChannelIndex>) -> Self {
+    fn new(index: Option<ChannelIndex>, reorder_window: u32) -> Self {
         Self {
             index,
             seq_alloc: SeqAlloc::new(Seq::ZERO),
             in_reliable_expected_seq: Seq::ZERO,
+            in_reliable_tracker: SeqTracker::with_window(reorder_window),
             in_reliable_contiguous_packets: VecDeque::new(),
             in_reliable_packets: VecDeque::new(),
         }
     }
 
-    fn new_without_index() -> Self {
-        Self::new(None)
+    fn new_without_index(reorder_window: u32) -> Self {
+        Self::new(None, reorder_window)
+    }
+
+    fn new_with_index_version(index: NonZero<u32>, version: NonZero<u32>, reorder_window: u32) -> Self {
+        Self::new(Some(ChannelIndex { index, version }), reorder_window)
     }
 
-    fn new_with_index_version(index: NonZero<u32>, version: NonZero<u32>) -> Self {
-        Self::new(Some(ChannelIndex { index, version }))
+    fn new_with_index(index: NonZero<u32>, reorder_window: u32) -> Self {
+        Self::new_with_index_version(index, NonZero::new(1).unwrap(), reorder_window)
     }
 
-    fn new_with_index(index: NonZero<u32>) -> Self {
-        Self::new_with_index_version(index, NonZero::new(1).unwrap())
+    /// Return true if the given reliable sequence number is not too far ahead of the
+    /// next expected one to be buffered.
+    fn is_in_reliable_window(&self, sequence_num: Seq) -> bool {
+        sequence_num.wrapping_cmp(self.in_reliable_expected_seq).is_le()
+            || sequence_num - self.in_reliable_expected_seq < self.in_reliable_tracker.window()
     }
 
     /// Add a received (in) reliable packet to the internal re-ordering logic of this 
@@ -722,7 +808,7 @@ impl OnChannelData {
     /// 
     /// After this function has filled contiguous and buffered packets, you may want to
     /// user [`Self::pop_in_reliable_bundle`] to pop any completed contiguous bundle.
-    fn add_in_reliable_packet(&mut self, packet: PacketLocked) {
+    fn add_in_reliable_packet(&mut self, packet: PacketLocked, warn_threshold: usize) {
 
         debug_assert!(packet.config().reliable(), "given packet should be reliable");
 
@@ -733,6 +819,7 @@ impl OnChannelData {
 
                 // This is the best scenario, packet is received in-order, so we push the
                 // packet after the currently contiguous sequence.
+                self.in_reliable_tracker.track(sequence_num);
                 self.in_reliable_expected_seq += 1;
                 self.in_reliable_contiguous_packets.push_back(packet);
 
@@ -754,9 +841,14 @@ impl OnChannelData {
             }
             Ordering::Greater => {
 
+                if self.in_reliable_tracker.track(sequence_num) == SeqStatus::Duplicate {
+                    trace!("Duplicate buffered reliable packet: {sequence_num}");
+                    return;
+                }
+
                 // Warning if we get many buffered packets which indicate that we probably
                 // lost track of one of the 
-                if self.in_reliable_packets.len() > 50 {
+                if self.in_reliable_packets.len() > warn_threshold {
                     warn!("Buffered too many in reliable packets: {}", self.in_reliable_packets.len());
                 }
 
@@ -938,3 +1030,99 @@ impl Fragments {
     }
 
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Prepare a bundle with a single empty packet and return that packet's config.
+    fn prepare_single(mut channel: Channel<'_>, reliable: bool) -> (Packet, PacketConfig) {
+        let mut bundle = Bundle::new();
+        bundle.push_empty();
+        channel.prepare(&mut bundle, reliable);
+        let packet = bundle.into_iter().next().unwrap();
+        let mut config = PacketConfig::new();
+        packet.read_config(&mut config).unwrap();
+        (packet, config)
+    }
+
+    #[test]
+    fn off_channel_acks() {
+
+        let a_addr = "127.0.0.1:20016".parse().unwrap();
+        let b_addr = "127.0.0.1:20017".parse().unwrap();
+        let mut a = Protocol::new();
+        let mut b = Protocol::new();
+
+        let (packet, _) = prepare_single(a.off_channel(b_addr), false);
+        assert!(!b.accept(packet, a_addr).unwrap().has_pending_acks());
+
+        let (packet, config) = prepare_single(a.off_channel(b_addr), true);
+        assert!(b.accept(packet, a_addr).unwrap().has_pending_acks());
+
+        let (_, ack_config) = prepare_single(b.off_channel(a_addr), false);
+        assert_eq!(ack_config.single_acks(), &[config.sequence_num()]);
+        assert_eq!(ack_config.cumulative_ack(), None);
+        assert!(!b.off_channel(a_addr).has_pending_acks());
+
+    }
+
+    #[test]
+    fn on_channel_cumulative_ack() {
+
+        let a_addr = "127.0.0.1:20016".parse().unwrap();
+        let b_addr = "127.0.0.1:20017".parse().unwrap();
+        let mut a = Protocol::new();
+        let mut b = Protocol::new();
+
+        let mut last_seq = Seq::ZERO;
+        for _ in 0..3 {
+            let (packet, config) = prepare_single(a.channel(b_addr, None), true);
+            last_seq = config.sequence_num();
+            b.accept(packet, a_addr).unwrap();
+        }
+
+        let (_, ack_config) = prepare_single(b.channel(a_addr, None), false);
+        assert_eq!(ack_config.cumulative_ack(), Some(last_seq + 1));
+        assert!(ack_config.single_acks().is_empty());
+
+    }
+
+    #[test]
+    fn on_channel_reorder_window() {
+
+        let a_addr = "127.0.0.1:20016".parse().unwrap();
+        let b_addr = "127.0.0.1:20017".parse().unwrap();
+        let mut a = Protocol::new();
+        let mut b = Protocol::new();
+        b.set_reorder_window(4);
+        b.set_reorder_warn_threshold(1);
+
+        let packets = (0..6)
+            .map(|_| prepare_single(a.channel(b_addr, None), true).0)
+            .collect::<Vec<_>>();
+
+        // Too far ahead of the expected sequence number 0.
+        assert!(b.accept(packets[5].clone(), a_addr).is_none());
+
+        // Buffered, then duplicate of a buffered packet.
+        assert!(b.accept(packets[2].clone(), a_addr).unwrap().next_bundle().is_none());
+        assert!(b.accept(packets[2].clone(), a_addr).unwrap().next_bundle().is_none());
+
+        assert!(b.accept(packets[0].clone(), a_addr).unwrap().next_bundle().is_some());
+        let mut channel = b.accept(packets[1].clone(), a_addr).unwrap();
+        assert!(channel.next_bundle().is_some());
+        assert!(channel.next_bundle().is_some());
+        assert!(channel.next_bundle().is_none());
+
+        // Now within the window, packets 3 and 4 are still missing.
+        assert!(b.accept(packets[5].clone(), a_addr).unwrap().next_bundle().is_none());
+        let mut channel = b.accept(packets[3].clone(), a_addr).unwrap();
+        assert!(channel.next_bundle().is_some());
+        assert!(channel.next_bundle().is_none());
+
+    }
+
+}
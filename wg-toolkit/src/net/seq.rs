@@ -1,6 +1,7 @@
 //! Small utility for safe and checked storage of sequence numbers, as used on network.
 
 use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::collections::VecDeque;
 use std::cmp::Ordering;
 use std::fmt;
 
@@ -143,6 +144,145 @@ impl SeqAlloc {
 }
 
 
+/// A tracker of received sequence numbers, used for duplicate and loss detection. 
+/// It keeps a sliding window of the sequence numbers received behind the latest one,
+/// sequence numbers further behind than this window can't be distinguished from a new
+/// cycle of sequence numbers and so the tracker is restarted from them.
+#[derive(Debug)]
+pub struct SeqTracker {
+    /// Size of the window, the latest sequence number is included.
+    window: u32,
+    /// The latest sequence number received, none if nothing has been received.
+    latest: Option<Seq>,
+    /// For each sequence number in the window, starting from the latest one and going
+    /// backward, true if it has been received. Its length is at most the window.
+    received: VecDeque<bool>,
+}
+
+/// Status of a sequence number given to [`SeqTracker::track`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqStatus {
+    /// The sequence number is ahead of the latest one, the number of sequence numbers
+    /// skipped in between is given, it is zero when received in order.
+    Ahead { gap: u32 },
+    /// The sequence number is within the window behind the latest one, and has not 
+    /// been received yet, this fills a previous gap.
+    Late,
+    /// The sequence number is within the window and has already been received.
+    Duplicate,
+    /// The sequence number is too far behind the latest one to be within the window, 
+    /// this is interpreted as a new cycle and the tracker restarted from it. This is
+    /// also returned for the first sequence number tracked.
+    Restart,
+}
+
+impl SeqTracker {
+
+    /// Default size of the window.
+    pub const DEFAULT_WINDOW: u32 = 256;
+
+    /// Create a new tracker with the default window size.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_window(Self::DEFAULT_WINDOW)
+    }
+
+    /// Create a new tracker with the given window size, the window size must be greater
+    /// than zero and less than half the range of sequence numbers, so that wrapping 
+    /// comparisons are not ambiguous within the window.
+    pub fn with_window(window: u32) -> Self {
+        assert!(window > 0 && window < Seq::SIZE / 2, "invalid window size");
+        Self {
+            window,
+            latest: None,
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Return the size of the window.
+    #[inline]
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    /// Return the latest sequence number tracked, if any.
+    #[inline]
+    pub fn latest(&self) -> Option<Seq> {
+        self.latest
+    }
+
+    /// Track the given sequence number and return its status relative to previously
+    /// tracked sequence numbers.
+    pub fn track(&mut self, seq: Seq) -> SeqStatus {
+
+        let Some(latest) = self.latest else {
+            self.restart(seq);
+            return SeqStatus::Restart;
+        };
+
+        match seq.wrapping_cmp(latest) {
+            Ordering::Greater => {
+
+                let delta = seq - latest;
+                let gap = delta - 1;
+
+                if delta >= self.window {
+                    self.received.clear();
+                } else {
+                    for _ in 0..gap {
+                        self.received.push_front(false);
+                    }
+                }
+
+                self.received.push_front(true);
+                self.received.truncate(self.window as usize);
+                self.latest = Some(seq);
+                SeqStatus::Ahead { gap }
+
+            }
+            Ordering::Equal => SeqStatus::Duplicate,
+            Ordering::Less => {
+
+                let delta = latest - seq;
+                if delta >= self.window {
+                    self.restart(seq);
+                    return SeqStatus::Restart;
+                }
+
+                // If the sequence number is not in the received window, it has been 
+                // truncated by a large gap, it's still considered late.
+                let index = delta as usize;
+                if index >= self.received.len() {
+                    self.received.resize(index + 1, false);
+                }
+
+                if std::mem::replace(&mut self.received[index], true) {
+                    SeqStatus::Duplicate
+                } else {
+                    SeqStatus::Late
+                }
+
+            }
+        }
+
+    }
+
+    fn restart(&mut self, seq: Seq) {
+        self.latest = Some(seq);
+        self.received.clear();
+        self.received.push_back(true);
+    }
+
+}
+
+impl Default for SeqTracker {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
 
@@ -176,4 +316,32 @@ mod tests {
 
     }
 
+    #[test]
+    fn tracker_window() {
+
+        let mut tracker = SeqTracker::with_window(8);
+        let base = Seq(0x0FFF_FFF0);
+
+        assert_eq!(tracker.track(base), SeqStatus::Restart);
+        for i in 1..=30 {
+            assert_eq!(tracker.track(base + i), SeqStatus::Ahead { gap: 0 });
+        }
+
+        // Crossed the wrapping point.
+        assert_eq!(tracker.latest(), Some(Seq(14)));
+
+        assert_eq!(tracker.track(base + 30), SeqStatus::Duplicate);
+        assert_eq!(tracker.track(base + 24), SeqStatus::Duplicate);
+        assert_eq!(tracker.track(base + 10), SeqStatus::Restart);
+        assert_eq!(tracker.latest(), Some(base + 10));
+
+        assert_eq!(tracker.track(base + 14), SeqStatus::Ahead { gap: 3 });
+        assert_eq!(tracker.track(base + 12), SeqStatus::Late);
+        assert_eq!(tracker.track(base + 12), SeqStatus::Duplicate);
+        assert_eq!(tracker.track(base + 11), SeqStatus::Late);
+        assert_eq!(tracker.track(base + 100), SeqStatus::Ahead { gap: 85 });
+        assert_eq!(tracker.track(base + 95), SeqStatus::Late);
+
+    }
+
 }
@@ -6,10 +6,11 @@ use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::io::{self, Cursor};
 use std::time::Duration;
+use std::thread;
 
 use blowfish::Blowfish;
 
-use tracing::trace;
+use tracing::{trace, debug};
 
 use super::filter::{BlowfishReader, BlowfishWriter, blowfish::BLOCK_SIZE};
 use super::packet::{self, Packet};
@@ -40,6 +41,8 @@ struct Inner {
     /// Possible symmetric encryption on given socket addresses. Behind a shared 
     /// read/write lock because most of the time we don't modify it.
     encryption: RwLock<HashMap<SocketAddr, Arc<Blowfish>>>,
+    /// Optional retry policy for transient send errors.
+    send_retry: RwLock<Option<SendRetry>>,
     total_send_size: AtomicUsize,
     total_send_count: AtomicUsize,
     total_recv_size: AtomicUsize,
@@ -53,6 +56,7 @@ impl PacketSocket {
             inner: Arc::new(Inner {
                 socket: UdpSocket::bind(addr)?,
                 encryption: RwLock::new(HashMap::new()),
+                send_retry: RwLock::new(None),
                 total_send_size: AtomicUsize::new(0),
                 total_send_count: AtomicUsize::new(0),
                 total_recv_size: AtomicUsize::new(0),
@@ -73,6 +77,23 @@ impl PacketSocket {
         self.inner.socket.set_write_timeout(dur)
     }
 
+    /// Move the socket into or out of non-blocking mode, when non-blocking, receiving
+    /// without any pending packet returns an error of kind [`io::ErrorKind::WouldBlock`].
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.inner.socket.set_nonblocking(nonblocking)
+    }
+
+    /// Retry sending packets on transient errors, such as when the system has no
+    /// buffer space available or the send would block, see [`SendRetry`].
+    pub fn set_send_retry(&self, retry: SendRetry) {
+        *self.inner.send_retry.write().unwrap() = Some(retry);
+    }
+
+    /// Remove the send retry policy, transient send errors are then returned directly.
+    pub fn remove_send_retry(&self) {
+        *self.inner.send_retry.write().unwrap() = None;
+    }
+
     #[inline]
     pub fn set_encryption(&mut self, addr: SocketAddr, blowfish: Arc<Blowfish>) {
         self.inner.encryption.write().unwrap().insert(addr, blowfish);
@@ -132,7 +153,8 @@ impl PacketSocket {
     pub fn send_without_encryption(&self, packet: &Packet, addr: SocketAddr) -> io::Result<usize> {
         self.inner.total_send_size.fetch_add(packet.len(), Ordering::Relaxed);
         self.inner.total_send_count.fetch_add(1, Ordering::Relaxed);
-        self.inner.socket.send_to(packet.slice(), addr)
+        let retry = *self.inner.send_retry.read().unwrap();
+        send_with_retry(retry, || self.inner.socket.send_to(packet.slice(), addr))
     }
 
     /// Send a packet to the given peer.
@@ -155,16 +177,39 @@ impl PacketSocket {
 
     /// Send all packets in a bundle to the given peer, without encryption if set for the address.
     pub fn send_bundle_without_encryption(&self, bundle: &Bundle, addr: SocketAddr) -> io::Result<usize> {
+        
+        #[cfg(all(feature = "sendmmsg", target_os = "linux"))]
+        if bundle.len() > 1 {
+            let packets = bundle.iter().collect::<Vec<_>>();
+            return self.send_packets_without_encryption(&packets, addr);
+        }
+
         let mut size = 0;
         for packet in bundle.iter() {
             size += self.send_without_encryption(packet, addr)?;
         }
         Ok(size)
+
     }
 
     /// Send all packets in a bundle to the given peer.
     pub fn send_bundle(&self, bundle: &Bundle, addr: SocketAddr) -> io::Result<usize> {
         if let Some(blowfish) = self.inner.encryption.read().unwrap().get(&addr) {
+
+            #[cfg(all(feature = "sendmmsg", target_os = "linux"))]
+            if bundle.len() > 1 {
+                
+                // All packets need to be encrypted before sending them all at once.
+                let packets = bundle.iter().map(|packet| {
+                    let mut dst_packet = Packet::new();
+                    encrypt_packet_raw(packet, &blowfish, &mut dst_packet);
+                    dst_packet
+                }).collect::<Vec<_>>();
+
+                let packets = packets.iter().collect::<Vec<_>>();
+                return self.send_packets_without_encryption(&packets, addr);
+
+            }
             
             let mut dst_packet = encryption_packet::take();
             let mut size = 0;
@@ -184,6 +229,96 @@ impl PacketSocket {
         }
     }
 
+    /// Send all the given packets to the given peer using a single syscall (unless
+    /// interrupted), without encryption.
+    #[cfg(all(feature = "sendmmsg", target_os = "linux"))]
+    fn send_packets_without_encryption(&self, packets: &[&Packet], addr: SocketAddr) -> io::Result<usize> {
+        
+        let retry = *self.inner.send_retry.read().unwrap();
+        let size = mmsg::send_to(&self.inner.socket, packets, addr, retry)?;
+        self.inner.total_send_size.fetch_add(size, Ordering::Relaxed);
+        self.inner.total_send_count.fetch_add(packets.len(), Ordering::Relaxed);
+        Ok(size)
+
+    }
+
+}
+
+/// A retry policy for sending packets when the socket returns a transient error, this
+/// happens on busy systems when no buffer space is available or when the send would
+/// block. Other errors, including connection reset, are never retried, and interrupted
+/// sends are always retried immediately without counting as a retry.
+/// 
+/// The backoff sleeps on the sending thread, which is usually the thread polling the
+/// application, so no packet is received while backing off, the total backoff of a 
+/// single send is therefore capped by [`Self::max_backoff`].
+#[derive(Debug, Clone, Copy)]
+pub struct SendRetry {
+    /// Maximum number of retries after the first failed attempt.
+    pub attempts: u32,
+    /// The backoff before the first retry, it is multiplied by the retry number for
+    /// the following ones.
+    pub backoff: Duration,
+    /// Maximum total time spent backing off for a single send, the last backoff is 
+    /// shortened to fit and no more retry is done once it has been reached.
+    pub max_backoff: Duration,
+}
+
+impl SendRetry {
+
+    /// Return true if the given error should be retried after the given number of 
+    /// previous retries, if so this function sleeps for the backoff duration.
+    fn retry(&self, retries: u32, error: &io::Error) -> bool {
+        
+        if retries >= self.attempts || !is_transient_send_error(error) {
+            return false;
+        }
+
+        // Previous backoffs sum to: backoff * (1 + 2 + ... + retries).
+        let slept = self.backoff * (retries * (retries + 1) / 2);
+        let Some(remaining) = self.max_backoff.checked_sub(slept).filter(|d| !d.is_zero()) else {
+            return false;
+        };
+
+        debug!("Transient send error, retry {}/{}: {error}", retries + 1, self.attempts);
+        thread::sleep((self.backoff * (retries + 1)).min(remaining));
+        true
+
+    }
+
+}
+
+/// Call the given send function until it succeeds or the retry policy gives up.
+fn send_with_retry(retry: Option<SendRetry>, mut send: impl FnMut() -> io::Result<usize>) -> io::Result<usize> {
+    let mut retries = 0;
+    loop {
+        match send() {
+            Ok(size) => return Ok(size),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if retry.is_some_and(|retry| retry.retry(retries, &e)) => retries += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Return true if the given error is a transient send error that can be retried.
+fn is_transient_send_error(error: &io::Error) -> bool {
+
+    /// The raw OS error code for "no buffer space available".
+    #[cfg(target_os = "linux")]
+    const ENOBUFS: i32 = 105;
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    const ENOBUFS: i32 = 55;
+    #[cfg(windows)]
+    const ENOBUFS: i32 = 10055; // WSAENOBUFS
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios", target_os = "freebsd", windows)))]
+    const ENOBUFS: i32 = -1;
+
+    match error.kind() {
+        io::ErrorKind::WouldBlock => true,
+        _ => error.raw_os_error() == Some(ENOBUFS),
+    }
+
 }
 
 /// A snapshot of packet socket statistics.
@@ -240,7 +375,10 @@ fn decrypt_packet_raw(src_packet: &Packet, bf: &Blowfish, dst_packet: &mut Packe
     // Get the wastage count and compute the packet's length.
     // Note that wastage count also it self length.
     let wastage = dst[wastage_begin];
-    assert!(wastage <= BLOCK_SIZE as u8, "temporary check that wastage is not greater than block size");
+    if wastage == 0 || wastage as usize > BLOCK_SIZE {
+        trace!("Invalid destination packet wastage: {wastage}, block size: {BLOCK_SIZE}");
+        return false;
+    }
 
     dst_packet.set_len(len - wastage as usize - ENCRYPTION_MAGIC.len());
     // Copy the prefix directly because it is clear.
@@ -289,7 +427,11 @@ fn encrypt_packet_raw(src_packet: &Packet, bf: &Blowfish, dst_packet: &mut Packe
 }
 
 /// Decrypt a source packet given a blowfish key, return the clear packet if success,
-/// if the decryption fails it return the source packet not touched.
+/// if the decryption fails it return the source packet not touched. This is the exact
+/// reverse of [`encrypt_packet`], the packet is considered invalid if its encrypted 
+/// length is not a multiple of the block size, or if the magic or wastage footer is 
+/// invalid. The checksum, if any, is not verified here because it's part of the clear
+/// data, it is verified when reading the packet's config.
 pub fn decrypt_packet(src_packet: Packet, bf: &Blowfish) -> Result<Packet, Packet> {
     let mut dst_packet = encryption_packet::take();
     if decrypt_packet_raw(&src_packet, bf, &mut dst_packet) {
@@ -301,7 +443,11 @@ pub fn decrypt_packet(src_packet: Packet, bf: &Blowfish) -> Result<Packet, Packe
     }
 }
 
-/// Encrypt a source packet given a blowfish key, return the encrypted packet.
+/// Encrypt a source packet given a blowfish key, return the encrypted packet. This uses
+/// the on-wire format of the game: the prefix is kept clear, the rest of the packet is
+/// padded with zeros to the block size with a footer made of the encryption magic and 
+/// the wastage count, and then encrypted. The packet's config, and so its checksum if 
+/// any, should be written before encryption, like when sending a bundle.
 pub fn encrypt_packet(src_packet: Packet, bf: &Blowfish) -> Packet {
     let mut dst_packet = encryption_packet::take();
     encrypt_packet_raw(&src_packet, bf, &mut dst_packet);
@@ -330,3 +476,262 @@ mod encryption_packet {
     }
 
 }
+
+/// Internal module for sending multiple packets with a single `sendmmsg` syscall.
+#[cfg(all(feature = "sendmmsg", target_os = "linux"))]
+mod mmsg {
+
+    use std::net::{SocketAddr, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::{io, mem, ptr};
+
+    use crate::net::packet::Packet;
+    use super::SendRetry;
+
+    /// Send all packets to the given address, returning the total size sent.
+    pub fn send_to(socket: &UdpSocket, packets: &[&Packet], addr: SocketAddr, retry: Option<SendRetry>) -> io::Result<usize> {
+
+        let (mut name, name_len) = socket_addr_to_raw(addr);
+
+        let mut iovecs = packets.iter()
+            .map(|packet| libc::iovec {
+                iov_base: packet.slice().as_ptr() as *mut libc::c_void,
+                iov_len: packet.len(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut msgs = iovecs.iter_mut()
+            .map(|iovec| {
+                // SAFETY: The header is a plain C structure, zeroed is valid.
+                let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+                msg.msg_hdr.msg_name = &mut name as *mut libc::sockaddr_storage as *mut libc::c_void;
+                msg.msg_hdr.msg_namelen = name_len;
+                msg.msg_hdr.msg_iov = iovec;
+                msg.msg_hdr.msg_iovlen = 1;
+                msg
+            })
+            .collect::<Vec<_>>();
+
+        let fd = socket.as_raw_fd();
+        let mut sent = 0;
+        let mut retries = 0;
+
+        // The syscall may send less messages than requested, so we loop.
+        while sent < msgs.len() {
+
+            let remaining = &mut msgs[sent..];
+            // SAFETY: All pointers are valid for the duration of the call: the messages
+            // and their iovec, the address and packets' buffers are all borrowed here.
+            let ret = unsafe {
+                libc::sendmmsg(fd, remaining.as_mut_ptr(), remaining.len() as _, 0)
+            };
+
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                } else if retry.is_some_and(|retry| retry.retry(retries, &err)) {
+                    // Already sent messages are kept, we only retry the remaining ones.
+                    retries += 1;
+                    continue;
+                }
+                return Err(err);
+            }
+
+            sent += ret as usize;
+
+        }
+
+        Ok(msgs.iter().map(|msg| msg.msg_len as usize).sum())
+
+    }
+
+    /// Convert a standard socket address to its raw representation.
+    fn socket_addr_to_raw(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+
+        // SAFETY: The storage is a plain C structure, zeroed is valid.
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let raw = libc::sockaddr_in {
+                    sin_family: libc::AF_INET as libc::sa_family_t,
+                    sin_port: addr.port().to_be(),
+                    sin_addr: libc::in_addr { s_addr: u32::from_ne_bytes(addr.ip().octets()) },
+                    sin_zero: [0; 8],
+                };
+                // SAFETY: The storage is large enough for any address.
+                unsafe { ptr::write(&mut storage as *mut _ as *mut libc::sockaddr_in, raw) };
+                mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(addr) => {
+                let raw = libc::sockaddr_in6 {
+                    sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                    sin6_port: addr.port().to_be(),
+                    sin6_flowinfo: addr.flowinfo(),
+                    sin6_addr: libc::in6_addr { s6_addr: addr.ip().octets() },
+                    sin6_scope_id: addr.scope_id(),
+                };
+                // SAFETY: Same as above.
+                unsafe { ptr::write(&mut storage as *mut _ as *mut libc::sockaddr_in6, raw) };
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+
+        (storage, len as libc::socklen_t)
+
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn encryption() {
+
+        use blowfish::cipher::KeyInit;
+        use crate::net::bundle::Bundle;
+        use crate::net::element::DebugElementVariable16;
+        use crate::net::packet::PacketConfig;
+
+        let blowfish = Blowfish::new_from_slice(&[0x42; 16]).unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(DebugElementVariable16::<1> { data: b"hello world".to_vec() });
+        let mut config = PacketConfig::new();
+        config.set_has_checksum(true);
+        bundle.write_config(&mut config);
+        bundle.write_prefix(0x12345678);
+
+        for packet in bundle.iter() {
+
+            let clear = packet.slice().to_vec();
+            let encrypted = encrypt_packet(packet.clone(), &blowfish);
+            assert_eq!(encrypted.read_prefix(), 0x12345678);
+            assert_eq!((encrypted.len() - packet::PACKET_PREFIX_LEN) % BLOCK_SIZE, 0);
+            assert_ne!(&encrypted.slice()[packet::PACKET_PREFIX_LEN..], &clear[packet::PACKET_PREFIX_LEN..]);
+
+            let decrypted = decrypt_packet(encrypted, &blowfish).unwrap();
+            assert_eq!(decrypted.slice(), &clear[..]);
+            decrypted.read_config_locked().unwrap();
+
+        }
+
+        // Garbage is not decrypted.
+        let mut garbage = Packet::new();
+        garbage.set_len(packet::PACKET_PREFIX_LEN + 16);
+        assert!(decrypt_packet(garbage, &blowfish).is_err());
+
+    }
+
+    #[test]
+    #[cfg(all(feature = "sendmmsg", target_os = "linux"))]
+    fn send_bundle_mmsg() {
+
+        use blowfish::cipher::KeyInit;
+        use crate::net::bundle::Bundle;
+        use crate::net::element::DebugElementVariable16;
+        use crate::net::packet::PacketConfig;
+
+        let mut sender = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let mut receiver = PacketSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        receiver.set_recv_timeout(Some(Duration::from_secs(5))).unwrap();
+        let sender_addr = sender.addr().unwrap();
+        let receiver_addr = receiver.addr().unwrap();
+
+        let mut bundle = Bundle::new();
+        bundle.element_writer().write_simple(DebugElementVariable16::<1> { data: vec![0x42; 4000] });
+        bundle.write_config(&mut PacketConfig::new());
+        assert!(bundle.len() > 1);
+
+        let size = sender.send_bundle_without_encryption(&bundle, receiver_addr).unwrap();
+        assert_eq!(size, bundle.iter().map(Packet::len).sum::<usize>());
+        assert_eq!(sender.stat().total_send_count, bundle.len());
+        assert_eq!(sender.stat().total_send_size, size);
+
+        for packet in bundle.iter() {
+            let (received, addr) = receiver.recv().unwrap();
+            assert_eq!(addr, sender_addr);
+            assert_eq!(received.slice(), packet.slice());
+        }
+
+        // Encrypted packets are all sent at once too, and decrypted one by one.
+        let blowfish = Arc::new(Blowfish::new_from_slice(&[0x42; 16]).unwrap());
+        sender.set_encryption(receiver_addr, Arc::clone(&blowfish));
+        receiver.set_encryption(sender_addr, blowfish);
+        sender.send_bundle(&bundle, receiver_addr).unwrap();
+        assert_eq!(sender.stat().total_send_count, bundle.len() * 2);
+
+        for packet in bundle.iter() {
+            let (received, _) = receiver.recv().unwrap();
+            assert_eq!(received.slice(), packet.slice());
+        }
+
+    }
+
+    #[test]
+    fn send_retry() {
+
+        let retry = SendRetry { attempts: 3, backoff: Duration::from_millis(1), max_backoff: Duration::from_secs(1) };
+
+        // Two transient errors, then success.
+        let mut calls = 0;
+        let ret = send_with_retry(Some(retry), || {
+            calls += 1;
+            if calls <= 2 {
+                Err(io::ErrorKind::WouldBlock.into())
+            } else {
+                Ok(42)
+            }
+        });
+        assert_eq!(ret.unwrap(), 42);
+        assert_eq!(calls, 3);
+
+        // Connection reset is never retried.
+        let mut calls = 0;
+        let ret = send_with_retry(Some(retry), || {
+            calls += 1;
+            Err(io::ErrorKind::ConnectionReset.into())
+        });
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(calls, 1);
+
+        // Give up after the configured number of retries.
+        let mut calls = 0;
+        let ret = send_with_retry(Some(retry), || {
+            calls += 1;
+            Err(io::ErrorKind::WouldBlock.into())
+        });
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(calls, 4);
+
+        // Interrupted sends are not counted as retries.
+        let mut calls = 0;
+        let ret = send_with_retry(Some(retry), || {
+            calls += 1;
+            match calls {
+                1..=5 => Err(io::ErrorKind::Interrupted.into()),
+                6 => Err(io::ErrorKind::WouldBlock.into()),
+                _ => Ok(42),
+            }
+        });
+        assert_eq!(ret.unwrap(), 42);
+        assert_eq!(calls, 7);
+
+        // Give up once the total backoff is reached: 1 ms then 2 ms shortened to 1 ms.
+        let retry = SendRetry { attempts: 10, backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(2) };
+        let mut calls = 0;
+        let ret = send_with_retry(Some(retry), || {
+            calls += 1;
+            Err(io::ErrorKind::WouldBlock.into())
+        });
+        assert_eq!(ret.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(calls, 3);
+
+    }
+
+}
@@ -1,6 +1,6 @@
 //! Deserialization module for Packed XML.
 
-use std::io::{self, Read, Seek, Cursor};
+use std::io::{self, Read, Seek, SeekFrom, Cursor};
 
 use smallvec::SmallVec;
 use thiserror::Error;
@@ -11,10 +11,38 @@ use crate::util::io::WgReadExt;
 use super::{DataType, Element, Value, Vector, MAGIC};
 
 
+/// Maximum nesting depth of elements, deeper elements are rejected with an invalid
+/// data error to avoid stack overflow on malformed files.
+pub const MAX_DEPTH: usize = 256;
+
 /// Read a packed XML data from an readable and seek-able object.
 /// 
 /// *The content will be read starting from the initial position of the reader.*
-pub fn from_reader<R: Read + Seek>(mut reader: R) -> Result<Box<Element>, DeError> {
+pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Box<Element>, DeError> {
+    from_reader_inner(reader, false)
+}
+
+
+/// Read a packed XML data from an readable and seek-able object, like [`from_reader`]
+/// but strictly checking that all declared data lengths stay within the reader's 
+/// data, a [`DeError::Truncated`] error is returned otherwise.
+/// 
+/// *The content will be read starting from the initial position of the reader.*
+pub fn from_reader_checked<R: Read + Seek>(reader: R) -> Result<Box<Element>, DeError> {
+    from_reader_inner(reader, true)
+}
+
+
+fn from_reader_inner<R: Read + Seek>(mut reader: R, checked: bool) -> Result<Box<Element>, DeError> {
+
+    let end = if checked {
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        Some(end)
+    } else {
+        None
+    };
 
     // Validate file's magic
     if !reader.check_exact(MAGIC)? {
@@ -27,7 +55,7 @@ pub fn from_reader<R: Read + Seek>(mut reader: R) -> Result<Box<Element>, DeErro
     // Parsing
     let dict = read_dictionary(&mut reader)?;
     let mut element = Box::new(Element::new());
-    read_element(&mut reader, &mut *element, &dict[..])?;
+    read_element(&mut reader, &mut *element, &dict[..], end, 0)?;
     Ok(element)
 
 }
@@ -41,6 +69,29 @@ pub fn from_bytes<B: AsRef<[u8]>>(data: B) -> Result<Box<Element>, DeError> {
 }
 
 
+/// Read a packed XML file by mapping it in memory, this avoids reading the whole file
+/// into an intermediate buffer before parsing it.
+/// 
+/// The file must not be modified while it's being parsed, doing so may lead to
+/// inconsistent parsing, or worse.
+#[cfg(feature = "mmap")]
+pub fn from_mmap<P: AsRef<std::path::Path>>(path: P) -> Result<Box<Element>, DeError> {
+
+    let file = std::fs::File::open(path)?;
+
+    // Mapping an empty file is an error on some platforms, and in any case a file 
+    // smaller than the magic is not valid.
+    if file.metadata()?.len() < MAGIC.len() as u64 {
+        return Err(DeError::InvalidMagic);
+    }
+
+    // SAFETY: The caller is warned that the file must not be modified while mapped.
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    from_bytes(&mmap[..])
+
+}
+
+
 /// Internal function to read dictionary.
 fn read_dictionary<R: Read + Seek>(reader: &mut R) -> Result<Vec<String>, DeError> {
     let mut dict = Vec::new();
@@ -76,10 +127,25 @@ fn read_child_descriptor<R: Read>(reader: &mut R) -> Result<ChildDescriptor, DeE
 
 
 /// Internal function that reads the current's element descriptor
-/// and its children.
-fn read_element<R: Read>(reader: &mut R, element: &mut Element, dict: &[String]) -> Result<(), DeError> {
+/// and its children. If the end of the data is given, all data lengths are checked
+/// against it. The depth is the nesting depth of the element, checked against
+/// [`MAX_DEPTH`].
+fn read_element<R: Read + Seek>(reader: &mut R, element: &mut Element, dict: &[String], end: Option<u64>, depth: usize) -> Result<(), DeError> {
     
+    if depth >= MAX_DEPTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "elements nested too deeply").into());
+    }
+
+    if let Some(end) = end {
+        check_truncated(reader, 2 + 4, end)?;
+    }
+
     let children_count = reader.read_u16()? as usize;
+
+    if let Some(end) = end {
+        check_truncated(reader, 4 + 6 * children_count as u64, end)?;
+    }
+
     let self_descriptor = read_data_descriptor(&mut *reader)?;
     let mut children_descriptors = SmallVec::<[ChildDescriptor; 16]>::new();
     
@@ -87,14 +153,22 @@ fn read_element<R: Read>(reader: &mut R, element: &mut Element, dict: &[String])
         children_descriptors.push(read_child_descriptor(&mut *reader)?);
     }
 
-    read_data(&mut *reader, &mut element.value, &self_descriptor, dict, 0)?;
+    // Start of the element's data, only needed when checking.
+    let data_start = match end {
+        Some(_) => reader.stream_position()?,
+        None => 0,
+    };
+
+    read_data(&mut *reader, &mut element.value, &self_descriptor, dict, 0, end.map(|end| (data_start, end)), depth)?;
     let mut offset = self_descriptor.end_offset;
 
     for child in children_descriptors {
         let mut value = Value::Boolean(false);
-        read_data(&mut *reader, &mut value, &child.data, dict, offset)?;
+        read_data(&mut *reader, &mut value, &child.data, dict, offset, end.map(|end| (data_start, end)), depth)?;
         offset = child.data.end_offset;
-        element.add_children(&dict[child.name_index], value);
+        let name = dict.get(child.name_index)
+            .ok_or(DeError::InvalidNameIndex(child.name_index))?;
+        element.add_children(name, value);
     }
 
     Ok(())
@@ -102,13 +176,36 @@ fn read_element<R: Read>(reader: &mut R, element: &mut Element, dict: &[String])
 }
 
 
-/// Internal function to read a value.
-fn read_data<R: Read>(reader: &mut R, value: &mut Value, desc: &DataDescriptor, dict: &[String], offset: u32) -> Result<(), DeError> {
-    let len = (desc.end_offset - offset) as usize;
+/// Internal function to check that the given length can be read from the reader's 
+/// current position without exceeding the given end.
+fn check_truncated<R: Seek>(reader: &mut R, len: u64, end: u64) -> Result<(), DeError> {
+    let at = reader.stream_position()?;
+    if at + len > end {
+        Err(DeError::Truncated { at })
+    } else {
+        Ok(())
+    }
+}
+
+
+/// Internal function to read a value. If the start of the parent element's data and
+/// the end of the data are given, the value's length is checked against it. The depth
+/// is the one of the parent element.
+fn read_data<R: Read + Seek>(reader: &mut R, value: &mut Value, desc: &DataDescriptor, dict: &[String], offset: u32, bounds: Option<(u64, u64)>, depth: usize) -> Result<(), DeError> {
+    
+    let len = desc.end_offset.checked_sub(offset)
+        .ok_or(DeError::InvalidDataOffset(desc.end_offset))? as usize;
+
+    if let Some((data_start, end)) = bounds {
+        if data_start + desc.end_offset as u64 > end {
+            return Err(DeError::Truncated { at: data_start + offset as u64 });
+        }
+    }
+
     *value = match desc.ty {
         DataType::Element => {
             let mut element = Box::new(Element::new());
-            read_element(reader, &mut *element, dict)?;
+            read_element(reader, &mut *element, dict, bounds.map(|(_, end)| end), depth + 1)?;
             Value::Element(element)
         },
         DataType::String => Value::String(read_string(reader, len)?),
@@ -216,7 +313,100 @@ pub enum DeError {
     /// Invalid vector length, not a multiple a 4 bytes (f32).
     #[error("invalid data length of {0} bytes for a vector")]
     InvalidVectorLen(usize),
+    /// A data end offset is lower than the previous one.
+    #[error("invalid data end offset {0}")]
+    InvalidDataOffset(u32),
+    /// A child name index is not present in the dictionary.
+    #[error("invalid name index {0}")]
+    InvalidNameIndex(usize),
+    /// The data is shorter than declared, only returned when reading with
+    /// [`from_reader_checked`].
+    #[error("truncated data at {at}")]
+    Truncated {
+        /// Offset in the reader of the truncated data.
+        at: u64,
+    },
     /// IO error while unpacking.
     #[error("io error: {0}")]
     Io(#[from] io::Error),
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::pxml::to_writer;
+
+    #[test]
+    fn checked_truncated() {
+
+        let mut element = Element::new();
+        element.add_children("name", Value::String("hello world!".to_string()));
+        element.add_children("count", Value::Integer(1000));
+
+        let mut data = Vec::new();
+        to_writer(Cursor::new(&mut data), &element).unwrap();
+
+        let checked = from_reader_checked(Cursor::new(&data[..])).unwrap();
+        assert_eq!(checked.get_child("name").and_then(Value::as_string), Some("hello world!"));
+        assert_eq!(checked.get_child("count").and_then(Value::as_integer), Some(1000));
+
+        // Truncate in the middle of the string value.
+        let truncated = &data[..data.len() - 6];
+        match from_reader_checked(Cursor::new(truncated)) {
+            Err(DeError::Truncated { at }) => assert_eq!(at, data.len() as u64 - 14),
+            res => panic!("unexpected result: {res:?}"),
+        }
+
+    }
+
+
+    #[test]
+    fn max_depth() {
+
+        fn nested(depth: usize) -> Vec<u8> {
+            let mut element = Element::new();
+            for _ in 1..depth {
+                let mut parent = Element::new();
+                parent.add_children("child", Value::Element(Box::new(element)));
+                element = parent;
+            }
+            let mut data = Vec::new();
+            to_writer(Cursor::new(&mut data), &element).unwrap();
+            data
+        }
+
+        assert!(from_bytes(nested(MAX_DEPTH)).is_ok());
+        match from_bytes(nested(MAX_DEPTH + 1)) {
+            Err(DeError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            res => panic!("unexpected result: {res:?}"),
+        }
+
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn mmap() {
+
+        use crate::util::TempDir;
+
+        let mut element = Element::new();
+        element.add_children("name", Value::String("hello world!".to_string()));
+
+        let dir_path = TempDir::new("pxml-mmap");
+        let path = dir_path.join("mmap.xml");
+        let mut data = Vec::new();
+        to_writer(Cursor::new(&mut data), &element).unwrap();
+        std::fs::write(&path, &data).unwrap();
+        let mapped = from_mmap(&path);
+        // A file smaller than the magic is rejected before mapping.
+        std::fs::write(&path, &data[..2]).unwrap();
+        let short = from_mmap(&path);
+
+        assert_eq!(mapped.unwrap().get_child("name").and_then(Value::as_string), Some("hello world!"));
+        assert!(matches!(short, Err(DeError::InvalidMagic)));
+
+    }
+
+}
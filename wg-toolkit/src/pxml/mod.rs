@@ -13,9 +13,13 @@ use smallvec::SmallVec;
 
 mod de;
 mod ser;
+mod typed;
 
-pub use de::{from_reader, from_bytes, DeError};
+pub use de::{from_reader, from_reader_checked, from_bytes, DeError, MAX_DEPTH};
+#[cfg(feature = "mmap")]
+pub use de::from_mmap;
 pub use ser::to_writer;
+pub use typed::{from_element, from_value, TypedError, ElementDeserializer, ValueDeserializer};
 
 
 /// Magic of a packed XML file.
@@ -34,7 +38,7 @@ pub enum Value {
 
 /// A packed XML f32 vector of values, this may contains one value or more.
 #[derive(Debug, Clone)]
-pub struct Vector(SmallVec<[f32; 3]>);
+pub struct Vector(pub(crate) SmallVec<[f32; 3]>);
 
 /// A packed element.
 #[derive(Debug, Clone)]
@@ -87,6 +91,13 @@ impl Element {
         self.children.iter_mut().find_map(|(k, v)| (k == key).then_some(v))
     }
 
+    /// Clone the first child with the given key as an owned element, this can be used
+    /// to extract a subtree and insert it elsewhere. This returns none if there is no
+    /// child with this key or if the child is not an element.
+    pub fn extract_child(&self, key: &str) -> Option<Element> {
+        self.get_child(key)?.as_element().cloned()
+    }
+
     pub fn insert_child(&mut self, index: usize, name: String, value: Value) -> &'_ mut Value {
         self.children.insert(index, (name, value));
         &mut self.children[index].1
@@ -96,10 +107,116 @@ impl Element {
         self.insert_child(self.children.len(), name, value)
     }
 
+    /// Get the child at the given index, indices are the same as the order of
+    /// [`Self::iter_children_all`].
+    pub fn child_at(&self, index: usize) -> Option<&(String, Value)> {
+        self.children.get(index)
+    }
+
+    /// Get the child at the given index mutably, see [`Self::child_at`].
+    pub fn child_at_mut(&mut self, index: usize) -> Option<&mut (String, Value)> {
+        self.children.get_mut(index)
+    }
+
+    /// Replace the child at the given index, if the index is equal to the number of
+    /// children then the child is appended. This function panics if the index is
+    /// greater than the number of children.
+    pub fn set_child_at(&mut self, index: usize, name: String, value: Value) -> &'_ mut Value {
+        if index == self.children.len() {
+            self.push_child(name, value)
+        } else {
+            let child = &mut self.children[index];
+            *child = (name, value);
+            &mut child.1
+        }
+    }
+
+    /// Swap the two children at the given indices, this function panics if any of
+    /// the indices is out of bounds.
+    pub fn swap_children(&mut self, a: usize, b: usize) {
+        self.children.swap(a, b);
+    }
+
+    /// Apply the given patch element onto this element, this is typically used to layer
+    /// overrides on top of a base configuration. Each child of the patch is matched with
+    /// a child of this element with the same key and replaced or merged depending on
+    /// the strategy, patch children that have no match are appended.
+    /// 
+    /// Because keys are not unique, the n-th child with a given key in the patch is
+    /// matched with the n-th child with the same key in this element, so patching an
+    /// element with duplicated keys only requires the patch to repeat these keys in the
+    /// same order. Children of this element that are not present in the patch are left
+    /// untouched.
+    pub fn merge_from(&mut self, patch: &Element, strategy: MergeStrategy) {
+
+        if strategy == MergeStrategy::Deep {
+            self.merge_value_from(&patch.value);
+        }
+
+        for (patch_index, (key, patch_value)) in patch.children.iter().enumerate() {
+
+            let occurrence = patch.children[..patch_index].iter()
+                .filter(|(k, _)| k == key)
+                .count();
+
+            let child = self.children.iter_mut()
+                .filter(|(k, _)| k == key)
+                .nth(occurrence);
+
+            match (child, patch_value) {
+                (Some((_, Value::Element(element))), Value::Element(patch_element)) if strategy == MergeStrategy::Deep => {
+                    element.merge_from(patch_element, strategy);
+                }
+                (Some((_, value)), _) => {
+                    *value = patch_value.clone();
+                }
+                (None, _) => {
+                    self.children.push((key.clone(), patch_value.clone()));
+                }
+            }
+
+        }
+
+    }
+
+    /// Internal function to merge the proper value of a patch element, an empty string
+    /// being the default value of an element, it is not considered as an override.
+    fn merge_value_from(&mut self, patch_value: &Value) {
+        match patch_value {
+            Value::String(s) if s.is_empty() => {}
+            value => self.value = value.clone(),
+        }
+    }
+
+}
+
+/// The strategy used by [`Element::merge_from`] for children present in both the
+/// element and the patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeStrategy {
+    /// The child is replaced as a whole by the patch's child, including all of its
+    /// own children.
+    Replace,
+    /// If both children are elements, the patch's child is recursively merged into
+    /// the existing one, otherwise the child is replaced. The proper value of merged
+    /// elements is only replaced if the patch's one is not an empty string.
+    #[default]
+    Deep,
 }
 
 impl Value {
 
+    /// Return the name of this value's type, used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Element(_) => "element",
+            Self::String(_) => "string",
+            Self::Integer(_) => "integer",
+            Self::Boolean(_) => "boolean",
+            Self::Vector(_) => "vector",
+        }
+    }
+
     /// Try to get this value as an element if possible.
     #[inline]
     pub fn as_element(&self) -> Option<&Element> {
@@ -113,12 +230,17 @@ impl Value {
     }
 
     /// Try to get this value as an integer if possible.
+    /// 
+    /// Booleans are never coerced to integers, so that a value stored as a boolean
+    /// can be distinguished from an integer and is serialized back as a boolean.
     #[inline]
     pub fn as_integer(&self) -> Option<i64> {
         if let Self::Integer(n) = *self { Some(n) } else { None }
     }
 
     /// Try to get this value as a boolean is possible.
+    /// 
+    /// Integers are never coerced to booleans, see [`Self::as_integer`].
     #[inline]
     pub fn as_boolean(&self) -> Option<bool> {
         if let Self::Boolean(b) = *self { Some(b) } else { None }
@@ -284,7 +406,8 @@ enum DataType {
     Element = 0,
     String = 1,
     Integer = 2,
-    /// A 32-bit float vector of any size.
+    /// A 32-bit float vector of any size, legacy encoding used to name it float, 3D
+    /// vector or affine transform depending on its size.
     Vector = 3,
     Boolean = 4,
     /// This special kind act like a compressed string.
@@ -331,3 +454,122 @@ fn parse_string_vector<const LEN: usize>(s: &str) -> Option<[f32; LEN]> {
     }
     Some(ret)
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn extract_child() {
+
+        let mut host = Element::new();
+        host.add_children("name", Value::String("WGTK".to_string()));
+
+        let mut element = Element::new();
+        element.add_children("host", Value::Element(Box::new(host)));
+        element.add_children("count", Value::Integer(1));
+
+        let mut extracted = element.extract_child("host").unwrap();
+        *extracted.get_child_mut("name").unwrap() = Value::String("other".to_string());
+        extracted.add_children("url", Value::String("localhost".to_string()));
+
+        let host = element.get_child("host").unwrap().as_element().unwrap();
+        assert_eq!(host.len(), 1);
+        assert_eq!(host.get_child("name").unwrap().as_string(), Some("WGTK"));
+        assert_eq!(extracted.get_child("name").unwrap().as_string(), Some("other"));
+
+        assert!(element.extract_child("count").is_none());
+        assert!(element.extract_child("missing").is_none());
+
+    }
+
+    #[test]
+    fn child_at() {
+
+        let mut element = Element::new();
+        element.add_children("a", Value::Integer(1));
+        element.add_children("b", Value::Integer(2));
+
+        assert_eq!(element.child_at(1).map(|(k, v)| (k.as_str(), v.as_integer())), Some(("b", Some(2))));
+        assert!(element.child_at(2).is_none());
+        assert!(element.child_at_mut(2).is_none());
+        element.child_at_mut(0).unwrap().1 = Value::Integer(3);
+
+        // Replace an existing child, then append at the end.
+        *element.set_child_at(1, "c".to_string(), Value::Integer(4)) = Value::Integer(5);
+        element.set_child_at(2, "d".to_string(), Value::Integer(6));
+        assert_eq!(element.len(), 3);
+
+        element.swap_children(0, 2);
+        element.swap_children(1, 1);
+        let children = element.iter_children_all()
+            .map(|(k, v)| (k.as_str(), v.as_integer().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(children, [("d", 6), ("c", 5), ("a", 3)]);
+
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_child_at_out_of_range() {
+        let mut element = Element::new();
+        element.add_children("a", Value::Integer(1));
+        element.set_child_at(2, "b".to_string(), Value::Integer(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_children_out_of_range() {
+        let mut element = Element::new();
+        element.add_children("a", Value::Integer(1));
+        element.swap_children(0, 1);
+    }
+
+    #[test]
+    fn merge_from() {
+
+        let mut graphics = Element::new();
+        graphics.add_children("quality", Value::Integer(2));
+        graphics.add_children("vsync", Value::Boolean(true));
+
+        let mut base = Element::new();
+        base.add_children("graphics", Value::Element(Box::new(graphics)));
+        base.add_children("path", Value::String("res/a".to_string()));
+        base.add_children("path", Value::String("res/b".to_string()));
+
+        let mut patch_graphics = Element::new();
+        patch_graphics.add_children("quality", Value::Integer(4));
+
+        let mut patch = Element::new();
+        patch.add_children("graphics", Value::Element(Box::new(patch_graphics)));
+
+        // Deep merge only changes the patched nested value.
+        let mut deep = base.clone();
+        deep.merge_from(&patch, MergeStrategy::Deep);
+        let graphics = deep.get_child("graphics").unwrap().as_element().unwrap();
+        assert_eq!(graphics.len(), 2);
+        assert_eq!(graphics.get_child("quality").unwrap().as_integer(), Some(4));
+        assert_eq!(graphics.get_child("vsync").unwrap().as_boolean(), Some(true));
+        assert_eq!(deep.iter_children("path").filter_map(Value::as_string).collect::<Vec<_>>(), ["res/a", "res/b"]);
+
+        // Replace drops the unpatched nested value.
+        let mut replaced = base.clone();
+        replaced.merge_from(&patch, MergeStrategy::Replace);
+        let graphics = replaced.get_child("graphics").unwrap().as_element().unwrap();
+        assert_eq!(graphics.len(), 1);
+        assert_eq!(graphics.get_child("quality").unwrap().as_integer(), Some(4));
+
+        // Duplicated keys are matched by occurrence, extra ones are appended.
+        let mut patch = Element::new();
+        patch.add_children("path", Value::String("res/a".to_string()));
+        patch.add_children("path", Value::String("res/c".to_string()));
+        patch.add_children("path", Value::String("res/d".to_string()));
+        base.merge_from(&patch, MergeStrategy::Deep);
+        assert_eq!(base.len(), 4);
+        assert_eq!(base.iter_children("path").filter_map(Value::as_string).collect::<Vec<_>>(), ["res/a", "res/c", "res/d"]);
+
+    }
+
+}
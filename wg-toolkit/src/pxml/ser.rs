@@ -2,7 +2,7 @@
 
 use std::io::{self, Write, Seek, SeekFrom};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use smallvec::SmallVec;
 use base64::Engine;
@@ -16,6 +16,10 @@ use super::{MAGIC, Element, Value, DataType};
 /// 
 /// *The content will be written starting from the inital position
 /// of the writer.*
+/// 
+/// Vectors are written with the same data type as the legacy float, 3D vector and 
+/// affine transform values, so a single-component vector is written exactly like a
+/// legacy float.
 pub fn to_writer<W: Write + Seek>(mut writer: W, element: &Element) -> io::Result<()> {
 
     // Write the magic number.
@@ -35,6 +39,69 @@ pub fn to_writer<W: Write + Seek>(mut writer: W, element: &Element) -> io::Resul
 }
 
 
+impl Element {
+
+    /// Compute the size, in bytes, of the whole Packed XML document that would be 
+    /// written by [`to_writer`] with this element as root, without serializing it.
+    pub fn encoded_size(&self) -> usize {
+        let mut dict = HashSet::new();
+        // Magic, zero byte and the last empty cstring of the dictionary.
+        MAGIC.len() + 1 + self.encoded_dict_size(&mut dict) + 1 + self.encoded_node_size()
+    }
+
+    /// Compute the size of the dictionary entries for names not already present.
+    fn encoded_dict_size<'a>(&'a self, dict: &mut HashSet<&'a String>) -> usize {
+        let mut size = 0;
+        for (k, v) in &self.children {
+            if dict.insert(k) {
+                size += k.len() + 1;
+            }
+            if let Value::Element(child_element) = v {
+                size += child_element.encoded_dict_size(&mut *dict);
+            }
+        }
+        size
+    }
+
+    /// Compute the size of this element as written in the tree, see [`write_element`].
+    fn encoded_node_size(&self) -> usize {
+        2 + 4 + 6 * self.children.len() 
+            + self.value.encoded_size() 
+            + self.children.iter().map(|(_, v)| v.encoded_size()).sum::<usize>()
+    }
+
+}
+
+impl Value {
+
+    /// Compute the size, in bytes, of this value's data as written in an element, 
+    /// without serializing it. For elements, this is the size of the element's node
+    /// and its children, excluding the document's header and dictionary.
+    pub fn encoded_size(&self) -> usize {
+        match self {
+            Value::Element(element) => element.encoded_node_size(),
+            Value::String(s) => compress_string(s).map(|c| c.len()).unwrap_or(s.len()),
+            &Value::Integer(n) => {
+                if n == 0 {
+                    0
+                } else if i8::try_from(n).is_ok() {
+                    1
+                } else if i16::try_from(n).is_ok() {
+                    2
+                } else if i32::try_from(n).is_ok() {
+                    4
+                } else {
+                    8
+                }
+            }
+            &Value::Boolean(b) => if b { 1 } else { 0 },
+            Value::Vector(v) => 4 * v.len(),
+        }
+    }
+
+}
+
+
 /// Internal function to analyze and fill the node's name dictionary.
 fn write_and_fill_dict<'a, W: Write + Seek>(writer: &mut W, element: &'a Element, dict: &mut HashMap<&'a String, u16>, next_index: &mut u16) -> io::Result<()> {
     
@@ -119,12 +186,9 @@ fn write_value<W: Write + Seek>(writer: &mut W, value: &Value, dict: &HashMap<&S
             write_element(writer, &*child_element, dict).map(|len| (DataType::Element, len))
         }
         Value::String(s) => {
-            // Here we check if the input can possibly be compressed.
-            if !s.is_empty() && s.len() % 4 == 0 {
-                if let Ok(compressed) = base64::prelude::BASE64_STANDARD.decode(s.as_bytes()) {
-                    writer.write_all(&compressed[..])?;
-                    return Ok((DataType::CompressedString, compressed.len()))
-                }
+            if let Some(compressed) = compress_string(s) {
+                writer.write_all(&compressed[..])?;
+                return Ok((DataType::CompressedString, compressed.len()))
             }
             writer.write_string(s)?;
             Ok((DataType::String, s.len()))
@@ -161,7 +225,113 @@ fn write_value<W: Write + Seek>(writer: &mut W, value: &Value, dict: &HashMap<&S
 }
 
 
+/// Internal function to check if the given string can be compressed, if so the 
+/// compressed bytes are returned.
+fn compress_string(s: &str) -> Option<Vec<u8>> {
+    // Here we check if the input can possibly be compressed.
+    if !s.is_empty() && s.len() % 4 == 0 {
+        base64::prelude::BASE64_STANDARD.decode(s.as_bytes()).ok()
+    } else {
+        None
+    }
+}
+
+
 #[inline]
 fn calc_data_descriptor(ty: DataType, offset: usize) -> u32 {
     (ty.to_raw() << 28) | (offset as u32 & 0x00FFFFFFF)
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::pxml::Vector;
+
+    #[test]
+    fn scalar_vector() {
+
+        let mut element = Element::new();
+        element.add_children("v", Value::Vector(Vector(SmallVec::from_slice(&[1.5]))));
+
+        let mut data = Vec::new();
+        to_writer(Cursor::new(&mut data), &element).unwrap();
+
+        // Magic, zero, dictionary "v\0\0", children count and self descriptor.
+        let children = &data[4 + 1 + 3 + 2 + 4..];
+        let v_descriptor = u32::from_le_bytes(children[2..6].try_into().unwrap());
+        assert_eq!(v_descriptor >> 28, 3, "expected legacy float data type");
+        assert_eq!(v_descriptor & 0x0FFFFFFF, 4);
+        assert_eq!(&children[6..], 1.5f32.to_le_bytes());
+
+    }
+
+    #[test]
+    fn boolean_round_trip() {
+
+        let mut element = Element::new();
+        element.add_children("t", Value::Boolean(true));
+        element.add_children("f", Value::Boolean(false));
+        element.add_children("i", Value::Integer(1));
+        element.add_children("z", Value::Integer(0));
+
+        let mut data = Vec::new();
+        to_writer(Cursor::new(&mut data), &element).unwrap();
+
+        // Magic, zero, dictionary "t\0f\0i\0z\0\0", children count and self descriptor.
+        let children = &data[4 + 1 + 9 + 2 + 4..];
+        let descriptor = |i: usize| u32::from_le_bytes(children[6 * i + 2..6 * i + 6].try_into().unwrap());
+        assert_eq!(descriptor(0) >> 28, DataType::Boolean.to_raw());
+        assert_eq!(descriptor(1) >> 28, DataType::Boolean.to_raw());
+        assert_eq!(descriptor(2) >> 28, DataType::Integer.to_raw());
+        assert_eq!(descriptor(3) >> 28, DataType::Integer.to_raw());
+
+        let decoded = crate::pxml::from_bytes(&data).unwrap();
+        assert!(matches!(decoded.get_child("t"), Some(&Value::Boolean(true))));
+        assert!(matches!(decoded.get_child("f"), Some(&Value::Boolean(false))));
+        assert!(matches!(decoded.get_child("i"), Some(&Value::Integer(1))));
+        assert!(matches!(decoded.get_child("z"), Some(&Value::Integer(0))));
+        assert_eq!(decoded.get_child("t").and_then(Value::as_integer), None);
+        assert_eq!(decoded.get_child("i").and_then(Value::as_boolean), None);
+
+        // Serializing the decoded element gives back the exact same bytes.
+        let mut round_trip = Vec::new();
+        to_writer(Cursor::new(&mut round_trip), &decoded).unwrap();
+        assert_eq!(round_trip, data);
+
+    }
+
+    #[test]
+    fn encoded_size() {
+
+        let mut child = Element::new();
+        child.value = Value::String("aGVsbG8gd29ybGQh".to_string());
+        child.add_children("i", Value::Integer(-100));
+        child.add_children("i", Value::Integer(1000));
+        child.add_children("s", Value::String("not base64!".to_string()));
+
+        let mut nested = Element::new();
+        nested.add_children("e", Value::Element(Box::new(child.clone())));
+        nested.add_children("v", Value::Vector(Vector(SmallVec::from_slice(&[1.0, 2.0, 3.0]))));
+
+        let mut element = Element::new();
+        element.value = Value::Integer(i64::MAX);
+        element.add_children("e", Value::Element(Box::new(child)));
+        element.add_children("n", Value::Element(Box::new(nested)));
+        for n in [0, 1, -1, 300, -70_000, 1 << 40] {
+            element.add_children("i", Value::Integer(n));
+        }
+        element.add_children("b", Value::Boolean(true));
+        element.add_children("b", Value::Boolean(false));
+        element.add_children("s", Value::String("dGVzdA==".to_string()));
+
+        let mut data = Vec::new();
+        to_writer(Cursor::new(&mut data), &element).unwrap();
+        assert_eq!(element.encoded_size(), data.len());
+
+    }
+
+}
@@ -0,0 +1,494 @@
+//! Deserialization of Packed XML elements into typed structures, through serde.
+//!
+//! Elements are deserialized as maps where children with the same name are grouped,
+//! such group can be deserialized as a sequence, or as a single value in which case
+//! only the first child of the group is used. Primitive values are loosely converted,
+//! for example numbers can be parsed from strings, because Packed XML files are often
+//! converted from text XML where all values are strings.
+
+use std::fmt;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Unexpected, Visitor};
+use smallvec::SmallVec;
+use indexmap::IndexMap;
+use thiserror::Error;
+
+use super::{Element, Value};
+
+
+/// Implement deserializer methods by forwarding them to another method, optionally
+/// on another deserializer, arguments are only passed if listed after the target.
+macro_rules! delegate_deserialize {
+    ( |$self:ident| $target:expr => $(
+        $method:ident $( ( $( $arg:ident : $arg_ty:ty ),* ) )? => $target_method:ident $( ( $( $pass:ident ),* ) )?
+    ),* $(,)? ) => {
+        $(
+            #[allow(unused_variables)]
+            fn $method<V: Visitor<'de>>($self, $( $( $arg: $arg_ty, )* )? visitor: V) -> Result<V::Value, Self::Error> {
+                $target.$target_method($( $( $pass, )* )? visitor)
+            }
+        )*
+    };
+}
+
+
+/// Deserialize the given element into a typed value.
+pub fn from_element<'de, T: Deserialize<'de>>(element: &'de Element) -> Result<T, TypedError> {
+    T::deserialize(ElementDeserializer(element))
+}
+
+/// Deserialize the given value into a typed value.
+pub fn from_value<'de, T: Deserialize<'de>>(value: &'de Value) -> Result<T, TypedError> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+
+/// Error that can happen while deserializing an element into a typed value.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct TypedError(String);
+
+impl de::Error for TypedError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+
+/// A deserializer for an element, deserialized as a map of its children if requested,
+/// or as its own value otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct ElementDeserializer<'de>(pub &'de Element);
+
+/// A deserializer for a single value.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueDeserializer<'de>(pub &'de Value);
+
+/// A deserializer for a group of children values with the same name.
+#[derive(Debug, Clone)]
+struct GroupDeserializer<'de>(SmallVec<[&'de Value; 1]>);
+
+/// Map access to the children of an element, grouped by names.
+struct ChildrenAccess<'de> {
+    /// The remaining groups of children, in order of first appearance of their name.
+    groups: indexmap::map::IntoIter<&'de str, SmallVec<[&'de Value; 1]>>,
+    /// The group of the last key returned.
+    group: Option<GroupDeserializer<'de>>,
+}
+
+impl<'de> ChildrenAccess<'de> {
+
+    fn new(element: &'de Element) -> Self {
+        let mut groups = IndexMap::<_, SmallVec<_>>::new();
+        for (key, value) in element.iter_children_all() {
+            groups.entry(key.as_str()).or_default().push(value);
+        }
+        Self { groups: groups.into_iter(), group: None }
+    }
+
+}
+
+/// Sequence access to a list of values.
+struct ValuesAccess<'de, I> {
+    values: I,
+    _marker: std::marker::PhantomData<&'de ()>,
+}
+
+
+impl<'de> Deserializer<'de> for ElementDeserializer<'de> {
+
+    type Error = TypedError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.0.len() != 0 {
+            self.deserialize_map(visitor)
+        } else {
+            ValueDeserializer(&self.0.value).deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ChildrenAccess::new(self.0))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    delegate_deserialize! { |self| ValueDeserializer(&self.0.value) =>
+        deserialize_bool => deserialize_bool,
+        deserialize_i8 => deserialize_i64,
+        deserialize_i16 => deserialize_i64,
+        deserialize_i32 => deserialize_i64,
+        deserialize_i64 => deserialize_i64,
+        deserialize_u8 => deserialize_i64,
+        deserialize_u16 => deserialize_i64,
+        deserialize_u32 => deserialize_i64,
+        deserialize_u64 => deserialize_i64,
+        deserialize_f32 => deserialize_f64,
+        deserialize_f64 => deserialize_f64,
+        deserialize_char => deserialize_str,
+        deserialize_str => deserialize_str,
+        deserialize_string => deserialize_str,
+        deserialize_identifier => deserialize_str,
+        deserialize_bytes => deserialize_any,
+        deserialize_byte_buf => deserialize_any,
+        deserialize_unit => deserialize_any,
+        deserialize_seq => deserialize_seq,
+        deserialize_unit_struct(name: &'static str) => deserialize_any,
+        deserialize_tuple(len: usize) => deserialize_seq,
+        deserialize_tuple_struct(name: &'static str, len: usize) => deserialize_seq,
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]) => deserialize_enum(name, variants),
+    }
+
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+
+    type Error = TypedError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Element(element) => ElementDeserializer(element).deserialize_any(visitor),
+            Value::String(s) => visitor.visit_borrowed_str(s),
+            &Value::Integer(n) => visitor.visit_i64(n),
+            &Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Vector(v) => match v.as_float() {
+                Some(x) => visitor.visit_f32(x),
+                None => self.deserialize_seq(visitor),
+            }
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match value_bool(self.0) {
+            Some(b) => visitor.visit_bool(b),
+            None => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match value_integer(self.0) {
+            Some(n) => visitor.visit_i64(n),
+            None => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match value_float(self.0) {
+            Some(x) => visitor.visit_f64(x),
+            None => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Element(element) => ValueDeserializer(&element.value).deserialize_str(visitor),
+            Value::String(s) => visitor.visit_borrowed_str(s),
+            &Value::Integer(n) => visitor.visit_string(n.to_string()),
+            &Value::Boolean(b) => visitor.visit_borrowed_str(if b { "true" } else { "false" }),
+            Value::Vector(_) => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Element(element) => ValueDeserializer(&element.value).deserialize_seq(visitor),
+            Value::Vector(v) => visitor.visit_seq(ValuesAccess {
+                values: v.iter().map(|&x| de::value::F32Deserializer::new(x)),
+                _marker: std::marker::PhantomData,
+            }),
+            _ => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Element(element) => ElementDeserializer(element).deserialize_struct(name, fields, visitor),
+            _ => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Element(element) => ElementDeserializer(element).deserialize_map(visitor),
+            _ => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Element(element) => ValueDeserializer(&element.value).deserialize_enum(name, variants, visitor),
+            Value::String(s) => visitor.visit_enum(s.trim().into_deserializer()),
+            _ => Err(de::Error::invalid_type(unexpected(self.0), &visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    delegate_deserialize! { |self| self =>
+        deserialize_i8 => deserialize_i64,
+        deserialize_i16 => deserialize_i64,
+        deserialize_i32 => deserialize_i64,
+        deserialize_u8 => deserialize_i64,
+        deserialize_u16 => deserialize_i64,
+        deserialize_u32 => deserialize_i64,
+        deserialize_u64 => deserialize_i64,
+        deserialize_f32 => deserialize_f64,
+        deserialize_char => deserialize_str,
+        deserialize_string => deserialize_str,
+        deserialize_identifier => deserialize_str,
+        deserialize_bytes => deserialize_any,
+        deserialize_byte_buf => deserialize_any,
+        deserialize_unit => deserialize_any,
+        deserialize_unit_struct(name: &'static str) => deserialize_any,
+        deserialize_tuple_struct(name: &'static str, len: usize) => deserialize_seq,
+    }
+
+}
+
+impl<'de> Deserializer<'de> for GroupDeserializer<'de> {
+
+    type Error = TypedError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.0.len() > 1 {
+            self.deserialize_seq(visitor)
+        } else {
+            ValueDeserializer(self.0[0]).deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        // A single vector value is a sequence of its components.
+        if let [value @ Value::Vector(_)] = self.0[..] {
+            return ValueDeserializer(value).deserialize_seq(visitor);
+        }
+        visitor.visit_seq(ValuesAccess {
+            values: self.0.into_iter().map(ValueDeserializer),
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    delegate_deserialize! { |self| ValueDeserializer(self.0[0]) =>
+        deserialize_bool => deserialize_bool,
+        deserialize_i8 => deserialize_i8,
+        deserialize_i16 => deserialize_i16,
+        deserialize_i32 => deserialize_i32,
+        deserialize_i64 => deserialize_i64,
+        deserialize_u8 => deserialize_u8,
+        deserialize_u16 => deserialize_u16,
+        deserialize_u32 => deserialize_u32,
+        deserialize_u64 => deserialize_u64,
+        deserialize_f32 => deserialize_f32,
+        deserialize_f64 => deserialize_f64,
+        deserialize_char => deserialize_char,
+        deserialize_str => deserialize_str,
+        deserialize_string => deserialize_string,
+        deserialize_bytes => deserialize_bytes,
+        deserialize_byte_buf => deserialize_byte_buf,
+        deserialize_unit => deserialize_unit,
+        deserialize_map => deserialize_map,
+        deserialize_identifier => deserialize_identifier,
+        deserialize_ignored_any => deserialize_ignored_any,
+        deserialize_unit_struct(name: &'static str) => deserialize_unit_struct(name),
+        deserialize_tuple(len: usize) => deserialize_tuple(len),
+        deserialize_tuple_struct(name: &'static str, len: usize) => deserialize_tuple_struct(name, len),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]) => deserialize_struct(name, fields),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]) => deserialize_enum(name, variants),
+    }
+
+}
+
+impl<'de> MapAccess<'de> for ChildrenAccess<'de> {
+
+    type Error = TypedError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, values)) = self.groups.next() else {
+            return Ok(None);
+        };
+        self.group = Some(GroupDeserializer(values));
+        seed.deserialize(de::value::BorrowedStrDeserializer::new(key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let group = self.group.take().expect("next value called before next key");
+        seed.deserialize(group)
+    }
+
+}
+
+impl<'de, I> SeqAccess<'de> for ValuesAccess<'de, I>
+where
+    I: Iterator,
+    I::Item: Deserializer<'de, Error = TypedError>,
+{
+
+    type Error = TypedError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        match self.values.next() {
+            Some(de) => seed.deserialize(de).map(Some),
+            None => Ok(None),
+        }
+    }
+
+}
+
+
+fn value_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Element(element) => value_bool(&element.value),
+        Value::String(s) => match s.trim() {
+            s if s.eq_ignore_ascii_case("true") => Some(true),
+            s if s.eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        },
+        &Value::Integer(n) => Some(n != 0),
+        &Value::Boolean(b) => Some(b),
+        Value::Vector(_) => None,
+    }
+}
+
+fn value_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Element(element) => value_integer(&element.value),
+        Value::String(s) => s.trim().parse().ok(),
+        &Value::Integer(n) => Some(n),
+        &Value::Boolean(b) => Some(b as i64),
+        Value::Vector(_) => None,
+    }
+}
+
+fn value_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Element(element) => value_float(&element.value),
+        Value::String(s) => s.trim().parse().ok(),
+        &Value::Integer(n) => Some(n as f64),
+        Value::Vector(v) => v.as_float().map(|x| x as f64),
+        Value::Boolean(_) => None,
+    }
+}
+
+fn unexpected(value: &Value) -> Unexpected<'_> {
+    match value {
+        Value::Element(_) => Unexpected::Map,
+        Value::String(s) => Unexpected::Str(s),
+        &Value::Integer(n) => Unexpected::Signed(n),
+        &Value::Boolean(b) => Unexpected::Bool(b),
+        Value::Vector(_) => Unexpected::Seq,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use serde::Deserialize;
+    use smallvec::smallvec;
+
+    use super::super::Vector;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        name: String,
+        count: u32,
+        ratio: f32,
+        enabled: bool,
+        tags: Vec<String>,
+        #[serde(default)]
+        extra: Option<u8>,
+    }
+
+    #[test]
+    fn deserialize_struct() {
+
+        let mut element = Element::new();
+        element.add_children("name", Value::String("hello".to_string()));
+        element.add_children("tags", Value::String("a".to_string()));
+        element.add_children("count", Value::String(" 42 ".to_string()));
+        element.add_children("ratio", Value::Integer(2));
+        element.add_children("ignored", Value::Boolean(true));
+        element.add_children("enabled", Value::Boolean(true));
+        element.add_children("tags", Value::String("b".to_string()));
+
+        let config = from_element::<Config>(&element).unwrap();
+        assert_eq!(config, Config {
+            name: "hello".to_string(),
+            count: 42,
+            ratio: 2.0,
+            enabled: true,
+            tags: vec!["a".to_string(), "b".to_string()],
+            extra: None,
+        });
+
+        element.add_children("extra", Value::Integer(7));
+        element.add_children("name", Value::String("world".to_string()));
+        let config = from_element::<Config>(&element).unwrap();
+        assert_eq!(config.extra, Some(7));
+        assert_eq!(config.name, "hello");
+
+        *element.get_child_mut("extra").unwrap() = Value::Integer(300);
+        assert!(from_element::<Config>(&element).is_err());
+
+    }
+
+    #[test]
+    fn deserialize_vector_seq() {
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Transform {
+            position: Vec<f32>,
+            scales: Vec<f32>,
+        }
+
+        let mut element = Element::new();
+        element.add_children("position", Value::Vector(Vector(smallvec![1.0, 2.0, 3.0])));
+        element.add_children("scales", Value::Vector(Vector(smallvec![0.5])));
+        element.add_children("scales", Value::Integer(2));
+
+        let transform = from_element::<Transform>(&element).unwrap();
+        assert_eq!(transform, Transform {
+            position: vec![1.0, 2.0, 3.0],
+            scales: vec![0.5, 2.0],
+        });
+
+    }
+
+}
@@ -0,0 +1,267 @@
+//! Asynchronous interface to the resources filesystem, built on top of the blocking 
+//! filesystem and running its operations on tokio's blocking thread pool.
+
+use std::task::{ready, Context, Poll};
+use std::future::Future;
+use std::path::PathBuf;
+use std::io::{self, Read};
+use std::pin::Pin;
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::task::{self, JoinHandle};
+
+use super::{ResFilesystem, ResReadFile, ResDirEntry, ResStat};
+
+
+/// Maximum length read from a file by a single blocking task.
+const MAX_READ_LEN: usize = 64 * 1024;
+
+
+/// An asynchronous wrapper around [`ResFilesystem`], each operation is run on tokio's
+/// blocking thread pool, so this must be used within a tokio runtime. 
+/// 
+/// The underlying filesystem is shared, so its cache is shared between all clones of 
+/// this filesystem and with the blocking filesystem it has been created from. Its 
+/// internal lock is only held while looking up nodes, so concurrent reads of files'
+/// contents are not serialized.
+#[derive(Debug, Clone)]
+pub struct AsyncResFilesystem {
+    inner: ResFilesystem,
+}
+
+impl AsyncResFilesystem {
+
+    /// Create a new asynchronous resources filesystem, see [`ResFilesystem::new`].
+    pub async fn new<P: Into<PathBuf>>(dir_path: P) -> io::Result<Self> {
+        let dir_path = dir_path.into();
+        let inner = spawn(move || ResFilesystem::new(dir_path)).await?;
+        Ok(Self { inner })
+    }
+
+    /// Wrap an existing blocking filesystem, the cache is shared with it.
+    pub fn from_sync(inner: ResFilesystem) -> Self {
+        Self { inner }
+    }
+
+    /// Get the underlying blocking filesystem.
+    #[inline]
+    pub fn as_sync(&self) -> &ResFilesystem {
+        &self.inner
+    }
+
+    /// See [`ResFilesystem::stat`].
+    pub async fn stat<P: AsRef<str>>(&self, node_path: P) -> io::Result<ResStat> {
+        let inner = self.inner.clone();
+        let node_path = node_path.as_ref().to_string();
+        spawn(move || inner.stat(node_path)).await
+    }
+
+    /// See [`ResFilesystem::read`], the returned file implements [`AsyncRead`].
+    pub async fn read<P: AsRef<str>>(&self, file_path: P) -> io::Result<AsyncResReadFile> {
+        let inner = self.inner.clone();
+        let file_path = file_path.as_ref().to_string();
+        let file = spawn(move || inner.read(file_path)).await?;
+        Ok(AsyncResReadFile {
+            size: file.size()?,
+            expected_crc: file.expected_crc(),
+            file: Some(file),
+            task: None,
+            data: Vec::new(),
+            data_pos: 0,
+        })
+    }
+
+    /// See [`ResFilesystem::read_to_vec`].
+    pub async fn read_to_vec<P: AsRef<str>>(&self, file_path: P) -> io::Result<Vec<u8>> {
+        let inner = self.inner.clone();
+        let file_path = file_path.as_ref().to_string();
+        spawn(move || inner.read_to_vec(file_path)).await
+    }
+
+    /// See [`ResFilesystem::read_dir`], all entries are read before returning, the 
+    /// first error that happens while reading entries is returned.
+    pub async fn read_dir<P: AsRef<str>>(&self, dir_path: P) -> io::Result<Vec<ResDirEntry>> {
+        let inner = self.inner.clone();
+        let dir_path = dir_path.as_ref().to_string();
+        spawn(move || inner.read_dir(dir_path)?.collect()).await
+    }
+
+}
+
+impl From<ResFilesystem> for AsyncResFilesystem {
+    fn from(inner: ResFilesystem) -> Self {
+        Self::from_sync(inner)
+    }
+}
+
+/// An asynchronous handle to reading a resource file, see [`AsyncResFilesystem::read`].
+/// Each read is run on tokio's blocking thread pool.
+#[derive(Debug)]
+pub struct AsyncResReadFile {
+    /// The full size of the file, see [`ResReadFile::size`].
+    size: u64,
+    /// See [`ResReadFile::expected_crc`].
+    expected_crc: Option<u32>,
+    /// The file, none while a read task is pending or if the task panicked.
+    file: Option<ResReadFile>,
+    /// The pending read task, returning the file back.
+    task: Option<JoinHandle<(ResReadFile, io::Result<Vec<u8>>)>>,
+    /// Data read by the last task but not yet returned.
+    data: Vec<u8>,
+    /// Position of the remaining data to return.
+    data_pos: usize,
+}
+
+impl AsyncResReadFile {
+
+    /// See [`ResReadFile::size`].
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// See [`ResReadFile::expected_crc`].
+    #[inline]
+    pub fn expected_crc(&self) -> Option<u32> {
+        self.expected_crc
+    }
+
+}
+
+impl AsyncRead for AsyncResReadFile {
+
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+
+        let this = self.get_mut();
+
+        loop {
+
+            if this.data_pos < this.data.len() {
+                let len = buf.remaining().min(this.data.len() - this.data_pos);
+                buf.put_slice(&this.data[this.data_pos..][..len]);
+                this.data_pos += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(task) = &mut this.task {
+
+                let ret = ready!(Pin::new(task).poll(cx));
+                this.task = None;
+                let (file, res) = ret.map_err(io::Error::other)?;
+                this.file = Some(file);
+
+                this.data = res?;
+                this.data_pos = 0;
+                if this.data.is_empty() {
+                    return Poll::Ready(Ok(()));  // End of file.
+                }
+
+                continue;
+
+            }
+
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let Some(mut file) = this.file.take() else {
+                return Poll::Ready(Err(io::Error::other("file lost after a read task panicked")));
+            };
+
+            let len = buf.remaining().min(MAX_READ_LEN);
+            let mut data = std::mem::take(&mut this.data);
+            this.task = Some(task::spawn_blocking(move || {
+                data.resize(len, 0);
+                let res = file.read(&mut data).map(|read_len| {
+                    data.truncate(read_len);
+                    data
+                });
+                (file, res)
+            }));
+
+        }
+
+    }
+
+}
+
+/// Internal function to run a blocking operation and flatten its result.
+async fn spawn<T, F>(func: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(func).await.map_err(io::Error::other)?
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::res::PACKAGES_DIR_NAME;
+    use crate::util::TempDir;
+
+    #[tokio::test]
+    async fn read() {
+
+        let dir_path = TempDir::new("res-async");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts")).unwrap();
+        // Larger than a single read task.
+        let data = (0..MAX_READ_LEN * 2 + 100).map(|i| i as u8).collect::<Vec<_>>();
+        fs::write(dir_path.join("scripts").join("foo.bin"), &data).unwrap();
+
+        let res = AsyncResFilesystem::new(dir_path.path()).await.unwrap();
+        assert!(res.stat("scripts").await.unwrap().is_dir());
+        assert_eq!(res.read_to_vec("scripts/foo.bin").await.unwrap(), data);
+
+        let mut file = res.read("scripts/foo.bin").await.unwrap();
+        assert_eq!(file.size(), data.len() as u64);
+        let mut read_data = Vec::new();
+        file.read_to_end(&mut read_data).await.unwrap();
+        assert_eq!(read_data, data);
+
+        let entries = res.read_dir("scripts").await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name(), "foo.bin");
+        assert_eq!(res.read("scripts/bar.bin").await.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    }
+
+    #[tokio::test]
+    async fn concurrent_reads() {
+
+        let dir_path = TempDir::new("res-async-concurrent");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts")).unwrap();
+        let foo = (0..MAX_READ_LEN * 3).map(|i| i as u8).collect::<Vec<_>>();
+        let bar = (0..MAX_READ_LEN * 2).map(|i| (i / 3) as u8).collect::<Vec<_>>();
+        fs::write(dir_path.join("scripts").join("foo.bin"), &foo).unwrap();
+        fs::write(dir_path.join("scripts").join("bar.bin"), &bar).unwrap();
+
+        let res = AsyncResFilesystem::new(dir_path.path()).await.unwrap();
+
+        // Both reads are spawned before any of them is awaited.
+        let tasks = ["scripts/foo.bin", "scripts/bar.bin"].map(|path| {
+            let res = res.clone();
+            tokio::spawn(async move {
+                let mut file = res.read(path).await?;
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).await?;
+                io::Result::Ok(data)
+            })
+        });
+
+        let [foo_task, bar_task] = tasks;
+        let (foo_data, bar_data) = tokio::join!(foo_task, bar_task);
+        assert_eq!(foo_data.unwrap().unwrap(), foo);
+        assert_eq!(bar_data.unwrap().unwrap(), bar);
+
+    }
+
+}
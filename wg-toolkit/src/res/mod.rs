@@ -1,24 +1,67 @@
 //! Game's resources fetching and indexing.
 
 pub mod package;
+#[cfg(feature = "tokio")]
+pub mod asyncio;
 
 use core::fmt;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashSet};
 use std::io::{Read, Seek, SeekFrom};
 use std::fs::{File, ReadDir};
 use std::sync::{Arc, Mutex};
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::{fs, io};
 
 use indexmap::IndexMap;
+use thiserror::Error;
 
 use package::{PackageReader, PackageFileReader};
 
+use crate::pxml;
+
 
 /// Name of the directory storing packages in the "res/" directory.
 const PACKAGES_DIR_NAME: &'static str = "packages";
 
 
+/// Normalize a resource path, leading and trailing separators are trimmed and repeated
+/// separators are collapsed, the path is only reallocated if needed. None is returned 
+/// if the path contains any `.` or `..` component, or any `\` or `:` that are 
+/// separators or prefixes on some platforms, so that a path can't escape the resources
+/// root. All paths given to [`ResFilesystem`] are normalized with this.
+pub fn normalize_path(path: &str) -> Option<Cow<'_, str>> {
+
+    if path.contains(['\\', ':']) {
+        return None;
+    }
+
+    let components = path.split('/').filter(|c| !c.is_empty());
+    let is_normal = |c: &str| {
+        let mut components = Path::new(c).components();
+        matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+    };
+
+    if !components.clone().all(is_normal) {
+        return None;
+    }
+
+    let trimmed = path.trim_matches('/');
+    if trimmed.contains("//") {
+        Some(Cow::Owned(components.collect::<Vec<_>>().join("/")))
+    } else {
+        Some(Cow::Borrowed(trimmed))
+    }
+
+}
+
+/// Internal function to normalize a path, returning an invalid input error if the path
+/// is rejected.
+fn normalize_path_checked(path: &str) -> io::Result<Cow<'_, str>> {
+    normalize_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid resource path"))
+}
+
+
 /// A virtual read-only filesystem you can use to walk through the game's resources. This
 /// filesystem is designed to work really fast on systems where it will run for a long
 /// time and take advantage of its internal cache, but it will also work on run-once
@@ -55,6 +98,9 @@ struct SharedMut {
     /// Package open errors are silently ignored when reading files and directories, so
     /// this vector contains the errors that may happen and can later be retrieved.
     package_open_errors: Vec<(PathBuf, io::Error)>,
+    /// When enabled, directory listing yield package open errors, see 
+    /// [`ResFilesystem::set_strict_listing`].
+    strict_listing: bool,
     /// Cache for known files and directories.
     node_cache: NodeCache,
 }
@@ -92,6 +138,7 @@ impl ResFilesystem {
                     pending_package_path: pending_package_cache,
                     package_reader_cache: IndexMap::new(),
                     package_open_errors: Vec::new(),
+                    strict_listing: false,
                     node_cache: NodeCache::new(),
                 }),
             }),
@@ -99,14 +146,38 @@ impl ResFilesystem {
 
     }
 
+    /// Enable or disable case-insensitive lookup of nodes in packages, this is disabled
+    /// by default. When enabled, each path component that has no exact match in its
+    /// directory is matched ignoring ASCII case, if many nodes match, the first one in
+    /// byte order is used. Exact matches are always preferred, so this has no cost on
+    /// paths that are correctly cased.
+    /// 
+    /// Note that this option is shared with all clones of this filesystem, and that 
+    /// native files are still looked up with the native filesystem semantics.
+    pub fn with_case_insensitive(self, case_insensitive: bool) -> Self {
+        self.shared.mutable.lock().unwrap().node_cache.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Enable or disable strict directory listing, this is disabled by default. When
+    /// enabled, iterators returned by [`Self::read_dir`] and [`Self::list_by_extension`]
+    /// yield an error for each package that failed to open, after all entries have been
+    /// returned, because the directory may have entries in these broken packages that 
+    /// would otherwise be silently omitted. If no package failed to open, the listing
+    /// is the same as in non-strict mode.
+    /// 
+    /// Note that this option is shared with all clones of this filesystem, and only 
+    /// applies to iterators created after the change.
+    pub fn set_strict_listing(&self, strict: bool) {
+        self.shared.mutable.lock().unwrap().strict_listing = strict;
+    }
+
     /// Get various information about a given path, wether its a directory or file, its
     /// size or the number of children the directory has.
     pub fn stat<P: AsRef<str>>(&self, node_path: P) -> io::Result<ResStat> {
         
-        let node_path = node_path.as_ref();
-        if node_path.starts_with('/') || node_path.ends_with('/') {
-            return Err(io::ErrorKind::NotFound.into());
-        }
+        let node_path = normalize_path_checked(node_path.as_ref())?;
+        let node_path = &*node_path;
 
         let native_file_path = self.shared.dir_path.join(node_path);
         match native_file_path.metadata() {
@@ -126,10 +197,8 @@ impl ResFilesystem {
     /// Read a file from its path in the resource filesystem.
     pub fn read<P: AsRef<str>>(&self, file_path: P) -> io::Result<ResReadFile> {
 
-        let file_path = file_path.as_ref();
-        if file_path.starts_with('/') || file_path.ends_with('/') {
-            return Err(io::ErrorKind::NotFound.into());
-        }
+        let file_path = normalize_path_checked(file_path.as_ref())?;
+        let file_path = &*file_path;
 
         let native_file_path = self.shared.dir_path.join(file_path);
         if native_file_path.is_file() {
@@ -145,21 +214,94 @@ impl ResFilesystem {
 
     }
 
+    /// Find the path of the package containing the given file, this can be used to
+    /// know where a file comes from. None is returned if the file is a native file,
+    /// and a not found error is returned if the file doesn't exists. Like 
+    /// [`Self::read`], this may index pending packages until the file is found.
+    pub fn locate<P: AsRef<str>>(&self, file_path: P) -> io::Result<Option<PathBuf>> {
+
+        let file_path = normalize_path_checked(file_path.as_ref())?;
+        let file_path = &*file_path;
+
+        let native_file_path = self.shared.dir_path.join(file_path);
+        if native_file_path.is_file() {
+            return Ok(None);
+        }
+
+        self.shared.mutable.lock().unwrap()
+            .locate(file_path)
+            .map(Some)
+
+    }
+
+    /// Read a whole file into a vector, the vector is allocated with the exact size of
+    /// the file before reading it. An unexpected end of file error is returned if the 
+    /// file is shorter than its size.
+    pub fn read_to_vec<P: AsRef<str>>(&self, file_path: P) -> io::Result<Vec<u8>> {
+
+        let mut file = self.read(file_path)?;
+        let size = file.size()?;
+        
+        let mut buf = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut buf)?;
+
+        if (buf.len() as u64) < size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file is shorter than its size"));
+        }
+
+        Ok(buf)
+
+    }
+
+    /// Read a whole file and decode it as a Packed XML element.
+    pub fn read_pxml<P: AsRef<str>>(&self, file_path: P) -> Result<Box<pxml::Element>, ReadPxmlError> {
+        let file = self.read(file_path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ReadPxmlError::NotFound,
+            _ => ReadPxmlError::Io(e),
+        })?;
+        Ok(pxml::from_reader(file)?)
+    }
+
+    /// Verify the integrity of a file by computing the CRC-32 of its content and 
+    /// comparing it against the one stored in its package. The file is read in a 
+    /// streaming way. Native files have no stored checksum, so they are always valid.
+    pub fn verify<P: AsRef<str>>(&self, file_path: P) -> io::Result<bool> {
+        let mut file = self.read(file_path)?;
+        match file.expected_crc() {
+            Some(expected_crc) => Ok(compute_crc(&mut file)? == expected_crc),
+            None => Ok(true),
+        }
+    }
+
+    /// Compute the CRC-32 of a file's content, the file is read in a streaming way.
+    /// This can be used to compare native files against an external manifest.
+    pub fn crc<P: AsRef<str>>(&self, file_path: P) -> io::Result<u32> {
+        compute_crc(&mut self.read(file_path)?)
+    }
+
     /// Read a directory's entries in the resource filesystem. This function may be 
     /// blocking a short time because it needs to find the first node of that directory.
     /// 
     /// This function may return a file not found error if no package contains this 
     /// directory.
     pub fn read_dir<P: AsRef<str>>(&self, dir_path: P) -> io::Result<ResReadDir> {
+        self.read_dir_inner(dir_path.as_ref(), None)
+    }
 
-        // Instant error if leading separator.
-        let dir_path = dir_path.as_ref();
-        if dir_path.starts_with('/') || dir_path.starts_with('.') {
-            return Err(io::ErrorKind::NotFound.into());
-        }
+    /// Read a directory's file entries that have the given extension (with or without
+    /// the leading dot), this returns the same entries as filtering [`Self::read_dir`]
+    /// by file name suffix, but entries are not built for non-matching names. Note that
+    /// this doesn't return directories, even if their name has the extension.
+    pub fn list_by_extension<P: AsRef<str>, E: AsRef<str>>(&self, dir_path: P, ext: E) -> io::Result<ResReadDir> {
+        let ext = ext.as_ref();
+        let ext = ext.strip_prefix('.').unwrap_or(ext);
+        self.read_dir_inner(dir_path.as_ref(), Some(Arc::from(format!(".{ext}"))))
+    }
+
+    fn read_dir_inner(&self, dir_path: &str, suffix: Option<Arc<str>>) -> io::Result<ResReadDir> {
 
-        // Remove an possible trailing separator.
-        let dir_path = dir_path.strip_suffix('/').unwrap_or(dir_path);
+        let dir_path = normalize_path_checked(dir_path)?;
+        let dir_path = &*dir_path;
 
         let native_dir_path = self.shared.dir_path.join(dir_path);
         let native_read_dir = fs::read_dir(native_dir_path).ok();
@@ -183,9 +325,13 @@ impl ResFilesystem {
             }
         }
 
+        let strict = mutable.strict_listing.then(|| (Arc::clone(&self.shared), 0));
+
         Ok(ResReadDir {
             dir_path: Arc::from(dir_path),
             common: Box::new(CommonReadDir {
+                suffix,
+                strict,
                 native_read_dir,
                 package_read_dir: dir_index.map(|dir_index| PackageReadDir {
                     shared: Arc::clone(&self.shared),
@@ -288,6 +434,25 @@ impl SharedMut {
 
     }
 
+    /// See [`ResFilesystem::locate()`].
+    fn locate(&mut self, file_path: &str) -> io::Result<PathBuf> {
+
+        loop {
+
+            if let Some((_, file_info)) = self.node_cache.find_file(file_path) {
+                let (package_path, _) = self.package_reader_cache.get_index(file_info.package_index)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "file indexed in an unknown package"))?;
+                return Ok(package_path.clone());
+            }
+
+            if !self.try_open_pending_package() {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+        }
+
+    }
+
     /// See [`ResFilesystem::stat()`].
     fn stat(&mut self, node_path: &str) -> io::Result<ResStat> {
 
@@ -324,6 +489,28 @@ impl SharedMut {
 #[derive(Debug)]
 pub struct ResReadFile(ReadFileInner);
 
+impl ResReadFile {
+
+    /// Return the full size of the file's content, this is the size from the file's
+    /// metadata for native files and from the package's central directory otherwise.
+    pub fn size(&self) -> io::Result<u64> {
+        match &self.0 {
+            ReadFileInner::Package(package) => Ok(package.size()),
+            ReadFileInner::Native(file) => Ok(file.metadata()?.len()),
+        }
+    }
+
+    /// Return the CRC-32 of the file's content, as stored in its package, none is
+    /// returned for native files.
+    pub fn expected_crc(&self) -> Option<u32> {
+        match &self.0 {
+            ReadFileInner::Package(package) => Some(package.expected_crc()),
+            ReadFileInner::Native(_) => None,
+        }
+    }
+
+}
+
 /// Inner handle to
 #[derive(Debug)]
 enum ReadFileInner {
@@ -368,6 +555,21 @@ impl Seek for ResReadFile {
 }
 
 
+/// Internal function to compute the CRC-32 of a reader's content in a streaming way.
+fn compute_crc<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = [0; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(hasher.finalize()),
+            Ok(len) => hasher.update(&buf[..len]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+
 /// A directory read iterator that lazily open packages as iteration advance.
 /// 
 /// IMPL NOTE: This structure is quite heavy, it may be necessary to box its inner state.
@@ -380,10 +582,15 @@ pub struct ResReadDir {
 
 #[derive(Debug)]
 struct CommonReadDir {
+    /// If present, only files with a name ending with this suffix are returned.
+    suffix: Option<Arc<str>>,
     /// The native read dir result that maybe used for iteration before the package part.
     native_read_dir: Option<ReadDir>,
     /// The package read dir mode, yielded after the native read dir if present.
     package_read_dir: Option<PackageReadDir>,
+    /// If strict listing is enabled, the shared data and the index of the next package
+    /// open error to yield once all entries have been returned.
+    strict: Option<(Arc<Shared>, usize)>,
 }
 
 #[derive(Debug)]
@@ -420,11 +627,20 @@ impl Iterator for ResReadDir {
 
     fn next(&mut self) -> Option<Self::Item> {
 
-        if let Some(native_read_dir) = &mut self.common.native_read_dir {
+        while let Some(native_read_dir) = &mut self.common.native_read_dir {
             match native_read_dir.next() {
                 Some(Ok(entry)) => {
                     
                     let file_name = entry.file_name();
+
+                    // Filter before querying metadata, a non-matching name can't be
+                    // duplicated by a matching package name, so it's not saved.
+                    if let Some(suffix) = &self.common.suffix {
+                        if !file_name.as_encoded_bytes().ends_with(suffix.as_bytes()) {
+                            continue;
+                        }
+                    }
+
                     let metadata = match entry.metadata() {
                         Ok(res) => res,
                         Err(e) => return Some(Err(e)),
@@ -435,11 +651,17 @@ impl Iterator for ResReadDir {
                         None => return Some(Err(io::ErrorKind::InvalidData.into())),
                     };
 
+                    // The name is saved even if the entry is not returned, because a
+                    // native directory still shadows a package file of the same name.
                     let name = Arc::<str>::from(file_name);
                     if let Some(package_read_dir) = &mut self.common.package_read_dir {
                         package_read_dir.native_names.insert(Arc::clone(&name));
                     }
 
+                    if self.common.suffix.is_some() && metadata.is_dir() {
+                        continue;
+                    }
+
                     return Some(Ok(ResDirEntry { 
                         dir_path: Arc::clone(&self.dir_path), 
                         name,
@@ -476,6 +698,11 @@ impl Iterator for ResReadDir {
                     for (child_name, &child_index) in &dir_info.children {
                         max_child_index = max_child_index.max(child_index);
                         if child_index >= package_read_dir.last_children_last_node_index {
+                            if let Some(suffix) = &self.common.suffix {
+                                if !child_name.ends_with(&**suffix) {
+                                    continue;
+                                }
+                            }
                             // Don't return names that already have been by native iter.
                             if !package_read_dir.native_names.contains(child_name) {
                                 package_read_dir.remaining_names.push((Arc::clone(child_name), child_index));
@@ -491,6 +718,9 @@ impl Iterator for ResReadDir {
                 if let Some((node_name, node_index)) = package_read_dir.remaining_names.pop() {
 
                     let node_info = mutable.node_cache.get_node(node_index).unwrap();
+                    if self.common.suffix.is_some() && node_info.as_dir().is_some() {
+                        continue;
+                    }
 
                     return Some(Ok(ResDirEntry {
                         dir_path: Arc::clone(&self.dir_path),
@@ -514,13 +744,23 @@ impl Iterator for ResReadDir {
 
                 // If there are no more file, we try opening more packages.
                 if !mutable.try_open_pending_package() {
-                    return None; // No more package to open, no more file to return.
+                    break; // No more package to open, no more file to return.
                 }
 
             }
 
         }
 
+        // All packages have been opened at this point, so we know every package that 
+        // failed to open and may have contained entries.
+        if let Some((shared, error_index)) = &mut self.common.strict {
+            let mutable = shared.mutable.lock().unwrap();
+            if let Some((package_path, e)) = mutable.package_open_errors.get(*error_index) {
+                *error_index += 1;
+                return Some(Err(io::Error::new(e.kind(), format!("failed to open package {}: {e}", package_path.display()))));
+            }
+        }
+
         None
 
     }
@@ -601,6 +841,30 @@ impl ResStat {
 }
 
 
+/// Error returned when reading a file as Packed XML, see [`ResFilesystem::read_pxml`].
+#[derive(Debug, Error)]
+pub enum ReadPxmlError {
+    /// The file doesn't exist.
+    #[error("file not found")]
+    NotFound,
+    /// Other IO error while opening the file.
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    /// The file is not valid Packed XML, this also includes IO errors while reading.
+    #[error("pxml error: {0}")]
+    Pxml(#[from] pxml::DeError),
+}
+
+impl From<ReadPxmlError> for io::Error {
+    fn from(e: ReadPxmlError) -> Self {
+        match e {
+            ReadPxmlError::NotFound => io::ErrorKind::NotFound.into(),
+            ReadPxmlError::Io(e) => e,
+            ReadPxmlError::Pxml(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
 /// The node cache structure.
 struct NodeCache {
     /// Inner file informations tree.
@@ -611,6 +875,8 @@ struct NodeCache {
     dir_children_max_count: usize,
     /// Just for stats.
     node_name_max_len: usize,
+    /// True to fallback to case-insensitive matching of node names when not found.
+    case_insensitive: bool,
 }
 
 /// Kind of cached node information, absent, file or directory node.
@@ -648,6 +914,7 @@ impl NodeCache {
             dir_count: 0,
             dir_children_max_count: 0,
             node_name_max_len: 0,
+            case_insensitive: false,
         }
     }
 
@@ -740,15 +1007,24 @@ impl NodeCache {
     /// Find a node info in cache from the given path. In general it should not have 
     /// leading nor trailing directory separator. The index of the node within internal
     /// nodes array is already returned.
+    /// 
+    /// If case-insensitive lookup is enabled, a path component that has no exact match
+    /// is matched ignoring ASCII case against the directory's children, the first match
+    /// in byte order is chosen if there are many.
     fn find_node(&self, node_path: &str) -> Option<(usize, &NodeInfo)> {
 
         let mut current_node_index = 0;
         if !node_path.is_empty() {
             for node_part in node_path.split('/') {
-                current_node_index = *self.nodes[current_node_index]
-                    .as_dir()?
-                    .children
-                    .get(node_part)?;
+                let children = &self.nodes[current_node_index].as_dir()?.children;
+                current_node_index = match children.get(node_part) {
+                    Some(&child_index) => child_index,
+                    None if self.case_insensitive => {
+                        children.iter()
+                            .find_map(|(name, &child_index)| name.eq_ignore_ascii_case(node_part).then_some(child_index))?
+                    }
+                    None => return None,
+                };
             }
         }
 
@@ -829,6 +1105,285 @@ impl fmt::Debug for NodeCache {
             .field("dir_count", &self.dir_count)
             .field("dir_children_max_count", &self.dir_children_max_count)
             .field("node_name_max_len", &self.node_name_max_len)
+            .field("case_insensitive", &self.case_insensitive)
             .finish()
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::Path;
+
+    use crate::util::TempDir;
+
+    use super::*;
+
+    /// Write a minimal package with the given files, stored without compression.
+    fn write_package(path: &Path, files: &[(&str, &[u8])]) {
+
+        let mut data = Vec::new();
+        let mut central = Vec::new();
+
+        for &(name, content) in files {
+
+            let offset = data.len() as u32;
+            let crc = crc32fast::hash(content);
+            let size = (content.len() as u32).to_le_bytes();
+            let name_len = (name.len() as u16).to_le_bytes();
+
+            data.extend_from_slice(&0x04034b50u32.to_le_bytes());
+            data.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            data.extend_from_slice(&crc.to_le_bytes());
+            data.extend_from_slice(&size);
+            data.extend_from_slice(&size);
+            data.extend_from_slice(&name_len);
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(content);
+
+            central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            central.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            central.extend_from_slice(&crc.to_le_bytes());
+            central.extend_from_slice(&size);
+            central.extend_from_slice(&size);
+            central.extend_from_slice(&name_len);
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+
+        }
+
+        let central_offset = data.len() as u32;
+        let count = (files.len() as u16).to_le_bytes();
+        data.extend_from_slice(&central);
+        data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&count);
+        data.extend_from_slice(&count);
+        data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        data.extend_from_slice(&central_offset.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+
+        fs::write(path, data).unwrap();
+
+    }
+
+    #[test]
+    fn normalize() {
+        assert!(matches!(normalize_path("foo/bar"), Some(Cow::Borrowed("foo/bar"))));
+        assert!(matches!(normalize_path("/foo/bar/"), Some(Cow::Borrowed("foo/bar"))));
+        assert_eq!(normalize_path("foo//bar///baz/").as_deref(), Some("foo/bar/baz"));
+        assert_eq!(normalize_path("/").as_deref(), Some(""));
+        assert_eq!(normalize_path("foo.bar/.baz").as_deref(), Some("foo.bar/.baz"));
+        assert_eq!(normalize_path("../x"), None);
+        assert_eq!(normalize_path("foo/./bar"), None);
+        assert_eq!(normalize_path("foo/.."), None);
+        assert_eq!(normalize_path("..\\x"), None);
+        assert_eq!(normalize_path("foo\\bar"), None);
+        assert_eq!(normalize_path("C:/x"), None);
+        assert_eq!(normalize_path("\\\\server\\share"), None);
+    }
+
+    #[test]
+    fn read_normalized() {
+
+        let dir_path = TempDir::new("res-norm");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("foo")).unwrap();
+        fs::write(dir_path.join("foo").join("bar"), b"native").unwrap();
+        fs::write(dir_path.join("secret"), b"").unwrap();
+        write_package(&dir_path.join(PACKAGES_DIR_NAME).join("foo.pkg"), &[("foo/baz", b"packaged")]);
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        assert_eq!(res.read_to_vec("foo/bar/").unwrap(), b"native");
+        assert_eq!(res.read_to_vec("/foo//bar").unwrap(), b"native");
+        assert_eq!(res.read_to_vec("foo/baz/").unwrap(), b"packaged");
+        assert_eq!(res.read("foo/../secret").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(res.read("foo\\bar").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    }
+
+    #[test]
+    fn strict_listing() {
+
+        let dir_path = TempDir::new("res-strict");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts")).unwrap();
+        fs::write(dir_path.join(PACKAGES_DIR_NAME).join("broken.pkg"), b"not a package").unwrap();
+        fs::write(dir_path.join("scripts").join("foo.xml"), b"").unwrap();
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        let entries = res.read_dir("scripts").unwrap().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].as_ref().unwrap().name(), "foo.xml");
+
+        res.set_strict_listing(true);
+        let entries = res.read_dir("scripts").unwrap().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].as_ref().unwrap().name(), "foo.xml");
+        assert!(entries[1].as_ref().unwrap_err().to_string().contains("broken.pkg"));
+
+    }
+
+    #[test]
+    fn locate() {
+
+        let dir_path = TempDir::new("res-locate");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts")).unwrap();
+        let package_path = dir_path.join(PACKAGES_DIR_NAME).join("scripts.pkg");
+        write_package(&package_path, &[("scripts/bar.xml", b"bar")]);
+        fs::write(dir_path.join("scripts").join("foo.xml"), b"foo").unwrap();
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        assert_eq!(res.locate("scripts/foo.xml").unwrap(), None);
+        assert_eq!(res.locate("scripts/bar.xml").unwrap(), Some(package_path));
+        assert_eq!(res.locate("scripts/baz.xml").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(res.read_to_vec("scripts/bar.xml").unwrap(), b"bar");
+
+    }
+
+    #[test]
+    fn read_to_vec() {
+
+        let dir_path = TempDir::new("res-vec");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("native")).unwrap();
+        let large = (0..100_000u32).map(|i| i as u8).collect::<Vec<u8>>();
+        fs::write(dir_path.join("native").join("large.bin"), &large).unwrap();
+        fs::write(dir_path.join("native").join("empty.bin"), b"").unwrap();
+        write_package(&dir_path.join(PACKAGES_DIR_NAME).join("packaged.pkg"), &[
+            ("packaged/large.bin", &large),
+            ("packaged/empty.bin", b""),
+        ]);
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        for path in ["native/large.bin", "native/empty.bin", "packaged/large.bin", "packaged/empty.bin"] {
+            let data = res.read_to_vec(path).unwrap();
+            assert_eq!(data.len() as u64, res.stat(path).unwrap().size(), "{path}");
+            assert_eq!(data, &large[..data.len()], "{path}");
+        }
+
+    }
+
+    #[test]
+    fn verify() {
+
+        let dir_path = TempDir::new("res-verify");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts")).unwrap();
+        fs::write(dir_path.join("scripts").join("native.xml"), b"native").unwrap();
+        let package_path = dir_path.join(PACKAGES_DIR_NAME).join("scripts.pkg");
+        write_package(&package_path, &[("scripts/intact.xml", b"intact"), ("scripts/modified.xml", b"original")]);
+
+        // Modify the stored content without updating its checksum.
+        let mut data = fs::read(&package_path).unwrap();
+        let offset = data.windows(8).position(|w| w == b"original").unwrap();
+        data[offset..offset + 8].copy_from_slice(b"modified");
+        fs::write(&package_path, data).unwrap();
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        assert!(res.verify("scripts/native.xml").unwrap());
+        assert!(res.verify("scripts/intact.xml").unwrap());
+        assert!(!res.verify("scripts/modified.xml").unwrap());
+        assert_eq!(res.crc("scripts/modified.xml").unwrap(), crc32fast::hash(b"modified"));
+        assert_eq!(res.verify("scripts/missing.xml").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    fn case_insensitive() {
+
+        let dir_path = TempDir::new("res-case");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        write_package(&dir_path.join(PACKAGES_DIR_NAME).join("scripts.pkg"), &[
+            ("scripts/Foo.xml", b"upper"),
+            ("scripts/bar.xml", b"bar"),
+        ]);
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        assert_eq!(res.read_to_vec("scripts/Foo.xml").unwrap(), b"upper");
+        assert_eq!(res.read_to_vec("scripts/foo.xml").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let res = res.with_case_insensitive(true);
+        assert_eq!(res.read_to_vec("scripts/foo.xml").unwrap(), b"upper");
+        assert_eq!(res.read_to_vec("Scripts/BAR.xml").unwrap(), b"bar");
+        assert!(res.stat("SCRIPTS").unwrap().is_dir());
+        assert_eq!(res.read_to_vec("scripts/baz.xml").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    }
+
+
+    #[test]
+    fn list_by_extension() {
+
+        let dir_path = TempDir::new("res-ext");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts").join("native.xml")).unwrap();
+        fs::write(dir_path.join("scripts").join("foo.xml"), b"native").unwrap();
+        fs::write(dir_path.join("scripts").join("foo.txt"), b"").unwrap();
+        write_package(&dir_path.join(PACKAGES_DIR_NAME).join("scripts.pkg"), &[
+            ("scripts/foo.xml", b"packaged"),
+            ("scripts/bar.xml", b"bar"),
+            ("scripts/bar.py", b""),
+            ("scripts/dir.xml/baz.xml", b""),
+            // Shadowed by the native directory of the same name.
+            ("scripts/native.xml", b""),
+        ]);
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+
+        let names = |read_dir: ResReadDir| {
+            let mut entries = read_dir.map(|entry| entry.unwrap()).collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.name().cmp(b.name()));
+            entries.into_iter().map(|entry| (entry.name().to_string(), entry.stat().is_dir())).collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(res.read_dir("scripts").unwrap()), [
+            ("bar.py".to_string(), false),
+            ("bar.xml".to_string(), false),
+            ("dir.xml".to_string(), true),
+            ("foo.txt".to_string(), false),
+            ("foo.xml".to_string(), false),
+            ("native.xml".to_string(), true),
+        ]);
+
+        let expected = [("bar.xml".to_string(), false), ("foo.xml".to_string(), false)];
+        assert_eq!(names(res.list_by_extension("scripts", "xml").unwrap()), expected);
+        assert_eq!(names(res.list_by_extension("scripts", ".xml").unwrap()), expected);
+        assert!(names(res.list_by_extension("scripts", "pkg").unwrap()).is_empty());
+
+    }
+
+
+    #[test]
+    fn read_pxml() {
+
+        let dir_path = TempDir::new("res-pxml");
+        fs::create_dir_all(dir_path.join(PACKAGES_DIR_NAME)).unwrap();
+        fs::create_dir_all(dir_path.join("scripts")).unwrap();
+
+        let mut element = pxml::Element::new();
+        element.add_children("name", pxml::Value::String("hello".to_string()));
+        let mut data = Vec::new();
+        pxml::to_writer(io::Cursor::new(&mut data), &element).unwrap();
+        write_package(&dir_path.join(PACKAGES_DIR_NAME).join("scripts.pkg"), &[("scripts/packed.xml", &data)]);
+        fs::write(dir_path.join("scripts").join("text.xml"), b"<root></root>").unwrap();
+
+        let res = ResFilesystem::new(dir_path.path()).unwrap();
+        let element = res.read_pxml("scripts/packed.xml").unwrap();
+        assert_eq!(element.get_child("name").and_then(pxml::Value::as_string), Some("hello"));
+
+        let error = res.read_pxml("scripts/missing.xml").unwrap_err();
+        assert!(matches!(error, ReadPxmlError::NotFound));
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::NotFound);
+
+        let error = res.read_pxml("scripts/text.xml").unwrap_err();
+        assert!(matches!(error, ReadPxmlError::Pxml(pxml::DeError::InvalidMagic)));
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::InvalidData);
+
+    }
+
+}
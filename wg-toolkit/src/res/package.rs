@@ -56,6 +56,8 @@ struct PackageFileInternalInfo {
     /// so the compressed size should be equal, but this will be checked later if the
     /// file is actually opened.
     size: u32,
+    /// CRC-32 of the uncompressed file's content.
+    crc32: u32,
 }
 
 impl<R: Read + Seek> PackageReader<R> {
@@ -138,7 +140,9 @@ impl<R: Read + Seek> PackageReader<R> {
             }
 
             // Skip most of the header that we don't care at this point.
-            reader.seek_relative(20)?;
+            reader.seek_relative(12)?;
+            let crc32 = reader.read_u32()?;
+            reader.seek_relative(4)?;
             // Uncompressed size is used as 
             let uncompressed_size = reader.read_u32()?;
             // Then we read all variable lengths.
@@ -175,6 +179,7 @@ impl<R: Read + Seek> PackageReader<R> {
                 name_len: file_name_len,
                 header_offset: relative_offset,
                 size: uncompressed_size,
+                crc32,
             });
 
         }
@@ -216,6 +221,7 @@ impl<R: Read + Seek> PackageReader<R> {
             PackageFileInfo {
                 name: &self.name_buffer[info.name_offset as usize..][..info.name_len as usize],
                 size: info.size,
+                crc32: info.crc32,
             }
         })
     }
@@ -226,6 +232,7 @@ impl<R: Read + Seek> PackageReader<R> {
             PackageFileInfo {
                 name: &self.name_buffer[info.name_offset as usize..][..info.name_len as usize],
                 size: info.size,
+                crc32: info.crc32,
             }
         })
     }
@@ -289,6 +296,7 @@ impl<R: Read + Seek> PackageReader<R> {
             inner: &mut self.inner,
             initial_len: compressed_size,
             remaining_len: compressed_size,
+            expected_crc: info.crc32,
         })
 
     }
@@ -303,6 +311,8 @@ pub struct PackageFileInfo<'a> {
     pub name: &'a str,
     /// The size of this file when read.
     pub size: u32,
+    /// The CRC-32 of this file's content, as stored in the package.
+    pub crc32: u32,
 }
 
 /// A handle for reading a file in a package.
@@ -314,10 +324,25 @@ pub struct PackageFileReader<R: Read + Seek> {
     initial_len: u32,
     /// Remaining length to read from the file.
     remaining_len: u32,
+    /// The CRC-32 of the file's content stored in the package.
+    expected_crc: u32,
 }
 
 impl<R: Read + Seek> PackageFileReader<R> {
 
+    /// Return the full size of this file's content.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.initial_len as u64
+    }
+
+    /// Return the CRC-32 of the file's content, as stored in the package, this can be
+    /// used to verify the integrity of the data read.
+    #[inline]
+    pub fn expected_crc(&self) -> u32 {
+        self.expected_crc
+    }
+
     /// A fast copy of this package file reader. **The caller must ensure** that the
     /// new reader points to the same blob of data as the current one and has exact
     /// same seek boundaries. If not, this will result in incorrect yet safe data read.
@@ -333,6 +358,7 @@ impl<R: Read + Seek> PackageFileReader<R> {
             inner: reader,
             initial_len: self.initial_len,
             remaining_len: self.remaining_len,
+            expected_crc: self.expected_crc,
         })
     }
 
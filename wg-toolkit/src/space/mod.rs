@@ -2,15 +2,18 @@
 
 pub mod section;
 
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, Cursor, Read, Write, Seek, SeekFrom};
+use std::collections::HashMap;
 
-use section::{Section, BWTB};
+use section::{Section, SectionId, SectionMeta, BWTB};
 
 
 /// A structure representing a full compiled space.
 pub struct CompiledSpace<R> {
     pub inner: R,
     pub bwtb: BWTB,
+    /// Encoded sections that replace the original ones when writing.
+    replaced: HashMap<SectionId, Vec<u8>>,
 }
 
 impl<R: Read + Seek> CompiledSpace<R> {
@@ -25,15 +28,173 @@ impl<R: Read + Seek> CompiledSpace<R> {
         Ok(CompiledSpace {
             inner,
             bwtb,
+            replaced: HashMap::new(),
         })
 
     }
 
-    /// Decode a section from this compiled space.
-    pub fn decode_section<S: Section>(&mut self) -> Option<S> {
-        let meta = self.bwtb.get_section_meta(S::ID)?;
-        self.inner.seek(SeekFrom::Start(meta.off as u64)).ok()?;
-        Some(S::decode(&mut self.inner).unwrap())
+    /// Decode a section from this compiled space, returning none if the section is
+    /// not present in the header.
+    pub fn decode_section<S: Section>(&mut self) -> io::Result<Option<S>> {
+        let Some(meta) = self.bwtb.get_section_meta(S::ID) else { return Ok(None) };
+        self.inner.seek(SeekFrom::Start(meta.off as u64))?;
+        S::decode(&mut self.inner).map(Some)
+    }
+
+    /// Encode the given section and use it instead of the original section when
+    /// writing this compiled space. The section must already exists in the header.
+    pub fn replace_section<S: Section>(&mut self, section: &S) -> io::Result<()> {
+
+        if self.bwtb.get_section_meta(S::ID).is_none() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "section not found in header"));
+        }
+
+        let mut buf = Cursor::new(Vec::new());
+        section.encode(&mut buf)?;
+        self.replaced.insert(*S::ID, buf.into_inner());
+        Ok(())
+
+    }
+
+    /// Write this whole compiled space to the given writer. Sections are written
+    /// in the header's order, right after the header, replaced sections are used
+    /// instead of original bytes and the header's offsets and lengths are updated
+    /// accordingly.
+    pub fn write<W: Write>(&mut self, write: &mut W) -> io::Result<()> {
+
+        let mut off = SectionMeta::SIZE * (1 + self.bwtb.sections.len());
+        let sections = self.bwtb.sections.iter()
+            .map(|meta| {
+                let len = self.replaced.get(&meta.id).map(Vec::len).unwrap_or(meta.len);
+                let new_meta = SectionMeta {
+                    id: meta.id,
+                    version: meta.version,
+                    off,
+                    len,
+                    sections_count: meta.sections_count,
+                };
+                off += len;
+                new_meta
+            })
+            .collect::<Vec<_>>();
+
+        let root = &self.bwtb.root;
+        let bwtb = BWTB::new(SectionMeta {
+            id: root.id,
+            version: root.version,
+            off: root.off,
+            len: root.len,
+            sections_count: sections.len(),
+        }, sections);
+
+        bwtb.encode(write)?;
+
+        for meta in &self.bwtb.sections {
+            if let Some(data) = self.replaced.get(&meta.id) {
+                write.write_all(data)?;
+            } else {
+                self.inner.seek(SeekFrom::Start(meta.off as u64))?;
+                let copied = io::copy(&mut (&mut self.inner).take(meta.len as u64), write)?;
+                if copied != meta.len as u64 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "section is truncated"));
+                }
+            }
+        }
+
+        Ok(())
+
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use super::section::{get_hash_from_str, BWST, BWCS};
+    use crate::util::io::WgWriteExt;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+
+        let raw_id = SectionId::new(*b"BWXX");
+        let raw_data = [1, 2, 3, 4, 5, 6, 7];
+
+        let mut strings = HashMap::new();
+        strings.insert(get_hash_from_str("spaces/test"), "spaces/test".to_string());
+        let mut bwst_data = Cursor::new(Vec::new());
+        BWST { strings }.encode(&mut bwst_data).unwrap();
+        let bwst_data = bwst_data.into_inner();
+
+        // Build the space by hand, the raw section comes first.
+        let header_len = SectionMeta::SIZE * 3;
+        let bwtb = BWTB::new(
+            SectionMeta { id: SectionId::new(*b"BWTB"), version: 1, off: 0, len: header_len, sections_count: 2 },
+            vec![
+                SectionMeta { id: raw_id, version: 2, off: header_len, len: raw_data.len(), sections_count: 0 },
+                SectionMeta { id: *BWST::ID, version: 3, off: header_len + raw_data.len(), len: bwst_data.len(), sections_count: 0 },
+            ],
+        );
+
+        let mut data = Vec::new();
+        bwtb.encode(&mut data).unwrap();
+        assert_eq!(data.len(), header_len);
+        data.extend_from_slice(&raw_data);
+        data.extend_from_slice(&bwst_data);
+
+        // Without replaced section, the space is written as-is.
+        let mut space = CompiledSpace::new(Cursor::new(data.clone())).unwrap();
+        let mut out = Vec::new();
+        space.write(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        // Replace the string table with a larger one.
+        let mut bwst = space.decode_section::<BWST>().unwrap().unwrap();
+        bwst.strings.insert(get_hash_from_str("spaces/other"), "spaces/other".to_string());
+        space.replace_section(&bwst).unwrap();
+        let mut out = Vec::new();
+        space.write(&mut out).unwrap();
+
+        let mut space = CompiledSpace::new(Cursor::new(out)).unwrap();
+        let raw_meta = space.bwtb.get_section_meta(&raw_id).unwrap();
+        assert_eq!((raw_meta.version, raw_meta.off, raw_meta.len), (2, header_len, raw_data.len()));
+        assert_eq!(&space.inner.get_ref()[raw_meta.off..][..raw_meta.len], raw_data);
+        assert_eq!(space.bwtb.get_section_meta(BWST::ID).unwrap().version, 3);
+
+        let bwst = space.decode_section::<BWST>().unwrap().unwrap();
+        assert_eq!(bwst.strings.len(), 2);
+        assert_eq!(bwst.get_string(get_hash_from_str("spaces/other")), Some("spaces/other"));
+
+    }
+
+    #[test]
+    fn decode_section_errors() {
+
+        // A single settings section with an invalid size.
+        let mut bwcs_data = Vec::new();
+        bwcs_data.write_single_head(20).unwrap();
+        bwcs_data.extend_from_slice(&[0; 20]);
+
+        let header_len = SectionMeta::SIZE * 2;
+        let bwtb = BWTB::new(
+            SectionMeta { id: SectionId::new(*b"BWTB"), version: 1, off: 0, len: header_len, sections_count: 1 },
+            vec![
+                SectionMeta { id: *BWCS::ID, version: 1, off: header_len, len: bwcs_data.len(), sections_count: 0 },
+            ],
+        );
+
+        let mut data = Vec::new();
+        bwtb.encode(&mut data).unwrap();
+        data.extend_from_slice(&bwcs_data);
+
+        let mut space = CompiledSpace::new(Cursor::new(data)).unwrap();
+        assert!(space.decode_section::<BWST>().unwrap().is_none());
+        assert_eq!(space.decode_section::<BWCS>().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
     }
 
 }
@@ -1,10 +1,16 @@
-use std::io::{Read, Seek};
+use std::io::{self, Read, Write, Seek};
 
-use super::{Section, SectionId};
-use crate::util::io::WgReadExt;
+use super::{Section, SectionId, BWST};
+use crate::util::io::{WgReadExt, WgWriteExt};
 
 
-/// AssetList section, defines a list of assets for this space.
+/// Alias for the [`BWAL`] section, with a more explicit name.
+pub type AssetList = BWAL;
+
+/// AssetList section, defines a list of assets for this space. Each asset is defined by
+/// its type and the hash of its resource path, this hash references a string of the
+/// [`BWST`] string table section and can be resolved with [`Self::iter_resolved`] or
+/// [`AssetInfo::resolve`].
 #[derive(Debug)]
 pub struct BWAL {
     pub assets: Vec<AssetInfo>
@@ -12,9 +18,9 @@ pub struct BWAL {
 
 impl Section for BWAL {
 
-    const ID: &'static SectionId = b"BWAL";
+    const ID: &'static SectionId = &SectionId::new(*b"BWAL");
 
-    fn decode<R: Read + Seek>(read: &mut R) -> std::io::Result<Self> {
+    fn decode<R: Read + Seek>(read: &mut R) -> io::Result<Self> {
 
         let assets = read.read_vector(|buf| {
 
@@ -23,7 +29,7 @@ impl Section for BWAL {
                 2 => AssetType::WaterReflectionTexture,
                 5 => AssetType::ControlPointRadiusPath,
                 6 => AssetType::ModelResource,
-                _ => panic!("invalid asset type")
+                n => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid asset type: {n}")))
             };
 
             Ok(AssetInfo {
@@ -37,24 +43,104 @@ impl Section for BWAL {
 
     }
 
+    fn encode<W: Write + Seek>(&self, write: &mut W) -> io::Result<()> {
+        write.write_vector(&self.assets, 8, |asset, buf| {
+            buf.write_u32(match asset.asset_type {
+                AssetType::ParticlesResource => 1,
+                AssetType::WaterReflectionTexture => 2,
+                AssetType::ControlPointRadiusPath => 5,
+                AssetType::ModelResource => 6,
+            })?;
+            buf.write_u32(asset.string_fnv)
+        })
+    }
+
+}
+
+impl BWAL {
+
+    /// Iterate over all assets in order, with their resource path resolved from the
+    /// given string table, the path is [`None`] if its hash is unknown to the table.
+    pub fn iter_resolved<'a>(&'a self, bwst: &'a BWST) -> impl Iterator<Item = (&'a AssetInfo, Option<&'a str>)> + 'a {
+        self.assets.iter().map(move |asset| (asset, asset.resolve(bwst)))
+    }
+
 }
 
 
 /// An compiled space asset info.
 /// Decoded by [BWAL] section.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AssetInfo {
     pub asset_type: AssetType,
+    /// Hash of the asset's resource path in the [`BWST`] string table.
     pub string_fnv: u32
 }
 
+impl AssetInfo {
+
+    /// Resolve the resource path of this asset from the given string table, [`None`]
+    /// is returned if the hash is unknown to the table.
+    #[inline]
+    pub fn resolve<'a>(&self, bwst: &'a BWST) -> Option<&'a str> {
+        bwst.get_string(self.string_fnv)
+    }
+
+}
+
 
 /// An asset type for an [AssetInfo].
 /// Decoded by [BWAL] section.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AssetType {
     ParticlesResource,
     WaterReflectionTexture,
     ControlPointRadiusPath,
     ModelResource
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use super::super::{get_hash_from_str, BWST};
+    use super::*;
+
+    #[test]
+    fn resolve() {
+
+        let path = "content/Buildings/bld_01.model";
+        let mut strings = HashMap::new();
+        strings.insert(get_hash_from_str(path), path.to_string());
+        let bwst = BWST { strings };
+
+        let mut buf = Cursor::new(Vec::new());
+        BWAL {
+            assets: vec![
+                AssetInfo { asset_type: AssetType::ModelResource, string_fnv: get_hash_from_str(path) },
+                AssetInfo { asset_type: AssetType::ParticlesResource, string_fnv: 0 },
+            ]
+        }.encode(&mut buf).unwrap();
+        buf.set_position(0);
+        let bwal = AssetList::decode(&mut buf).unwrap();
+
+        let assets = bwal.iter_resolved(&bwst)
+            .map(|(asset, path)| (asset.asset_type, path))
+            .collect::<Vec<_>>();
+        assert_eq!(assets, [(AssetType::ModelResource, Some(path)), (AssetType::ParticlesResource, None)]);
+
+        // Unknown asset types are errors.
+        let mut buf = Cursor::new(Vec::new());
+        buf.write_vector(&[(3u32, 0u32)], 8, |&(ty, fnv), buf| {
+            buf.write_u32(ty)?;
+            buf.write_u32(fnv)
+        }).unwrap();
+        buf.set_position(0);
+        assert_eq!(BWAL::decode(&mut buf).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    }
+
+}
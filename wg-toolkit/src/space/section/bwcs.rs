@@ -1,10 +1,12 @@
-use std::io::{Read, Seek};
+use std::io::{self, Read, Write, Seek};
 
 use super::{Section, SectionId};
-use crate::util::io::WgReadExt;
+use crate::util::io::{WgReadExt, WgWriteExt};
 
 
-/// CompiledSpaceSettings section.
+/// CompiledSpaceSettings section, this is a single fixed-size element of 6 floats that
+/// are global to the space, their meaning is not yet known. Note that this section 
+/// doesn't contain any chunk items (entities, markers, props), it is always 24 bytes.
 #[derive(Debug)]
 pub struct BWCS {
     pub values: [f32; 6]
@@ -12,12 +14,14 @@ pub struct BWCS {
 
 impl Section for BWCS {
 
-    const ID: &'static SectionId = b"BWCS";
+    const ID: &'static SectionId = &SectionId::new(*b"BWCS");
 
-    fn decode<R: Read + Seek>(read: &mut R) -> std::io::Result<Self> {
+    fn decode<R: Read + Seek>(read: &mut R) -> io::Result<Self> {
 
         let size = read.read_single_head()?;
-        assert_eq!(size, 24);
+        if size != 24 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid settings size: {size}")));
+        }
 
         let mut values = [0.0; 6];
         for value in &mut values {
@@ -28,4 +32,12 @@ impl Section for BWCS {
 
     }
 
+    fn encode<W: Write + Seek>(&self, write: &mut W) -> io::Result<()> {
+        write.write_single_head(24)?;
+        for &value in &self.values {
+            write.write_f32(value)?;
+        }
+        Ok(())
+    }
+
 }
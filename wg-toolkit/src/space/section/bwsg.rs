@@ -1,11 +1,16 @@
 use std::collections::HashMap;
-use std::io::{Read, Seek};
+use std::io::{Read, Write, Seek};
 
 use super::{Section, SectionId, BWST};
-use crate::util::io::WgReadExt;
+use super::bwst::encode_strings;
+use crate::util::io::{WgReadExt, WgWriteExt};
 
 
 /// StaticGeometry section, defines models and positions.
+/// 
+/// Note that positions are offsets of vertices blocks in the geometry data, not world
+/// positions, this section doesn't contain any transform and so models can't be placed
+/// in the world from this section only.
 #[derive(Debug)]
 pub struct BWSG {
     pub strings: HashMap<u32, String>,
@@ -15,7 +20,7 @@ pub struct BWSG {
 
 impl Section for BWSG {
 
-    const ID: &'static SectionId = b"BWSG";
+    const ID: &'static SectionId = &SectionId::new(*b"BWSG");
 
     fn decode<R: Read + Seek>(read: &mut R) -> std::io::Result<Self> {
 
@@ -49,6 +54,37 @@ impl Section for BWSG {
 
     }
 
+    fn encode<W: Write + Seek>(&self, write: &mut W) -> std::io::Result<()> {
+
+        encode_strings(&self.strings, write)?;
+
+        write.write_vector(&self.models, 20, |model, buf| {
+            buf.write_u32(model.vertices_fnv)?;
+            buf.write_u32(model.id_from)?;
+            buf.write_u32(model.id_to)?;
+            buf.write_u32(model.vertices_count)?;
+            buf.write_u32(model.vertices_type_fnv)
+        })?;
+
+        write.write_vector(&self.positions, 20, |position, buf| {
+            buf.write_u64(position.typ)?;
+            buf.write_u32(position.size)?;
+            buf.write_u32(position.data_sizes_id)?;
+            buf.write_u32(position.position)
+        })
+
+    }
+
+}
+
+
+impl BWSG {
+
+    /// Try to get a string from its hash, used to resolve the hashes of models.
+    pub fn get_string(&self, hash: u32) -> Option<&str> {
+        Some(self.strings.get(&hash)?.as_str())
+    }
+
 }
 
 
@@ -1,8 +1,8 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Write, Seek, SeekFrom};
 use std::collections::HashMap;
 
 use super::{Section, SectionId};
-use crate::util::io::WgReadExt;
+use crate::util::io::{WgReadExt, WgWriteExt};
 use crate::util::fnv::fnv1a_64;
 
 
@@ -14,7 +14,7 @@ pub struct BWST {
 
 impl Section for BWST {
 
-    const ID: &'static SectionId = b"BWST";
+    const ID: &'static SectionId = &SectionId::new(*b"BWST");
 
     fn decode<R: Read + Seek>(read: &mut R) -> std::io::Result<Self> {
 
@@ -43,15 +43,52 @@ impl Section for BWST {
 
     }
 
+    fn encode<W: Write + Seek>(&self, write: &mut W) -> std::io::Result<()> {
+        encode_strings(&self.strings, write)
+    }
+
 }
 
 impl BWST {
 
-    /// Try to get a string from its hash.
+    /// Try to get a string from its hash, this is how other sections reference
+    /// strings of this table, [`None`] is returned for unknown hashes.
     pub fn get_string(&self, hash: u32) -> Option<&str> {
         Some(self.strings.get(&hash)?.as_str())
     }
 
+    /// Iterate over all strings of this table with their hash, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> + '_ {
+        self.strings.iter().map(|(&hash, string)| (hash, string.as_str()))
+    }
+
+}
+
+
+/// Encode a string table, entries are sorted by hash and each string is
+/// null-terminated in the strings' buffer. This is also used by sections
+/// embedding a string table.
+pub(super) fn encode_strings<W: Write>(strings: &HashMap<u32, String>, write: &mut W) -> std::io::Result<()> {
+
+    let mut entries = strings.iter().collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|&(&fnv, _)| fnv);
+
+    let mut off = 0;
+    write.write_vector(&entries, 12, |&(&fnv, string), buf| {
+        buf.write_u32(fnv)?;
+        buf.write_u32(off as u32)?;
+        buf.write_u32(string.len() as u32)?;
+        off += string.len() + 1;
+        Ok(())
+    })?;
+
+    write.write_u32(off as u32)?;
+    for &(_, string) in &entries {
+        write.write_cstring(string)?;
+    }
+
+    Ok(())
+
 }
 
 
@@ -64,3 +101,36 @@ pub fn get_hash(data: &[u8]) -> u32 {
 pub fn get_hash_from_str(string: &str) -> u32 {
     get_hash(string.as_bytes())
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn lookup() {
+
+        let mut strings = HashMap::new();
+        for s in ["spaces/01_karelia/terrain", "content/Buildings/bld_01.model"] {
+            strings.insert(get_hash_from_str(s), s.to_string());
+        }
+
+        let mut buf = Cursor::new(Vec::new());
+        BWST { strings }.encode(&mut buf).unwrap();
+        buf.set_position(0);
+        let bwst = BWST::decode(&mut buf).unwrap();
+
+        let hash = get_hash_from_str("content/Buildings/bld_01.model");
+        assert_eq!(bwst.get_string(hash), Some("content/Buildings/bld_01.model"));
+        assert_eq!(bwst.get_string(hash.wrapping_add(1)), None);
+
+        let mut entries = bwst.iter().collect::<Vec<_>>();
+        entries.sort_unstable();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|&(hash, string)| hash == get_hash_from_str(string)));
+
+    }
+
+}
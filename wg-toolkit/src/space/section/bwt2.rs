@@ -1,7 +1,7 @@
-use std::io::{Read, Seek};
+use std::io::{Read, Write, Seek};
 
 use super::{Section, SectionId};
-use crate::util::io::WgReadExt;
+use crate::util::io::{WgReadExt, WgWriteExt};
 
 
 /// Terrain2 section, providing many information about `cdata_processed` files and many
@@ -11,14 +11,18 @@ pub struct BWT2 {
     pub settings1: TerrainSettings1,
     pub settings2: TerrainSettings2,
     pub chunks: Vec<TerrainChunk>,
+    /// Unknown vector following chunks, kept for re-encoding.
+    pub unknown_3: Vec<u32>,
     pub lod_distances: Vec<f32>,
+    /// Unknown vector following LOD distances, kept for re-encoding.
+    pub unknown_6: Vec<(u32, u32)>,
     pub outland_cascades: Vec<OutlandCascade>,
     pub tiles_fnv: Vec<u32>
 }
 
 impl Section for BWT2 {
 
-    const ID: &'static SectionId = b"BWT2";
+    const ID: &'static SectionId = &SectionId::new(*b"BWT2");
 
     fn decode<R: Read + Seek>(read: &mut R) -> std::io::Result<Self> {
 
@@ -44,7 +48,7 @@ impl Section for BWT2 {
         })?;
 
         // currently unused
-        let _3 = read.read_vector(|buf| buf.read_u32())?;
+        let unknown_3 = read.read_vector(|buf| buf.read_u32())?;
 
         let settings2_size = read.read_single_head()?;
         assert_eq!(settings2_size, 128);
@@ -63,16 +67,20 @@ impl Section for BWT2 {
             blend_map_size: read.read_u32()?,
             lod_texture_distance: read.read_f32()?,
             macro_lod_start: read.read_f32()?,
-            start_bias: { read.skip::<4>()?; read.read_f32()? },
+            unknown_0: read.read_u32()?,
+            start_bias: read.read_f32()?,
             end_bias: read.read_f32()?,
             direct_occlusion: read.read_f32()?,
             reverb_occlusion: read.read_f32()?,
             wrap_u: read.read_f32()?,
             wrap_v: read.read_f32()?,
-            blend_macro_influence: {
-                read.skip::<16>()?;
-                read.read_f32()?
-            },
+            unknown_1: [
+                read.read_u32()?,
+                read.read_u32()?,
+                read.read_u32()?,
+                read.read_u32()?,
+            ],
+            blend_macro_influence: read.read_f32()?,
             blend_global_threshold: read.read_f32()?,
             blend_height: read.read_f32()?,
             disabled_blend_height: read.read_f32()?,
@@ -93,7 +101,7 @@ impl Section for BWT2 {
         let lod_distances = read.read_vector(|buf| buf.read_f32())?;
 
         // currently unused
-        let _6 = read.read_vector(|buf| { buf.read_u32()?; buf.read_u32() })?;
+        let unknown_6 = read.read_vector(|buf| Ok((buf.read_u32()?, buf.read_u32()?)))?;
 
         let outland_cascades = read.read_vector(|buf| {
             Ok(OutlandCascade {
@@ -120,13 +128,92 @@ impl Section for BWT2 {
             settings1,
             settings2,
             chunks,
+            unknown_3,
             lod_distances,
+            unknown_6,
             outland_cascades,
             tiles_fnv
         })
 
     }
 
+    fn encode<W: Write + Seek>(&self, write: &mut W) -> std::io::Result<()> {
+
+        let settings1 = &self.settings1;
+        write.write_single_head(32)?;
+        write.write_f32(settings1.chunk_size)?;
+        write.write_i32(settings1.min_x)?;
+        write.write_i32(settings1.max_x)?;
+        write.write_i32(settings1.min_y)?;
+        write.write_i32(settings1.max_y)?;
+        write.write_u32(settings1.normal_map_fnv)?;
+        write.write_u32(settings1.global_map_fnv)?;
+        write.write_u32(settings1.noise_texture_fnv)?;
+
+        write.write_vector(&self.chunks, 8, |chunk, buf| {
+            buf.write_u32(chunk.resource_fnv)?;
+            buf.write_i16(chunk.loc_x)?;
+            buf.write_i16(chunk.loc_y)
+        })?;
+
+        write.write_vector(&self.unknown_3, 4, |&n, buf| buf.write_u32(n))?;
+
+        let settings2 = &self.settings2;
+        let mut terrain_flags = 0;
+        if settings2.blend_map_caching { terrain_flags |= 0x1; }
+        if settings2.normal_map_caching { terrain_flags |= 0x2; }
+        if settings2.enable_auto_rebuild_normal_map { terrain_flags |= 0x8; }
+        if settings2.enable_auto_rebuild_water_geometry { terrain_flags |= 0x20; }
+
+        write.write_single_head(128)?;
+        write.write_u32(settings2.terrain_version)?;
+        write.write_u32(terrain_flags)?;
+        write.write_u32(settings2.height_map_size)?;
+        write.write_u32(settings2.normal_map_size)?;
+        write.write_u32(settings2.hole_map_size)?;
+        write.write_u32(settings2.shadow_map_size)?;
+        write.write_u32(settings2.blend_map_size)?;
+        write.write_f32(settings2.lod_texture_distance)?;
+        write.write_f32(settings2.macro_lod_start)?;
+        write.write_u32(settings2.unknown_0)?;
+        write.write_f32(settings2.start_bias)?;
+        write.write_f32(settings2.end_bias)?;
+        write.write_f32(settings2.direct_occlusion)?;
+        write.write_f32(settings2.reverb_occlusion)?;
+        write.write_f32(settings2.wrap_u)?;
+        write.write_f32(settings2.wrap_v)?;
+        for &n in &settings2.unknown_1 {
+            write.write_u32(n)?;
+        }
+        write.write_f32(settings2.blend_macro_influence)?;
+        write.write_f32(settings2.blend_global_threshold)?;
+        write.write_f32(settings2.blend_height)?;
+        write.write_f32(settings2.disabled_blend_height)?;
+        for &n in settings2.vt_lod_params.iter().chain(&settings2.bounding_box) {
+            write.write_f32(n)?;
+        }
+
+        write.write_vector(&self.lod_distances, 4, |&n, buf| buf.write_f32(n))?;
+
+        write.write_vector(&self.unknown_6, 8, |&(a, b), buf| {
+            buf.write_u32(a)?;
+            buf.write_u32(b)
+        })?;
+
+        write.write_vector(&self.outland_cascades, 48, |cascade, buf| {
+            for &n in cascade.extent_min.iter().chain(&cascade.extent_max) {
+                buf.write_f32(n)?;
+            }
+            buf.write_u32(cascade.height_map_fnv)?;
+            buf.write_u32(cascade.normal_map_fnv)?;
+            buf.write_u32(cascade.tile_map_fnv)?;
+            buf.write_f32(cascade.tile_scale)
+        })?;
+
+        write.write_vector(&self.tiles_fnv, 4, |&n, buf| buf.write_u32(n))
+
+    }
+
 }
 
 
@@ -192,6 +279,8 @@ pub struct TerrainSettings2 {
     pub lod_texture_distance: f32,
     /// terrain/lodInfo/macroLODStart
     pub macro_lod_start: f32,
+    /// Unknown value, kept for re-encoding.
+    pub unknown_0: u32,
     /// terrain/lodInfo/startBias
     pub start_bias: f32,
     /// terrain/lodInfo/endBias
@@ -204,6 +293,8 @@ pub struct TerrainSettings2 {
     pub wrap_u: f32,
     /// terrain/detailNormal/wrapV
     pub wrap_v: f32,
+    /// Unknown values, kept for re-encoding.
+    pub unknown_1: [u32; 4],
     /// terrain/blendMacroInfluence
     pub blend_macro_influence: f32,
     /// terrain/blendGlobalThreshold
@@ -1,9 +1,9 @@
 use std::fmt::{self, Formatter};
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{self, Read, Write};
 
 use super::{SectionId};
-use crate::util::io::WgReadExt;
+use crate::util::io::{WgReadExt, WgWriteExt};
 
 
 /// Header section, defining all offsets for real sections. This section is a fake section
@@ -16,25 +16,40 @@ pub struct BWTB {
 
 impl BWTB {
 
+    /// Create a new header section from its root metadata and sections' metadata.
+    pub fn new(root: SectionMeta, sections: Vec<SectionMeta>) -> BWTB {
+        BWTB {
+            root,
+            sections_from_id: sections.iter()
+                .enumerate()
+                .map(|(i, r)| (r.id, i))
+                .collect(),
+            sections,
+        }
+    }
+
     pub fn decode<R: Read>(read: &mut R) -> std::io::Result<BWTB> {
 
         let root = SectionMeta::decode(read)?;
-        assert_eq!(&root.id, b"BWTB");
+        assert_eq!(root.id, SectionId::new(*b"BWTB"));
 
         let mut sections = Vec::with_capacity(root.sections_count);
         for _ in 0..root.sections_count {
             sections.push(SectionMeta::decode(read)?);
         }
 
-        Ok(BWTB {
-            root,
-            sections_from_id: sections.iter()
-                .enumerate()
-                .map(|(i, r)| (r.id.clone(), i))
-                .collect(),
-            sections,
-        })
+        Ok(BWTB::new(root, sections))
+
+    }
 
+    /// Encode this header section, the sections count of the root metadata is
+    /// replaced by the actual number of sections.
+    pub fn encode<W: Write>(&self, write: &mut W) -> std::io::Result<()> {
+        self.root.encode(write, self.sections.len())?;
+        for section in &self.sections {
+            section.encode(write, section.sections_count)?;
+        }
+        Ok(())
     }
 
     /// Get section metadata from its identifier.
@@ -49,6 +64,7 @@ impl BWTB {
 /// used by the fake [BWTB] header section.
 pub struct SectionMeta {
     pub id: SectionId,
+    pub version: u32,
     pub off: usize,
     pub len: usize,
     pub sections_count: usize
@@ -56,19 +72,23 @@ pub struct SectionMeta {
 
 impl SectionMeta {
 
+    /// Size of an encoded section metadata.
+    pub const SIZE: usize = 24;
+
     fn decode<R: Read>(read: &mut R) -> std::io::Result<SectionMeta> {
 
-        let mut id = [0; 4];
-        read.read_exact(&mut id)?;
+        let mut id = SectionId::default();
+        read.read_exact(&mut id.0)?;
 
-        read.read_u32()?;
+        let version = read.read_u32()?;
         let off = read.read_u32()? as usize;
-        read.read_u32()?;
+        read.read_u32()?;  // Unused, always zero.
         let len = read.read_u32()? as usize;
         let rows_count = read.read_u32()? as usize;
 
         Ok(SectionMeta {
             id,
+            version,
             off,
             len,
             sections_count: rows_count
@@ -76,13 +96,25 @@ impl SectionMeta {
 
     }
 
+    fn encode<W: Write>(&self, write: &mut W, sections_count: usize) -> std::io::Result<()> {
+        write.write_all(self.id.as_bytes())?;
+        let off = u32::try_from(self.off)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "section offset is too large"))?;
+        write.write_u32(self.version)?;
+        write.write_u32(off)?;
+        write.write_u32(0)?;
+        write.write_u32(self.len as u32)?;
+        write.write_u32(sections_count as u32)
+    }
+
 }
 
 impl fmt::Debug for SectionMeta {
 
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("SectionMeta")
-            .field("id", &self.id.iter().map(|&c| c as char).collect::<String>())
+            .field("id", &format_args!("{}", self.id))
+            .field("version", &self.version)
             .field("off", &self.off)
             .field("len", &self.len)
             .finish()
@@ -1,6 +1,8 @@
 //! Compiled space sections structures definitions.
 
-use std::io::{self, Read, Seek};
+use std::io::{self, Read, Write, Seek};
+
+use crate::util::FourCC;
 
 
 mod bwtb;
@@ -18,8 +20,8 @@ pub use bwsg::*;
 pub use bwt2::*;
 
 
-/// Alias for 4-bytes array, which is used to identify sections in a compiled space.
-pub type SectionId = [u8; 4];
+/// Alias for a four character code, which is used to identify sections in a compiled space.
+pub type SectionId = FourCC;
 
 
 /// Common trait for section in compiled space binaries.
@@ -29,4 +31,6 @@ pub trait Section: Sized {
 
     fn decode<R: Read + Seek>(read: &mut R) -> io::Result<Self>;
 
+    fn encode<W: Write + Seek>(&self, write: &mut W) -> io::Result<()>;
+
 }
@@ -9,26 +9,64 @@ use std::num::Wrapping;
 use sha2::{Sha256, Digest};
 
 
-const BW_SIZE_SHIFT: u32 = 20;
+/// Default number of bits of the graph size used by BigWorld.
+pub const BW_SIZE_SHIFT: u32 = 20;
 const BW_MAX_PATH_LEN: usize = 8192;
-const BW_PROOF_SIZE: usize = 42;
+/// Default length of the cycle to find used by BigWorld.
+pub const BW_PROOF_SIZE: usize = 42;
+
+
+/// Return true if the given graph parameters are valid, the number of bits of the graph
+/// size must be in range 2..32 and the cycle length must be even and non-zero.
+pub fn is_valid_params(edge_bits: u32, cycle_len: usize) -> bool {
+    (2..32).contains(&edge_bits) && cycle_len != 0 && cycle_len.is_multiple_of(2)
+}
 
 /// Cuckoo context with support for BigWorld changes.
 #[derive(Debug)]
 pub struct CuckooContext {
     sip_hash: SipHashContext,
     max_nonce: u32,
+    /// Number of bits of the graph size, used by [`Self::work_bw`].
+    edge_bits: u32,
+    /// Length of the cycle to find, used by [`Self::work_bw`].
+    cycle_len: usize,
 }
 
 impl CuckooContext {
 
+    /// Create a new context with the default BigWorld graph parameters: 20 edge bits
+    /// and a cycle length of 42.
     pub fn new(max_nonce: u32, prefix: &[u8]) -> Self {
+        Self::with_params(max_nonce, prefix, BW_SIZE_SHIFT, BW_PROOF_SIZE)
+    }
+
+    /// Create a new context with specific graph parameters, the number of bits of the
+    /// graph size and the length of the cycle to find. These parameters are used by
+    /// [`Self::work_bw`] and [`Self::verify_bw`]. This function panics if these 
+    /// parameters are not valid, see [`is_valid_params`].
+    pub fn with_params(max_nonce: u32, prefix: &[u8], edge_bits: u32, cycle_len: usize) -> Self {
+        assert!(is_valid_params(edge_bits, cycle_len), "invalid cuckoo cycle parameters");
         Self {
             max_nonce,
             sip_hash: SipHashContext::new_with_prefix(prefix),
+            edge_bits,
+            cycle_len,
         }
     }
 
+    /// Return the number of bits of the graph size.
+    #[inline]
+    pub fn edge_bits(&self) -> u32 {
+        self.edge_bits
+    }
+
+    /// Return the length of the cycle to find.
+    #[inline]
+    pub fn cycle_len(&self) -> usize {
+        self.cycle_len
+    }
+
     #[inline]
     fn sip_node(&self, size: u32, nonce: u32, uorv: u32) -> u32 {
         let nonce = (Wrapping(nonce) * Wrapping(2) + Wrapping(uorv)).0;
@@ -50,12 +88,15 @@ impl CuckooContext {
         (u0 + 1, v0 + 1 + (size / 2))
     }
 
+    /// Find a solution with the BigWorld implementation, using this context's graph
+    /// parameters.
     pub fn work_bw(&self) -> Option<Vec<u32>> {
-        self.work(BW_SIZE_SHIFT, BW_MAX_PATH_LEN, BW_PROOF_SIZE)
+        self.work(self.edge_bits, BW_MAX_PATH_LEN, self.cycle_len)
     }
 
+    /// Verify a solution using this context's graph parameters.
     pub fn verify_bw(&self, solution: &[u32]) -> bool {
-        solution.len() == BW_PROOF_SIZE && self.verify(BW_SIZE_SHIFT, solution)
+        solution.len() == self.cycle_len && self.verify(self.edge_bits, solution)
     }
 
     pub fn work(&self, 
@@ -332,3 +373,30 @@ impl SipHashContext {
     }
 
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn custom_params() {
+
+        // Find a prefix that has a solution with small parameters.
+        let (prefix, solution) = (0u32..1000)
+            .find_map(|n| {
+                let prefix = n.to_le_bytes();
+                let solution = CuckooContext::with_params(600, &prefix, 10, 6).work_bw()?;
+                Some((prefix, solution))
+            })
+            .expect("no solution found");
+
+        assert_eq!(solution.len(), 6);
+        assert!(CuckooContext::with_params(600, &prefix, 10, 6).verify_bw(&solution));
+        assert!(!CuckooContext::with_params(600, &prefix, 11, 6).verify_bw(&solution));
+        assert!(!CuckooContext::new(600, &prefix).verify_bw(&solution));
+
+    }
+
+}
@@ -113,6 +113,102 @@ pub trait WgReadExt: Read {
         ReadBytesExt::read_f64::<LE>(self)
     }
 
+    /// Little-endian variant of [`Self::read_u16`].
+    #[inline]
+    fn read_u16_le(&mut self) -> io::Result<u16> {
+        ReadBytesExt::read_u16::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_u16`].
+    #[inline]
+    fn read_u16_be(&mut self) -> io::Result<u16> {
+        ReadBytesExt::read_u16::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_i16`].
+    #[inline]
+    fn read_i16_le(&mut self) -> io::Result<i16> {
+        ReadBytesExt::read_i16::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_i16`].
+    #[inline]
+    fn read_i16_be(&mut self) -> io::Result<i16> {
+        ReadBytesExt::read_i16::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_u32`].
+    #[inline]
+    fn read_u32_le(&mut self) -> io::Result<u32> {
+        ReadBytesExt::read_u32::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_u32`].
+    #[inline]
+    fn read_u32_be(&mut self) -> io::Result<u32> {
+        ReadBytesExt::read_u32::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_i32`].
+    #[inline]
+    fn read_i32_le(&mut self) -> io::Result<i32> {
+        ReadBytesExt::read_i32::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_i32`].
+    #[inline]
+    fn read_i32_be(&mut self) -> io::Result<i32> {
+        ReadBytesExt::read_i32::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_u64`].
+    #[inline]
+    fn read_u64_le(&mut self) -> io::Result<u64> {
+        ReadBytesExt::read_u64::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_u64`].
+    #[inline]
+    fn read_u64_be(&mut self) -> io::Result<u64> {
+        ReadBytesExt::read_u64::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_i64`].
+    #[inline]
+    fn read_i64_le(&mut self) -> io::Result<i64> {
+        ReadBytesExt::read_i64::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_i64`].
+    #[inline]
+    fn read_i64_be(&mut self) -> io::Result<i64> {
+        ReadBytesExt::read_i64::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_f32`].
+    #[inline]
+    fn read_f32_le(&mut self) -> io::Result<f32> {
+        ReadBytesExt::read_f32::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_f32`].
+    #[inline]
+    fn read_f32_be(&mut self) -> io::Result<f32> {
+        ReadBytesExt::read_f32::<BE>(self)
+    }
+
+    /// Little-endian variant of [`Self::read_f64`].
+    #[inline]
+    fn read_f64_le(&mut self) -> io::Result<f64> {
+        ReadBytesExt::read_f64::<LE>(self)
+    }
+
+    /// Big-endian variant of [`Self::read_f64`].
+    #[inline]
+    fn read_f64_be(&mut self) -> io::Result<f64> {
+        ReadBytesExt::read_f64::<BE>(self)
+    }
+
     /// Read a single boolean from the underlying reader.
     #[inline]
     fn read_bool(&mut self) -> io::Result<bool> {
@@ -218,11 +314,17 @@ pub trait WgReadExt: Read {
     }
 
     fn read_sock_addr_v4(&mut self) -> io::Result<SocketAddrV4> {
+        self.read_sock_addr_v4_salt().map(|(addr, _salt)| addr)
+    }
+
+    /// Read a socket address followed by its salt, the salt is sometimes used to
+    /// carry additional data alongside the address.
+    fn read_sock_addr_v4_salt(&mut self) -> io::Result<(SocketAddrV4, u16)> {
         let mut ip_raw = [0; 4];
         self.read_exact(&mut ip_raw[..])?;
-        let port = ReadBytesExt::read_u16::<BE>(self)?;
-        let _salt = ReadBytesExt::read_u16::<LE>(self)?;
-        Ok(SocketAddrV4::new(Ipv4Addr::from(ip_raw), port))
+        let port = self.read_u16_be()?;
+        let salt = ReadBytesExt::read_u16::<LE>(self)?;
+        Ok((SocketAddrV4::new(Ipv4Addr::from(ip_raw), port), salt))
     }
 
     #[inline]
@@ -396,6 +498,102 @@ pub trait WgWriteExt: Write {
         WriteBytesExt::write_f64::<LE>(self, n)
     }
 
+    /// Little-endian variant of [`Self::write_u16`].
+    #[inline]
+    fn write_u16_le(&mut self, n: u16) -> io::Result<()> {
+        WriteBytesExt::write_u16::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_u16`].
+    #[inline]
+    fn write_u16_be(&mut self, n: u16) -> io::Result<()> {
+        WriteBytesExt::write_u16::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_i16`].
+    #[inline]
+    fn write_i16_le(&mut self, n: i16) -> io::Result<()> {
+        WriteBytesExt::write_i16::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_i16`].
+    #[inline]
+    fn write_i16_be(&mut self, n: i16) -> io::Result<()> {
+        WriteBytesExt::write_i16::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_u32`].
+    #[inline]
+    fn write_u32_le(&mut self, n: u32) -> io::Result<()> {
+        WriteBytesExt::write_u32::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_u32`].
+    #[inline]
+    fn write_u32_be(&mut self, n: u32) -> io::Result<()> {
+        WriteBytesExt::write_u32::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_i32`].
+    #[inline]
+    fn write_i32_le(&mut self, n: i32) -> io::Result<()> {
+        WriteBytesExt::write_i32::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_i32`].
+    #[inline]
+    fn write_i32_be(&mut self, n: i32) -> io::Result<()> {
+        WriteBytesExt::write_i32::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_u64`].
+    #[inline]
+    fn write_u64_le(&mut self, n: u64) -> io::Result<()> {
+        WriteBytesExt::write_u64::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_u64`].
+    #[inline]
+    fn write_u64_be(&mut self, n: u64) -> io::Result<()> {
+        WriteBytesExt::write_u64::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_i64`].
+    #[inline]
+    fn write_i64_le(&mut self, n: i64) -> io::Result<()> {
+        WriteBytesExt::write_i64::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_i64`].
+    #[inline]
+    fn write_i64_be(&mut self, n: i64) -> io::Result<()> {
+        WriteBytesExt::write_i64::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_f32`].
+    #[inline]
+    fn write_f32_le(&mut self, n: f32) -> io::Result<()> {
+        WriteBytesExt::write_f32::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_f32`].
+    #[inline]
+    fn write_f32_be(&mut self, n: f32) -> io::Result<()> {
+        WriteBytesExt::write_f32::<BE>(self, n)
+    }
+
+    /// Little-endian variant of [`Self::write_f64`].
+    #[inline]
+    fn write_f64_le(&mut self, n: f64) -> io::Result<()> {
+        WriteBytesExt::write_f64::<LE>(self, n)
+    }
+
+    /// Big-endian variant of [`Self::write_f64`].
+    #[inline]
+    fn write_f64_be(&mut self, n: f64) -> io::Result<()> {
+        WriteBytesExt::write_f64::<BE>(self, n)
+    }
+
     /// Write a single boolean to the underlying writer.
     #[inline]
     fn write_bool(&mut self, b: bool) -> io::Result<()> {
@@ -440,9 +638,14 @@ pub trait WgWriteExt: Write {
     }
 
     fn write_sock_addr_v4(&mut self, addr: SocketAddrV4) -> io::Result<()> {
+        self.write_sock_addr_v4_salt(addr, 0)
+    }
+
+    /// Write a socket address followed by the given salt.
+    fn write_sock_addr_v4_salt(&mut self, addr: SocketAddrV4, salt: u16) -> io::Result<()> {
         self.write_all(&addr.ip().octets()[..])?;
-        WriteBytesExt::write_u16::<BE>(self, addr.port())?;
-        WriteBytesExt::write_u16::<LE>(self, 0)?; // Salt
+        self.write_u16_be(addr.port())?;
+        WriteBytesExt::write_u16::<LE>(self, salt)?;
         Ok(())
     }
 
@@ -480,15 +683,15 @@ pub trait WgWriteExt: Write {
         self.write_u32(count as u32)
     }
 
-    /// Write a vector of structure. Items give in the iterator are converted 
-    /// through the given function. This function take the 
-    fn write_vector<T, I, F>(&mut self, vec: &[T], size: usize, mut func: F) -> io::Result<()>
+    /// Write a vector of structure. Items of the given slice are written
+    /// through the given function, each structure must be exactly `size` bytes.
+    fn write_vector<T, F>(&mut self, vec: &[T], size: usize, mut func: F) -> io::Result<()>
     where
-        F: FnMut(&T, &mut Self),
+        F: FnMut(&T, &mut Self) -> io::Result<()>,
     {
         self.write_vector_head(size, vec.len())?;
         for elt in vec {
-            (func)(elt, self);
+            (func)(elt, self)?;
         }
         Ok(())
     }
@@ -500,7 +703,9 @@ impl<W: Write + ?Sized> WgWriteExt for W {}
 
 
 /// A wrapper for a [`Read`] or [`Write`] implementor that will increment
-/// an internal counter when a byte is either read or written.
+/// an internal counter when a byte is either read or written. Partial reads and
+/// writes are counted, even if a subsequent call fails.
+#[derive(Debug)]
 pub struct IoCounter<I> {
     inner: I,
     count: usize,
@@ -516,11 +721,25 @@ impl<I> IoCounter<I> {
         }
     }
 
+    /// Return the number of bytes read or written so far.
     #[inline]
     pub fn count(&self) -> usize {
         self.count
     }
 
+    /// Get a reference to the inner reader or writer.
+    #[inline]
+    pub fn get_ref(&self) -> &I {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader or writer, bytes read or written
+    /// directly through it are not counted.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
     #[inline]
     pub fn into_inner(self) -> I {
         self.inner
@@ -548,12 +767,6 @@ impl<W: Write> Write for IoCounter<W> {
         Ok(len)
     }
 
-    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.inner.write_all(buf)?;
-        self.count += buf.len();
-        Ok(())
-    }
-
     #[inline]
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
@@ -561,6 +774,75 @@ impl<W: Write> Write for IoCounter<W> {
 
 }
 
+/// Alias for an [`IoCounter`] wrapping a reader, counting bytes read.
+pub type CountingReader<R> = IoCounter<R>;
+
+/// Alias for an [`IoCounter`] wrapping a writer, counting bytes written.
+pub type CountingWriter<W> = IoCounter<W>;
+
+
+/// A wrapper for a [`Read`] implementor that writes all bytes read to a [`Write`]
+/// implementor, this can be used to save the raw bytes of a stream while parsing it.
+/// Only the bytes returned by each read are written, and they are fully written before
+/// returning, the read fails if the bytes can't be written.
+#[derive(Debug)]
+pub struct TeeReader<R, W> {
+    inner: R,
+    writer: W,
+}
+
+impl<R, W> TeeReader<R, W> {
+
+    #[inline]
+    pub fn new(inner: R, writer: W) -> Self {
+        Self {
+            inner,
+            writer,
+        }
+    }
+
+    /// Get a reference to the inner reader.
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader, bytes read directly through it are
+    /// not written to the writer.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get a reference to the writer.
+    #[inline]
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the writer.
+    #[inline]
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> (R, W) {
+        (self.inner, self.writer)
+    }
+
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.writer.write_all(&buf[..len])?;
+        Ok(len)
+    }
+
+}
+
 
 #[derive(Debug)]
 pub struct SliceCursor<'a>(&'a [u8]);
@@ -603,12 +885,168 @@ impl<'a> SliceCursor<'a> {
 }
 
 
+/// The default options for decoding pickles of game data, python 2 strings are decoded
+/// as strings if valid UTF-8 and recursive structures are replaced by none.
 #[inline]
 pub fn serde_pickle_de_options() -> serde_pickle::DeOptions {
     serde_pickle::DeOptions::new().decode_strings_relaxed().replace_recursive_structures()
 }
 
+/// Strict options for decoding pickles, same as [`serde_pickle_de_options`] but any 
+/// recursive structure is an error, so no data can be lost.
+#[inline]
+pub fn serde_pickle_de_options_strict() -> serde_pickle::DeOptions {
+    serde_pickle::DeOptions::new().decode_strings_relaxed()
+}
+
+/// Lenient decoding of pickles, globals that cannot be resolved and recursive
+/// structures are kept with a generic representation instead of returning an error
+/// or being replaced by none, so no data is lost, see [`super::pickle`].
+#[inline]
+pub fn pickle_value_from_slice_lenient(data: &[u8]) -> io::Result<serde_pickle::Value> {
+    super::pickle::value_from_slice(data)
+}
+
 #[inline]
 pub fn serde_pickle_ser_options() -> serde_pickle::SerOptions {
     serde_pickle::SerOptions::new().proto_v2()
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{Read, Write};
+
+    use super::{WgReadExt, WgWriteExt, TeeReader, CountingReader, CountingWriter};
+    use super::{serde_pickle_de_options, serde_pickle_de_options_strict, pickle_value_from_slice_lenient};
+
+    #[test]
+    fn primitives_round_trip() {
+
+        let mut buf = Vec::new();
+        buf.write_u8(0x12).unwrap();
+        buf.write_i8(-0x12).unwrap();
+        buf.write_u16_le(0x1234).unwrap();
+        buf.write_u16_be(0x1234).unwrap();
+        buf.write_i16_le(-0x1234).unwrap();
+        buf.write_i16_be(-0x1234).unwrap();
+        buf.write_u24(0x123456).unwrap();
+        buf.write_u32_le(0x12345678).unwrap();
+        buf.write_u32_be(0x12345678).unwrap();
+        buf.write_i32_le(-0x12345678).unwrap();
+        buf.write_i32_be(-0x12345678).unwrap();
+        buf.write_u64_le(0x123456789ABCDEF0).unwrap();
+        buf.write_u64_be(0x123456789ABCDEF0).unwrap();
+        buf.write_i64_le(-0x123456789ABCDEF0).unwrap();
+        buf.write_i64_be(-0x123456789ABCDEF0).unwrap();
+        buf.write_f32_le(1.5).unwrap();
+        buf.write_f32_be(1.5).unwrap();
+        buf.write_f64_le(-2.25).unwrap();
+        buf.write_f64_be(-2.25).unwrap();
+        buf.write_bool(true).unwrap();
+        buf.write_cstring("hello").unwrap();
+
+        assert_eq!(&buf[2..6], &[0x34, 0x12, 0x12, 0x34]);
+
+        let mut read = &buf[..];
+        assert_eq!(read.read_u8().unwrap(), 0x12);
+        assert_eq!(read.read_i8().unwrap(), -0x12);
+        assert_eq!(read.read_u16().unwrap(), 0x1234);
+        assert_eq!(read.read_u16_be().unwrap(), 0x1234);
+        assert_eq!(read.read_i16_le().unwrap(), -0x1234);
+        assert_eq!(read.read_i16_be().unwrap(), -0x1234);
+        assert_eq!(read.read_u24().unwrap(), 0x123456);
+        assert_eq!(read.read_u32_le().unwrap(), 0x12345678);
+        assert_eq!(read.read_u32_be().unwrap(), 0x12345678);
+        assert_eq!(read.read_i32_le().unwrap(), -0x12345678);
+        assert_eq!(read.read_i32_be().unwrap(), -0x12345678);
+        assert_eq!(read.read_u64_le().unwrap(), 0x123456789ABCDEF0);
+        assert_eq!(read.read_u64_be().unwrap(), 0x123456789ABCDEF0);
+        assert_eq!(read.read_i64_le().unwrap(), -0x123456789ABCDEF0);
+        assert_eq!(read.read_i64_be().unwrap(), -0x123456789ABCDEF0);
+        assert_eq!(read.read_f32_le().unwrap(), 1.5);
+        assert_eq!(read.read_f32_be().unwrap(), 1.5);
+        assert_eq!(read.read_f64_le().unwrap(), -2.25);
+        assert_eq!(read.read_f64_be().unwrap(), -2.25);
+        assert!(read.read_bool().unwrap());
+        assert_eq!(read.read_cstring_variable().unwrap(), "hello");
+        assert!(read.is_empty());
+
+    }
+
+    #[test]
+    fn tee_reader() {
+
+        let data = (0..=255u8).collect::<Vec<_>>();
+        let mut tee = TeeReader::new(&data[..], Vec::new());
+
+        let mut buf = [0; 10];
+        assert_eq!(tee.read(&mut buf).unwrap(), 10);
+        assert_eq!(tee.read_u32().unwrap(), u32::from_le_bytes([10, 11, 12, 13]));
+        assert_eq!(tee.writer().len(), 14);
+
+        let mut rest = Vec::new();
+        tee.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), 256 - 14);
+
+        let (_, written) = tee.into_inner();
+        assert_eq!(written, data);
+
+    }
+
+    #[test]
+    fn io_counter() {
+
+        let mut writer = CountingWriter::new(Vec::new());
+        writer.write_u32(0x12345678).unwrap();
+        writer.write_cstring("hello").unwrap();
+        assert_eq!(writer.count(), 10);
+        // Bytes written through the inner writer are not counted.
+        writer.get_mut().push(0);
+        writer.write_all(&[1, 2, 3]).unwrap();
+        assert_eq!(writer.count(), 13);
+        let data = writer.into_inner();
+        assert_eq!(data.len(), 14);
+
+        let mut reader = CountingReader::new(&data[..]);
+        assert_eq!(reader.read_u32().unwrap(), 0x12345678);
+        assert_eq!(reader.count(), 4);
+        assert_eq!(reader.read_cstring_variable().unwrap(), "hello");
+        assert_eq!(reader.count(), 10);
+
+        // Partial reads are counted up to the end of the inner reader.
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(reader.count(), 14);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(reader.count(), 14);
+
+    }
+
+    #[test]
+    fn pickle_de_options() {
+
+        use serde_pickle::{HashableValue, Value};
+
+        let dict = |key: &str, value: Value| {
+            Value::Dict([(HashableValue::String(key.to_string()), value)].into_iter().collect())
+        };
+
+        // Protocol 2 pickle of l = []; l.append(l), the recursive reference is replaced
+        // by none, except with the strict options, and kept with the lenient decoding.
+        let recursive = b"\x80\x02]q\x00h\x00a.";
+        assert_eq!(serde_pickle::value_from_slice(recursive, serde_pickle_de_options()).unwrap(), Value::List(vec![Value::None]));
+        assert!(serde_pickle::value_from_slice(recursive, serde_pickle_de_options_strict()).is_err());
+        assert_eq!(pickle_value_from_slice_lenient(recursive).unwrap(), Value::List(vec![dict("__ref__", Value::I64(0))]));
+
+        // Protocol 2 pickle of the collections.deque global, unresolved globals are only
+        // kept with the lenient decoding.
+        let global = b"\x80\x02ccollections\ndeque\nq\x00.";
+        assert!(serde_pickle::value_from_slice(global, serde_pickle_de_options()).is_err());
+        assert!(serde_pickle::value_from_slice(global, serde_pickle_de_options_strict()).is_err());
+        assert_eq!(pickle_value_from_slice_lenient(global).unwrap(), dict("__global__", Value::String("collections.deque".to_string())));
+
+    }
+
+}
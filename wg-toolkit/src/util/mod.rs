@@ -1,11 +1,14 @@
 //! Provides various internal utilities.
 
 use std::fmt::{self, Write};
+use std::time::Duration;
+use std::str::FromStr;
 
 pub mod io;
 pub mod fnv;
 pub mod cuckoo;
 pub mod thread;
+pub mod pickle;
 
 
 /// A helper structure for pretty printing of bytes. It provides format implementations 
@@ -32,6 +35,8 @@ impl fmt::LowerHex for BytesFmt<'_> {
 
 /// A helper structure for pretty printing of bytes with ASCII escaping if not printable.
 /// We are intentionally not using standard escape sequence, to avoid being too verbose.
+/// The display implementation is the same as the debug one, so it can be directly 
+/// converted to a string using `to_string()`.
 pub struct AsciiFmt<'a>(pub &'a [u8]);
 
 impl fmt::Debug for AsciiFmt<'_> {
@@ -101,6 +106,101 @@ impl fmt::Debug for AsciiFmt<'_> {
     }
 }
 
+impl fmt::Display for AsciiFmt<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A four character code, commonly used by the engine to identify sections or chunks in
+/// binary files. The code is stored as its 4 raw bytes in file order, so it doesn't 
+/// depend on the endianness, the display and debug implementations print graphic ASCII 
+/// characters as-is and other bytes as `\xNN` hex escapes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct FourCC(pub [u8; 4]);
+
+impl FourCC {
+
+    /// Create a new four character code from its raw bytes.
+    #[inline]
+    pub const fn new(bytes: [u8; 4]) -> Self {
+        Self(bytes)
+    }
+
+    /// Create a four character code from a little-endian 32-bit integer, this is the
+    /// reverse of [`Self::as_u32`].
+    #[inline]
+    pub const fn from_u32(n: u32) -> Self {
+        Self(n.to_le_bytes())
+    }
+
+    /// Return the raw bytes of this code, in file order.
+    #[inline]
+    pub const fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+
+    /// Return this code as a 32-bit integer, the bytes are interpreted as little-endian
+    /// so the integer has the same memory layout as the code in files.
+    #[inline]
+    pub const fn as_u32(&self) -> u32 {
+        u32::from_le_bytes(self.0)
+    }
+
+}
+
+impl From<[u8; 4]> for FourCC {
+    #[inline]
+    fn from(bytes: [u8; 4]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<FourCC> for [u8; 4] {
+    #[inline]
+    fn from(code: FourCC) -> Self {
+        code.0
+    }
+}
+
+impl FromStr for FourCC {
+
+    type Err = FourCCError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        bytes.try_into().map(Self).map_err(|_| FourCCError(bytes.len()))
+    }
+
+}
+
+impl fmt::Display for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &byte in &self.0 {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                f.write_char(byte as char)?;
+            } else {
+                write!(f, "\\x{byte:02X}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FourCC {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FourCC(\"{self}\")")
+    }
+}
+
+/// Error returned when parsing a [`FourCC`] from a string that is not exactly 4 bytes 
+/// long, the actual length is given.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("four character code must be 4 bytes long, got {0}")]
+pub struct FourCCError(pub usize);
+
 /// A helper structure to truncate the output of some display implementor, adding 
 /// trailing '...' if necessary.
 pub struct TruncateFmt<F>(pub F, pub usize);
@@ -135,3 +235,106 @@ impl fmt::Display for SizeFmt {
         }
     }
 }
+
+/// A helper structure for pretty printing of durations, sub-microsecond durations are 
+/// printed in nanoseconds, sub-millisecond in microseconds, sub-second durations are
+/// printed in milliseconds with one decimal and larger durations in seconds with two
+/// decimals. Values are rounded to the nearest displayed unit.
+pub struct DurationFmt(pub Duration);
+
+impl fmt::Display for DurationFmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_nanos() {
+            n @ 0..=999 =>
+                write!(f, "{n} ns"),
+            n @ 1_000..=999_499 =>
+                write!(f, "{} µs", (n + 500) / 1_000),
+            n @ 999_500..=999_949_999 =>
+                write!(f, "{:.1} ms", n as f64 / 1_000_000.0),
+            n =>
+                write!(f, "{:.2} s", n as f64 / 1_000_000_000.0),
+        }
+    }
+}
+
+/// A temporary directory for tests, created empty in the system's temporary directory
+/// and recursively removed when dropped. The given name is suffixed with the process
+/// id, so it must be unique among tests.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("wgtk-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    #[inline]
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+
+    type Target = std::path::Path;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn duration_fmt() {
+        assert_eq!(DurationFmt(Duration::from_nanos(999)).to_string(), "999 ns");
+        assert_eq!(DurationFmt(Duration::from_nanos(1_499)).to_string(), "1 µs");
+        assert_eq!(DurationFmt(Duration::from_micros(999)).to_string(), "999 µs");
+        assert_eq!(DurationFmt(Duration::from_nanos(999_500)).to_string(), "1.0 ms");
+        assert_eq!(DurationFmt(Duration::from_micros(1_500)).to_string(), "1.5 ms");
+        assert_eq!(DurationFmt(Duration::from_micros(999_949)).to_string(), "999.9 ms");
+        assert_eq!(DurationFmt(Duration::from_micros(999_950)).to_string(), "1.00 s");
+        assert_eq!(DurationFmt(Duration::from_millis(12_345)).to_string(), "12.35 s");
+    }
+
+    #[test]
+    fn ascii_fmt_display() {
+        let data = b"\x01\x02hello world\x00\xFFab";
+        assert_eq!(AsciiFmt(data).to_string(), format!("{:?}", AsciiFmt(data)));
+        assert_eq!(AsciiFmt(data).to_string(), "0102 \"hello world\" 00FF6162");
+    }
+
+    #[test]
+    fn four_cc() {
+        let code = "BWTB".parse::<FourCC>().unwrap();
+        assert_eq!(code, FourCC(*b"BWTB"));
+        assert_eq!(code.to_string(), "BWTB");
+        assert_eq!(format!("{code:?}"), "FourCC(\"BWTB\")");
+        assert_eq!(FourCC::from_u32(code.as_u32()), code);
+        assert_eq!(code.as_u32(), u32::from_le_bytes(*b"BWTB"));
+        assert_eq!(FourCC(*b"A\x00\xFFz").to_string(), "A\\x00\\xFFz");
+        assert_eq!("BWT".parse::<FourCC>(), Err(FourCCError(3)));
+    }
+
+}
@@ -0,0 +1,904 @@
+//! A lenient python pickle decoder for game data.
+//!
+//! Game data often contains objects created from python classes that cannot be
+//! resolved, such as `collections.deque`, the regular decoder either fails or replaces
+//! them by none. This decoder instead keeps these objects with a generic
+//! representation, a dictionary with the following keys, only present if relevant:
+//!
+//! - `__global__`: the `module.name` of the global, or the value that was called;
+//! - `__args__`: the arguments given to the global when calling it;
+//! - `__kwargs__`: the keyword arguments given to the global when calling it;
+//! - `__state__`: the state that was set on the object after its creation;
+//! - `__items__`: the items appended to the object, for list-like objects;
+//! - `__dictitems__`: the items set on the object, for dict-like objects.
+//!
+//! Globals that are referenced without being called are represented by a dictionary
+//! with only the `__global__` key. Builtin types (`set`, `frozenset`, `list`, `tuple`,
+//! `bytearray`) and bytes encoded with `_codecs.encode` are resolved to their value.
+//! References of a structure to itself, or to one of its parents, are represented by
+//! a dictionary with only the `__ref__` key containing the memo index of the
+//! referenced structure.
+//!
+//! As with [`super::io::serde_pickle_de_options`], python 2 strings are decoded as
+//! strings if valid UTF-8 or bytes otherwise, integers that don't fit in 64 bits are
+//! errors. Values nested deeper than [`MAX_DEPTH`], or pickles that expand too much
+//! because they reference the same memoized values many times, are also errors.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Read};
+
+use serde_pickle::{HashableValue, Value};
+
+
+/// Maximum nesting depth of a decoded value.
+pub const MAX_DEPTH: usize = 256;
+
+/// Maximum size of a decoded value relative to the length of the pickle, the size of
+/// a value is its number of values plus the length of its strings and bytes. This
+/// bounds the expansion of memoized values that are referenced many times.
+pub const MAX_EXPANSION: usize = 64;
+
+/// Decode a pickle from the given reader, the whole reader is read.
+pub fn value_from_reader<R: Read>(mut read: R) -> io::Result<Value> {
+    let mut data = Vec::new();
+    read.read_to_end(&mut data)?;
+    value_from_slice(&data)
+}
+
+/// Decode a pickle from the given bytes.
+pub fn value_from_slice(data: &[u8]) -> io::Result<Value> {
+    Decoder {
+        data,
+        pos: 0,
+        stack: Vec::new(),
+        marks: Vec::new(),
+        memo: HashMap::new(),
+        nodes: Vec::new(),
+    }.decode()
+}
+
+
+/// Internal value that may reference a memoized value, this is needed because python
+/// containers are memoized before being filled. Containers on the stack are stored in
+/// the decoder's nodes and referenced by [`Val::Node`], so that values are never
+/// nested more than a few levels and can be cloned or dropped without recursion.
+#[derive(Debug, Clone)]
+enum Val {
+    None,
+    Bool(bool),
+    I64(i64),
+    F64(f64),
+    Bytes(Vec<u8>),
+    String(String),
+    List(Vec<Val>),
+    Tuple(Vec<Val>),
+    Set(Vec<Val>),
+    FrozenSet(Vec<Val>),
+    Dict(Vec<(Val, Val)>),
+    Global(String),
+    Object(Box<Object>),
+    Ref(u32),
+    Node(usize),
+}
+
+/// Internal object created from a global that cannot be resolved.
+#[derive(Debug, Clone)]
+struct Object {
+    callable: Val,
+    args: Option<Val>,
+    kwargs: Option<Val>,
+    state: Option<Val>,
+    items: Vec<Val>,
+    dict_items: Vec<(Val, Val)>,
+}
+
+impl Object {
+
+    fn new(callable: Val, args: Option<Val>) -> Self {
+        Self {
+            callable,
+            args,
+            kwargs: None,
+            state: None,
+            items: Vec::new(),
+            dict_items: Vec::new(),
+        }
+    }
+
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    stack: Vec<Val>,
+    marks: Vec<usize>,
+    memo: HashMap<u32, Val>,
+    nodes: Vec<Val>,
+}
+
+/// State of the resolution of the decoded value.
+struct Resolution {
+    /// Memo indices being resolved, used to detect recursive structures.
+    refs: Vec<u32>,
+    /// Current nesting depth.
+    depth: usize,
+    /// Remaining size that can be resolved.
+    budget: usize,
+}
+
+impl Resolution {
+
+    fn charge(&mut self, size: usize) -> io::Result<()> {
+        self.budget = self.budget.checked_sub(size)
+            .ok_or_else(|| invalid_data("pickle expands too much"))?;
+        Ok(())
+    }
+
+}
+
+impl<'a> Decoder<'a> {
+
+    fn decode(mut self) -> io::Result<Value> {
+        loop {
+            let opcode = self.read_u8()?;
+            match opcode {
+                // Protocol and framing.
+                0x80 => { self.read_u8()?; }
+                0x95 => { self.read_bytes(8)?; }
+                b'.' => {
+                    let val = self.pop()?;
+                    let mut res = Resolution {
+                        refs: Vec::new(),
+                        depth: 0,
+                        budget: self.data.len().saturating_mul(MAX_EXPANSION),
+                    };
+                    return self.resolve(&val, &mut res);
+                }
+                // Integers and floats.
+                b'I' => {
+                    let val = match self.read_line()? {
+                        b"00" => Val::Bool(false),
+                        b"01" => Val::Bool(true),
+                        line => Val::I64(parse_line(line)?),
+                    };
+                    self.stack.push(val);
+                }
+                b'J' => {
+                    let val = i32::from_le_bytes(self.read_array()?);
+                    self.stack.push(Val::I64(val as i64));
+                }
+                b'K' => {
+                    let val = self.read_u8()?;
+                    self.stack.push(Val::I64(val as i64));
+                }
+                b'M' => {
+                    let val = u16::from_le_bytes(self.read_array()?);
+                    self.stack.push(Val::I64(val as i64));
+                }
+                b'L' => {
+                    let line = self.read_line()?;
+                    let line = line.strip_suffix(b"L").unwrap_or(line);
+                    self.stack.push(Val::I64(parse_line(line)?));
+                }
+                0x8A => {
+                    let len = self.read_u8()? as usize;
+                    let val = decode_long(self.read_bytes(len)?)?;
+                    self.stack.push(Val::I64(val));
+                }
+                0x8B => {
+                    let len = u32::from_le_bytes(self.read_array()?) as usize;
+                    let val = decode_long(self.read_bytes(len)?)?;
+                    self.stack.push(Val::I64(val));
+                }
+                b'F' => {
+                    let line = self.read_line()?;
+                    self.stack.push(Val::F64(parse_line(line)?));
+                }
+                b'G' => {
+                    let val = f64::from_be_bytes(self.read_array()?);
+                    self.stack.push(Val::F64(val));
+                }
+                // Constants.
+                b'N' => self.stack.push(Val::None),
+                0x88 => self.stack.push(Val::Bool(true)),
+                0x89 => self.stack.push(Val::Bool(false)),
+                // Python 2 strings, bytes and unicode strings.
+                b'S' => {
+                    let line = self.read_line()?;
+                    let val = decode_py2_str(unescape_string(line)?);
+                    self.stack.push(val);
+                }
+                b'T' => {
+                    let len = i32::from_le_bytes(self.read_array()?);
+                    let len = usize::try_from(len).map_err(|_| invalid_data("negative string length"))?;
+                    let val = decode_py2_str(self.read_bytes(len)?.to_vec());
+                    self.stack.push(val);
+                }
+                b'U' => {
+                    let len = self.read_u8()? as usize;
+                    let val = decode_py2_str(self.read_bytes(len)?.to_vec());
+                    self.stack.push(val);
+                }
+                b'V' => {
+                    let line = self.read_line()?;
+                    self.stack.push(Val::String(unescape_raw_unicode(line)?));
+                }
+                b'X' | 0x8C | 0x8D => {
+                    let len = self.read_len(opcode == 0x8C, opcode == 0x8D)?;
+                    let val = std::str::from_utf8(self.read_bytes(len)?)
+                        .map_err(|_| invalid_data("invalid utf-8 string"))?;
+                    self.stack.push(Val::String(val.to_string()));
+                }
+                b'B' | b'C' | 0x8E | 0x96 => {
+                    let len = self.read_len(opcode == b'C', matches!(opcode, 0x8E | 0x96))?;
+                    let val = self.read_bytes(len)?.to_vec();
+                    self.stack.push(Val::Bytes(val));
+                }
+                // Containers.
+                b']' => self.push_node(Val::List(Vec::new())),
+                b')' => self.push_node(Val::Tuple(Vec::new())),
+                b'}' => self.push_node(Val::Dict(Vec::new())),
+                0x8F => self.push_node(Val::Set(Vec::new())),
+                b'(' => self.marks.push(self.stack.len()),
+                b'l' => {
+                    let items = self.pop_mark()?;
+                    self.push_node(Val::List(items));
+                }
+                b't' => {
+                    let items = self.pop_mark()?;
+                    self.push_node(Val::Tuple(items));
+                }
+                0x85..=0x87 => {
+                    let len = (opcode - 0x84) as usize;
+                    if self.stack.len() < len {
+                        return Err(invalid_data("stack underflow"));
+                    }
+                    let items = self.stack.split_off(self.stack.len() - len);
+                    self.push_node(Val::Tuple(items));
+                }
+                b'd' => {
+                    let items = self.pop_mark()?;
+                    self.push_node(Val::Dict(into_pairs(items)?));
+                }
+                0x91 => {
+                    let items = self.pop_mark()?;
+                    self.push_node(Val::FrozenSet(items));
+                }
+                b'a' => {
+                    let item = self.pop()?;
+                    self.append(vec![item])?;
+                }
+                b'e' => {
+                    let items = self.pop_mark()?;
+                    self.append(items)?;
+                }
+                b's' => {
+                    let value = self.pop()?;
+                    let key = self.pop()?;
+                    self.set_items(vec![(key, value)])?;
+                }
+                b'u' => {
+                    let items = self.pop_mark()?;
+                    self.set_items(into_pairs(items)?)?;
+                }
+                0x90 => {
+                    let items = self.pop_mark()?;
+                    match self.target()? {
+                        Val::Set(set) => set.extend(items),
+                        _ => return Err(invalid_data("cannot add items to a non-set")),
+                    }
+                }
+                // Stack manipulation.
+                b'0' => {
+                    if self.marks.last() == Some(&self.stack.len()) {
+                        self.marks.pop();
+                    } else {
+                        self.pop()?;
+                    }
+                }
+                b'1' => { self.pop_mark()?; }
+                b'2' => {
+                    let val = self.stack.last().ok_or_else(|| invalid_data("stack underflow"))?.clone();
+                    self.stack.push(val);
+                }
+                // Memo.
+                b'p' | b'q' | b'r' | 0x94 => {
+                    let index = match opcode {
+                        b'p' => parse_line(self.read_line()?)?,
+                        b'q' => self.read_u8()? as u32,
+                        b'r' => u32::from_le_bytes(self.read_array()?),
+                        _ => self.memo.len() as u32,
+                    };
+                    let top = self.stack.last_mut().ok_or_else(|| invalid_data("stack underflow"))?;
+                    let val = std::mem::replace(top, Val::Ref(index));
+                    self.memo.insert(index, val);
+                }
+                b'g' | b'h' | b'j' => {
+                    let index = match opcode {
+                        b'g' => parse_line(self.read_line()?)?,
+                        b'h' => self.read_u8()? as u32,
+                        _ => u32::from_le_bytes(self.read_array()?),
+                    };
+                    if !self.memo.contains_key(&index) {
+                        return Err(invalid_data(format_args!("missing memo index {index}")));
+                    }
+                    self.stack.push(Val::Ref(index));
+                }
+                // Globals and objects.
+                b'c' => {
+                    let module = self.read_str_line()?;
+                    let name = self.read_str_line()?;
+                    self.stack.push(Val::Global(format!("{module}.{name}")));
+                }
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    match (self.deref(module), self.deref(name)) {
+                        (Val::String(module), Val::String(name)) =>
+                            self.stack.push(Val::Global(format!("{module}.{name}"))),
+                        _ => return Err(invalid_data("stack global requires strings")),
+                    }
+                }
+                b'R' => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let val = self.reduce(callable, args);
+                    self.push_node(val);
+                }
+                0x81 => {
+                    let args = self.pop()?;
+                    let cls = self.pop()?;
+                    self.push_node(Val::Object(Box::new(Object::new(cls, Some(args)))));
+                }
+                0x92 => {
+                    let kwargs = self.pop()?;
+                    let args = self.pop()?;
+                    let cls = self.pop()?;
+                    let mut object = Object::new(cls, Some(args));
+                    object.kwargs = Some(kwargs);
+                    self.push_node(Val::Object(Box::new(object)));
+                }
+                b'i' => {
+                    let module = self.read_str_line()?;
+                    let name = self.read_str_line()?;
+                    let args = self.pop_mark()?;
+                    let object = Object::new(Val::Global(format!("{module}.{name}")), Some(Val::Tuple(args)));
+                    self.push_node(Val::Object(Box::new(object)));
+                }
+                b'o' => {
+                    let mut items = self.pop_mark()?.into_iter();
+                    let cls = items.next().ok_or_else(|| invalid_data("missing object class"))?;
+                    let object = Object::new(cls, Some(Val::Tuple(items.collect())));
+                    self.push_node(Val::Object(Box::new(object)));
+                }
+                b'b' => {
+                    let state = self.pop()?;
+                    let state = match self.deref(state.clone()) {
+                        Val::Dict(items) => Ok(items),
+                        _ => Err(state),
+                    };
+                    match (self.target()?, state) {
+                        (Val::Object(object), Ok(items)) => object.state = Some(Val::Dict(items)),
+                        (Val::Object(object), Err(state)) => object.state = Some(state),
+                        (Val::Dict(dict), Ok(items)) => dict.extend(items),
+                        _ => return Err(invalid_data("cannot build a non-object")),
+                    }
+                }
+                opcode => return Err(invalid_data(format_args!("unsupported opcode 0x{opcode:02X}"))),
+            }
+        }
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_array<const LEN: usize>(&mut self) -> io::Result<[u8; LEN]> {
+        Ok(self.read_bytes(LEN)?.try_into().unwrap())
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.pos..)
+            .and_then(|data| data.get(..len))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Read a length that is either 1, 4 or 8 bytes long.
+    fn read_len(&mut self, short: bool, long: bool) -> io::Result<usize> {
+        let len = if short {
+            self.read_u8()? as u64
+        } else if long {
+            u64::from_le_bytes(self.read_array()?)
+        } else {
+            u32::from_le_bytes(self.read_array()?) as u64
+        };
+        usize::try_from(len).map_err(|_| invalid_data("length too large"))
+    }
+
+    fn read_line(&mut self) -> io::Result<&'a [u8]> {
+        let data = self.data.get(self.pos..).unwrap_or_default();
+        let len = data.iter().position(|&b| b == b'\n')
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        self.pos += len + 1;
+        let line = &data[..len];
+        Ok(line.strip_suffix(b"\r").unwrap_or(line))
+    }
+
+    fn read_str_line(&mut self) -> io::Result<&'a str> {
+        std::str::from_utf8(self.read_line()?).map_err(|_| invalid_data("invalid utf-8 line"))
+    }
+
+    fn pop(&mut self) -> io::Result<Val> {
+        if self.marks.last().is_some_and(|&mark| mark >= self.stack.len()) {
+            return Err(invalid_data("stack underflow"));
+        }
+        self.stack.pop().ok_or_else(|| invalid_data("stack underflow"))
+    }
+
+    /// Push a container on the stack, stored in the nodes.
+    fn push_node(&mut self, val: Val) {
+        self.nodes.push(val);
+        self.stack.push(Val::Node(self.nodes.len() - 1));
+    }
+
+    fn pop_mark(&mut self) -> io::Result<Vec<Val>> {
+        let mark = self.marks.pop().ok_or_else(|| invalid_data("missing mark"))?;
+        if mark > self.stack.len() {
+            return Err(invalid_data("stack underflow"));
+        }
+        Ok(self.stack.split_off(mark))
+    }
+
+    /// Return the container at the top of the stack, following memo references, this
+    /// is the target of operations that modify containers.
+    fn target(&mut self) -> io::Result<&mut Val> {
+
+        let mut val = self.stack.last().ok_or_else(|| invalid_data("stack underflow"))?;
+
+        // Bounded to avoid looping on references to themselves.
+        for _ in 0..=self.memo.len() {
+            match *val {
+                Val::Ref(index) => val = self.memo.get(&index)
+                    .ok_or_else(|| invalid_data(format_args!("invalid memo index {index}")))?,
+                Val::Node(index) => return Ok(&mut self.nodes[index]),
+                _ => break,
+            }
+        }
+
+        Err(invalid_data("target is not a container"))
+
+    }
+
+    /// Follow memo references and nodes of the given value, returning a copy of the
+    /// referenced value, or none if the reference is invalid.
+    fn deref(&self, mut val: Val) -> Val {
+        for _ in 0..=self.memo.len() {
+            match val {
+                Val::Ref(index) => val = self.memo.get(&index).cloned().unwrap_or(Val::None),
+                Val::Node(index) => return self.nodes[index].clone(),
+                val => return val,
+            }
+        }
+        Val::None
+    }
+
+    fn append(&mut self, items: Vec<Val>) -> io::Result<()> {
+        match self.target()? {
+            Val::List(list) => list.extend(items),
+            Val::Object(object) => object.items.extend(items),
+            _ => return Err(invalid_data("cannot append to a non-list")),
+        }
+        Ok(())
+    }
+
+    fn set_items(&mut self, items: Vec<(Val, Val)>) -> io::Result<()> {
+        match self.target()? {
+            Val::Dict(dict) => dict.extend(items),
+            Val::Object(object) => object.dict_items.extend(items),
+            _ => return Err(invalid_data("cannot set items of a non-dict")),
+        }
+        Ok(())
+    }
+
+    /// Call the given callable with the given arguments, builtin types are resolved and
+    /// any other callable is kept as an object.
+    fn reduce(&self, callable: Val, args: Val) -> Val {
+
+        if let Val::Global(name) = self.deref(callable.clone()) {
+
+            let args_items = match self.deref(args.clone()) {
+                Val::Tuple(items) => Some(items),
+                _ => None,
+            };
+
+            let items = |index: usize| match args_items.as_ref().and_then(|items| items.get(index)).map(|item| self.deref(item.clone())) {
+                Some(Val::List(items) | Val::Tuple(items) | Val::Set(items) | Val::FrozenSet(items)) => Some(items),
+                _ => None,
+            };
+
+            let args_len = args_items.as_ref().map(Vec::len);
+            let name = name.strip_prefix("__builtin__.").or_else(|| name.strip_prefix("builtins.")).unwrap_or(&name);
+
+            let val = match (name, args_len) {
+                ("set", Some(0)) => Some(Val::Set(Vec::new())),
+                ("set", Some(1)) => items(0).map(Val::Set),
+                ("frozenset", Some(0)) => Some(Val::FrozenSet(Vec::new())),
+                ("frozenset", Some(1)) => items(0).map(Val::FrozenSet),
+                ("list", Some(0)) => Some(Val::List(Vec::new())),
+                ("list", Some(1)) => items(0).map(Val::List),
+                ("tuple", Some(0)) => Some(Val::Tuple(Vec::new())),
+                ("tuple", Some(1)) => items(0).map(Val::Tuple),
+                ("bytearray", Some(0)) => Some(Val::Bytes(Vec::new())),
+                ("bytearray" | "_codecs.encode", Some(1 | 2)) => {
+                    let args_items = args_items.as_ref().unwrap();
+                    let encoding = args_items.get(1).map(|encoding| self.deref(encoding.clone()));
+                    match (self.deref(args_items[0].clone()), encoding) {
+                        (Val::Bytes(bytes), None) if name == "bytearray" => Some(Val::Bytes(bytes)),
+                        (Val::String(s), Some(Val::String(encoding))) if matches!(&*encoding, "latin1" | "latin-1") =>
+                            s.chars().map(|c| u8::try_from(c).ok()).collect::<Option<Vec<u8>>>().map(Val::Bytes),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some(val) = val {
+                return val;
+            }
+
+        }
+
+        Val::Object(Box::new(Object::new(callable, Some(args))))
+
+    }
+
+    /// Resolve the given value into a pickle value, checking the nesting depth and the
+    /// size of the resolved value.
+    fn resolve(&self, val: &Val, res: &mut Resolution) -> io::Result<Value> {
+        if res.depth >= MAX_DEPTH {
+            return Err(invalid_data("pickle nested too deeply"));
+        }
+        res.charge(1)?;
+        res.depth += 1;
+        let ret = self.resolve_inner(val, res);
+        res.depth -= 1;
+        ret
+    }
+
+    fn resolve_inner(&self, val: &Val, res: &mut Resolution) -> io::Result<Value> {
+        Ok(match val {
+            Val::None => Value::None,
+            &Val::Bool(b) => Value::Bool(b),
+            &Val::I64(n) => Value::I64(n),
+            &Val::F64(n) => Value::F64(n),
+            Val::Bytes(bytes) => {
+                res.charge(bytes.len())?;
+                Value::Bytes(bytes.clone())
+            }
+            Val::String(s) => {
+                res.charge(s.len())?;
+                Value::String(s.clone())
+            }
+            Val::List(items) => Value::List(self.resolve_items(items, res)?),
+            Val::Tuple(items) => Value::Tuple(self.resolve_items(items, res)?),
+            Val::Set(items) => Value::Set(self.resolve_set(items, res)?),
+            Val::FrozenSet(items) => Value::FrozenSet(self.resolve_set(items, res)?),
+            Val::Dict(items) => Value::Dict(self.resolve_dict(items, res)?),
+            Val::Global(name) => {
+                res.charge(name.len())?;
+                let mut dict = BTreeMap::new();
+                dict.insert(HashableValue::String("__global__".to_string()), Value::String(name.clone()));
+                Value::Dict(dict)
+            }
+            Val::Object(object) => {
+
+                let mut dict = BTreeMap::new();
+                let mut insert = |key: &str, value: Value| {
+                    dict.insert(HashableValue::String(key.to_string()), value);
+                };
+
+                match self.deref(object.callable.clone()) {
+                    Val::Global(name) => {
+                        res.charge(name.len())?;
+                        insert("__global__", Value::String(name));
+                    }
+                    _ => insert("__global__", self.resolve(&object.callable, res)?),
+                }
+
+                for (key, value) in [("__args__", &object.args), ("__kwargs__", &object.kwargs), ("__state__", &object.state)] {
+                    if let Some(value) = value {
+                        insert(key, self.resolve(value, res)?);
+                    }
+                }
+
+                if !object.items.is_empty() {
+                    insert("__items__", Value::List(self.resolve_items(&object.items, res)?));
+                }
+
+                if !object.dict_items.is_empty() {
+                    insert("__dictitems__", Value::Dict(self.resolve_dict(&object.dict_items, res)?));
+                }
+
+                Value::Dict(dict)
+
+            }
+            &Val::Ref(index) => {
+                // Recursive structures are represented by a reference to the memo index.
+                if res.refs.contains(&index) {
+                    let mut dict = BTreeMap::new();
+                    dict.insert(HashableValue::String("__ref__".to_string()), Value::I64(index as i64));
+                    return Ok(Value::Dict(dict));
+                }
+                let val = self.memo.get(&index)
+                    .ok_or_else(|| invalid_data(format_args!("invalid memo index {index}")))?;
+                res.refs.push(index);
+                let ret = self.resolve_inner(val, res);
+                res.refs.pop();
+                ret?
+            }
+            &Val::Node(index) => self.resolve_inner(&self.nodes[index], res)?,
+        })
+    }
+
+    fn resolve_items(&self, items: &[Val], res: &mut Resolution) -> io::Result<Vec<Value>> {
+        items.iter().map(|item| self.resolve(item, res)).collect()
+    }
+
+    fn resolve_hashable(&self, val: &Val, res: &mut Resolution) -> io::Result<HashableValue> {
+        self.resolve(val, res)?.into_hashable()
+            .map_err(|e| invalid_data(format_args!("unhashable value: {e}")))
+    }
+
+    fn resolve_set(&self, items: &[Val], res: &mut Resolution) -> io::Result<BTreeSet<HashableValue>> {
+        items.iter().map(|item| self.resolve_hashable(item, res)).collect()
+    }
+
+    fn resolve_dict(&self, items: &[(Val, Val)], res: &mut Resolution) -> io::Result<BTreeMap<HashableValue, Value>> {
+        items.iter()
+            .map(|(key, value)| Ok((self.resolve_hashable(key, res)?, self.resolve(value, res)?)))
+            .collect()
+    }
+
+}
+
+fn invalid_data(msg: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn parse_line<T: std::str::FromStr>(line: &[u8]) -> io::Result<T> {
+    std::str::from_utf8(line).ok()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(|| invalid_data(format_args!("invalid number: {}", String::from_utf8_lossy(line))))
+}
+
+/// Decode a little-endian two's complement integer, it must fit in 64 bits.
+fn decode_long(bytes: &[u8]) -> io::Result<i64> {
+    let negative = bytes.last().is_some_and(|&b| b & 0x80 != 0);
+    let mut buf = if negative { [0xFF; 8] } else { [0; 8] };
+    let (low, high) = bytes.split_at(bytes.len().min(8));
+    buf[..low.len()].copy_from_slice(low);
+    let val = i64::from_le_bytes(buf);
+    let ext = if val < 0 { 0xFF } else { 0x00 };
+    if high.iter().any(|&b| b != ext) {
+        return Err(invalid_data("integer too large"));
+    }
+    Ok(val)
+}
+
+/// Python 2 strings are decoded as strings if valid UTF-8, or bytes otherwise.
+fn decode_py2_str(bytes: Vec<u8>) -> Val {
+    match String::from_utf8(bytes) {
+        Ok(s) => Val::String(s),
+        Err(e) => Val::Bytes(e.into_bytes()),
+    }
+}
+
+/// Unescape a quoted python 2 string representation.
+fn unescape_string(line: &[u8]) -> io::Result<Vec<u8>> {
+
+    let content = match line {
+        [b'\'', content @ .., b'\''] | [b'"', content @ .., b'"'] => content,
+        _ => return Err(invalid_data("invalid quoted string")),
+    };
+
+    let mut ret = Vec::with_capacity(content.len());
+    let mut iter = content.iter().copied();
+    while let Some(b) = iter.next() {
+        if b != b'\\' {
+            ret.push(b);
+            continue;
+        }
+        match iter.next() {
+            Some(b'n') => ret.push(b'\n'),
+            Some(b'r') => ret.push(b'\r'),
+            Some(b't') => ret.push(b'\t'),
+            Some(b'x') => {
+                let hex = [iter.next(), iter.next()];
+                let hex = hex.iter().flatten().map(|&b| b as char).collect::<String>();
+                ret.push(u8::from_str_radix(&hex, 16).map_err(|_| invalid_data("invalid string escape"))?);
+            }
+            Some(b @ b'0'..=b'7') => {
+                // Octal escapes are at most 3 digits.
+                let mut val = (b - b'0') as u32;
+                let mut rest = iter.clone();
+                for _ in 0..2 {
+                    match rest.next() {
+                        Some(b @ b'0'..=b'7') => {
+                            val = val * 8 + (b - b'0') as u32;
+                            iter.next();
+                        }
+                        _ => break,
+                    }
+                }
+                ret.push(val as u8);
+            }
+            Some(b) => ret.push(b),
+            None => return Err(invalid_data("invalid string escape")),
+        }
+    }
+
+    Ok(ret)
+
+}
+
+/// Decode a raw-unicode-escape line, only `\uXXXX` and `\UXXXXXXXX` are escapes and
+/// other bytes are latin-1 characters.
+fn unescape_raw_unicode(line: &[u8]) -> io::Result<String> {
+
+    let mut ret = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < line.len() {
+        let len = match &line[i..] {
+            [b'\\', b'u', ..] => 4,
+            [b'\\', b'U', ..] => 8,
+            _ => {
+                ret.push(line[i] as char);
+                i += 1;
+                continue;
+            }
+        };
+        let hex = line.get(i + 2..i + 2 + len).ok_or_else(|| invalid_data("invalid unicode escape"))?;
+        let c = std::str::from_utf8(hex).ok()
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| invalid_data("invalid unicode escape"))?;
+        ret.push(c);
+        i += 2 + len;
+    }
+
+    Ok(ret)
+
+}
+
+fn into_pairs(items: Vec<Val>) -> io::Result<Vec<(Val, Val)>> {
+    if !items.len().is_multiple_of(2) {
+        return Err(invalid_data("odd number of dict items"));
+    }
+    let mut iter = items.into_iter();
+    let mut pairs = Vec::with_capacity(iter.len() / 2);
+    while let (Some(key), Some(value)) = (iter.next(), iter.next()) {
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn key(s: &str) -> HashableValue {
+        HashableValue::String(s.to_string())
+    }
+
+    #[test]
+    fn unknown_global() {
+
+        // Protocol 2 pickle of {'q': deque([1, u'a']), 'l': l, 'm': l} with l = [1, 2],
+        // note that the list is memoized before being filled.
+        let data = b"\x80\x02}q\x00(U\x01qccollections\ndeque\nq\x01]q\x02(K\x01X\x01\x00\x00\x00ae\x85Rq\x03U\x01l]q\x04(K\x01K\x02eU\x01mh\x04u.";
+
+        let Value::Dict(dict) = value_from_slice(data).unwrap() else { panic!() };
+        let list = Value::List(vec![Value::I64(1), Value::I64(2)]);
+        assert_eq!(dict.get(&key("l")), Some(&list));
+        assert_eq!(dict.get(&key("m")), Some(&list));
+
+        let Some(Value::Dict(deque)) = dict.get(&key("q")) else { panic!() };
+        assert_eq!(deque.len(), 2);
+        assert_eq!(deque.get(&key("__global__")), Some(&Value::String("collections.deque".to_string())));
+        assert_eq!(deque.get(&key("__args__")), Some(&Value::Tuple(vec![
+            Value::List(vec![Value::I64(1), Value::String("a".to_string())]),
+        ])));
+
+    }
+
+    #[test]
+    fn builtins_and_recursion() {
+
+        // Protocol 2 pickle of set([1]) and of l = []; l.append(l).
+        let set = value_from_slice(b"\x80\x02c__builtin__\nset\n](K\x01e\x85R.").unwrap();
+        assert_eq!(set, Value::Set([HashableValue::I64(1)].into_iter().collect()));
+
+        let recursive = value_from_slice(b"\x80\x02]q\x00h\x00a.").unwrap();
+        let back_ref = Value::Dict([(key("__ref__"), Value::I64(0))].into_iter().collect());
+        assert_eq!(recursive, Value::List(vec![back_ref]));
+
+        // Protocol 0 pickle of (u'\xe9t\xe9', 'a\nb', 12345678901L).
+        let tuple = value_from_slice(b"(V\\u00e9t\\u00e9\nS'a\\nb'\nL12345678901L\ntp0\n.").unwrap();
+        assert_eq!(tuple, Value::Tuple(vec![
+            Value::String("été".to_string()),
+            Value::String("a\nb".to_string()),
+            Value::I64(12345678901),
+        ]));
+
+        assert_eq!(value_from_slice(b"\x80\x02P0\n.").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(value_from_slice(b"\x80\x02\x8a\x09\x00\x00\x00\x00\x00\x00\x00\x00\x01.").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    }
+
+    #[test]
+    fn protocol_3_and_4() {
+
+        // Protocol 3 pickle of (b'xy', 'z').
+        let tuple = value_from_slice(b"\x80\x03C\x02xyq\x00X\x01\x00\x00\x00zq\x01\x86q\x02.").unwrap();
+        assert_eq!(tuple, Value::Tuple(vec![
+            Value::Bytes(b"xy".to_vec()),
+            Value::String("z".to_string()),
+        ]));
+
+        // Protocol 4 pickle of {'a': deque([1]), 'b': b'xy', 'c': '\xe9'}, using frame,
+        // short unicode, memoize and stack global opcodes.
+        let data = b"\x80\x04\x959\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x01a\x94\x8c\x0bcollections\x94\x8c\x05deque\x94\x93\x94)R\x94K\x01a\x8c\x01b\x94C\x02xy\x94\x8c\x01c\x94\x8c\x02\xc3\xa9\x94u.";
+
+        let Value::Dict(dict) = value_from_slice(data).unwrap() else { panic!() };
+        assert_eq!(dict.len(), 3);
+        assert_eq!(dict.get(&key("b")), Some(&Value::Bytes(b"xy".to_vec())));
+        assert_eq!(dict.get(&key("c")), Some(&Value::String("é".to_string())));
+
+        let Some(Value::Dict(deque)) = dict.get(&key("a")) else { panic!() };
+        assert_eq!(deque.get(&key("__global__")), Some(&Value::String("collections.deque".to_string())));
+        assert_eq!(deque.get(&key("__items__")), Some(&Value::List(vec![Value::I64(1)])));
+
+        // Stack global requires a module and a name that are strings.
+        assert_eq!(value_from_slice(b"\x80\x04K\x01K\x02\x93.").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    }
+
+    #[test]
+    fn limits() {
+
+        // Lists nested deeper than the limit, without memo.
+        let mut data = b"\x80\x02".to_vec();
+        data.extend(std::iter::repeat_n(b']', 200_000));
+        data.extend(std::iter::repeat_n(b'a', 199_999));
+        data.push(b'.');
+        assert_eq!(value_from_slice(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Each list contains the previous one twice, the expansion is exponential.
+        let mut data = b"\x80\x02]q\x00".to_vec();
+        for i in 1..64u8 {
+            data.extend_from_slice(&[b']', b'(', b'h', i - 1, b'h', i - 1, b'e', b'q', i]);
+        }
+        data.push(b'.');
+        assert_eq!(value_from_slice(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Nesting just below the limit is fine.
+        let mut data = b"\x80\x02".to_vec();
+        data.extend(std::iter::repeat_n(b']', MAX_DEPTH));
+        data.extend(std::iter::repeat_n(b'a', MAX_DEPTH - 1));
+        data.push(b'.');
+        let mut val = &value_from_slice(&data).unwrap();
+        let mut depth = 1;
+        while let Value::List(items) = val {
+            let Some(item) = items.first() else { break };
+            val = item;
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_DEPTH);
+
+    }
+
+}
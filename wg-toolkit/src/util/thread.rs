@@ -2,6 +2,7 @@
 
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use std::thread;
 
 use crossbeam_channel::{Receiver, Sender};
@@ -30,7 +31,28 @@ impl<T: Send + 'static> ThreadPoll<T> {
     /// be added to the internal queue that can be retrieved with [`Self::poll`], this
     /// producer's thread terminates when this aggregator is dropped. In order for this
     /// to properly work you should be using some kind of timeout on the producer.
-    pub fn spawn<F>(&self, mut producer: F)
+    pub fn spawn<F>(&self, producer: F)
+    where 
+        F: FnMut() -> Option<T>,
+        F: Send + 'static,
+    {
+        self.spawn_inner(None, producer);
+    }
+
+    /// Same as [`Self::spawn`] but the worker thread is given the name, this is useful
+    /// to tell workers apart in a debugger or profiler. Nul bytes, which are not 
+    /// supported in thread names, are replaced so that naming never prevents spawning.
+    /// Note that the name seen by the OS may be truncated (to 15 bytes on Linux), so
+    /// the distinguishing part of the name should come first.
+    pub fn spawn_named<F>(&self, name: impl Into<String>, producer: F)
+    where 
+        F: FnMut() -> Option<T>,
+        F: Send + 'static,
+    {
+        self.spawn_inner(Some(name.into()), producer);
+    }
+
+    fn spawn_inner<F>(&self, name: Option<String>, mut producer: F)
     where 
         F: FnMut() -> Option<T>,
         F: Send + 'static,
@@ -38,9 +60,14 @@ impl<T: Send + 'static> ThreadPoll<T> {
 
         let tx = self.tx.clone();
         let num = self.count.fetch_add(1, Ordering::Relaxed);
+
+        let name = match name {
+            Some(name) => name.replace('\0', "?"),
+            None => format!("poll-worker-{num}"),
+        };
         
         thread::Builder::new()
-            .name(format!("poll-worker-{num}"))
+            .name(name)
             .spawn(move || {
                 trace!("New poll worker #{num} ({})", std::any::type_name::<F>());
                 while let Some(value) = producer() {
@@ -56,14 +83,32 @@ impl<T: Send + 'static> ThreadPoll<T> {
 
     /// Same as [`Self::spawn`] but also returning a handle that, when dropped, will end
     /// the associated worker thread.
-    pub fn spawn_with_handle<F>(&self, mut producer: F) -> ThreadPollHandle
+    pub fn spawn_with_handle<F>(&self, producer: F) -> ThreadPollHandle
+    where 
+        F: FnMut() -> Option<T>,
+        F: Send + 'static,
+    {
+        self.spawn_with_handle_inner(None, producer)
+    }
+
+    /// Same as [`Self::spawn_with_handle`] but the worker thread is given the name, 
+    /// see [`Self::spawn_named`].
+    pub fn spawn_with_handle_named<F>(&self, name: impl Into<String>, producer: F) -> ThreadPollHandle
+    where 
+        F: FnMut() -> Option<T>,
+        F: Send + 'static,
+    {
+        self.spawn_with_handle_inner(Some(name.into()), producer)
+    }
+
+    fn spawn_with_handle_inner<F>(&self, name: Option<String>, mut producer: F) -> ThreadPollHandle
     where 
         F: FnMut() -> Option<T>,
         F: Send + 'static,
     {
         let alive = Arc::new(AtomicBool::new(true));
         let thread_alive = Arc::clone(&alive);
-        self.spawn(move || if thread_alive.load(Ordering::Relaxed) {
+        self.spawn_inner(name, move || if thread_alive.load(Ordering::Relaxed) {
             producer()
         } else {
             None
@@ -77,6 +122,14 @@ impl<T: Send + 'static> ThreadPoll<T> {
         self.rx.recv().unwrap()
     }
 
+    /// Block until a new value is available or the given timeout is reached, in which
+    /// case none is returned. A value produced after the timeout is not lost and will
+    /// be returned by the next poll.
+    pub fn poll_timeout(&self, timeout: Duration) -> Option<T> {
+        // Same as above, the "disconnected" error should not happen.
+        self.rx.recv_timeout(timeout).ok()
+    }
+
     /// Non-blocking poll.
     pub fn try_poll(&self) -> Option<T> {
         // Don't care of the "disconnected" error because it should not happen.
@@ -94,3 +147,58 @@ impl Drop for ThreadPollHandle {
         self.0.store(false, Ordering::Relaxed);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn spawn_named() {
+
+        let poll = ThreadPoll::new();
+        
+        let mut sent = false;
+        poll.spawn_named("w-9788-127.0.0.1", move || {
+            (!std::mem::replace(&mut sent, true)).then(|| thread::current().name().map(str::to_string))
+        });
+
+        let mut sent = false;
+        let _handle = poll.spawn_with_handle_named("bad\0name", move || {
+            (!std::mem::replace(&mut sent, true)).then(|| thread::current().name().map(str::to_string))
+        });
+
+        let mut names = [poll.poll(), poll.poll()];
+        names.sort();
+        assert_eq!(names, [Some("bad?name".to_string()), Some("w-9788-127.0.0.1".to_string())]);
+
+    }
+
+    #[test]
+    fn poll_timeout() {
+
+        let poll = ThreadPoll::<u32>::new();
+        assert_eq!(poll.poll_timeout(Duration::from_millis(20)), None);
+        assert_eq!(poll.try_poll(), None);
+
+        // The value is produced before the deadline.
+        let mut sent = false;
+        poll.spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            (!std::mem::replace(&mut sent, true)).then_some(1)
+        });
+        assert_eq!(poll.poll_timeout(Duration::from_secs(5)), Some(1));
+
+        // The value is produced after the deadline but not lost.
+        let mut sent = false;
+        poll.spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            (!std::mem::replace(&mut sent, true)).then_some(2)
+        });
+        assert_eq!(poll.poll_timeout(Duration::from_millis(20)), None);
+        assert_eq!(poll.poll_timeout(Duration::from_secs(5)), Some(2));
+
+    }
+
+}